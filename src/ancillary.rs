@@ -0,0 +1,297 @@
+//! Typed forms of the small fixed-layout ancillary chunks.
+
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AncillaryError {
+    #[error("AncillaryError {0} data is {1} bytes, expected {2}")]
+    InvalidLength(&'static str, usize, usize),
+    #[error("AncillaryError unknown pHYs unit {0}")]
+    UnknownUnit(u8),
+    #[error("AncillaryError unknown sRGB rendering intent {0}")]
+    UnknownIntent(u8),
+    #[error("AncillaryError gamma must be positive")]
+    InvalidGamma,
+}
+
+/// A chunk with a fixed layout that maps onto a Rust value.
+pub trait AncillaryChunk: Sized {
+    const TYPE: &'static str;
+
+    fn from_data(data: &[u8]) -> Result<Self, AncillaryError>;
+
+    fn to_data(&self) -> Vec<u8>;
+
+    fn chunk_type() -> ChunkType {
+        Self::TYPE.parse().expect("ancillary chunk types are valid")
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        Chunk::new(Self::chunk_type(), self.to_data())
+    }
+}
+
+/// What the pixel counts of pHYs are measured against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// Only the aspect ratio is known.
+    Unknown = 0,
+    Meter = 1,
+}
+
+/// Intended pixel size or aspect ratio.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.pHYs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicalDimensions {
+    pub pixels_per_unit_x: u32,
+    pub pixels_per_unit_y: u32,
+    pub unit: Unit,
+}
+
+/// Meters in an inch, for converting pixels per meter to dots per inch.
+const METERS_PER_INCH: f64 = 0.0254;
+
+impl PhysicalDimensions {
+    /// Dimensions for the same resolution in both directions, in dots per inch.
+    pub fn from_dpi(dpi: f64) -> Self {
+        let pixels_per_meter = (dpi / METERS_PER_INCH).round() as u32;
+        PhysicalDimensions {
+            pixels_per_unit_x: pixels_per_meter,
+            pixels_per_unit_y: pixels_per_meter,
+            unit: Unit::Meter,
+        }
+    }
+
+    /// The horizontal and vertical resolution in dots per inch, if the unit is known.
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        match self.unit {
+            Unit::Meter => Some((
+                f64::from(self.pixels_per_unit_x) * METERS_PER_INCH,
+                f64::from(self.pixels_per_unit_y) * METERS_PER_INCH,
+            )),
+            Unit::Unknown => None,
+        }
+    }
+}
+
+impl AncillaryChunk for PhysicalDimensions {
+    const TYPE: &'static str = "pHYs";
+
+    fn from_data(data: &[u8]) -> Result<Self, AncillaryError> {
+        let data: &[u8; 9] = fixed(Self::TYPE, data)?;
+        Ok(PhysicalDimensions {
+            pixels_per_unit_x: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            pixels_per_unit_y: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            unit: match data[8] {
+                0 => Unit::Unknown,
+                1 => Unit::Meter,
+                unit => return Err(AncillaryError::UnknownUnit(unit)),
+            },
+        })
+    }
+
+    fn to_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(9);
+        data.extend(self.pixels_per_unit_x.to_be_bytes());
+        data.extend(self.pixels_per_unit_y.to_be_bytes());
+        data.push(self.unit as u8);
+        data
+    }
+}
+
+/// Image gamma, stored as the gamma times 100000.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.gAMA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gamma(pub u32);
+
+impl Gamma {
+    pub const SCALE: f64 = 100_000.0;
+
+    pub fn from_f64(gamma: f64) -> Result<Self, AncillaryError> {
+        let scaled = (gamma * Self::SCALE).round();
+        if !(1.0..=f64::from(u32::MAX)).contains(&scaled) {
+            return Err(AncillaryError::InvalidGamma);
+        }
+        Ok(Gamma(scaled as u32))
+    }
+
+    pub fn value(self) -> f64 {
+        f64::from(self.0) / Self::SCALE
+    }
+}
+
+impl AncillaryChunk for Gamma {
+    const TYPE: &'static str = "gAMA";
+
+    fn from_data(data: &[u8]) -> Result<Self, AncillaryError> {
+        let data: &[u8; 4] = fixed(Self::TYPE, data)?;
+        Ok(Gamma(u32::from_be_bytes(*data)))
+    }
+
+    fn to_data(&self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+}
+
+/// How an sRGB image should be rendered.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.sRGB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual = 0,
+    RelativeColorimetric = 1,
+    Saturation = 2,
+    AbsoluteColorimetric = 3,
+}
+
+impl fmt::Display for RenderingIntent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RenderingIntent::Perceptual => "perceptual",
+            RenderingIntent::RelativeColorimetric => "relative colorimetric",
+            RenderingIntent::Saturation => "saturation",
+            RenderingIntent::AbsoluteColorimetric => "absolute colorimetric",
+        };
+        f.write_str(name)
+    }
+}
+
+impl AncillaryChunk for RenderingIntent {
+    const TYPE: &'static str = "sRGB";
+
+    fn from_data(data: &[u8]) -> Result<Self, AncillaryError> {
+        let [intent]: &[u8; 1] = fixed(Self::TYPE, data)?;
+        Ok(match intent {
+            0 => RenderingIntent::Perceptual,
+            1 => RenderingIntent::RelativeColorimetric,
+            2 => RenderingIntent::Saturation,
+            3 => RenderingIntent::AbsoluteColorimetric,
+            &intent => return Err(AncillaryError::UnknownIntent(intent)),
+        })
+    }
+
+    fn to_data(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+}
+
+/// A CIE 1931 x,y chromaticity, each coordinate times 100000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chromaticity {
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Chromaticity {
+    pub fn to_f64(self) -> (f64, f64) {
+        (
+            f64::from(self.x) / Gamma::SCALE,
+            f64::from(self.y) / Gamma::SCALE,
+        )
+    }
+}
+
+/// The white point and primaries of the display the image was made for.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.cHRM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chromaticities {
+    pub white: Chromaticity,
+    pub red: Chromaticity,
+    pub green: Chromaticity,
+    pub blue: Chromaticity,
+}
+
+impl Chromaticities {
+    /// The values the spec recommends writing alongside sRGB.
+    pub const SRGB: Chromaticities = Chromaticities {
+        white: Chromaticity { x: 31270, y: 32900 },
+        red: Chromaticity { x: 64000, y: 33000 },
+        green: Chromaticity { x: 30000, y: 60000 },
+        blue: Chromaticity { x: 15000, y: 6000 },
+    };
+}
+
+impl AncillaryChunk for Chromaticities {
+    const TYPE: &'static str = "cHRM";
+
+    fn from_data(data: &[u8]) -> Result<Self, AncillaryError> {
+        let data: &[u8; 32] = fixed(Self::TYPE, data)?;
+        let value =
+            |at: usize| u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+        let point = |at: usize| Chromaticity {
+            x: value(at),
+            y: value(at + 4),
+        };
+        Ok(Chromaticities {
+            white: point(0),
+            red: point(8),
+            green: point(16),
+            blue: point(24),
+        })
+    }
+
+    fn to_data(&self) -> Vec<u8> {
+        [self.white, self.red, self.green, self.blue]
+            .iter()
+            .flat_map(|point| [point.x.to_be_bytes(), point.y.to_be_bytes()])
+            .flatten()
+            .collect()
+    }
+}
+
+fn fixed<'a, const N: usize>(
+    chunk_type: &'static str,
+    data: &'a [u8],
+) -> Result<&'a [u8; N], AncillaryError> {
+    data.try_into()
+        .map_err(|_| AncillaryError::InvalidLength(chunk_type, data.len(), N))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_physical_dimensions() {
+        let phys = PhysicalDimensions::from_dpi(72.0);
+        assert_eq!(phys.pixels_per_unit_x, 2835);
+        let data = phys.to_data();
+        assert_eq!(data, [0, 0, 0x0b, 0x13, 0, 0, 0x0b, 0x13, 1]);
+        assert_eq!(PhysicalDimensions::from_data(&data), Ok(phys));
+        let (x, _) = phys.dpi().unwrap();
+        assert!((x - 72.0).abs() < 0.01);
+        assert_eq!(
+            PhysicalDimensions::from_data(&[0; 8]),
+            Err(AncillaryError::InvalidLength("pHYs", 8, 9))
+        );
+    }
+
+    #[test]
+    fn test_gamma_and_intent() {
+        let gamma = Gamma::from_f64(1.0 / 2.2).unwrap();
+        assert_eq!(gamma, Gamma(45455));
+        assert_eq!(Gamma::from_data(&gamma.to_data()), Ok(gamma));
+        assert_eq!(Gamma::from_f64(0.0), Err(AncillaryError::InvalidGamma));
+
+        let intent = RenderingIntent::from_data(&[1]).unwrap();
+        assert_eq!(intent, RenderingIntent::RelativeColorimetric);
+        assert_eq!(intent.to_data(), [1]);
+        assert_eq!(
+            RenderingIntent::from_data(&[4]),
+            Err(AncillaryError::UnknownIntent(4))
+        );
+    }
+
+    #[test]
+    fn test_chromaticities() {
+        let data = Chromaticities::SRGB.to_data();
+        assert_eq!(data.len(), 32);
+        assert_eq!(&data[..4], 31270u32.to_be_bytes());
+        assert_eq!(Chromaticities::from_data(&data), Ok(Chromaticities::SRGB));
+        assert_eq!(Chromaticities::SRGB.red.to_f64(), (0.64, 0.33));
+    }
+}
@@ -0,0 +1,175 @@
+use thiserror::Error;
+
+use crate::envelope::{EnvelopeError, FileInfo};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    #[error("ArchiveError archive is truncated")]
+    Truncated,
+    #[error("ArchiveError more than one member is named {0}")]
+    DuplicateName(String),
+    #[error("ArchiveError no member named {0}")]
+    NotFound(String),
+    #[error("ArchiveError invalid member: {0}")]
+    InvalidMember(#[from] EnvelopeError),
+}
+
+/// A file stored in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub info: FileInfo,
+    pub data: Vec<u8>,
+}
+
+/// Several files bundled into one payload.
+///
+/// Layout: `member count: u32 BE`, then for each member `info len: u32 BE`, the
+/// `FileInfo` as stored in an envelope, and `info.size` bytes of data.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Archive {
+    members: Vec<Member>,
+}
+
+impl Archive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a member, keeping names unique so every member can be extracted.
+    pub fn add(&mut self, name: &str, mode: u32, data: Vec<u8>) -> Result<(), ArchiveError> {
+        FileInfo::validate_name(name)?;
+        if self.member(name).is_some() {
+            return Err(ArchiveError::DuplicateName(name.to_string()));
+        }
+
+        let info = FileInfo {
+            name: name.to_string(),
+            size: data.len() as u64,
+            mode,
+        };
+        self.members.push(Member { info, data });
+        Ok(())
+    }
+
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+
+    pub fn member(&self, name: &str) -> Option<&Member> {
+        self.members.iter().find(|member| member.info.name == name)
+    }
+
+    /// Looks up each of `names`, failing on the first that isn't in the archive.
+    pub fn select(&self, names: &[String]) -> Result<Vec<&Member>, ArchiveError> {
+        names
+            .iter()
+            .map(|name| {
+                self.member(name)
+                    .ok_or_else(|| ArchiveError::NotFound(name.clone()))
+            })
+            .collect()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(self.members.len() as u32).to_be_bytes());
+        for member in &self.members {
+            let info = member.info.to_bytes();
+            bytes.extend_from_slice(&(info.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&info);
+            bytes.extend_from_slice(&member.data);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ArchiveError> {
+        let (count, mut rest) = read_u32(bytes)?;
+
+        let mut archive = Self::new();
+        for _ in 0..count {
+            let (info_len, remaining) = read_u32(rest)?;
+            let (info, remaining) = take(remaining, info_len as usize)?;
+            let info = FileInfo::from_bytes(info)?;
+            let size = usize::try_from(info.size).map_err(|_| ArchiveError::Truncated)?;
+            let (data, remaining) = take(remaining, size)?;
+            archive.add(&info.name, info.mode, data.to_vec())?;
+            rest = remaining;
+        }
+        Ok(archive)
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> Result<(u32, &[u8]), ArchiveError> {
+    let (value, rest) = take(bytes, 4)?;
+    Ok((u32::from_be_bytes(value.try_into().unwrap()), rest))
+}
+
+fn take(bytes: &[u8], len: usize) -> Result<(&[u8], &[u8]), ArchiveError> {
+    if bytes.len() < len {
+        return Err(ArchiveError::Truncated);
+    }
+    Ok(bytes.split_at(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_archive() -> Archive {
+        let mut archive = Archive::new();
+        archive.add("a.txt", 0o644, b"hello".to_vec()).unwrap();
+        archive.add("b.key", 0o600, vec![0, 1, 2, 255]).unwrap();
+        archive.add("empty", 0o644, Vec::new()).unwrap();
+        archive
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let archive = testing_archive();
+        let parsed = Archive::from_bytes(&archive.to_bytes()).unwrap();
+        assert_eq!(parsed, archive);
+        assert_eq!(parsed.members().len(), 3);
+        assert_eq!(parsed.member("b.key").unwrap().info.mode, 0o600);
+        assert_eq!(parsed.member("b.key").unwrap().data, vec![0, 1, 2, 255]);
+    }
+
+    #[test]
+    fn test_select() {
+        let archive = testing_archive();
+        let names = ["empty".to_string(), "a.txt".to_string()];
+        let selected = archive.select(&names).unwrap();
+        assert_eq!(selected[0].info.name, "empty");
+        assert_eq!(selected[1].info.name, "a.txt");
+
+        assert_eq!(
+            archive.select(&["missing".to_string()]),
+            Err(ArchiveError::NotFound("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_invalid_members() {
+        let mut archive = testing_archive();
+        assert_eq!(
+            archive.add("a.txt", 0o644, Vec::new()),
+            Err(ArchiveError::DuplicateName("a.txt".to_string()))
+        );
+        assert_eq!(
+            archive.add("../a.txt", 0o644, Vec::new()),
+            Err(ArchiveError::InvalidMember(EnvelopeError::InvalidFileName(
+                "../a.txt".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let bytes = testing_archive().to_bytes();
+        for len in [0, 3, 10, bytes.len() - 5] {
+            assert_eq!(
+                Archive::from_bytes(&bytes[..len]),
+                Err(ArchiveError::Truncated)
+            );
+        }
+    }
+}
@@ -0,0 +1,3224 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use png_secret::capacity::Method;
+use png_secret::chunk_type::ChunkType;
+use png_secret::envelope::{Compression, Metadata, Padding};
+use png_secret::fec::Fec;
+use png_secret::glob::{PathPattern, Pattern};
+use png_secret::lsb::{Channels, LsbOptions};
+use png_secret::palette::Rgb;
+use png_secret::payload;
+use png_secret::png::InsertPosition;
+use png_secret::redundancy;
+use png_secret::time::TimeChunk;
+
+const DEFAULT_BACKUP_SUFFIX: &str = ".bak";
+
+pub const USAGE: &str = "\
+Usage: png-secret <command> [options]
+
+Commands:
+  encode <file>... (<chunk-type> | --random-type | --as-text <keyword> |
+                    --as-ztxt <keyword> | --as-itxt <keyword> [--language <tag>]
+                    [--translated-keyword <keyword>] |
+                    --lsb [--channels all|color|alpha|opaque] [--bits-per-sample <n>])
+         ([--base64 | --hex] <message> | --file <path>...)
+         [-o <output>] [--backup[=<suffix>]] [-n | --dry-run]
+         [--position before-iend|after-ihdr|after:<type>|before:<type>|<index>]
+         [--max-chunk-size <bytes>] [--compress none|deflate[:<level>]]
+         [--pad-to <bytes>|<percent>%] [--creator <name>] [--comment <text>]
+         [--content-type <type>] [--created-at <YYYY-MM-DDTHH:MM:SS> | --created-at now]
+         [--expires <YYYY-MM-DD>[T<HH:MM:SS>]] [--raw | --envelope]
+         [--hmac] [--passphrase <passphrase> | --keyfile <path> |
+          --passphrase-fd <fd> | --password-fd <fd>]
+         [--fec rs:<n>] [--redundancy <copies>] [--spread <file>]...
+         [--generate-carrier [noise:]<width>x<height>[:<rrggbb>]
+          [--carrier-depth 8|16] [--seed <n>]]
+      Store a message or a file in chunks of the given type, splitting it if it
+      is large. With --base64 or --hex the message spells out binary data.
+      Files keep their names and permissions; several are bundled into one
+      archive. The chunks go before IEND unless --position says otherwise, which
+      can also place them after the last or before the first chunk of a type.
+      A warning is printed if that breaks the order the PNG specification sets.
+      --random-type picks a fresh private, ancillary, safe-to-copy chunk type
+      such as ruSt instead, one the image doesn't have yet, and prints it for
+      decode, so the chunks don't carry the same type every time.
+      Deflate output is a standard zlib stream, levels 0 to 9 (default 6).
+      --pad-to adds random bytes after the payload, up to a random length of
+      at most <bytes>, or by up to <percent>% of its size, so two messages of
+      about the same size don't give chunks of the same length. The padding
+      is not encrypted; it only blurs the length.
+      --creator, --comment, --content-type (a media type such as text/plain)
+      and --created-at record who made the payload, when and what it is;
+      decode --info shows them. --expires asks decode to refuse the payload
+      after that time (UTC, the end of the day for a bare date), as for
+      credentials meant to be used once; it is a request, not protection.
+      The payload is wrapped in an envelope carrying a SHA-256 digest of the
+      message, which decode checks, except in a text entry (see --as-text).
+      --raw stores the message alone instead, as the pngme book does and so
+      that other tools see it as is, but then corruption goes unnoticed; it
+      can't be given with --file, a key or any option recorded in the
+      envelope.
+      With a key, an HMAC-SHA256 tag is added so tampering can be detected;
+      --hmac alone prompts for the passphrase.
+      --fec rs:<n> adds <n> Reed-Solomon parity bytes (1 to 128) to every block
+      of up to 255 - <n> payload bytes, interleaved, so decode can repair up to
+      <n>/2 damaged bytes per block: chunk data corrupted in transit, or low
+      bits an edit changed under --lsb. It grows the payload by about
+      <n>/(255 - <n>).
+      --redundancy stores that many copies of the payload (up to 16), in chunks
+      apart, before IEND, before IDAT and after IHDR in turn, or one after
+      another in the pixels, so losing or damaging one copy loses nothing:
+      decode reads every copy it finds and takes a majority vote on each byte.
+      --spread cuts the payload into pieces instead, one for <file> and one for
+      each image given, in that order, every piece headed with its place; the
+      images given are written in place. No image holds the whole payload, and
+      decode needs them all.
+      --as-text stores the payload as a single standard tEXt entry under
+      <keyword> instead, so other PNG tools can show it. The entry holds the
+      message itself, as is if it is Latin-1 and as base64 otherwise; the
+      envelope is stored, as base64, only when --envelope asks for its digest
+      or another option needs it. --as-ztxt writes a zTXt entry instead, its
+      text zlib compressed as the specification requires. --as-itxt writes a
+      UTF-8 iTXt entry, which holds any UTF-8 message as is, tagged with a
+      language such as de-DE and the keyword translated into it.
+      --lsb hides the payload in the lowest bit of each pixel sample instead,
+      leaving no chunk behind and the image looking the same, for 8- and 16-bit
+      grayscale and truecolor images, interlaced or not. Indexed images are
+      turned into 8-bit truecolor first, which usually makes them much larger;
+      a warning gives the new size. --bits-per-sample
+      uses the lowest 1 to 8 bits instead (1 by default): more room, and more
+      visible change, though 16-bit samples hardly show it. --channels picks
+      the samples used: all of them (the default), only color, only alpha, or
+      only the colors of fully opaque pixels.
+      With a key the samples are used in an order derived from it, scattering
+      the bits over the image so they can't be found without it.
+      --generate-carrier needs no image: <file> is created as an RGB image of
+      the given size to hold the payload, of one color (white by default) or,
+      with noise:, of random blotches and grain like a noisy photo. The same
+      --seed gives the same noise; without one a random seed is used and
+      printed. --carrier-depth sets the bits per sample, 8 by default.
+  decode <file>... (<chunk-type> | --as-text <keyword> [--language <tag>] |
+                    --lsb [--channels all|color|alpha|opaque] [--bits-per-sample <n>])
+         [--nth <n>] [--list | --info] [--extract <name>]...
+         [--extract-to <dir> | [--base64 | --hex] [-o <output>]]
+         [--passphrase <passphrase> | --keyfile <path> |
+          --passphrase-fd <fd> | --password-fd <fd>]
+         [--spread <file>]... [--ignore-expiry]
+      Print the message stored in chunks of the given type, checking its tag.
+      --nth picks a later message when several are stored under the same type.
+      Embedded files are listed with --list, or restored under their original
+      names into <dir> (the current directory for --extract); --extract picks
+      single files out of an archive. --info describes the payload instead of
+      printing it: its kind and size, compression, padding, file name and the
+      metadata encode recorded. -o writes the raw bytes of the message, or
+      of a single file, to <output> (- for stdout); --base64 encodes them first
+      and --hex prints an offset/hex/ASCII dump.
+      Prompts for the passphrase if the message is tagged and no key is given.
+      Every payload encode wraps carries a SHA-256 digest that is checked too,
+      so corruption is reported even without a key; only a message stored
+      with encode --raw or in a text entry, or by another tool, is read as is.
+      Compressed messages are decompressed automatically, and damage to a
+      payload stored with --fec is repaired, reporting the bytes corrected;
+      chunks failing their CRC check are then read anyway. A payload encode
+      --spread stored is put back together from <file> and every --spread
+      image, given in any order. A payload past the time encode --expires set
+      is refused, or read with a warning given --ignore-expiry.
+      --as-text reads the tEXt, zTXt or iTXt entry stored under <keyword> instead;
+      --language only considers iTXt entries with that language tag.
+      --lsb reads a payload encode --lsb hid in the pixels, given the same
+      --channels, --bits-per-sample and, if it had one, the key, which is not
+      prompted for.
+  fix-crc <file> [--type <chunk-type>]... [-o <output>] [--backup[=<suffix>]]
+          [-n | --dry-run]
+      Recompute the crc of the selected chunks (all by default) and rewrite the file.
+  list <file> [--format table|json|csv|yaml]
+      Print a table of every chunk: index, byte offset, type, length, crc and
+      whether it matches, and the property flags from the type's letter case.
+      Also available as print.
+  inspect <file> [--json | --format table|json|csv|yaml]
+      Like list, followed by the modification time from tIME and the keyword
+      and text of every tEXt, zTXt and iTXt chunk, and the ancillary chunks
+      whose data has the entropy of compressed or encrypted data though their
+      type holds nothing of the kind, as hidden payloads do. --json prints the
+      whole report as a JSON object instead, with every chunk's entropy.
+  stats <file> [--format table|json|csv|yaml]
+      Summarize the file: its size, the number of chunks and data bytes of each
+      type, critical against ancillary data, the largest chunk, and how much data
+      sits in chunk types the PNG specification does not define.
+  capacity <file> [--method chunk|lsb|alpha] [--bits-per-sample <n>]
+           [--max-chunk-size <bytes>] [--payload <path>]
+      Report the largest payload each method (or only --method) can hide in
+      the image: chunk for encode's default, lsb for --lsb and alpha for --lsb
+      --channels alpha, the last two with the given --bits-per-sample. Beside
+      the bytes encode stores, it estimates how much text fits once --compress
+      deflates it to about a third. The chunk method has no practical limit,
+      unless --max-chunk-size is made small, and is reported so. --payload
+      measures a real file instead: its size as is and deflated, and whether
+      either fits.
+  detect <file> [--format table|json|csv|yaml]
+      Look for signs of hidden payloads, for triage. First the traces known
+      tools leave: the chunk types of the pngme book, png-secret's envelopes in
+      chunks, text or the pixels, OpenStego's header in the pixels, and the
+      magic bytes of encrypted files and archives. Then, for 8-bit grayscale and
+      truecolor images, each channel gets the chi-square attack
+      (the probability that its value pairs were evened out, and how far from
+      the start they were), RS analysis and sample pair analysis (estimates of
+      the share of samples changed). The suspicion score, from 0 to 100%, is
+      the highest channel's; natural images stay near 0, but noise and
+      dithering can raise it, and small payloads hardly do.
+  scan <dir>... [--lsb] [--passphrases <file>] [--exclude <pattern>]...
+       [-j, --jobs <n>] [-q, --quiet] [--format table|json|csv|yaml]
+      Look for hidden payloads in every PNG file in and below each <dir>, or
+      in the images and patterns given: png-secret's envelopes, segments,
+      copies and pieces in chunks and text entries, in the pixels too with
+      --lsb, and the traces detect knows other tools leave. Prints one line
+      per payload: the image, where it is, its size in bytes, what it is and
+      whether it carries an integrity tag. Given a file of passphrases, one
+      per line, each tagged payload is checked against them and the line of
+      the one that fits is shown; with --lsb each is also tried as the key a
+      payload's bits were scattered with. Images that can't be read are
+      warned about and skipped.
+  watch <dir> [--interval <seconds>] [--existing]
+        --on-create (decode | verify | scrub) [<options>]...
+      Watch <dir> for new PNG files and run the command after --on-create on
+      each, as if given the image as its <file>: decode to extract what a
+      drop folder receives, scrub to clean it. The directory is looked at
+      every second, or every --interval, rather than waited on for
+      filesystem notifications, which would take a crate this build doesn't
+      have or hand-written unsafe bindings. An image is taken once its size
+      and modification time stay the same between two looks, so one still
+      being written is left until it is done. An image changed after it was
+      run on is run on again.
+      --existing runs on the images already there as well. Hidden files are
+      ignored, and so are images --exclude leaves out. Runs until
+      interrupted; an image the command fails on is reported and the watch
+      goes on.
+  verify <file>...
+      Check the signature, every chunk's crc and the order of the chunks (IHDR
+      first, the standard chunks in the places reorder puts them, consecutive
+      IDATs and IEND last, each unique chunk only once), printing every problem
+      with its byte offset. Exits with a non-zero status if anything is wrong.
+  diff <old> <new> [--data] [--all]
+      Compare two images chunk by chunk, printing the chunks only in <old> (-),
+      only in <new> (+) and those of the same type whose data changed (~), with
+      their lengths and crcs. --data adds the dump lines that changed; --all also
+      prints the chunks the two have in common.
+  compare --baseline <clean> <file>
+      Forensic comparison with a known-clean original: the chunks <file>
+      added, removed or changed, leaving IDAT aside, then the decoded pixels,
+      sample by sample: how many samples, pixels and bits changed, which bits
+      of a sample, in which channels, rows and rectangle, and whether the
+      changes look like LSB embedding, touching only the lowest bits of about
+      half the samples they span, with a guess at the payload size. The images
+      must have the same size, color type and bit depth for the pixels.
+  hexdump <file> <chunk-type> [--nth <n>] [--range <start>..<end>]
+      Print an offset/hex/ASCII dump of the data of the first chunk of the
+      given type, or the nth one. --range dumps only the bytes from <start> up
+      to <end>, either of which may be left out; offsets are decimal or 0x hex.
+  remove <file>... (<chunk-type> | --match <pattern>) [--all | --nth <n>] [--force]
+         [-o <output>] [--backup[=<suffix>]] [-n | --dry-run]
+      Remove the first chunk of the given type, the nth one, or all of them.
+      --match takes a wildcard pattern such as 'ru??' or '[a-z]*' instead.
+      Critical chunks are only removed with --force.
+  replace <file> <chunk-type> ([--base64 | --hex] <new-data> | --file <path>)
+          [--nth <n>] [-o <output>] [--backup[=<suffix>]] [-n | --dry-run]
+      Swap the data of the first chunk of the given type, or the nth one, for
+      <new-data> or the contents of <path>, keeping the chunk where it is and
+      recomputing its length and crc.
+  reorder <file> [-o <output>] [--backup[=<suffix>]] [-n | --dry-run]
+      Move chunks into the order the PNG specification requires: gAMA, sRGB and
+      the like before PLTE, tRNS and bKGD between PLTE and IDAT, pHYs before
+      IDAT, the IDATs together and IEND last. Other chunks stay behind the chunk
+      they followed. No data is changed.
+  touch-time <file> (--show | [--set <YYYY-MM-DDTHH:MM:SS> | --set now]
+             [-o <output>] [--backup[=<suffix>]] [-n | --dry-run])
+      Set the tIME chunk to the given UTC time, or to now as the specification
+      recommends after changing an image, adding one before IEND if there is
+      none. --show prints the stored time instead.
+  exif <file> [--list | --extract[=<path>] |
+              (--strip | --inject <path>) [-o <output>] [--backup[=<suffix>]]
+              [-n | --dry-run]]
+      Show the eXIf chunk: its size, byte order and the tags that say where the
+      image came from, such as Make, Model, Software and DateTime. --extract
+      writes the Exif blob to <path> (- for stdout), by default the file name
+      with an .exif extension. --strip removes it and --inject stores the blob
+      read from <path>, raw TIFF or with the Exif\\0\\0 prefix of a JPEG APP1
+      segment, in place of any existing one or else before the first IDAT.
+  scrub <file>... [--keep <chunk-type>]... [-o <output>] [--backup[=<suffix>]]
+        [-n | --dry-run]
+      Remove every ancillary chunk whose type the PNG specification does not
+      define, such as private chunks carrying hidden data. Critical chunks and
+      standard ones are kept, and so are the types given with --keep.
+  strip-metadata <file> [--keep <chunk-type>]... [--keep-keyword <keyword>]...
+                 [-o <output>] [--backup[=<suffix>]] [-n | --dry-run]
+      Remove the chunks that say where an image comes from rather than how it
+      looks: tEXt, zTXt, iTXt, tIME, eXIf and dSIG. --keep spares a whole type,
+      --keep-keyword the text chunks with that keyword, such as Copyright.
+  copy-chunks --from <source> --to <file> --types <chunk-type>[,<chunk-type>]...
+              [--force] [-o <output>] [--backup[=<suffix>]] [-n | --dry-run]
+      Copy the ancillary chunks of the given types from <source> into <file>.
+      Chunks that came before the image data in <source> go before it in <file>
+      too, the others before IEND. Unsafe-to-copy chunks depend on the image
+      data, so they are only copied between images with the same critical
+      chunks, unless --force is given.
+  export-chunks <file> <dir>
+      Write the data of every chunk to <dir>/NNN_<type>.bin, numbered in file
+      order, and a manifest.json listing each chunk's file, type, offset, length
+      and crc, for working on the chunks with other tools. Existing files in
+      <dir> are never overwritten.
+  import-chunks <dir> (-o <output> | --into <file> [--position <position>]
+                [-o <output>]) [--backup[=<suffix>]] [-n | --dry-run]
+      The inverse of export-chunks: read <dir>/manifest.json and build an image
+      from the chunk files it lists, in its order, recomputing every length and
+      crc. With --into, the ancillary chunks are instead inserted into <file>
+      at --position (before IEND by default), so edited chunks can be put back.
+  help
+      Print this message.
+
+--backup copies a file about to be overwritten to <file><suffix> first,
+<suffix> being .bak unless given. --dry-run reports the chunks that would be
+added, removed or rewritten and the resulting size without writing anything.
+
+--format prints the results of list, inspect and stats as JSON, as CSV with one
+line per chunk or chunk type, or as YAML, instead of the table meant for reading.
+
+encode, decode, verify, remove and scrub take several images, or directories
+standing for every PNG file in them, and run on each in turn, carrying on past
+failures and listing them at the end. The images are then rewritten in place,
+so -o is refused, and a passphrase is asked for only once. A <file> can also be
+a pattern the shell left alone, as on Windows or when quoted: * and ? match
+within a name, ** any number of directories, as in 'imgs/**/*.png', with / to
+separate directories everywhere. --exclude <pattern> leaves out the images
+whose name matches, or whose path does for a pattern with a /; it can be
+given more than once. -j, --jobs <n> runs on <n> images at once, each one's
+output still coming out whole and in order; scan takes it too. While they
+run, as while encode or decode --lsb reads or writes the pixels, a progress
+line is drawn on stderr if both it and stdout are terminals; -q, --quiet
+leaves it out.
+
+A <file> of - reads the image from stdin; the result then goes to stdout unless
+-o is given, and -o - writes it to stdout in any case.
+
+Instead of prompting, the passphrase is taken from the PNG_SECRET_PASSPHRASE
+environment variable when it is set, or else from PNG_SECRET_PASSWORD.
+--password-fd is another name for --passphrase-fd.
+";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ArgsError {
+    #[error("ArgsError missing command\n\n{USAGE}")]
+    MissingCommand,
+    #[error("ArgsError unknown command {0}\n\n{USAGE}")]
+    UnknownCommand(String),
+    #[error("ArgsError unknown option {0}")]
+    UnknownOption(String),
+    #[error("ArgsError missing value for option --{0}")]
+    MissingValue(String),
+    #[error("ArgsError option --{0} does not take a value")]
+    UnexpectedValue(String),
+    #[error("ArgsError missing argument <{0}>")]
+    MissingArgument(&'static str),
+    #[error("ArgsError unexpected argument {0}")]
+    UnexpectedArgument(String),
+    #[error("ArgsError invalid value {1} for {0}")]
+    InvalidValue(String, String),
+    #[error("ArgsError options --{0} and --{1} cannot be used together")]
+    ConflictingOptions(&'static str, &'static str),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Boxed, being by far the largest.
+    Encode(Box<EncodeArgs>),
+    Decode(DecodeArgs),
+    FixCrc(FixCrcArgs),
+    List(ListArgs),
+    Inspect(InspectArgs),
+    Stats(StatsArgs),
+    Capacity(CapacityArgs),
+    Detect(DetectArgs),
+    Scan(ScanArgs),
+    Watch(WatchArgs),
+    Verify(VerifyArgs),
+    Diff(DiffArgs),
+    Compare(CompareArgs),
+    Hexdump(HexdumpArgs),
+    Remove(RemoveArgs),
+    Replace(ReplaceArgs),
+    Reorder(ReorderArgs),
+    TouchTime(TouchTimeArgs),
+    Exif(ExifArgs),
+    Scrub(ScrubArgs),
+    StripMetadata(StripMetadataArgs),
+    CopyChunks(CopyChunksArgs),
+    ExportChunks(ExportChunksArgs),
+    ImportChunks(ImportChunksArgs),
+    Help,
+}
+
+/// The images a command runs on besides its `file`, which may itself be a
+/// directory or a pattern, and how it goes about running on them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Batch {
+    /// Further images, directories of them or patterns matching them.
+    pub more: Vec<PathBuf>,
+    /// Images to leave out, by name or, for patterns with a `/`, by path.
+    pub exclude: Vec<PathPattern>,
+    /// How many images to run on at once.
+    pub jobs: usize,
+    /// Draw no progress line.
+    pub quiet: bool,
+}
+
+impl Batch {
+    /// For one of the images a batch runs on, which draws no progress line
+    /// of its own.
+    pub fn member() -> Self {
+        Self {
+            quiet: true,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self {
+            more: Vec::new(),
+            exclude: Vec::new(),
+            jobs: 1,
+            quiet: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeArgs {
+    pub file: PathBuf,
+    pub batch: Batch,
+    pub chunk_type: ChunkType,
+    /// Pick a random private chunk type instead of `chunk_type`.
+    pub random_type: bool,
+    pub input: Input,
+    pub format: Format,
+    pub output: Option<PathBuf>,
+    /// Suffix for a copy of the file about to be overwritten.
+    pub backup: Option<String>,
+    pub dry_run: bool,
+    pub max_chunk_size: Option<usize>,
+    pub position: InsertPosition,
+    pub compression: Option<Compression>,
+    /// Random padding to add to the envelope.
+    pub padding: Option<Padding>,
+    pub metadata: Metadata,
+    /// Store the message alone, with no envelope and so no digest.
+    pub raw: bool,
+    /// Wrap the message of a text entry in the envelope even when no other
+    /// option needs it, for its digest.
+    pub envelope: bool,
+    pub key: Option<KeySource>,
+    /// Store the payload as a standard text entry rather than in chunks of
+    /// `chunk_type`, which is then the entry's chunk type.
+    pub text: Option<TextTarget>,
+    /// Build a fresh image to write to `file` instead of reading it.
+    pub generate_carrier: Option<CarrierSpec>,
+    /// Hide the payload in the low bits of the pixels rather than in chunks.
+    pub lsb: Option<LsbOptions>,
+    /// Error correction to add to the payload.
+    pub fec: Option<Fec>,
+    /// Copies of the payload to store, 1 for a single one.
+    pub redundancy: u8,
+    /// Further images to spread the payload over, each written in place.
+    pub spread: Vec<PathBuf>,
+}
+
+/// The image `encode --generate-carrier` builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CarrierSpec {
+    pub width: u32,
+    pub height: u32,
+    pub color: Rgb,
+    /// Random noise rather than `color`.
+    pub noise: bool,
+    pub bit_depth: u8,
+    /// Seed for the noise, random if not given.
+    pub seed: Option<u64>,
+}
+
+impl FromStr for CarrierSpec {
+    type Err = ArgsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ArgsError::InvalidValue("--generate-carrier".to_string(), s.to_string());
+        let (noise, spec) = match s.strip_prefix("noise:") {
+            Some(spec) => (true, spec),
+            None => (false, s),
+        };
+        let (size, color) = match spec.split_once(':') {
+            Some(_) if noise => return Err(invalid()),
+            Some((size, color)) => (size, Some(color)),
+            None => (spec, None),
+        };
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let color = match color {
+            None => Rgb::new(255, 255, 255),
+            Some(hex) if hex.len() == 6 && hex.is_ascii() => {
+                let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16);
+                match (channel(0), channel(2), channel(4)) {
+                    (Ok(r), Ok(g), Ok(b)) => Rgb::new(r, g, b),
+                    _ => return Err(invalid()),
+                }
+            }
+            Some(_) => return Err(invalid()),
+        };
+        Ok(CarrierSpec {
+            width: width.parse().map_err(|_| invalid())?,
+            height: height.parse().map_err(|_| invalid())?,
+            color,
+            noise,
+            bit_depth: 8,
+            seed: None,
+        })
+    }
+}
+
+/// A standard text entry that holds the payload in place of private chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextTarget {
+    pub kind: TextKind,
+    pub keyword: String,
+    /// Language tag and translated keyword of an iTXt entry, empty if not given.
+    pub language_tag: String,
+    pub translated_keyword: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextKind {
+    /// Uncompressed tEXt.
+    #[default]
+    Text,
+    /// zlib-compressed zTXt.
+    Compressed,
+    /// UTF-8 iTXt.
+    International,
+}
+
+impl TextKind {
+    pub fn chunk_type(self) -> ChunkType {
+        match self {
+            TextKind::Text => ChunkType::TEXT,
+            TextKind::Compressed => ChunkType::ZTXT,
+            TextKind::International => ChunkType::ITXT,
+        }
+    }
+}
+
+/// How a message is spelled on the command line or printed by `decode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// UTF-8 text, or raw bytes when written to a file.
+    #[default]
+    Text,
+    Base64,
+    /// Hex digits on input, an offset/hex/ASCII dump on output.
+    Hex,
+}
+
+/// How the listing commands print their results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    #[default]
+    Table,
+    Json,
+    Csv,
+    Yaml,
+}
+
+impl FromStr for ReportFormat {
+    type Err = ArgsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "yaml" => Ok(Self::Yaml),
+            _ => Err(ArgsError::InvalidValue(
+                "--format".to_string(),
+                s.to_string(),
+            )),
+        }
+    }
+}
+
+/// What `encode` stores.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Message(String),
+    Files(Vec<PathBuf>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeArgs {
+    pub file: PathBuf,
+    pub batch: Batch,
+    pub chunk_type: ChunkType,
+    /// Which payload of `chunk_type` to read, counting from 1.
+    pub nth: usize,
+    pub list: bool,
+    /// Describe the payload rather than print it.
+    pub info: bool,
+    pub extract: Vec<String>,
+    pub extract_to: Option<PathBuf>,
+    pub format: Format,
+    /// Where to write the raw payload, `-` for stdout.
+    pub output: Option<PathBuf>,
+    pub key: Option<KeySource>,
+    /// Read the text entry under this keyword rather than chunks of `chunk_type`.
+    pub text_keyword: Option<String>,
+    /// Only read iTXt entries in this language.
+    pub language: Option<String>,
+    /// Read the payload from the low bits of the pixels.
+    pub lsb: Option<LsbOptions>,
+    /// The other images a spread payload was stored in.
+    pub spread: Vec<PathBuf>,
+    /// Read a payload past its expiry.
+    pub ignore_expiry: bool,
+}
+
+/// Where the key for a payload's integrity tag comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    Passphrase(String),
+    /// The contents of the file are hashed into the key.
+    Keyfile(PathBuf),
+    /// The first line read from an inherited file descriptor.
+    Fd(u32),
+    /// Use `PNG_SECRET_PASSPHRASE` or `PNG_SECRET_PASSWORD` if set,
+    /// otherwise ask on the terminal, or read a line from stdin when it is
+    /// not a terminal.
+    Prompt,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FixCrcArgs {
+    pub file: PathBuf,
+    pub chunk_types: Vec<ChunkType>,
+    pub output: Option<PathBuf>,
+    pub backup: Option<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ListArgs {
+    pub file: PathBuf,
+    pub format: ReportFormat,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InspectArgs {
+    pub file: PathBuf,
+    pub format: ReportFormat,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct StatsArgs {
+    pub file: PathBuf,
+    pub format: ReportFormat,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CapacityArgs {
+    pub file: PathBuf,
+    /// Every method when not given.
+    pub method: Option<Method>,
+    pub bits_per_sample: u8,
+    pub max_chunk_size: Option<usize>,
+    pub payload: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DetectArgs {
+    pub file: PathBuf,
+    pub format: ReportFormat,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ScanArgs {
+    pub file: PathBuf,
+    pub batch: Batch,
+    /// Read the low bits of the pixels too.
+    pub lsb: bool,
+    /// A file of passphrases, one per line, to try on tagged payloads.
+    pub passphrases: Option<PathBuf>,
+    pub format: ReportFormat,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct WatchArgs {
+    pub dir: PathBuf,
+    pub action: WatchAction,
+    /// How long to wait between looks at the directory.
+    pub interval: Duration,
+    /// Run on the images already in the directory too.
+    pub existing: bool,
+}
+
+/// What `watch` runs on every new image, the `file` of which is a
+/// placeholder until there is one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchAction {
+    Decode(DecodeArgs),
+    Verify(VerifyArgs),
+    Scrub(ScrubArgs),
+}
+
+impl WatchAction {
+    /// The batch options given with the command, of which only `--exclude`
+    /// means anything here.
+    pub fn batch(&self) -> &Batch {
+        match self {
+            WatchAction::Decode(args) => &args.batch,
+            WatchAction::Verify(args) => &args.batch,
+            WatchAction::Scrub(args) => &args.batch,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CompareArgs {
+    pub baseline: PathBuf,
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyArgs {
+    pub file: PathBuf,
+    pub batch: Batch,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DiffArgs {
+    pub old: PathBuf,
+    pub new: PathBuf,
+    pub data: bool,
+    pub all: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct HexdumpArgs {
+    pub file: PathBuf,
+    pub chunk_type: ChunkType,
+    /// Which chunk of `chunk_type` to dump, counting from 1.
+    pub nth: usize,
+    pub range: ByteRange,
+}
+
+/// A half-open slice of a chunk's data, `start..end`; no end means the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ByteRange {
+    pub start: usize,
+    pub end: Option<usize>,
+}
+
+impl FromStr for ByteRange {
+    type Err = ArgsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ArgsError::InvalidValue("--range".to_string(), s.to_string());
+        let offset = |offset: &str| match offset.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16).map_err(|_| invalid()),
+            None => offset.parse().map_err(|_| invalid()),
+        };
+
+        let (start, end) = s.split_once("..").ok_or_else(invalid)?;
+        let range = ByteRange {
+            start: if start.is_empty() { 0 } else { offset(start)? },
+            end: if end.is_empty() {
+                None
+            } else {
+                Some(offset(end)?)
+            },
+        };
+        match range.end {
+            Some(end) if end < range.start => Err(invalid()),
+            _ => Ok(range),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoveArgs {
+    pub file: PathBuf,
+    pub batch: Batch,
+    /// Chunk types to remove; a plain chunk type when given positionally.
+    pub pattern: Pattern,
+    pub selection: Selection,
+    /// Allow removing critical chunks, which leaves an unreadable image.
+    pub force: bool,
+    pub output: Option<PathBuf>,
+    pub backup: Option<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReplaceArgs {
+    pub file: PathBuf,
+    pub chunk_type: ChunkType,
+    /// Which chunk of `chunk_type` to replace, counting from 1.
+    pub nth: usize,
+    /// The new data, or a single file holding it.
+    pub input: Input,
+    pub format: Format,
+    pub output: Option<PathBuf>,
+    pub backup: Option<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReorderArgs {
+    pub file: PathBuf,
+    pub output: Option<PathBuf>,
+    pub backup: Option<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TouchTimeArgs {
+    pub file: PathBuf,
+    /// Print the stored time instead of changing it.
+    pub show: bool,
+    /// The time to store, the current time if not given.
+    pub time: Option<TimeChunk>,
+    pub output: Option<PathBuf>,
+    pub backup: Option<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExifArgs {
+    pub file: PathBuf,
+    pub action: ExifAction,
+    pub output: Option<PathBuf>,
+    pub backup: Option<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum ExifAction {
+    /// Print the eXIf chunk and the well-known tags in it.
+    #[default]
+    List,
+    /// Write the Exif blob to this file, `-` for stdout.
+    Extract(PathBuf),
+    Strip,
+    /// Store the Exif blob read from this file, replacing any eXIf chunk.
+    Inject(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrubArgs {
+    pub file: PathBuf,
+    pub batch: Batch,
+    /// Non-standard ancillary types to leave in place.
+    pub keep: Vec<ChunkType>,
+    pub output: Option<PathBuf>,
+    pub backup: Option<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct StripMetadataArgs {
+    pub file: PathBuf,
+    /// Metadata types to leave in place.
+    pub keep: Vec<ChunkType>,
+    /// Keywords of text chunks to leave in place.
+    pub keep_keywords: Vec<String>,
+    pub output: Option<PathBuf>,
+    pub backup: Option<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CopyChunksArgs {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub chunk_types: Vec<ChunkType>,
+    /// Copy unsafe-to-copy chunks even into an image with other critical chunks.
+    pub force: bool,
+    pub output: Option<PathBuf>,
+    pub backup: Option<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExportChunksArgs {
+    pub file: PathBuf,
+    pub dir: PathBuf,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImportChunksArgs {
+    pub dir: PathBuf,
+    /// An image to add the chunks to, instead of building a new one.
+    pub into: Option<PathBuf>,
+    pub position: InsertPosition,
+    pub output: Option<PathBuf>,
+    pub backup: Option<String>,
+    pub dry_run: bool,
+}
+
+/// Which of the matching chunks a command acts on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Selection {
+    #[default]
+    First,
+    /// Counting from 1.
+    Nth(usize),
+    All,
+}
+
+/// Parses the arguments that follow the program name.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, ArgsError> {
+    let mut args = args.into_iter();
+    let command = args.next().ok_or(ArgsError::MissingCommand)?;
+
+    match command.as_str() {
+        "encode" => parse_encode(args).map(|args| Command::Encode(Box::new(args))),
+        "decode" => parse_decode(args).map(Command::Decode),
+        "fix-crc" => parse_fix_crc(args).map(Command::FixCrc),
+        "list" | "print" => parse_list(args).map(Command::List),
+        "inspect" => parse_inspect(args).map(Command::Inspect),
+        "stats" => parse_stats(args).map(Command::Stats),
+        "capacity" => parse_capacity(args).map(Command::Capacity),
+        "detect" => parse_detect(args).map(Command::Detect),
+        "scan" => parse_scan(args).map(Command::Scan),
+        "watch" => parse_watch(args).map(Command::Watch),
+        "verify" => parse_verify(args).map(Command::Verify),
+        "diff" => parse_diff(args).map(Command::Diff),
+        "compare" => parse_compare(args).map(Command::Compare),
+        "hexdump" => parse_hexdump(args).map(Command::Hexdump),
+        "remove" => parse_remove(args).map(Command::Remove),
+        "replace" => parse_replace(args).map(Command::Replace),
+        "reorder" => parse_reorder(args).map(Command::Reorder),
+        "touch-time" => parse_touch_time(args).map(Command::TouchTime),
+        "exif" => parse_exif(args).map(Command::Exif),
+        "scrub" => parse_scrub(args).map(Command::Scrub),
+        "strip-metadata" => parse_strip_metadata(args).map(Command::StripMetadata),
+        "copy-chunks" => parse_copy_chunks(args).map(Command::CopyChunks),
+        "export-chunks" => parse_export_chunks(args).map(Command::ExportChunks),
+        "import-chunks" => parse_import_chunks(args).map(Command::ImportChunks),
+        "help" | "-h" | "--help" => Ok(Command::Help),
+        _ => Err(ArgsError::UnknownCommand(command)),
+    }
+}
+
+fn parse_encode<I: Iterator<Item = String>>(args: I) -> Result<EncodeArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::value("output", Some('o')),
+        Opt::optional_value("backup"),
+        Opt::flag("dry-run", Some('n')),
+        Opt::value("file", None),
+        Opt::flag("base64", None),
+        Opt::flag("hex", None),
+        Opt::value("max-chunk-size", None),
+        Opt::value("position", None),
+        Opt::value("compress", None),
+        Opt::value("pad-to", None),
+        Opt::value("creator", None),
+        Opt::value("comment", None),
+        Opt::value("content-type", None),
+        Opt::value("created-at", None),
+        Opt::value("expires", None),
+        Opt::flag("raw", None),
+        Opt::flag("envelope", None),
+        Opt::value("passphrase", None),
+        Opt::value("keyfile", None),
+        Opt::value("passphrase-fd", None),
+        Opt::value("password-fd", None),
+        Opt::flag("hmac", None),
+        Opt::value("as-text", None),
+        Opt::value("as-ztxt", None),
+        Opt::value("as-itxt", None),
+        Opt::value("language", None),
+        Opt::value("translated-keyword", None),
+        Opt::value("generate-carrier", None),
+        Opt::value("carrier-depth", None),
+        Opt::value("seed", None),
+        Opt::flag("lsb", None),
+        Opt::value("channels", None),
+        Opt::value("bits-per-sample", None),
+        Opt::value("fec", None),
+        Opt::value("redundancy", None),
+        Opt::value("spread", None),
+        Opt::flag("random-type", None),
+        Opt::value("exclude", None),
+        Opt::value("jobs", Some('j')),
+        Opt::flag("quiet", Some('q')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let chunk_type_given = !["as-text", "as-ztxt", "as-itxt", "lsb", "random-type"]
+        .iter()
+        .any(|long| matches.flag(long));
+    let message_given = matches.values("file").is_empty();
+    let (file, batch) = parse_files(
+        &mut matches,
+        usize::from(chunk_type_given) + usize::from(message_given),
+    )?;
+    matches.exclusive(&["as-text", "as-ztxt", "as-itxt"])?;
+    let text = [
+        ("as-text", TextKind::Text),
+        ("as-ztxt", TextKind::Compressed),
+        ("as-itxt", TextKind::International),
+    ]
+    .into_iter()
+    .find_map(|(long, kind)| {
+        Some(TextTarget {
+            kind,
+            keyword: matches.value(long)?,
+            language_tag: matches.value("language").unwrap_or_default(),
+            translated_keyword: matches.value("translated-keyword").unwrap_or_default(),
+        })
+    });
+    for long in ["language", "translated-keyword"] {
+        if matches.flag(long) && !matches.flag("as-itxt") {
+            return Err(ArgsError::InvalidValue(
+                format!("--{long}"),
+                "without --as-itxt".to_string(),
+            ));
+        }
+    }
+    if matches.flag("envelope") && text.is_none() {
+        return Err(ArgsError::InvalidValue(
+            "--envelope".to_string(),
+            "without --as-text, --as-ztxt or --as-itxt".to_string(),
+        ));
+    }
+    let chunk_type = match &text {
+        Some(text) => text.kind.chunk_type(),
+        None if matches.flag("lsb") => ChunkType::IDAT,
+        // Replaced by the random type once the image is read.
+        None if matches.flag("random-type") => ChunkType::IDAT,
+        None => parse_value("<chunk-type>", matches.positional("chunk-type")?)?,
+    };
+    let files = matches.values("file");
+    let input = if files.is_empty() {
+        Input::Message(matches.positional("message")?)
+    } else {
+        Input::Files(files.into_iter().map(PathBuf::from).collect())
+    };
+    matches.finish()?;
+    matches.exclusive(&["file", "base64", "hex"])?;
+    matches.exclusive(&["lsb", "as-text", "as-ztxt", "as-itxt", "max-chunk-size"])?;
+    matches.exclusive(&["lsb", "position"])?;
+    matches.exclusive(&["random-type", "lsb", "as-text", "as-ztxt", "as-itxt"])?;
+    // All of these need the envelope --raw leaves out.
+    for long in [
+        "file",
+        "compress",
+        "pad-to",
+        "creator",
+        "comment",
+        "content-type",
+        "created-at",
+        "expires",
+        "passphrase",
+        "keyfile",
+        "passphrase-fd",
+        "password-fd",
+        "hmac",
+        "envelope",
+    ] {
+        matches.exclusive(&["raw", long])?;
+    }
+
+    Ok(EncodeArgs {
+        file,
+        batch,
+        chunk_type,
+        random_type: matches.flag("random-type"),
+        input,
+        format: parse_format(&matches),
+        output: matches.value("output").map(PathBuf::from),
+        backup: parse_backup(&matches)?,
+        dry_run: matches.flag("dry-run"),
+        max_chunk_size: parse_max_chunk_size(&matches)?,
+        position: matches.parsed_value("position")?.unwrap_or_default(),
+        compression: matches.parsed_value("compress")?,
+        padding: matches.parsed_value("pad-to")?,
+        metadata: Metadata {
+            creator: matches.value("creator"),
+            created_at: match matches.value("created-at") {
+                Some(now) if now == "now" => Some(
+                    TimeChunk::now()
+                        .map_err(|_| ArgsError::InvalidValue("--created-at".to_string(), now))?,
+                ),
+                Some(time) => Some(parse_value("--created-at", time)?),
+                None => None,
+            },
+            comment: matches.value("comment"),
+            content_type: matches.value("content-type"),
+            expires: matches.value("expires").map(parse_expiry).transpose()?,
+        },
+        raw: matches.flag("raw"),
+        envelope: matches.flag("envelope"),
+        key: match parse_key_source(&matches)? {
+            None if matches.flag("hmac") => Some(KeySource::Prompt),
+            key => key,
+        },
+        text,
+        generate_carrier: parse_carrier(&matches)?,
+        lsb: parse_lsb(&matches)?,
+        fec: matches.parsed_value("fec")?,
+        redundancy: match matches.parsed_value::<u8>("redundancy")? {
+            None => 1,
+            Some(copies) if (1..=redundancy::MAX_COPIES).contains(&copies) => copies,
+            Some(copies) => {
+                return Err(ArgsError::InvalidValue(
+                    "--redundancy".to_string(),
+                    copies.to_string(),
+                ))
+            }
+        },
+        spread: matches
+            .values("spread")
+            .into_iter()
+            .map(PathBuf::from)
+            .collect(),
+    })
+}
+
+fn parse_lsb(matches: &Matches) -> Result<Option<LsbOptions>, ArgsError> {
+    if !matches.flag("lsb") {
+        for long in ["channels", "bits-per-sample"] {
+            if matches.flag(long) {
+                return Err(ArgsError::InvalidValue(
+                    format!("--{long}"),
+                    "without --lsb".to_string(),
+                ));
+            }
+        }
+        return Ok(None);
+    }
+    Ok(Some(LsbOptions {
+        channels: matches
+            .parsed_value::<Channels>("channels")?
+            .unwrap_or_default(),
+        bits_per_sample: parse_bits_per_sample(matches)?,
+        ..LsbOptions::default()
+    }))
+}
+
+fn parse_max_chunk_size(matches: &Matches) -> Result<Option<usize>, ArgsError> {
+    match matches.parsed_value::<usize>("max-chunk-size")? {
+        Some(size) if size > payload::MAX_SEGMENT_LEN => Err(ArgsError::InvalidValue(
+            "--max-chunk-size".to_string(),
+            size.to_string(),
+        )),
+        size => Ok(size),
+    }
+}
+
+fn parse_bits_per_sample(matches: &Matches) -> Result<u8, ArgsError> {
+    match matches.parsed_value::<u8>("bits-per-sample")? {
+        None => Ok(LsbOptions::default().bits_per_sample),
+        Some(bits) if (1..=LsbOptions::MAX_BITS_PER_SAMPLE).contains(&bits) => Ok(bits),
+        Some(bits) => Err(ArgsError::InvalidValue(
+            "--bits-per-sample".to_string(),
+            bits.to_string(),
+        )),
+    }
+}
+
+fn parse_carrier(matches: &Matches) -> Result<Option<CarrierSpec>, ArgsError> {
+    let Some(mut spec) = matches.parsed_value::<CarrierSpec>("generate-carrier")? else {
+        for long in ["carrier-depth", "seed"] {
+            if matches.flag(long) {
+                return Err(ArgsError::InvalidValue(
+                    format!("--{long}"),
+                    "without --generate-carrier".to_string(),
+                ));
+            }
+        }
+        return Ok(None);
+    };
+    if matches.flag("seed") && !spec.noise {
+        return Err(ArgsError::InvalidValue(
+            "--seed".to_string(),
+            "without a noise: carrier".to_string(),
+        ));
+    }
+    spec.bit_depth = matches.parsed_value("carrier-depth")?.unwrap_or(8);
+    spec.seed = matches.parsed_value("seed")?;
+    Ok(Some(spec))
+}
+
+fn parse_decode<I: Iterator<Item = String>>(args: I) -> Result<DecodeArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::value("nth", None),
+        Opt::flag("list", None),
+        Opt::flag("info", None),
+        Opt::value("extract", None),
+        Opt::value("extract-to", None),
+        Opt::value("output", Some('o')),
+        Opt::flag("base64", None),
+        Opt::flag("hex", None),
+        Opt::value("passphrase", None),
+        Opt::value("keyfile", None),
+        Opt::value("passphrase-fd", None),
+        Opt::value("password-fd", None),
+        Opt::value("as-text", None),
+        Opt::value("language", None),
+        Opt::flag("lsb", None),
+        Opt::value("channels", None),
+        Opt::value("bits-per-sample", None),
+        Opt::value("spread", None),
+        Opt::flag("ignore-expiry", None),
+        Opt::value("exclude", None),
+        Opt::value("jobs", Some('j')),
+        Opt::flag("quiet", Some('q')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let chunk_type_given = !matches.flag("as-text") && !matches.flag("lsb");
+    let (file, batch) = parse_files(&mut matches, usize::from(chunk_type_given))?;
+    let text_keyword = matches.value("as-text");
+    if text_keyword.is_none() && matches.flag("language") {
+        return Err(ArgsError::InvalidValue(
+            "--language".to_string(),
+            "without --as-text".to_string(),
+        ));
+    }
+    let chunk_type = match text_keyword {
+        Some(_) => TextKind::Text.chunk_type(),
+        None if matches.flag("lsb") => ChunkType::IDAT,
+        None => parse_value("<chunk-type>", matches.positional("chunk-type")?)?,
+    };
+    matches.finish()?;
+    matches.exclusive(&["list", "info", "extract-to", "output"])?;
+    matches.exclusive(&["list", "info", "extract-to", "base64", "hex"])?;
+    matches.exclusive(&["info", "extract"])?;
+    matches.exclusive(&["lsb", "as-text"])?;
+    matches.exclusive(&["lsb", "nth"])?;
+
+    Ok(DecodeArgs {
+        file,
+        batch,
+        chunk_type,
+        nth: parse_nth(&matches)?,
+        list: matches.flag("list"),
+        info: matches.flag("info"),
+        extract: matches.values("extract"),
+        extract_to: matches.value("extract-to").map(PathBuf::from),
+        format: parse_format(&matches),
+        output: matches.value("output").map(PathBuf::from),
+        key: parse_key_source(&matches)?,
+        text_keyword,
+        language: matches.value("language"),
+        lsb: parse_lsb(&matches)?,
+        spread: matches
+            .values("spread")
+            .into_iter()
+            .map(PathBuf::from)
+            .collect(),
+        ignore_expiry: matches.flag("ignore-expiry"),
+    })
+}
+
+/// `--expires`, a time or a date standing for its last second.
+fn parse_expiry(value: String) -> Result<TimeChunk, ArgsError> {
+    let time = if value.contains(['T', ' ']) {
+        value.clone()
+    } else {
+        format!("{value}T23:59:59")
+    };
+    time.parse()
+        .map_err(|_| ArgsError::InvalidValue("--expires".to_string(), value))
+}
+
+/// The first of the images a command runs on and the rest, leaving `keep`
+/// positionals after them.
+fn parse_files(matches: &mut Matches, keep: usize) -> Result<(PathBuf, Batch), ArgsError> {
+    let mut files = matches
+        .positionals_but("file", keep)?
+        .into_iter()
+        .map(PathBuf::from);
+    let file = files.next().expect("positionals_but takes at least one");
+    let batch = Batch {
+        more: files.collect(),
+        exclude: matches.parsed_values("exclude")?,
+        jobs: match matches.parsed_value("jobs")? {
+            Some(0) => {
+                return Err(ArgsError::InvalidValue(
+                    "--jobs".to_string(),
+                    "0".to_string(),
+                ))
+            }
+            Some(jobs) => jobs,
+            None => 1,
+        },
+        quiet: matches.flag("quiet"),
+    };
+    Ok((file, batch))
+}
+
+/// `--nth`, counting from 1 and defaulting to the first.
+fn parse_nth(matches: &Matches) -> Result<usize, ArgsError> {
+    match matches.parsed_value("nth")? {
+        Some(0) => Err(ArgsError::InvalidValue(
+            "--nth".to_string(),
+            "0".to_string(),
+        )),
+        Some(nth) => Ok(nth),
+        None => Ok(1),
+    }
+}
+
+fn parse_selection(matches: &Matches) -> Result<Selection, ArgsError> {
+    if matches.flag("all") {
+        return Ok(Selection::All);
+    }
+    match matches.value("nth") {
+        Some(_) => Ok(Selection::Nth(parse_nth(matches)?)),
+        None => Ok(Selection::First),
+    }
+}
+
+/// The suffix for `--backup[=SUFFIX]`, `.bak` when none is given.
+fn parse_backup(matches: &Matches) -> Result<Option<String>, ArgsError> {
+    if !matches.flag("backup") {
+        return Ok(None);
+    }
+    match matches.value("backup") {
+        Some(suffix) if suffix.is_empty() => {
+            Err(ArgsError::InvalidValue("--backup".to_string(), suffix))
+        }
+        Some(suffix) => Ok(Some(suffix)),
+        None => Ok(Some(DEFAULT_BACKUP_SUFFIX.to_string())),
+    }
+}
+
+fn parse_format(matches: &Matches) -> Format {
+    if matches.flag("base64") {
+        Format::Base64
+    } else if matches.flag("hex") {
+        Format::Hex
+    } else {
+        Format::Text
+    }
+}
+
+fn parse_key_source(matches: &Matches) -> Result<Option<KeySource>, ArgsError> {
+    matches.exclusive(&["passphrase", "keyfile", "passphrase-fd", "password-fd"])?;
+
+    if let Some(passphrase) = matches.value("passphrase") {
+        return Ok(Some(KeySource::Passphrase(passphrase)));
+    }
+    if let Some(keyfile) = matches.value("keyfile") {
+        return Ok(Some(KeySource::Keyfile(keyfile.into())));
+    }
+    // --password-fd is another name for it.
+    match matches.parsed_value("passphrase-fd")? {
+        Some(fd) => Ok(Some(KeySource::Fd(fd))),
+        None => Ok(matches.parsed_value("password-fd")?.map(KeySource::Fd)),
+    }
+}
+
+fn parse_fix_crc<I: Iterator<Item = String>>(args: I) -> Result<FixCrcArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::value("type", None),
+        Opt::value("output", Some('o')),
+        Opt::optional_value("backup"),
+        Opt::flag("dry-run", Some('n')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let file = matches.positional("file")?.into();
+    matches.finish()?;
+
+    Ok(FixCrcArgs {
+        file,
+        chunk_types: matches.parsed_values("type")?,
+        output: matches.value("output").map(PathBuf::from),
+        backup: parse_backup(&matches)?,
+        dry_run: matches.flag("dry-run"),
+    })
+}
+
+fn parse_list<I: Iterator<Item = String>>(args: I) -> Result<ListArgs, ArgsError> {
+    let mut matches = Matches::parse(args, &[Opt::value("format", None)])?;
+    let file = matches.positional("file")?.into();
+    matches.finish()?;
+
+    Ok(ListArgs {
+        file,
+        format: matches.parsed_value("format")?.unwrap_or_default(),
+    })
+}
+
+fn parse_inspect<I: Iterator<Item = String>>(args: I) -> Result<InspectArgs, ArgsError> {
+    const OPTS: &[Opt] = &[Opt::flag("json", None), Opt::value("format", None)];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let file = matches.positional("file")?.into();
+    matches.finish()?;
+    matches.exclusive(&["json", "format"])?;
+
+    let format = if matches.flag("json") {
+        ReportFormat::Json
+    } else {
+        matches.parsed_value("format")?.unwrap_or_default()
+    };
+    Ok(InspectArgs { file, format })
+}
+
+fn parse_stats<I: Iterator<Item = String>>(args: I) -> Result<StatsArgs, ArgsError> {
+    let mut matches = Matches::parse(args, &[Opt::value("format", None)])?;
+    let file = matches.positional("file")?.into();
+    matches.finish()?;
+
+    Ok(StatsArgs {
+        file,
+        format: matches.parsed_value("format")?.unwrap_or_default(),
+    })
+}
+
+fn parse_capacity<I: Iterator<Item = String>>(args: I) -> Result<CapacityArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::value("method", None),
+        Opt::value("bits-per-sample", None),
+        Opt::value("max-chunk-size", None),
+        Opt::value("payload", None),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let file = matches.positional("file")?.into();
+    matches.finish()?;
+
+    Ok(CapacityArgs {
+        file,
+        method: matches.parsed_value("method")?,
+        bits_per_sample: parse_bits_per_sample(&matches)?,
+        max_chunk_size: parse_max_chunk_size(&matches)?,
+        payload: matches.value("payload").map(PathBuf::from),
+    })
+}
+
+fn parse_detect<I: Iterator<Item = String>>(args: I) -> Result<DetectArgs, ArgsError> {
+    let mut matches = Matches::parse(args, &[Opt::value("format", None)])?;
+    let file = matches.positional("file")?.into();
+    matches.finish()?;
+
+    Ok(DetectArgs {
+        file,
+        format: matches.parsed_value("format")?.unwrap_or_default(),
+    })
+}
+
+fn parse_scan<I: Iterator<Item = String>>(args: I) -> Result<ScanArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::flag("lsb", None),
+        Opt::value("passphrases", None),
+        Opt::value("exclude", None),
+        Opt::value("jobs", Some('j')),
+        Opt::flag("quiet", Some('q')),
+        Opt::value("format", None),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let (file, batch) = parse_files(&mut matches, 0)?;
+    matches.finish()?;
+
+    Ok(ScanArgs {
+        file,
+        batch,
+        lsb: matches.flag("lsb"),
+        passphrases: matches.value("passphrases").map(PathBuf::from),
+        format: matches.parsed_value("format")?.unwrap_or_default(),
+    })
+}
+
+fn parse_watch<I: Iterator<Item = String>>(args: I) -> Result<WatchArgs, ArgsError> {
+    const OPTS: &[Opt] = &[Opt::value("interval", None), Opt::flag("existing", None)];
+
+    // Everything after --on-create is the command to run, options and all.
+    let mut args: Vec<String> = args.collect();
+    let action = match args.iter().position(|arg| arg == "--on-create") {
+        Some(at) => args.split_off(at).split_off(1),
+        None => return Err(ArgsError::MissingValue("on-create".to_string())),
+    };
+    let mut matches = Matches::parse(args.into_iter(), OPTS)?;
+    let dir = PathBuf::from(matches.positional("dir")?);
+    matches.finish()?;
+
+    let interval = match matches.parsed_value::<f64>("interval")? {
+        Some(seconds) => Duration::try_from_secs_f64(seconds)
+            .ok()
+            .filter(|interval| !interval.is_zero())
+            .ok_or_else(|| {
+                ArgsError::InvalidValue("--interval".to_string(), seconds.to_string())
+            })?,
+        None => Duration::from_secs(1),
+    };
+    Ok(WatchArgs {
+        action: parse_watch_action(action, &dir)?,
+        dir,
+        interval,
+        existing: matches.flag("existing"),
+    })
+}
+
+/// The command given after `--on-create`, parsed with `dir` standing in for
+/// the image it will run on.
+fn parse_watch_action(action: Vec<String>, dir: &Path) -> Result<WatchAction, ArgsError> {
+    let mut action = action.into_iter();
+    let name = action
+        .next()
+        .ok_or_else(|| ArgsError::MissingValue("on-create".to_string()))?;
+    let args = std::iter::once(dir.display().to_string()).chain(action);
+    let action = match name.as_str() {
+        "decode" => WatchAction::Decode(parse_decode(args)?),
+        "verify" => WatchAction::Verify(parse_verify(args)?),
+        "scrub" => WatchAction::Scrub(parse_scrub(args)?),
+        _ => return Err(ArgsError::InvalidValue("--on-create".to_string(), name)),
+    };
+    // The new image is the only one it runs on.
+    if let Some(more) = action.batch().more.first() {
+        return Err(ArgsError::UnexpectedArgument(more.display().to_string()));
+    }
+    Ok(action)
+}
+
+fn parse_compare<I: Iterator<Item = String>>(args: I) -> Result<CompareArgs, ArgsError> {
+    let mut matches = Matches::parse(args, &[Opt::value("baseline", None)])?;
+    let file = matches.positional("file")?.into();
+    matches.finish()?;
+
+    Ok(CompareArgs {
+        baseline: matches
+            .value("baseline")
+            .ok_or(ArgsError::MissingArgument("baseline"))?
+            .into(),
+        file,
+    })
+}
+
+fn parse_verify<I: Iterator<Item = String>>(args: I) -> Result<VerifyArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::value("exclude", None),
+        Opt::value("jobs", Some('j')),
+        Opt::flag("quiet", Some('q')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let (file, batch) = parse_files(&mut matches, 0)?;
+    matches.finish()?;
+
+    Ok(VerifyArgs { file, batch })
+}
+
+fn parse_diff<I: Iterator<Item = String>>(args: I) -> Result<DiffArgs, ArgsError> {
+    const OPTS: &[Opt] = &[Opt::flag("data", None), Opt::flag("all", None)];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let old = matches.positional("old")?.into();
+    let new = matches.positional("new")?.into();
+    matches.finish()?;
+
+    Ok(DiffArgs {
+        old,
+        new,
+        data: matches.flag("data"),
+        all: matches.flag("all"),
+    })
+}
+
+fn parse_hexdump<I: Iterator<Item = String>>(args: I) -> Result<HexdumpArgs, ArgsError> {
+    const OPTS: &[Opt] = &[Opt::value("nth", None), Opt::value("range", None)];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let file = matches.positional("file")?.into();
+    let chunk_type = parse_value("<chunk-type>", matches.positional("chunk-type")?)?;
+    matches.finish()?;
+
+    Ok(HexdumpArgs {
+        file,
+        chunk_type,
+        nth: parse_nth(&matches)?,
+        range: matches.parsed_value("range")?.unwrap_or_default(),
+    })
+}
+
+fn parse_remove<I: Iterator<Item = String>>(args: I) -> Result<RemoveArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::value("match", None),
+        Opt::flag("all", None),
+        Opt::value("nth", None),
+        Opt::flag("force", None),
+        Opt::value("output", Some('o')),
+        Opt::optional_value("backup"),
+        Opt::flag("dry-run", Some('n')),
+        Opt::value("exclude", None),
+        Opt::value("jobs", Some('j')),
+        Opt::flag("quiet", Some('q')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let chunk_type_given = !matches.flag("match");
+    let (file, batch) = parse_files(&mut matches, usize::from(chunk_type_given))?;
+    let pattern = match matches.value("match") {
+        Some(pattern) => parse_value("--match", pattern)?,
+        None => {
+            let chunk_type: ChunkType =
+                parse_value("<chunk-type>", matches.positional("chunk-type")?)?;
+            Pattern::new(&chunk_type.to_string()).expect("a chunk type has no wildcard characters")
+        }
+    };
+    matches.finish()?;
+    matches.exclusive(&["all", "nth"])?;
+
+    Ok(RemoveArgs {
+        file,
+        batch,
+        pattern,
+        selection: parse_selection(&matches)?,
+        force: matches.flag("force"),
+        output: matches.value("output").map(PathBuf::from),
+        backup: parse_backup(&matches)?,
+        dry_run: matches.flag("dry-run"),
+    })
+}
+
+fn parse_replace<I: Iterator<Item = String>>(args: I) -> Result<ReplaceArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::flag("base64", None),
+        Opt::flag("hex", None),
+        Opt::value("file", None),
+        Opt::value("nth", None),
+        Opt::value("output", Some('o')),
+        Opt::optional_value("backup"),
+        Opt::flag("dry-run", Some('n')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let file = matches.positional("file")?.into();
+    let chunk_type = parse_value("<chunk-type>", matches.positional("chunk-type")?)?;
+    let input = match &matches.values("file")[..] {
+        [] => Input::Message(matches.positional("new-data")?),
+        [path] => Input::Files(vec![path.into()]),
+        [_, extra, ..] => return Err(ArgsError::UnexpectedArgument(extra.clone())),
+    };
+    matches.finish()?;
+    matches.exclusive(&["file", "base64", "hex"])?;
+
+    Ok(ReplaceArgs {
+        file,
+        chunk_type,
+        nth: parse_nth(&matches)?,
+        input,
+        format: parse_format(&matches),
+        output: matches.value("output").map(PathBuf::from),
+        backup: parse_backup(&matches)?,
+        dry_run: matches.flag("dry-run"),
+    })
+}
+
+fn parse_reorder<I: Iterator<Item = String>>(args: I) -> Result<ReorderArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::value("output", Some('o')),
+        Opt::optional_value("backup"),
+        Opt::flag("dry-run", Some('n')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let file = matches.positional("file")?.into();
+    matches.finish()?;
+
+    Ok(ReorderArgs {
+        file,
+        output: matches.value("output").map(PathBuf::from),
+        backup: parse_backup(&matches)?,
+        dry_run: matches.flag("dry-run"),
+    })
+}
+
+fn parse_touch_time<I: Iterator<Item = String>>(args: I) -> Result<TouchTimeArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::flag("show", None),
+        Opt::value("set", None),
+        Opt::value("output", Some('o')),
+        Opt::optional_value("backup"),
+        Opt::flag("dry-run", Some('n')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let file = matches.positional("file")?.into();
+    matches.finish()?;
+    for long in ["set", "output", "backup", "dry-run"] {
+        matches.exclusive(&["show", long])?;
+    }
+
+    Ok(TouchTimeArgs {
+        file,
+        show: matches.flag("show"),
+        time: match matches.value("set") {
+            Some(now) if now == "now" => None,
+            Some(time) => Some(parse_value("--set", time)?),
+            None => None,
+        },
+        output: matches.value("output").map(PathBuf::from),
+        backup: parse_backup(&matches)?,
+        dry_run: matches.flag("dry-run"),
+    })
+}
+
+fn parse_exif<I: Iterator<Item = String>>(args: I) -> Result<ExifArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::flag("list", None),
+        Opt::optional_value("extract"),
+        Opt::flag("strip", None),
+        Opt::value("inject", None),
+        Opt::value("output", Some('o')),
+        Opt::optional_value("backup"),
+        Opt::flag("dry-run", Some('n')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let file: PathBuf = matches.positional("file")?.into();
+    matches.finish()?;
+    matches.exclusive(&["list", "extract", "strip", "inject"])?;
+    for long in ["output", "backup", "dry-run"] {
+        matches.exclusive(&["list", "extract", long])?;
+    }
+
+    let action = if matches.flag("extract") {
+        // `--extract` alone writes photo.exif next to photo.png.
+        ExifAction::Extract(match matches.value("extract") {
+            Some(path) => path.into(),
+            None => file.with_extension("exif"),
+        })
+    } else if matches.flag("strip") {
+        ExifAction::Strip
+    } else if let Some(path) = matches.value("inject") {
+        ExifAction::Inject(path.into())
+    } else {
+        ExifAction::List
+    };
+
+    Ok(ExifArgs {
+        file,
+        action,
+        output: matches.value("output").map(PathBuf::from),
+        backup: parse_backup(&matches)?,
+        dry_run: matches.flag("dry-run"),
+    })
+}
+
+fn parse_scrub<I: Iterator<Item = String>>(args: I) -> Result<ScrubArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::value("keep", None),
+        Opt::value("output", Some('o')),
+        Opt::optional_value("backup"),
+        Opt::flag("dry-run", Some('n')),
+        Opt::value("exclude", None),
+        Opt::value("jobs", Some('j')),
+        Opt::flag("quiet", Some('q')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let (file, batch) = parse_files(&mut matches, 0)?;
+    matches.finish()?;
+
+    Ok(ScrubArgs {
+        file,
+        batch,
+        keep: matches.parsed_values("keep")?,
+        output: matches.value("output").map(PathBuf::from),
+        backup: parse_backup(&matches)?,
+        dry_run: matches.flag("dry-run"),
+    })
+}
+
+fn parse_strip_metadata<I: Iterator<Item = String>>(
+    args: I,
+) -> Result<StripMetadataArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::value("keep", None),
+        Opt::value("keep-keyword", None),
+        Opt::value("output", Some('o')),
+        Opt::optional_value("backup"),
+        Opt::flag("dry-run", Some('n')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let file = matches.positional("file")?.into();
+    matches.finish()?;
+
+    Ok(StripMetadataArgs {
+        file,
+        keep: matches.parsed_values("keep")?,
+        keep_keywords: matches.values("keep-keyword"),
+        output: matches.value("output").map(PathBuf::from),
+        backup: parse_backup(&matches)?,
+        dry_run: matches.flag("dry-run"),
+    })
+}
+
+fn parse_copy_chunks<I: Iterator<Item = String>>(args: I) -> Result<CopyChunksArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::value("from", None),
+        Opt::value("to", None),
+        Opt::value("types", None),
+        Opt::flag("force", None),
+        Opt::value("output", Some('o')),
+        Opt::optional_value("backup"),
+        Opt::flag("dry-run", Some('n')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    matches.finish()?;
+
+    let required = |long: &'static str| matches.value(long).ok_or(ArgsError::MissingArgument(long));
+    let chunk_types = matches
+        .values("types")
+        .iter()
+        .flat_map(|types| types.split(','))
+        .map(|chunk_type| parse_value("--types", chunk_type.to_string()))
+        .collect::<Result<Vec<ChunkType>, _>>()?;
+    if chunk_types.is_empty() {
+        return Err(ArgsError::MissingArgument("types"));
+    }
+
+    Ok(CopyChunksArgs {
+        from: required("from")?.into(),
+        to: required("to")?.into(),
+        chunk_types,
+        force: matches.flag("force"),
+        output: matches.value("output").map(PathBuf::from),
+        backup: parse_backup(&matches)?,
+        dry_run: matches.flag("dry-run"),
+    })
+}
+
+fn parse_export_chunks<I: Iterator<Item = String>>(args: I) -> Result<ExportChunksArgs, ArgsError> {
+    let mut matches = Matches::parse(args, &[])?;
+    let file = matches.positional("file")?.into();
+    let dir = matches.positional("dir")?.into();
+    matches.finish()?;
+
+    Ok(ExportChunksArgs { file, dir })
+}
+
+fn parse_import_chunks<I: Iterator<Item = String>>(args: I) -> Result<ImportChunksArgs, ArgsError> {
+    const OPTS: &[Opt] = &[
+        Opt::value("into", None),
+        Opt::value("position", None),
+        Opt::value("output", Some('o')),
+        Opt::optional_value("backup"),
+        Opt::flag("dry-run", Some('n')),
+    ];
+
+    let mut matches = Matches::parse(args, OPTS)?;
+    let dir = matches.positional("dir")?.into();
+    matches.finish()?;
+
+    let into = matches.value("into").map(PathBuf::from);
+    let output = matches.value("output").map(PathBuf::from);
+    if into.is_none() && output.is_none() {
+        return Err(ArgsError::MissingArgument("output"));
+    }
+    if into.is_none() && matches.flag("position") {
+        return Err(ArgsError::InvalidValue(
+            "--position".to_string(),
+            "without --into".to_string(),
+        ));
+    }
+
+    Ok(ImportChunksArgs {
+        dir,
+        into,
+        position: matches.parsed_value("position")?.unwrap_or_default(),
+        output,
+        backup: parse_backup(&matches)?,
+        dry_run: matches.flag("dry-run"),
+    })
+}
+
+/// An option accepted by a command, spelled `--long` or `-s`.
+struct Opt {
+    long: &'static str,
+    short: Option<char>,
+    kind: OptKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OptKind {
+    Flag,
+    Value,
+    /// A flag that may carry a value, given only as `--long=value`.
+    OptionalValue,
+}
+
+impl Opt {
+    const fn flag(long: &'static str, short: Option<char>) -> Self {
+        Self {
+            long,
+            short,
+            kind: OptKind::Flag,
+        }
+    }
+
+    const fn value(long: &'static str, short: Option<char>) -> Self {
+        Self {
+            long,
+            short,
+            kind: OptKind::Value,
+        }
+    }
+
+    const fn optional_value(long: &'static str) -> Self {
+        Self {
+            long,
+            short: None,
+            kind: OptKind::OptionalValue,
+        }
+    }
+}
+
+/// The options and positional arguments of a single command line, in order.
+#[derive(Debug)]
+struct Matches {
+    positionals: VecDeque<String>,
+    options: Vec<(&'static str, Option<String>)>,
+}
+
+impl Matches {
+    fn parse<I: Iterator<Item = String>>(mut args: I, opts: &[Opt]) -> Result<Self, ArgsError> {
+        let mut matches = Matches {
+            positionals: VecDeque::new(),
+            options: Vec::new(),
+        };
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                matches.positionals.extend(args.by_ref());
+                break;
+            }
+
+            let (opt, inline_value) = if let Some(long) = arg.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let opt = opts.iter().find(|opt| opt.long == name);
+                (opt, value)
+            } else if arg.len() > 1 && arg.starts_with('-') {
+                let mut chars = arg[1..].chars();
+                let short = chars.next();
+                let rest: String = chars.collect();
+                let opt = opts
+                    .iter()
+                    .find(|opt| opt.short.is_some() && opt.short == short);
+                (opt, Some(rest).filter(|rest| !rest.is_empty()))
+            } else {
+                matches.positionals.push_back(arg);
+                continue;
+            };
+
+            let opt = opt.ok_or_else(|| ArgsError::UnknownOption(arg.clone()))?;
+            let value = match (opt.kind, inline_value) {
+                (OptKind::Value | OptKind::OptionalValue, Some(value)) => Some(value),
+                (OptKind::Value, None) => Some(
+                    args.next()
+                        .ok_or_else(|| ArgsError::MissingValue(opt.long.to_string()))?,
+                ),
+                (OptKind::Flag | OptKind::OptionalValue, None) => None,
+                (OptKind::Flag, Some(_)) => {
+                    return Err(ArgsError::UnexpectedValue(opt.long.to_string()))
+                }
+            };
+            matches.options.push((opt.long, value));
+        }
+
+        Ok(matches)
+    }
+
+    fn flag(&self, long: &str) -> bool {
+        self.options.iter().any(|(name, _)| *name == long)
+    }
+
+    /// The last value given for `long`, so later options override earlier ones.
+    fn value(&self, long: &str) -> Option<String> {
+        self.values(long).pop()
+    }
+
+    fn values(&self, long: &str) -> Vec<String> {
+        self.options
+            .iter()
+            .filter(|(name, _)| *name == long)
+            .filter_map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    fn parsed_value<T: FromStr>(&self, long: &str) -> Result<Option<T>, ArgsError> {
+        self.value(long)
+            .map(|value| parse_value(&format!("--{long}"), value))
+            .transpose()
+    }
+
+    fn parsed_values<T: FromStr>(&self, long: &str) -> Result<Vec<T>, ArgsError> {
+        self.values(long)
+            .into_iter()
+            .map(|value| parse_value(&format!("--{long}"), value))
+            .collect()
+    }
+
+    fn positional(&mut self, name: &'static str) -> Result<String, ArgsError> {
+        self.positionals
+            .pop_front()
+            .ok_or(ArgsError::MissingArgument(name))
+    }
+
+    /// Takes every positional but the last `keep`, and at least one: the
+    /// images a command runs on, ahead of the arguments that follow them.
+    fn positionals_but(
+        &mut self,
+        name: &'static str,
+        keep: usize,
+    ) -> Result<Vec<String>, ArgsError> {
+        let count = self.positionals.len().saturating_sub(keep).max(1);
+        (0..count).map(|_| self.positional(name)).collect()
+    }
+
+    /// Fails if more than one of `longs` was given.
+    fn exclusive(&self, longs: &[&'static str]) -> Result<(), ArgsError> {
+        let given: Vec<&'static str> = longs
+            .iter()
+            .copied()
+            .filter(|long| self.flag(long))
+            .collect();
+        match given[..] {
+            [first, second, ..] => Err(ArgsError::ConflictingOptions(first, second)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if any positional arguments were left unconsumed.
+    fn finish(&mut self) -> Result<(), ArgsError> {
+        match self.positionals.pop_front() {
+            Some(extra) => Err(ArgsError::UnexpectedArgument(extra)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parses a single argument, naming it as `--option` or `<positional>` on failure.
+fn parse_value<T: FromStr>(name: &str, value: String) -> Result<T, ArgsError> {
+    value
+        .parse()
+        .map_err(|_| ArgsError::InvalidValue(name.to_string(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(line: &str) -> Result<Command, ArgsError> {
+        parse(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_missing_command() {
+        assert_eq!(parse_str(""), Err(ArgsError::MissingCommand));
+        assert!(matches!(
+            parse_str("frobnicate"),
+            Err(ArgsError::UnknownCommand(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode() {
+        let command = parse_str("encode in.png ruSt hello --max-chunk-size 64").unwrap();
+        assert_eq!(
+            command,
+            Command::Encode(Box::new(EncodeArgs {
+                file: "in.png".into(),
+                batch: Batch::default(),
+                chunk_type: "ruSt".parse().unwrap(),
+                random_type: false,
+                input: Input::Message("hello".to_string()),
+                format: Format::Text,
+                output: None,
+                backup: None,
+                dry_run: false,
+                max_chunk_size: Some(64),
+                position: InsertPosition::BeforeIend,
+                compression: None,
+                padding: None,
+                metadata: Metadata::default(),
+                raw: false,
+                envelope: false,
+                key: None,
+                text: None,
+                generate_carrier: None,
+                lsb: None,
+                fec: None,
+                redundancy: 1,
+                spread: Vec::new(),
+            }))
+        );
+
+        let Command::Encode(args) = parse_str("encode in.png ruSt hello --raw").unwrap() else {
+            panic!("expected encode");
+        };
+        assert!(args.raw);
+        assert_eq!(
+            parse_str("encode in.png ruSt hello --raw --compress deflate"),
+            Err(ArgsError::ConflictingOptions("raw", "compress"))
+        );
+        assert_eq!(
+            parse_str("encode in.png ruSt --raw --file a.txt"),
+            Err(ArgsError::ConflictingOptions("raw", "file"))
+        );
+        let Command::Encode(args) =
+            parse_str("encode in.png --as-ztxt Secret hello --envelope").unwrap()
+        else {
+            panic!("expected encode");
+        };
+        assert!(args.envelope);
+        assert_eq!(
+            parse_str("encode in.png ruSt hello --envelope"),
+            Err(ArgsError::InvalidValue(
+                "--envelope".to_string(),
+                "without --as-text, --as-ztxt or --as-itxt".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_str("encode in.png --as-text Secret hello --raw --envelope"),
+            Err(ArgsError::ConflictingOptions("raw", "envelope"))
+        );
+
+        let command = parse_str("encode in.png --lsb hello --channels opaque").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.lsb.unwrap().channels, Channels::Opaque);
+        let command = parse_str("encode in.png --lsb --bits-per-sample 4 hello").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.lsb.unwrap().bits_per_sample, 4);
+        for line in [
+            "encode in.png --lsb --bits-per-sample 9 hello",
+            "encode in.png --lsb --bits-per-sample 0 hello",
+            "encode in.png ruSt --bits-per-sample 2 hello",
+        ] {
+            assert!(
+                matches!(parse_str(line), Err(ArgsError::InvalidValue(..))),
+                "{line}"
+            );
+        }
+        assert_eq!(args.input, Input::Message("hello".to_string()));
+        assert_eq!(
+            parse_str("encode in.png --lsb hello --position after:IHDR"),
+            Err(ArgsError::ConflictingOptions("lsb", "position"))
+        );
+
+        let command = parse_str("encode new.png ruSt hi --generate-carrier 16x8:ff8000").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(
+            args.generate_carrier,
+            Some(CarrierSpec {
+                width: 16,
+                height: 8,
+                color: Rgb::new(255, 128, 0),
+                noise: false,
+                bit_depth: 8,
+                seed: None,
+            })
+        );
+        let command = parse_str(
+            "encode new.png ruSt hi --generate-carrier noise:64x48 --seed 7 --carrier-depth 16",
+        )
+        .unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        let spec = args.generate_carrier.unwrap();
+        assert!(spec.noise);
+        assert_eq!((spec.width, spec.seed, spec.bit_depth), (64, Some(7), 16));
+        for line in [
+            "encode in.png ruSt hi --seed 7",
+            "encode in.png ruSt hi --generate-carrier 4x4 --seed 7",
+            "encode in.png ruSt hi --carrier-depth 16",
+        ] {
+            assert!(
+                matches!(parse_str(line), Err(ArgsError::InvalidValue(..))),
+                "{line}"
+            );
+        }
+        let spec: CarrierSpec = "2x3".parse().unwrap();
+        assert_eq!(spec.color, Rgb::new(255, 255, 255));
+        for bad in [
+            "16",
+            "16x",
+            "x8",
+            "16x8:fff",
+            "16x8:gg0000",
+            "noise:4x4:ffffff",
+        ] {
+            assert!(bad.parse::<CarrierSpec>().is_err(), "{bad}");
+        }
+
+        let command = parse_str("encode in.png --as-text Secret hello").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.chunk_type, "tEXt".parse().unwrap());
+        assert_eq!(
+            args.text,
+            Some(TextTarget {
+                kind: TextKind::Text,
+                keyword: "Secret".to_string(),
+                language_tag: String::new(),
+                translated_keyword: String::new(),
+            })
+        );
+        assert_eq!(args.input, Input::Message("hello".to_string()));
+        assert_eq!(
+            parse_str("encode in.png --as-text Secret hello --max-chunk-size 64"),
+            Err(ArgsError::ConflictingOptions("as-text", "max-chunk-size"))
+        );
+        let too_long = (payload::MAX_SEGMENT_LEN + 1).to_string();
+        for line in [
+            format!("encode in.png ruSt hello --max-chunk-size {too_long}"),
+            format!("capacity in.png --max-chunk-size {too_long}"),
+        ] {
+            assert_eq!(
+                parse_str(&line),
+                Err(ArgsError::InvalidValue(
+                    "--max-chunk-size".to_string(),
+                    too_long.clone()
+                ))
+            );
+        }
+        let line = format!(
+            "encode in.png ruSt hello --max-chunk-size {}",
+            payload::MAX_SEGMENT_LEN
+        );
+        assert!(parse_str(&line).is_ok());
+
+        let command = parse_str("encode in.png --as-ztxt Secret hello").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.chunk_type, "zTXt".parse().unwrap());
+        assert_eq!(args.text.unwrap().kind, TextKind::Compressed);
+        assert_eq!(
+            parse_str("encode in.png --as-ztxt Secret --as-text Secret hello"),
+            Err(ArgsError::ConflictingOptions("as-text", "as-ztxt"))
+        );
+
+        let command =
+            parse_str("encode in.png --as-itxt Titel --language de --translated-keyword Title hi")
+                .unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.chunk_type, "iTXt".parse().unwrap());
+        let text = args.text.unwrap();
+        assert_eq!(text.kind, TextKind::International);
+        assert_eq!(text.language_tag, "de");
+        assert_eq!(text.translated_keyword, "Title");
+        assert!(matches!(
+            parse_str("encode in.png --as-text Secret --language de hi"),
+            Err(ArgsError::InvalidValue(..))
+        ));
+
+        let command = parse_str("encode in.png ruSt hello --position after:IHDR").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(
+            args.position,
+            InsertPosition::AfterType("IHDR".parse().unwrap())
+        );
+
+        let command = parse_str("encode in.png ruSt hello --compress deflate:9").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.compression, Some(Compression::Deflate(9)));
+
+        let command = parse_str("encode in.png ruSt hello --pad-to 25%").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.padding, Some(Padding::Percent(25)));
+        assert!(parse_str("encode in.png ruSt hello --pad-to lots").is_err());
+
+        let command = parse_str(
+            "encode in.png ruSt hello --creator Ada --content-type text/plain \
+             --created-at 2024-03-01T12:34:56",
+        )
+        .unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(
+            args.metadata,
+            Metadata {
+                creator: Some("Ada".to_string()),
+                created_at: Some("2024-03-01T12:34:56".parse().unwrap()),
+                comment: None,
+                content_type: Some("text/plain".to_string()),
+                expires: None,
+            }
+        );
+        let command = parse_str("encode in.png ruSt hello --created-at now").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert!(args.metadata.created_at.is_some());
+
+        let command = parse_str("encode in.png ruSt hello --expires 2025-12-31").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(
+            args.metadata.expires,
+            Some("2025-12-31T23:59:59".parse().unwrap())
+        );
+        let command = parse_str("encode in.png ruSt hello --expires 2025-12-31T08:00:00").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(
+            args.metadata.expires,
+            Some("2025-12-31T08:00:00".parse().unwrap())
+        );
+        assert_eq!(
+            parse_str("encode in.png ruSt hello --expires 2025-02-30"),
+            Err(ArgsError::InvalidValue(
+                "--expires".to_string(),
+                "2025-02-30".to_string()
+            ))
+        );
+
+        let command = parse_str("encode in.png ruSt hello --fec rs:16").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.fec, Some(Fec { parity: 16 }));
+
+        let command = parse_str("encode in.png ruSt hello --redundancy 3").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.redundancy, 3);
+
+        let command = parse_str("encode a.png ruSt hello --spread b.png").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.spread, [PathBuf::from("b.png")]);
+
+        let command = parse_str("encode a.png --random-type hello").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert!(args.random_type);
+        assert_eq!(args.input, Input::Message("hello".to_string()));
+        assert!(parse_str("encode a.png --random-type --lsb hello").is_err());
+        assert!(parse_str("encode in.png ruSt hello --redundancy 0").is_err());
+        assert_eq!(
+            parse_str("encode in.png ruSt hello --fec rs:200"),
+            Err(ArgsError::InvalidValue(
+                "--fec".to_string(),
+                "rs:200".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_str("encode in.png ruSt hello --compress zstd"),
+            Err(ArgsError::InvalidValue(
+                "--compress".to_string(),
+                "zstd".to_string()
+            ))
+        );
+
+        let command = parse_str("encode in.png ruSt hello --hmac").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.key, Some(KeySource::Prompt));
+
+        let command = parse_str("encode in.png ruSt hello --hmac --passphrase pw").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.key, Some(KeySource::Passphrase("pw".to_string())));
+
+        assert_eq!(
+            parse_str("encode in.png ruSt hello --hmac=yes"),
+            Err(ArgsError::UnexpectedValue("hmac".to_string()))
+        );
+        assert_eq!(
+            parse_str("encode in.png ruSt"),
+            Err(ArgsError::MissingArgument("message"))
+        );
+
+        let command = parse_str("encode in.png ruSt --file secret.pdf").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.input, Input::Files(vec!["secret.pdf".into()]));
+
+        let command = parse_str("encode in.png ruSt --file a.txt --file b.key").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(
+            args.input,
+            Input::Files(vec!["a.txt".into(), "b.key".into()])
+        );
+        let command = parse_str("encode in.png ruSt --base64 aGk=").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.format, Format::Base64);
+        let command = parse_str("encode in.png ruSt --hex 00ff").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.format, Format::Hex);
+        assert_eq!(
+            parse_str("encode in.png ruSt --hex --base64 00"),
+            Err(ArgsError::ConflictingOptions("base64", "hex"))
+        );
+        assert_eq!(
+            parse_str("encode in.png ruSt --base64 --file secret.pdf"),
+            Err(ArgsError::ConflictingOptions("file", "base64"))
+        );
+        // Every positional ahead of the chunk type and message is an image.
+        let command = parse_str("encode a.png b.png dir ruSt hello").unwrap();
+        let Command::Encode(args) = command else {
+            panic!("expected encode");
+        };
+        assert_eq!(args.file, PathBuf::from("a.png"));
+        assert_eq!(
+            args.batch.more,
+            [PathBuf::from("b.png"), PathBuf::from("dir")]
+        );
+        assert_eq!(args.input, Input::Message("hello".to_string()));
+        assert_eq!(
+            parse_str("encode in.png ruSt hello --file secret.pdf"),
+            Err(ArgsError::InvalidValue(
+                "<chunk-type>".to_string(),
+                "hello".to_string()
+            ))
+        );
+        assert!(matches!(
+            parse_str("encode in.png ru5t hello"),
+            Err(ArgsError::InvalidValue(..))
+        ));
+    }
+
+    #[test]
+    fn test_decode() {
+        let command = parse_str("decode in.png ruSt --passphrase hunter2").unwrap();
+        assert_eq!(
+            command,
+            Command::Decode(DecodeArgs {
+                file: "in.png".into(),
+                batch: Batch::default(),
+                chunk_type: "ruSt".parse().unwrap(),
+                nth: 1,
+                list: false,
+                info: false,
+                extract: Vec::new(),
+                extract_to: None,
+                format: Format::Text,
+                output: None,
+                key: Some(KeySource::Passphrase("hunter2".to_string())),
+                text_keyword: None,
+                language: None,
+                lsb: None,
+                spread: Vec::new(),
+                ignore_expiry: false,
+            })
+        );
+
+        let command = parse_str("decode a.png b.png --lsb").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert_eq!(args.batch.more, [PathBuf::from("b.png")]);
+
+        let command = parse_str("decode in.png ruSt --info").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert!(args.info);
+        assert_eq!(
+            parse_str("decode in.png ruSt --info -o out.bin"),
+            Err(ArgsError::ConflictingOptions("info", "output"))
+        );
+
+        let command = parse_str("decode a.png ruSt --spread b.png --spread c.png").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert_eq!(
+            args.spread,
+            [PathBuf::from("b.png"), PathBuf::from("c.png")]
+        );
+
+        let command = parse_str("decode in.png --lsb").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert_eq!(args.lsb, Some(LsbOptions::default()));
+        assert_eq!(args.chunk_type, ChunkType::IDAT);
+        assert!(matches!(
+            parse_str("decode in.png ruSt --channels alpha"),
+            Err(ArgsError::InvalidValue(..))
+        ));
+        assert_eq!(
+            parse_str("decode in.png --lsb --nth 2"),
+            Err(ArgsError::ConflictingOptions("lsb", "nth"))
+        );
+
+        let command = parse_str("decode in.png --as-text Secret --nth 2 --language de").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert_eq!(args.text_keyword, Some("Secret".to_string()));
+        assert_eq!(args.language, Some("de".to_string()));
+        assert_eq!(args.nth, 2);
+        assert!(matches!(
+            parse_str("decode in.png ruSt --language de"),
+            Err(ArgsError::InvalidValue(..))
+        ));
+
+        let command = parse_str("decode in.png ruSt --nth 2").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert_eq!(args.nth, 2);
+        assert_eq!(
+            parse_str("decode in.png ruSt --nth 0"),
+            Err(ArgsError::InvalidValue(
+                "--nth".to_string(),
+                "0".to_string()
+            ))
+        );
+
+        let command = parse_str("decode in.png ruSt --extract-to out/").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert_eq!(args.extract_to, Some("out/".into()));
+
+        let command = parse_str("decode in.png ruSt --list --extract a --extract b").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert!(args.list);
+        assert_eq!(args.extract, vec!["a".to_string(), "b".to_string()]);
+
+        let command = parse_str("decode in.png ruSt -o - --extract a").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert_eq!(args.output, Some("-".into()));
+        assert_eq!(
+            parse_str("decode in.png ruSt --list -o out.bin"),
+            Err(ArgsError::ConflictingOptions("list", "output"))
+        );
+
+        let command = parse_str("decode in.png ruSt --base64 -o -").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert_eq!(args.format, Format::Base64);
+        assert_eq!(
+            parse_str("decode in.png ruSt --base64 --extract-to out"),
+            Err(ArgsError::ConflictingOptions("extract-to", "base64"))
+        );
+
+        let command = parse_str("decode in.png ruSt --keyfile secret.key").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert_eq!(args.key, Some(KeySource::Keyfile("secret.key".into())));
+
+        assert_eq!(
+            parse_str("decode in.png ruSt --keyfile k --passphrase p"),
+            Err(ArgsError::ConflictingOptions("passphrase", "keyfile"))
+        );
+
+        let command = parse_str("decode in.png ruSt --passphrase-fd 3").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert_eq!(args.key, Some(KeySource::Fd(3)));
+        assert_eq!(
+            parse_str("decode in.png ruSt --passphrase-fd 3 --keyfile k"),
+            Err(ArgsError::ConflictingOptions("keyfile", "passphrase-fd"))
+        );
+        let command = parse_str("decode in.png ruSt --password-fd 4").unwrap();
+        let Command::Decode(args) = command else {
+            panic!("expected decode");
+        };
+        assert_eq!(args.key, Some(KeySource::Fd(4)));
+        assert_eq!(
+            parse_str("decode in.png ruSt --password-fd 4 --passphrase-fd 3"),
+            Err(ArgsError::ConflictingOptions(
+                "passphrase-fd",
+                "password-fd"
+            ))
+        );
+        assert_eq!(
+            parse_str("encode in.png ruSt hi --password-fd 4 --raw"),
+            Err(ArgsError::ConflictingOptions("raw", "password-fd"))
+        );
+    }
+
+    #[test]
+    fn test_fix_crc() {
+        let command = parse_str("fix-crc in.png --type tEXt -o out.png --type=ruSt").unwrap();
+        assert_eq!(
+            command,
+            Command::FixCrc(FixCrcArgs {
+                file: "in.png".into(),
+                chunk_types: vec!["tEXt".parse().unwrap(), "ruSt".parse().unwrap()],
+                output: Some("out.png".into()),
+                backup: None,
+                dry_run: false,
+            })
+        );
+
+        let command = parse_str("fix-crc in.png --backup").unwrap();
+        let Command::FixCrc(args) = command else {
+            panic!("expected fix-crc");
+        };
+        assert_eq!(args.backup, Some(".bak".to_string()));
+
+        // The suffix must be inline so a following positional isn't taken for it.
+        let command = parse_str("fix-crc --backup in.png").unwrap();
+        let Command::FixCrc(args) = command else {
+            panic!("expected fix-crc");
+        };
+        assert_eq!(args.file, PathBuf::from("in.png"));
+        assert_eq!(args.backup, Some(".bak".to_string()));
+
+        let command = parse_str("fix-crc in.png --backup=.orig").unwrap();
+        let Command::FixCrc(args) = command else {
+            panic!("expected fix-crc");
+        };
+        assert_eq!(args.backup, Some(".orig".to_string()));
+
+        let command = parse_str("fix-crc in.png -n").unwrap();
+        let Command::FixCrc(args) = command else {
+            panic!("expected fix-crc");
+        };
+        assert!(args.dry_run);
+        assert_eq!(
+            parse_str("fix-crc in.png --backup="),
+            Err(ArgsError::InvalidValue(
+                "--backup".to_string(),
+                String::new()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        let expected = Command::List(ListArgs {
+            file: "in.png".into(),
+            format: ReportFormat::Table,
+        });
+        assert_eq!(parse_str("list in.png").unwrap(), expected);
+        assert_eq!(parse_str("print in.png").unwrap(), expected);
+        assert_eq!(parse_str("list"), Err(ArgsError::MissingArgument("file")));
+        assert_eq!(
+            parse_str("list in.png --format csv").unwrap(),
+            Command::List(ListArgs {
+                file: "in.png".into(),
+                format: ReportFormat::Csv,
+            })
+        );
+        assert_eq!(
+            parse_str("list in.png --format xml"),
+            Err(ArgsError::InvalidValue(
+                "--format".to_string(),
+                "xml".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_inspect() {
+        assert_eq!(
+            parse_str("inspect in.png --json").unwrap(),
+            Command::Inspect(InspectArgs {
+                file: "in.png".into(),
+                format: ReportFormat::Json,
+            })
+        );
+        assert_eq!(
+            parse_str("inspect in.png --format yaml").unwrap(),
+            Command::Inspect(InspectArgs {
+                file: "in.png".into(),
+                format: ReportFormat::Yaml,
+            })
+        );
+        assert_eq!(
+            parse_str("inspect in.png --json --format csv"),
+            Err(ArgsError::ConflictingOptions("json", "format"))
+        );
+    }
+
+    #[test]
+    fn test_capacity() {
+        assert_eq!(
+            parse_str("capacity in.png").unwrap(),
+            Command::Capacity(CapacityArgs {
+                file: "in.png".into(),
+                method: None,
+                bits_per_sample: 1,
+                max_chunk_size: None,
+                payload: None,
+            })
+        );
+        assert_eq!(
+            parse_str("capacity in.png --method alpha --bits-per-sample 2 --payload a.txt")
+                .unwrap(),
+            Command::Capacity(CapacityArgs {
+                file: "in.png".into(),
+                method: Some(Method::Alpha),
+                bits_per_sample: 2,
+                max_chunk_size: None,
+                payload: Some("a.txt".into()),
+            })
+        );
+        assert!(matches!(
+            parse_str("capacity in.png --method idat"),
+            Err(ArgsError::InvalidValue(..))
+        ));
+        assert_eq!(
+            parse_str("capacity in.png --bits-per-sample 9"),
+            Err(ArgsError::InvalidValue(
+                "--bits-per-sample".to_string(),
+                "9".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            parse_str("detect in.png --format json").unwrap(),
+            Command::Detect(DetectArgs {
+                file: "in.png".into(),
+                format: ReportFormat::Json,
+            })
+        );
+        assert_eq!(parse_str("detect"), Err(ArgsError::MissingArgument("file")));
+    }
+
+    #[test]
+    fn test_scan() {
+        assert_eq!(
+            parse_str("scan photos more.png --lsb --passphrases words.txt --format csv").unwrap(),
+            Command::Scan(ScanArgs {
+                file: "photos".into(),
+                batch: Batch {
+                    more: vec!["more.png".into()],
+                    ..Batch::default()
+                },
+                lsb: true,
+                passphrases: Some("words.txt".into()),
+                format: ReportFormat::Csv,
+            })
+        );
+        assert_eq!(parse_str("scan"), Err(ArgsError::MissingArgument("file")));
+    }
+
+    #[test]
+    fn test_watch() {
+        assert_eq!(
+            parse_str(
+                "watch drop --interval 0.5 --on-create scrub --keep ruSt --exclude *.tmp.png"
+            )
+            .unwrap(),
+            Command::Watch(WatchArgs {
+                dir: "drop".into(),
+                action: WatchAction::Scrub(ScrubArgs {
+                    file: "drop".into(),
+                    batch: Batch {
+                        exclude: vec!["*.tmp.png".parse().unwrap()],
+                        ..Batch::default()
+                    },
+                    keep: vec!["ruSt".parse().unwrap()],
+                    output: None,
+                    backup: None,
+                    dry_run: false,
+                }),
+                interval: Duration::from_millis(500),
+                existing: false,
+            })
+        );
+        let Command::Watch(args) =
+            parse_str("watch drop --existing --on-create decode ruSt").unwrap()
+        else {
+            panic!("not a watch");
+        };
+        assert!(matches!(args.action, WatchAction::Decode(_)));
+        assert_eq!(args.interval, Duration::from_secs(1));
+        assert!(args.existing);
+
+        assert_eq!(
+            parse_str("watch drop"),
+            Err(ArgsError::MissingValue("on-create".to_string()))
+        );
+        assert_eq!(
+            parse_str("watch drop --on-create list"),
+            Err(ArgsError::InvalidValue(
+                "--on-create".to_string(),
+                "list".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_str("watch drop --on-create verify other.png"),
+            Err(ArgsError::UnexpectedArgument("other.png".to_string()))
+        );
+        assert_eq!(
+            parse_str("watch drop --interval 0 --on-create verify"),
+            Err(ArgsError::InvalidValue(
+                "--interval".to_string(),
+                "0".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(
+            parse_str("compare --baseline clean.png in.png").unwrap(),
+            Command::Compare(CompareArgs {
+                baseline: "clean.png".into(),
+                file: "in.png".into(),
+            })
+        );
+        assert_eq!(
+            parse_str("compare in.png"),
+            Err(ArgsError::MissingArgument("baseline"))
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        assert_eq!(
+            parse_str("verify in.png").unwrap(),
+            Command::Verify(VerifyArgs {
+                file: "in.png".into(),
+                batch: Batch::default(),
+            })
+        );
+        assert_eq!(
+            parse_str("verify a.png dir --exclude *.bak.png -j 4 -q").unwrap(),
+            Command::Verify(VerifyArgs {
+                file: "a.png".into(),
+                batch: Batch {
+                    more: vec!["dir".into()],
+                    exclude: vec!["*.bak.png".parse().unwrap()],
+                    jobs: 4,
+                    quiet: true,
+                },
+            })
+        );
+        assert_eq!(
+            parse_str("verify dir --jobs 0"),
+            Err(ArgsError::InvalidValue(
+                "--jobs".to_string(),
+                "0".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_str("verify in.png --fix"),
+            Err(ArgsError::UnknownOption("--fix".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(
+            parse_str("diff a.png b.png --data").unwrap(),
+            Command::Diff(DiffArgs {
+                old: "a.png".into(),
+                new: "b.png".into(),
+                data: true,
+                all: false,
+            })
+        );
+        assert_eq!(
+            parse_str("diff a.png"),
+            Err(ArgsError::MissingArgument("new"))
+        );
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let command = parse_str("hexdump in.png tEXt --nth 2 --range 0x10..64").unwrap();
+        assert_eq!(
+            command,
+            Command::Hexdump(HexdumpArgs {
+                file: "in.png".into(),
+                chunk_type: "tEXt".parse().unwrap(),
+                nth: 2,
+                range: ByteRange {
+                    start: 16,
+                    end: Some(64),
+                },
+            })
+        );
+
+        let range = |s: &str| s.parse::<ByteRange>();
+        assert_eq!(range(".."), Ok(ByteRange::default()));
+        assert_eq!(
+            range("..8"),
+            Ok(ByteRange {
+                start: 0,
+                end: Some(8)
+            })
+        );
+        assert_eq!(
+            range("8.."),
+            Ok(ByteRange {
+                start: 8,
+                end: None
+            })
+        );
+        for invalid in ["8", "8..4", "x..", "0x..4"] {
+            assert_eq!(
+                range(invalid),
+                Err(ArgsError::InvalidValue(
+                    "--range".to_string(),
+                    invalid.to_string()
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn test_remove() {
+        let command = parse_str("remove in.png ruSt").unwrap();
+        assert_eq!(
+            command,
+            Command::Remove(RemoveArgs {
+                file: "in.png".into(),
+                batch: Batch::default(),
+                pattern: "ruSt".parse().unwrap(),
+                selection: Selection::First,
+                force: false,
+                output: None,
+                backup: None,
+                dry_run: false,
+            })
+        );
+
+        let command = parse_str("remove in.png --match ru?? --all").unwrap();
+        let Command::Remove(args) = command else {
+            panic!("expected remove");
+        };
+        assert!(args.pattern.matches("ruSt"));
+        assert_eq!(args.selection, Selection::All);
+
+        let command = parse_str("remove in.png tEXt --nth 2").unwrap();
+        let Command::Remove(args) = command else {
+            panic!("expected remove");
+        };
+        assert_eq!(args.selection, Selection::Nth(2));
+
+        assert_eq!(
+            parse_str("remove in.png ruSt --all --nth 2"),
+            Err(ArgsError::ConflictingOptions("all", "nth"))
+        );
+        let command = parse_str("remove a.png b.png ruSt").unwrap();
+        let Command::Remove(args) = command else {
+            panic!("expected remove");
+        };
+        assert_eq!(args.batch.more, [PathBuf::from("b.png")]);
+        let command = parse_str("remove a.png --match ru?? b.png").unwrap();
+        let Command::Remove(args) = command else {
+            panic!("expected remove");
+        };
+        assert_eq!(args.batch.more, [PathBuf::from("b.png")]);
+        assert_eq!(
+            parse_str("remove in.png"),
+            Err(ArgsError::MissingArgument("chunk-type"))
+        );
+        assert!(matches!(
+            parse_str("remove in.png --match ru[St"),
+            Err(ArgsError::InvalidValue(..))
+        ));
+    }
+
+    #[test]
+    fn test_fix_crc_errors() {
+        assert_eq!(
+            parse_str("fix-crc"),
+            Err(ArgsError::MissingArgument("file"))
+        );
+        assert_eq!(
+            parse_str("fix-crc a.png b.png"),
+            Err(ArgsError::UnexpectedArgument("b.png".to_string()))
+        );
+        assert_eq!(
+            parse_str("fix-crc a.png --type"),
+            Err(ArgsError::MissingValue("type".to_string()))
+        );
+        assert_eq!(
+            parse_str("fix-crc a.png --type r1St"),
+            Err(ArgsError::InvalidValue(
+                "--type".to_string(),
+                "r1St".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_str("fix-crc a.png --bogus"),
+            Err(ArgsError::UnknownOption("--bogus".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_short_option_forms() {
+        let attached = parse_str("fix-crc a.png -oout.png").unwrap();
+        let separate = parse_str("fix-crc a.png -o out.png").unwrap();
+        assert_eq!(attached, separate);
+    }
+
+    #[test]
+    fn test_replace() {
+        assert_eq!(
+            parse_str("replace in.png ruSt --hex 00ff --nth 2 -n").unwrap(),
+            Command::Replace(ReplaceArgs {
+                file: "in.png".into(),
+                chunk_type: "ruSt".parse().unwrap(),
+                nth: 2,
+                input: Input::Message("00ff".to_string()),
+                format: Format::Hex,
+                output: None,
+                backup: None,
+                dry_run: true,
+            })
+        );
+        let command = parse_str("replace in.png ruSt --file data.bin").unwrap();
+        let Command::Replace(args) = command else {
+            panic!("expected replace");
+        };
+        assert_eq!(args.input, Input::Files(vec!["data.bin".into()]));
+        assert_eq!(
+            parse_str("replace in.png ruSt --file a --file b"),
+            Err(ArgsError::UnexpectedArgument("b".to_string()))
+        );
+        assert_eq!(
+            parse_str("replace in.png ruSt"),
+            Err(ArgsError::MissingArgument("new-data"))
+        );
+    }
+
+    #[test]
+    fn test_reorder() {
+        assert_eq!(
+            parse_str("reorder in.png --backup=.orig").unwrap(),
+            Command::Reorder(ReorderArgs {
+                file: "in.png".into(),
+                output: None,
+                backup: Some(".orig".to_string()),
+                dry_run: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_touch_time() {
+        assert_eq!(
+            parse_str("touch-time in.png --set 2024-03-01T12:00:00Z -n").unwrap(),
+            Command::TouchTime(TouchTimeArgs {
+                file: "in.png".into(),
+                show: false,
+                time: Some(TimeChunk::new(2024, 3, 1, 12, 0, 0).unwrap()),
+                output: None,
+                backup: None,
+                dry_run: true,
+            })
+        );
+
+        let command = parse_str("touch-time in.png --set now").unwrap();
+        let Command::TouchTime(args) = command else {
+            panic!("expected touch-time");
+        };
+        assert_eq!(args.time, None);
+        assert!(matches!(
+            parse_str("touch-time in.png --set yesterday"),
+            Err(ArgsError::InvalidValue(..))
+        ));
+        assert_eq!(
+            parse_str("touch-time in.png --show -o out.png"),
+            Err(ArgsError::ConflictingOptions("show", "output"))
+        );
+    }
+
+    #[test]
+    fn test_exif() {
+        assert_eq!(
+            parse_str("exif photo.png").unwrap(),
+            Command::Exif(ExifArgs {
+                file: "photo.png".into(),
+                action: ExifAction::List,
+                output: None,
+                backup: None,
+                dry_run: false,
+            })
+        );
+
+        let command = parse_str("exif photo.png --extract").unwrap();
+        let Command::Exif(args) = command else {
+            panic!("expected exif");
+        };
+        assert_eq!(args.action, ExifAction::Extract("photo.exif".into()));
+
+        let command = parse_str("exif photo.png --inject blob.exif -o out.png").unwrap();
+        let Command::Exif(args) = command else {
+            panic!("expected exif");
+        };
+        assert_eq!(args.action, ExifAction::Inject("blob.exif".into()));
+        assert_eq!(args.output, Some("out.png".into()));
+        assert_eq!(
+            parse_str("exif photo.png --strip --inject blob.exif"),
+            Err(ArgsError::ConflictingOptions("strip", "inject"))
+        );
+        assert_eq!(
+            parse_str("exif photo.png --extract=a.exif -n"),
+            Err(ArgsError::ConflictingOptions("extract", "dry-run"))
+        );
+    }
+
+    #[test]
+    fn test_scrub() {
+        assert_eq!(
+            parse_str("scrub in.png --keep ruSt --keep abCd -n").unwrap(),
+            Command::Scrub(ScrubArgs {
+                file: "in.png".into(),
+                batch: Batch::default(),
+                keep: vec!["ruSt".parse().unwrap(), "abCd".parse().unwrap()],
+                output: None,
+                backup: None,
+                dry_run: true,
+            })
+        );
+        assert_eq!(
+            parse_str("scrub in.png --keep ru"),
+            Err(ArgsError::InvalidValue(
+                "--keep".to_string(),
+                "ru".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_strip_metadata() {
+        assert_eq!(
+            parse_str("strip-metadata in.png --keep tIME --keep-keyword Copyright -o out.png")
+                .unwrap(),
+            Command::StripMetadata(StripMetadataArgs {
+                file: "in.png".into(),
+                keep: vec!["tIME".parse().unwrap()],
+                keep_keywords: vec!["Copyright".to_string()],
+                output: Some("out.png".into()),
+                backup: None,
+                dry_run: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_export_chunks() {
+        assert_eq!(
+            parse_str("export-chunks in.png out").unwrap(),
+            Command::ExportChunks(ExportChunksArgs {
+                file: "in.png".into(),
+                dir: "out".into(),
+            })
+        );
+        assert_eq!(
+            parse_str("export-chunks in.png"),
+            Err(ArgsError::MissingArgument("dir"))
+        );
+    }
+
+    #[test]
+    fn test_import_chunks() {
+        assert_eq!(
+            parse_str("import-chunks out -o rebuilt.png").unwrap(),
+            Command::ImportChunks(ImportChunksArgs {
+                dir: "out".into(),
+                into: None,
+                position: InsertPosition::BeforeIend,
+                output: Some("rebuilt.png".into()),
+                backup: None,
+                dry_run: false,
+            })
+        );
+        let command = parse_str("import-chunks out --into in.png --position after-ihdr").unwrap();
+        let Command::ImportChunks(args) = command else {
+            panic!("expected import-chunks");
+        };
+        assert_eq!(args.into, Some("in.png".into()));
+        assert_eq!(args.position, InsertPosition::AfterIhdr);
+        assert_eq!(
+            parse_str("import-chunks out"),
+            Err(ArgsError::MissingArgument("output"))
+        );
+    }
+
+    #[test]
+    fn test_copy_chunks() {
+        assert_eq!(
+            parse_str("copy-chunks --from a.png --to b.png --types ruSt,tEXt --types zTXt")
+                .unwrap(),
+            Command::CopyChunks(CopyChunksArgs {
+                from: "a.png".into(),
+                to: "b.png".into(),
+                chunk_types: vec![
+                    "ruSt".parse().unwrap(),
+                    "tEXt".parse().unwrap(),
+                    "zTXt".parse().unwrap(),
+                ],
+                force: false,
+                output: None,
+                backup: None,
+                dry_run: false,
+            })
+        );
+        assert_eq!(
+            parse_str("copy-chunks --from a.png --types ruSt"),
+            Err(ArgsError::MissingArgument("to"))
+        );
+        assert_eq!(
+            parse_str("copy-chunks --from a.png --to b.png"),
+            Err(ArgsError::MissingArgument("types"))
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum Base64Error {
+    #[error("Base64Error invalid character {0:?} at offset {1}")]
+    InvalidByte(char, usize),
+    #[error("Base64Error input length is not a whole number of bytes")]
+    InvalidLength,
+    #[error("Base64Error misplaced padding")]
+    InvalidPadding,
+}
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const INVALID: u8 = 0xff;
+
+const DECODE_TABLE: [u8; 256] = {
+    let mut table = [INVALID; 256];
+    let mut i = 0;
+    while i < ALPHABET.len() {
+        table[ALPHABET[i] as usize] = i as u8;
+        i += 1;
+    }
+    table
+};
+
+/// Encodes `data` with the standard alphabet and padding (RFC 4648).
+pub fn encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for group in data.chunks(3) {
+        let bytes = [
+            group[0],
+            *group.get(1).unwrap_or(&0),
+            *group.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= group.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes standard base64, ignoring whitespace such as line breaks. Trailing
+/// padding may be left off.
+pub fn decode(encoded: &str) -> Result<Vec<u8>, Base64Error> {
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let (mut n, mut bits) = (0u32, 0u32);
+    let mut padding = 0;
+
+    for (offset, c) in encoded.char_indices() {
+        if c.is_ascii_whitespace() {
+            continue;
+        }
+        if c == '=' {
+            padding += 1;
+            continue;
+        }
+        if padding > 0 {
+            return Err(Base64Error::InvalidPadding);
+        }
+        let value = match u8::try_from(c) {
+            Ok(byte) if DECODE_TABLE[byte as usize] != INVALID => DECODE_TABLE[byte as usize],
+            _ => return Err(Base64Error::InvalidByte(c, offset)),
+        };
+
+        n = n << 6 | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((n >> bits) as u8);
+        }
+    }
+
+    // A final group of one symbol can't hold a byte; two or three leave 4 or 2 spare
+    // bits, which must be matched by the padding, if any.
+    let spare_symbols = match bits {
+        0 => 0,
+        4 => 2,
+        2 => 1,
+        _ => return Err(Base64Error::InvalidLength),
+    };
+    if padding != 0 && padding != spare_symbols {
+        return Err(Base64Error::InvalidPadding);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc4648_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (plain, encoded) in vectors {
+            assert_eq!(encode(plain.as_bytes()), encoded);
+            assert_eq!(decode(encoded).unwrap(), plain.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn test_lenient_input() {
+        assert_eq!(decode("Zm9v\nYmE").unwrap(), b"fooba");
+        assert_eq!(decode("  Zg\r\n").unwrap(), b"f");
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert_eq!(decode("Zm9v!"), Err(Base64Error::InvalidByte('!', 4)));
+        assert_eq!(decode("Zm9vY"), Err(Base64Error::InvalidLength));
+        assert_eq!(decode("Zg=a"), Err(Base64Error::InvalidPadding));
+        assert_eq!(decode("Zm8=="), Err(Base64Error::InvalidPadding));
+    }
+}
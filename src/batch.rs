@@ -0,0 +1,340 @@
+//! Running a command over several images: every image in directories, or
+//! every file matching a pattern.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::{bail, Context, Result};
+
+use png_secret::glob::PathPattern;
+
+use crate::args::Batch;
+use crate::output::{self, Held};
+use crate::progress::Progress;
+
+/// The images `file` and the rest of `batch` stand for: a directory gives
+/// the PNG files directly inside it, and a pattern such as `imgs/**/*.png`
+/// whatever files match it, both in name order. `None` for a single image,
+/// which the command handles as it always has.
+pub fn files(file: &Path, batch: &Batch) -> Result<Option<Vec<PathBuf>>> {
+    if batch.more.is_empty()
+        && batch.exclude.is_empty()
+        && !file.is_dir()
+        && pattern(file).is_none()
+    {
+        return Ok(None);
+    }
+    gather(file, batch, false).map(Some)
+}
+
+/// Like `files`, but going down into every directory below the ones given,
+/// and for a single image too.
+pub fn files_recursive(file: &Path, batch: &Batch) -> Result<Vec<PathBuf>> {
+    gather(file, batch, true)
+}
+
+fn gather(file: &Path, batch: &Batch, recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in std::iter::once(file).chain(batch.more.iter().map(PathBuf::as_path)) {
+        if path == Path::new("-") {
+            bail!("stdin can't be read as one of several images");
+        }
+        let found = if path.is_dir() {
+            images_in(path, recursive)?
+        } else if let Some(pattern) = pattern(path) {
+            expand(&pattern)?
+        } else {
+            vec![path.to_path_buf()]
+        };
+        for found in found {
+            if !files.contains(&found) && !is_excluded(&found, &batch.exclude) {
+                files.push(found);
+            }
+        }
+    }
+    if files.is_empty() {
+        bail!("no images to run on");
+    }
+    Ok(files)
+}
+
+/// The PNG files directly inside `dir`, or anywhere below it.
+fn images_in(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    if recursive {
+        let mut all = Vec::new();
+        walk(dir, &dir.to_string_lossy(), None, &mut all)?;
+        found.extend(
+            all.into_iter()
+                .map(PathBuf::from)
+                .filter(|path| is_png_name(path)),
+        );
+    } else {
+        for entry in
+            fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?
+        {
+            let entry = entry?.path();
+            if entry.is_file() && is_png_name(&entry) {
+                found.push(entry);
+            }
+        }
+    }
+    if found.is_empty() {
+        bail!("no PNG files in {}", dir.display());
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// `path` as a pattern, if it is one rather than a file that exists. The
+/// shell leaves patterns to expand on Windows, or when they are quoted.
+fn pattern(path: &Path) -> Option<PathPattern> {
+    let pattern = PathPattern::new(path.to_str()?).ok()?;
+    (!pattern.is_literal() && !path.exists()).then_some(pattern)
+}
+
+/// The files matching `pattern`, searched for from its base directory.
+fn expand(pattern: &PathPattern) -> Result<Vec<PathBuf>> {
+    let base = pattern.base();
+    let dir = if base.is_empty() { "." } else { base };
+    if !Path::new(dir).is_dir() {
+        bail!("no files match {pattern}, there is no directory {dir}");
+    }
+    let mut found = Vec::new();
+    walk(Path::new(dir), base, pattern.depth(), &mut found)?;
+    found.retain(|path| pattern.matches(path));
+    if found.is_empty() {
+        bail!("no files match {pattern}");
+    }
+    found.sort();
+    Ok(found.into_iter().map(PathBuf::from).collect())
+}
+
+/// Gathers the files under `dir`, written `prefix`, going at most `depth`
+/// directories further down. Links to directories are not followed.
+fn walk(dir: &Path, prefix: &str, depth: Option<usize>, found: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        let path = match prefix {
+            "" => name,
+            "/" => format!("/{name}"),
+            prefix => format!("{prefix}/{name}"),
+        };
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if depth != Some(0) {
+                walk(&entry.path(), &path, depth.map(|depth| depth - 1), found)?;
+            }
+        } else if entry.path().is_file() {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Whether an `--exclude` pattern matches `path`: its file name for a
+/// pattern without a `/`, otherwise the whole path.
+pub fn is_excluded(path: &Path, exclude: &[PathPattern]) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let components: Vec<String> = path
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .map(|component| match component {
+            Component::RootDir => String::new(),
+            component => component.as_os_str().to_string_lossy().into_owned(),
+        })
+        .collect();
+    let whole = components.join("/");
+    exclude.iter().any(|pattern| {
+        if pattern.as_str().contains('/') {
+            pattern.matches(&whole)
+        } else {
+            pattern.matches(&name)
+        }
+    })
+}
+
+/// Whether `path` ends in `.png`, in any case.
+pub fn is_png_name(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+/// Runs `work` on every one of `files` in `jobs` threads, handing each
+/// result to `done` on this thread in the order of `files`, as soon as it
+/// and those before it are ready. A single job runs them all on this thread.
+pub fn for_each<T: Send>(
+    files: &[PathBuf],
+    jobs: usize,
+    work: impl Fn(usize, &Path) -> T + Sync,
+    mut done: impl FnMut(&Path, T),
+) {
+    if jobs <= 1 {
+        for (i, file) in files.iter().enumerate() {
+            done(file, work(i, file));
+        }
+        return;
+    }
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(files.len()) {
+            let sender = sender.clone();
+            let (next, work) = (&next, &work);
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(i) else {
+                    break;
+                };
+                if sender.send((i, work(i, file))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Results that came in before those of earlier files.
+        let mut waiting: Vec<Option<T>> = files.iter().map(|_| None).collect();
+        let mut first = 0;
+        for (i, result) in receiver {
+            waiting[i] = Some(result);
+            while let Some(result) = waiting.get_mut(first).and_then(Option::take) {
+                done(&files[first], result);
+                first += 1;
+            }
+        }
+    });
+}
+
+/// Runs `command` on every one of `files`, as many at a time as `batch`
+/// says, carrying on past failures, and sums up how it went. Each file's
+/// output is headed with its name unless `labelled` says the command names
+/// the file itself. With more than one job, or a progress line, it is held
+/// back until the file is done, so it comes out in the same order and
+/// doesn't run into the line.
+pub fn run(
+    files: &[PathBuf],
+    batch: &Batch,
+    labelled: bool,
+    command: impl Fn(&Path) -> Result<()> + Sync,
+) -> Result<()> {
+    let mut progress = Progress::new(files.len(), batch.quiet);
+    let hold = batch.jobs > 1 || progress.is_shown();
+    // Set once output can't be written, after which the images left are
+    // not run on.
+    let stopped = AtomicBool::new(false);
+    let work = |i: usize, file: &Path| {
+        if stopped.load(Ordering::Relaxed) {
+            return (Ok(()), Held::default());
+        }
+        let run = || {
+            if !labelled {
+                if i > 0 {
+                    outln!()?;
+                }
+                outln!("{}:", file.display())?;
+            }
+            command(file)
+        };
+        if hold {
+            output::hold(run)
+        } else {
+            (run(), Held::default())
+        }
+    };
+    let mut failed = Vec::new();
+    let mut output_error = None;
+    for_each(files, batch.jobs, work, |file, (result, held)| {
+        if output_error.is_some() {
+            return;
+        }
+        progress.advance(&file.display().to_string());
+        let printed = progress.suspend(|| -> Result<()> {
+            output::print_held(&held)?;
+            match result {
+                Err(err) if output::is_broken_pipe(&err) => return Err(err),
+                Err(err) => {
+                    eoutln!("Error: {}: {err:#}", file.display())?;
+                    failed.push(file.display().to_string());
+                }
+                Ok(()) => {}
+            }
+            Ok(())
+        });
+        if let Err(err) = printed {
+            stopped.store(true, Ordering::Relaxed);
+            output_error = Some(err);
+        }
+    });
+    drop(progress);
+    if let Some(err) = output_error {
+        return Err(err);
+    }
+    if failed.is_empty() {
+        eoutln!("All {} files done", files.len())?;
+        return Ok(());
+    }
+    bail!(
+        "{} of {} files failed: {}",
+        failed.len(),
+        files.len(),
+        failed.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_png_name() {
+        assert!(is_png_name(Path::new("dir/a.png")));
+        assert!(is_png_name(Path::new("B.PNG")));
+        assert!(!is_png_name(Path::new("a.png.txt")));
+        assert!(!is_png_name(Path::new("png")));
+    }
+
+    #[test]
+    fn test_is_excluded() {
+        let exclude = ["*.bak.png".parse().unwrap(), "thumbs/**".parse().unwrap()];
+        assert!(is_excluded(Path::new("imgs/a.bak.png"), &exclude));
+        assert!(is_excluded(Path::new("./thumbs/x/a.png"), &exclude));
+        assert!(!is_excluded(Path::new("imgs/thumbs.png"), &exclude));
+        assert!(!is_excluded(Path::new("imgs/a.png"), &[]));
+    }
+
+    #[test]
+    fn test_for_each() {
+        let files: Vec<PathBuf> = (0..20).map(|i| PathBuf::from(format!("{i}.png"))).collect();
+        for jobs in [1, 4] {
+            let mut seen = Vec::new();
+            for_each(
+                &files,
+                jobs,
+                |i, file| {
+                    // Later files finish first.
+                    thread::sleep(std::time::Duration::from_millis(20 - i as u64));
+                    file.display().to_string()
+                },
+                |file, name| {
+                    assert_eq!(file.display().to_string(), name);
+                    seen.push(name);
+                },
+            );
+            let names: Vec<String> = files
+                .iter()
+                .map(|file| file.display().to_string())
+                .collect();
+            assert_eq!(seen, names);
+        }
+    }
+}
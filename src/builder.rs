@@ -0,0 +1,426 @@
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr, IhdrError};
+use crate::palette::{Palette, Rgb};
+use crate::png::Png;
+use crate::rng::Rng;
+use crate::zlib;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum BuilderError {
+    #[error("BuilderError invalid header")]
+    InvalidIhdr(#[from] IhdrError),
+    #[error("BuilderError image data would be {0} bytes, at most {1} are allowed")]
+    TooLarge(u64, u64),
+}
+
+/// What the pixels of a built image look like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fill {
+    /// Every pixel the builder's color.
+    Solid,
+    /// Smooth blotches with fine grain on top, loosely like a noisy photo,
+    /// the same for the same seed. Indexed images get a gray ramp palette.
+    Noise(u64),
+}
+
+/// Builds a minimal PNG from scratch: IHDR, a single IDAT and IEND, plus PLTE
+/// and tRNS for an indexed image. Any size, color type and bit depth the spec
+/// allows will do; alpha is dropped by color types without it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PngBuilder {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    color: Rgb,
+    alpha: u8,
+    fill: Fill,
+}
+
+impl PngBuilder {
+    /// The most decompressed image data `build` will produce.
+    pub const MAX_DATA_LEN: u64 = 1 << 28;
+
+    /// An opaque white 8-bit RGB image of `width` by `height`.
+    pub fn new(width: u32, height: u32) -> Self {
+        PngBuilder {
+            width,
+            height,
+            bit_depth: 8,
+            color_type: ColorType::Rgb,
+            color: Rgb::new(255, 255, 255),
+            alpha: 255,
+            fill: Fill::Solid,
+        }
+    }
+
+    pub fn with_color_type(mut self, color_type: ColorType) -> Self {
+        self.color_type = color_type;
+        self
+    }
+
+    pub fn with_bit_depth(mut self, bit_depth: u8) -> Self {
+        self.bit_depth = bit_depth;
+        self
+    }
+
+    pub fn with_color(mut self, color: Rgb) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_alpha(mut self, alpha: u8) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn with_fill(mut self, fill: Fill) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    pub fn ihdr(&self) -> Result<Ihdr, BuilderError> {
+        Ok(Ihdr::new(
+            self.width,
+            self.height,
+            self.bit_depth,
+            self.color_type,
+        )?)
+    }
+
+    pub fn build(&self) -> Result<Png, BuilderError> {
+        let ihdr = self.ihdr()?;
+        if ihdr.raw_data_len() > Self::MAX_DATA_LEN {
+            return Err(BuilderError::TooLarge(
+                ihdr.raw_data_len(),
+                Self::MAX_DATA_LEN,
+            ));
+        }
+
+        // Each row goes behind a filter type byte of 0 (none).
+        let mut data = Vec::with_capacity(ihdr.raw_data_len() as usize);
+        match self.fill {
+            Fill::Solid => {
+                let row = self.samples().repeat(self.width as usize);
+                let packed = pack_row(&ihdr, &row);
+                for _ in 0..self.height {
+                    data.push(0);
+                    data.extend_from_slice(&packed);
+                }
+            }
+            Fill::Noise(seed) => {
+                let noise = Noise::new(&ihdr, seed);
+                for y in 0..self.height {
+                    data.push(0);
+                    data.extend(pack_row(&ihdr, &noise.row(y, self.alpha)));
+                }
+            }
+        }
+
+        let mut chunks = vec![ihdr.to_chunk()];
+        if let Some(palette) = self.palette() {
+            chunks.push(palette.plte_chunk());
+            chunks.extend(palette.trns_chunk());
+        }
+        chunks.push(Chunk::new(
+            ChunkType::IDAT,
+            zlib::compress(&data, zlib::DEFAULT_LEVEL),
+        ));
+        chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+        Ok(Png::from_chunks(chunks))
+    }
+
+    fn palette(&self) -> Option<Palette> {
+        if self.color_type != ColorType::Indexed {
+            return None;
+        }
+        let mut palette = match self.fill {
+            Fill::Solid => Palette::new(vec![self.color]),
+            Fill::Noise(_) => {
+                let last = (1u32 << self.bit_depth) - 1;
+                let ramp = (0..=last)
+                    .map(|i| {
+                        let gray = (i * 255 / last) as u8;
+                        Rgb::new(gray, gray, gray)
+                    })
+                    .collect();
+                Palette::new(ramp)
+            }
+        }
+        .expect("at most 256 colors");
+        for index in 0..palette.len() {
+            palette
+                .set_alpha(index, self.alpha)
+                .expect("index in range");
+        }
+        Some(palette)
+    }
+
+    /// The samples of one solid pixel at 16 bits, cut down to the bit depth when
+    /// packed.
+    fn samples(&self) -> Vec<u16> {
+        let Rgb { r, g, b } = self.color;
+        let alpha = widen(self.alpha);
+        // ITU-R BT.601 luma, as most tools convert to grayscale.
+        let luma = ((u32::from(r) * 299 + u32::from(g) * 587 + u32::from(b) * 114) / 1000) as u8;
+        match self.color_type {
+            ColorType::Grayscale => vec![widen(luma)],
+            ColorType::GrayscaleAlpha => vec![widen(luma), alpha],
+            ColorType::Rgb => vec![widen(r), widen(g), widen(b)],
+            ColorType::Rgba => vec![widen(r), widen(g), widen(b), alpha],
+            ColorType::Indexed => vec![0],
+        }
+    }
+}
+
+/// An 8-bit sample at 16 bits, so 255 becomes 65535.
+fn widen(sample: u8) -> u16 {
+    u16::from(sample) * 257
+}
+
+/// The pixels of `Fill::Noise`: a coarse grid of random levels per channel,
+/// smoothly interpolated, plus grain that differs from pixel to pixel.
+struct Noise {
+    ihdr: Ihdr,
+    cell: f64,
+    grid_width: usize,
+    /// One grid of levels in `[0, 1)` for the brightness and one per color
+    /// channel for tint, each `grid_width` wide.
+    grids: Vec<Vec<f64>>,
+    seed: u64,
+}
+
+impl Noise {
+    /// The share of a sample the tint makes up in color images.
+    const TINT: f64 = 0.3;
+    /// How far grain moves a sample, before clamping.
+    const GRAIN: f64 = 0.08;
+
+    fn new(ihdr: &Ihdr, seed: u64) -> Self {
+        let cell = f64::from(ihdr.width.max(ihdr.height) / 6).max(4.0);
+        let grid_width = (f64::from(ihdr.width) / cell) as usize + 2;
+        let grid_height = (f64::from(ihdr.height) / cell) as usize + 2;
+        let mut rng = Rng::new(seed);
+        let grids = (0..4)
+            .map(|_| {
+                (0..grid_width * grid_height)
+                    .map(|_| rng.next_f64())
+                    .collect()
+            })
+            .collect();
+        Noise {
+            ihdr: *ihdr,
+            cell,
+            grid_width,
+            grids,
+            seed,
+        }
+    }
+
+    /// The samples of row `y` at 16 bits, or palette indices for an indexed image.
+    fn row(&self, y: u32, alpha: u8) -> Vec<u16> {
+        // Grain comes from its own stream per row so rows can be made in any order.
+        let mut grain = Rng::new(self.seed ^ u64::from(y).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let mut grain = || (0..4).map(|_| grain.next_f64()).sum::<f64>() / 2.0 - 1.0;
+        let color_type = self.ihdr.color_type;
+        let mut row = Vec::with_capacity(self.ihdr.width as usize * 4);
+        for x in 0..self.ihdr.width {
+            let luma = self.level(0, x, y);
+            match color_type {
+                ColorType::Rgb | ColorType::Rgba => {
+                    for channel in 1..=3 {
+                        let tint = self.level(channel, x, y);
+                        let level = luma * (1.0 - Self::TINT) + tint * Self::TINT;
+                        row.push(to_sample(level + grain() * Self::GRAIN));
+                    }
+                }
+                ColorType::Grayscale | ColorType::GrayscaleAlpha => {
+                    row.push(to_sample(luma + grain() * Self::GRAIN));
+                }
+                ColorType::Indexed => {
+                    let last = f64::from((1u16 << self.ihdr.bit_depth) - 1);
+                    let level = (luma + grain() * Self::GRAIN).clamp(0.0, 1.0);
+                    row.push((level * last).round() as u16);
+                }
+            }
+            if color_type.has_alpha() {
+                row.push(widen(alpha));
+            }
+        }
+        row
+    }
+
+    /// The level of `grid` at a pixel, eased between the four grid points around it.
+    fn level(&self, grid: usize, x: u32, y: u32) -> f64 {
+        let (gx, gy) = (f64::from(x) / self.cell, f64::from(y) / self.cell);
+        let (x0, y0) = (gx as usize, gy as usize);
+        let ease = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (tx, ty) = (ease(gx.fract()), ease(gy.fract()));
+        let at = |x: usize, y: usize| self.grids[grid][y * self.grid_width + x];
+        let top = at(x0, y0) * (1.0 - tx) + at(x0 + 1, y0) * tx;
+        let bottom = at(x0, y0 + 1) * (1.0 - tx) + at(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+fn to_sample(level: f64) -> u16 {
+    (level.clamp(0.0, 1.0) * f64::from(u16::MAX)).round() as u16
+}
+
+/// Packs a row of 16-bit samples at the image's bit depth: the top bits of each,
+/// most significant first for depths below 8 and big-endian for 16. Palette
+/// indices are packed as they are.
+fn pack_row(ihdr: &Ihdr, samples: &[u16]) -> Vec<u8> {
+    let depth = ihdr.bit_depth;
+    let indexed = ihdr.color_type == ColorType::Indexed;
+    let mut row = Vec::with_capacity(ihdr.row_bytes() as usize);
+    let (mut bits, mut filled) = (0u8, 0u8);
+    for &sample in samples {
+        match depth {
+            16 => row.extend_from_slice(&sample.to_be_bytes()),
+            8 if indexed => row.push(sample as u8),
+            8 => row.push((sample >> 8) as u8),
+            _ => {
+                let value = if indexed {
+                    sample as u8
+                } else {
+                    (sample >> (16 - depth)) as u8
+                };
+                bits |= value << (8 - depth - filled);
+                filled += depth;
+                if filled == 8 {
+                    row.push(bits);
+                    (bits, filled) = (0, 0);
+                }
+            }
+        }
+    }
+    if filled > 0 {
+        row.push(bits);
+    }
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify;
+
+    #[test]
+    fn test_build_default() {
+        let png = PngBuilder::new(3, 2).build().unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IEND"]);
+        assert!(verify::verify(&png.as_bytes()).is_empty());
+
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!((ihdr.width, ihdr.height), (3, 2));
+        let idat = png.chunk_by_type(ChunkType::IDAT).unwrap();
+        let data = zlib::decompress(idat.data()).unwrap();
+        assert_eq!(
+            data,
+            [[0, 255, 255, 255, 255, 255, 255, 255, 255, 255]; 2].concat()
+        );
+    }
+
+    #[test]
+    fn test_build_formats() {
+        let builder = PngBuilder::new(5, 1).with_color(Rgb::new(255, 0, 0));
+        let cases: [(ColorType, u8, &[u8]); 4] = [
+            (ColorType::Grayscale, 1, &[0, 0b0000_0000]),
+            (ColorType::Grayscale, 2, &[0, 0b0101_0101, 0b0100_0000]),
+            (ColorType::Rgba, 16, &[0, 255, 255, 0, 0, 0, 0, 255, 255]),
+            (ColorType::Indexed, 4, &[0, 0, 0, 0]),
+        ];
+        for (color_type, bit_depth, start) in cases {
+            let png = builder
+                .clone()
+                .with_color_type(color_type)
+                .with_bit_depth(bit_depth)
+                .build()
+                .unwrap();
+            assert!(verify::verify(&png.as_bytes()).is_empty());
+            let ihdr = png.ihdr().unwrap();
+            let data =
+                zlib::decompress(png.chunk_by_type(ChunkType::IDAT).unwrap().data()).unwrap();
+            assert_eq!(data.len() as u64, ihdr.raw_data_len());
+            assert!(
+                data.starts_with(start),
+                "{color_type} at {bit_depth}: {data:?}"
+            );
+        }
+
+        let png = builder
+            .with_color_type(ColorType::Indexed)
+            .with_alpha(0)
+            .build()
+            .unwrap();
+        let palette = png.palette().unwrap().unwrap();
+        assert_eq!(palette.colors(), [Rgb::new(255, 0, 0)]);
+        assert_eq!(palette.alpha(0), Some(0));
+    }
+
+    #[test]
+    fn test_build_noise() {
+        let builder = PngBuilder::new(40, 30).with_fill(Fill::Noise(9));
+        let image_data = |png: &Png| {
+            zlib::decompress(png.chunk_by_type(ChunkType::IDAT).unwrap().data()).unwrap()
+        };
+        let first = image_data(&builder.build().unwrap());
+        assert_eq!(first, image_data(&builder.build().unwrap()));
+        let other = builder.clone().with_fill(Fill::Noise(10)).build().unwrap();
+        assert_ne!(first, image_data(&other));
+
+        // Noise should leave few repeats for deflate to find, unlike a solid fill.
+        let distinct = first.iter().collect::<std::collections::HashSet<_>>().len();
+        assert!(distinct > 64, "{distinct} distinct bytes");
+
+        for (color_type, bit_depth) in [
+            (ColorType::Rgb, 16),
+            (ColorType::GrayscaleAlpha, 8),
+            (ColorType::Grayscale, 2),
+            (ColorType::Indexed, 4),
+        ] {
+            let png = builder
+                .clone()
+                .with_color_type(color_type)
+                .with_bit_depth(bit_depth)
+                .build()
+                .unwrap();
+            assert!(verify::verify(&png.as_bytes()).is_empty());
+            assert_eq!(
+                image_data(&png).len() as u64,
+                png.ihdr().unwrap().raw_data_len()
+            );
+        }
+        let png = builder
+            .with_color_type(ColorType::Indexed)
+            .with_bit_depth(2)
+            .build()
+            .unwrap();
+        assert_eq!(png.palette().unwrap().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_build_invalid() {
+        let error = PngBuilder::new(0, 1).build().unwrap_err();
+        assert_eq!(error, BuilderError::InvalidIhdr(IhdrError::ZeroDimension));
+        let error = PngBuilder::new(1, 1).with_bit_depth(4).build().unwrap_err();
+        assert!(matches!(
+            error,
+            BuilderError::InvalidIhdr(IhdrError::InvalidBitDepth(4, _))
+        ));
+        let error = PngBuilder::new(1 << 16, 1 << 16).build().unwrap_err();
+        assert!(matches!(
+            error,
+            BuilderError::TooLarge(_, PngBuilder::MAX_DATA_LEN)
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+//! How much payload a carrier takes with each way of hiding it.
+
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::lsb::Channels;
+use crate::payload;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CapacityError {
+    #[error("CapacityError unknown method {0}, expected chunk, lsb or alpha")]
+    UnknownMethod(String),
+}
+
+/// Where encode puts a payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Ancillary chunks, split into segments when large.
+    Chunk,
+    /// The low bits of every pixel sample.
+    Lsb,
+    /// The low bits of alpha samples only, as `--lsb --channels alpha`.
+    Alpha,
+}
+
+impl Method {
+    pub const ALL: [Method; 3] = [Method::Chunk, Method::Lsb, Method::Alpha];
+
+    /// The samples an LSB method writes to, or `None` for chunks.
+    pub fn channels(self) -> Option<Channels> {
+        match self {
+            Method::Chunk => None,
+            Method::Lsb => Some(Channels::All),
+            Method::Alpha => Some(Channels::Alpha),
+        }
+    }
+}
+
+impl FromStr for Method {
+    type Err = CapacityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chunk" => Ok(Method::Chunk),
+            "lsb" => Ok(Method::Lsb),
+            "alpha" => Ok(Method::Alpha),
+            _ => Err(CapacityError::UnknownMethod(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Method::Chunk => "chunk",
+            Method::Lsb => "lsb",
+            Method::Alpha => "alpha",
+        })
+    }
+}
+
+/// Capacities beyond this are more than any image sensibly holds, and are
+/// better reported as no practical limit than as a number.
+pub const PRACTICAL_LIMIT: u64 = 1 << 30;
+
+/// The largest payload `payload::split` can store in segments of
+/// `max_segment_len` bytes: as many as the u16 segment count allows, each no
+/// larger than a chunk can be.
+pub fn chunk_capacity(max_segment_len: usize) -> u64 {
+    let max_segment = payload::MAX_SEGMENT_LEN.min(max_segment_len);
+    u64::from(u16::MAX) * max_segment as u64
+}
+
+/// The file growth of storing a payload of `len` bytes in chunks of at most
+/// `max_segment_len` bytes: the payload and each chunk's length, type, crc and
+/// segment header.
+pub fn chunk_cost(len: usize, max_segment_len: usize) -> u64 {
+    const CHUNK_OVERHEAD: u64 = 12;
+    if len <= max_segment_len {
+        return len as u64 + CHUNK_OVERHEAD;
+    }
+    let segments = len.div_ceil(max_segment_len.max(1)) as u64;
+    len as u64 + segments * (CHUNK_OVERHEAD + payload::SEGMENT_HEADER_LEN as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    #[test]
+    fn test_method() {
+        for method in Method::ALL {
+            assert_eq!(method.to_string().parse::<Method>(), Ok(method));
+        }
+        assert_eq!(Method::Alpha.channels(), Some(Channels::Alpha));
+        assert_eq!(Method::Chunk.channels(), None);
+        assert_eq!(
+            "idat".parse::<Method>(),
+            Err(CapacityError::UnknownMethod("idat".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_chunk_capacity() {
+        assert_eq!(chunk_capacity(10), 655_350);
+        assert_eq!(chunk_capacity(usize::MAX), 65_535 * ((1 << 31) - 9));
+        // Only a small --max-chunk-size gives a figure worth showing.
+        assert!(chunk_capacity(payload::DEFAULT_SEGMENT_LEN) > PRACTICAL_LIMIT);
+        assert!(chunk_capacity(10) <= PRACTICAL_LIMIT);
+
+        let chunk_type = ChunkType::new(*b"ruSt");
+        for (len, max) in [(5, 10), (10, 10), (25, 10), (1, 1)] {
+            let chunks = payload::split(&chunk_type, &vec![0; len], max).unwrap();
+            let bytes: u64 = chunks.iter().map(|chunk| chunk.length() as u64 + 12).sum();
+            assert_eq!(chunk_cost(len, max), bytes, "{len} in {max}");
+        }
+    }
+}
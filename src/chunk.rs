@@ -1,8 +1,8 @@
 use std::convert::TryFrom;
 use std::fmt;
-use std::io::{BufReader, Read};
+use std::io::{self, BufReader, Read, Write};
 
-use crc::CRC_32_ISO_HDLC;
+use crc::{Crc, CRC_32_ISO_HDLC};
 use thiserror::Error;
 
 use crate::chunk_type::{ChunkType, ChunkTypeError};
@@ -13,6 +13,8 @@ pub enum ChunkError {
     ReadError(#[from] std::io::Error),
     #[error("ChunkError length is too long")]
     MaxLengthError,
+    #[error("ChunkError length {0} exceeds the configured maximum of {1}")]
+    LengthLimitExceeded(u32, u32),
     #[error("ChunkError invalid chunk type")]
     InvalidChunkType(#[from] ChunkTypeError),
     #[error("ChunkError invalid chunk Data (len {0}) is the wrong length (expected {1})")]
@@ -21,6 +23,51 @@ pub enum ChunkError {
     InvalidCrc,
 }
 
+#[cfg(not(feature = "fast-crc"))]
+type CrcImpl = crc::Table<1>;
+/// Slice-by-16 lookup: a 16 KiB table in exchange for much faster checksums of large chunks.
+#[cfg(feature = "fast-crc")]
+type CrcImpl = crc::Table<16>;
+
+static CHUNK_CRC: Crc<u32, CrcImpl> = Crc::<u32, CrcImpl>::new(&CRC_32_ISO_HDLC);
+
+/// The crc of a chunk covers its type and data, but not its length.
+pub(crate) fn checksum(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    let mut digest = CHUNK_CRC.digest();
+    digest.update(&chunk_type.bytes());
+    digest.update(data);
+    digest.finalize()
+}
+
+/// Controls how strictly chunks are parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Reject chunks whose stored crc does not match their contents.
+    /// When disabled the stored crc is kept as-is; see `Chunk::computed_crc`.
+    pub verify_crc: bool,
+    /// Largest data length accepted before giving up on a chunk, to bound memory use
+    /// on untrusted input.
+    pub max_chunk_len: u32,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            verify_crc: true,
+            max_chunk_len: Chunk::MAX_LENGTH,
+        }
+    }
+}
+
+impl ParseOptions {
+    pub fn lenient() -> Self {
+        Self {
+            verify_crc: false,
+            ..Self::default()
+        }
+    }
+}
+
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
 #[derive(Debug, Clone)]
 pub struct Chunk {
@@ -31,10 +78,12 @@ pub struct Chunk {
 }
 
 impl Chunk {
+    /// The spec limits chunk data to 2^31 - 1 bytes.
+    pub const MAX_LENGTH: u32 = (1 << 31) - 1;
+
     pub fn new(chunk_type: ChunkType, chunk_data: Vec<u8>) -> Self {
         let length = chunk_data.len() as u32;
-        let crc = crc::Crc::<u32>::new(&CRC_32_ISO_HDLC)
-            .checksum(&[&chunk_type.bytes(), chunk_data.as_slice()].concat());
+        let crc = checksum(&chunk_type, &chunk_data);
         Self {
             length,
             chunk_type,
@@ -43,6 +92,16 @@ impl Chunk {
         }
     }
 
+    /// A chunk keeping `crc` as its stored crc, even if it does not match.
+    pub(crate) fn with_crc(chunk_type: ChunkType, chunk_data: Vec<u8>, crc: u32) -> Self {
+        Self {
+            length: chunk_data.len() as u32,
+            chunk_type,
+            chunk_data,
+            crc,
+        }
+    }
+
     pub fn length(&self) -> u32 {
         self.length
     }
@@ -59,41 +118,131 @@ impl Chunk {
         self.crc
     }
 
+    /// The crc this chunk should have, which differs from `crc()` only for chunks parsed leniently.
+    pub fn computed_crc(&self) -> u32 {
+        checksum(&self.chunk_type, &self.chunk_data)
+    }
+
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc == self.computed_crc()
+    }
+
+    /// Replaces the stored crc with the computed one, returning whether it changed.
+    pub fn recompute_crc(&mut self) -> bool {
+        let computed = self.computed_crc();
+        let changed = self.crc != computed;
+        self.crc = computed;
+        changed
+    }
+
+    /// Replaces the data, updating the length and crc to match. A stale crc on a
+    /// leniently parsed chunk is replaced too.
+    pub fn set_data(&mut self, data: Vec<u8>) -> Result<(), ChunkError> {
+        self.length = Self::checked_length(data.len())?;
+        self.chunk_data = data;
+        self.crc = self.computed_crc();
+        Ok(())
+    }
+
+    /// Adds `data` to the end of the chunk's data, updating the length and crc.
+    pub fn append_data(&mut self, data: &[u8]) -> Result<(), ChunkError> {
+        self.length = Self::checked_length(self.chunk_data.len() + data.len())?;
+        self.chunk_data.extend_from_slice(data);
+        self.crc = self.computed_crc();
+        Ok(())
+    }
+
+    /// Changes the type, which the crc covers, so it is recomputed.
+    pub fn set_type(&mut self, chunk_type: ChunkType) {
+        self.chunk_type = chunk_type;
+        self.crc = self.computed_crc();
+    }
+
+    fn checked_length(len: usize) -> Result<u32, ChunkError> {
+        u32::try_from(len)
+            .ok()
+            .filter(|&length| length <= Self::MAX_LENGTH)
+            .ok_or(ChunkError::MaxLengthError)
+    }
+
+    /// Moves the data out without copying it.
+    pub fn into_data(self) -> Vec<u8> {
+        self.chunk_data
+    }
+
+    /// Splits the chunk into its type and owned data; the length and crc can be
+    /// recomputed from them with `Chunk::new`.
+    pub fn into_parts(self) -> (ChunkType, Vec<u8>) {
+        (self.chunk_type, self.chunk_data)
+    }
+
     pub fn data_as_string(&self) -> anyhow::Result<String> {
         Ok(String::from_utf8(self.chunk_data.clone())?)
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
-        self.length
-            .to_be_bytes()
-            .iter()
-            .chain(self.chunk_type.bytes().iter())
-            .chain(self.chunk_data.iter())
-            .chain(self.crc.to_be_bytes().iter())
-            .copied()
-            .collect()
+        let mut bytes = Vec::with_capacity(12 + self.chunk_data.len());
+        self.write_to(&mut bytes)
+            .expect("writing to a Vec<u8> cannot fail");
+        bytes
     }
-}
 
-impl TryFrom<&[u8]> for Chunk {
-    type Error = ChunkError;
+    /// Writes the length, type, data and crc of this chunk straight into `writer`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.length.to_be_bytes())?;
+        writer.write_all(&self.chunk_type.bytes())?;
+        writer.write_all(&self.chunk_data)?;
+        writer.write_all(&self.crc.to_be_bytes())?;
+        Ok(())
+    }
+}
 
-    fn try_from(bytes: &[u8]) -> Result<Self, ChunkError> {
-        let mut reader = BufReader::new(bytes);
+impl Chunk {
+    /// Reads a single chunk from `reader`, leaving it positioned at the next chunk.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, ChunkError> {
+        Self::read_from_with(reader, &ParseOptions::default())
+    }
 
+    pub fn read_from_with<R: Read>(
+        reader: &mut R,
+        options: &ParseOptions,
+    ) -> Result<Self, ChunkError> {
         let mut buf: [u8; 4] = [0; 4];
-
         reader.read_exact(&mut buf)?;
-        let length = u32::from_be_bytes(buf);
-        if length > u32::MAX {
+        Self::read_with_length(u32::from_be_bytes(buf), reader, options)
+    }
+
+    pub fn parse_with(bytes: &[u8], options: &ParseOptions) -> Result<Self, ChunkError> {
+        let mut reader = BufReader::new(bytes);
+        Self::read_from_with(&mut reader, options)
+    }
+
+    /// Reads the remainder of a chunk whose length field has already been consumed.
+    pub(crate) fn read_with_length<R: Read>(
+        length: u32,
+        reader: &mut R,
+        options: &ParseOptions,
+    ) -> Result<Self, ChunkError> {
+        if length > Self::MAX_LENGTH {
             return Err(ChunkError::MaxLengthError);
         }
+        if length > options.max_chunk_len {
+            return Err(ChunkError::LengthLimitExceeded(
+                length,
+                options.max_chunk_len,
+            ));
+        }
 
+        let mut buf: [u8; 4] = [0; 4];
         reader.read_exact(&mut buf)?;
         let chunk_type = ChunkType::try_from(buf)?;
 
-        let mut chunk_data: Vec<u8> = vec![0; length as usize];
-        reader.read_exact(&mut chunk_data)?;
+        // Grow the buffer as data arrives rather than trusting the length up front.
+        let mut chunk_data: Vec<u8> = Vec::new();
+        reader
+            .by_ref()
+            .take(u64::from(length))
+            .read_to_end(&mut chunk_data)?;
         if chunk_data.len() != length as usize {
             return Err(ChunkError::InvalidChunkData(
                 chunk_data.len(),
@@ -103,9 +252,7 @@ impl TryFrom<&[u8]> for Chunk {
 
         reader.read_exact(&mut buf)?;
         let provided_crc = u32::from_be_bytes(buf);
-        let true_crc = crc::Crc::<u32>::new(&CRC_32_ISO_HDLC)
-            .checksum(&[&chunk_type.bytes(), chunk_data.as_slice()].concat());
-        if provided_crc != true_crc {
+        if options.verify_crc && provided_crc != checksum(&chunk_type, &chunk_data) {
             return Err(ChunkError::InvalidCrc);
         }
 
@@ -118,6 +265,14 @@ impl TryFrom<&[u8]> for Chunk {
     }
 }
 
+impl TryFrom<&[u8]> for Chunk {
+    type Error = ChunkError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, ChunkError> {
+        Self::parse_with(bytes, &ParseOptions::default())
+    }
+}
+
 impl fmt::Display for Chunk {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Chunk {{",)?;
@@ -239,6 +394,137 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_lenient_parse_keeps_bad_crc() {
+        let data_length: u32 = 42;
+        let chunk_type = "RuSt".as_bytes();
+        let message_bytes = "This is where your secret message will be!".as_bytes();
+        let crc: u32 = 2882656333;
+
+        let chunk_data: Vec<u8> = data_length
+            .to_be_bytes()
+            .iter()
+            .chain(chunk_type.iter())
+            .chain(message_bytes.iter())
+            .chain(crc.to_be_bytes().iter())
+            .copied()
+            .collect();
+
+        let chunk = Chunk::parse_with(chunk_data.as_ref(), &ParseOptions::lenient()).unwrap();
+        assert_eq!(chunk.crc(), 2882656333);
+        assert_eq!(chunk.computed_crc(), 2882656334);
+        assert!(!chunk.has_valid_crc());
+        assert_eq!(chunk.as_bytes(), chunk_data);
+
+        let strict = Chunk::parse_with(chunk_data.as_ref(), &ParseOptions::default());
+        assert!(matches!(strict, Err(ChunkError::InvalidCrc)));
+    }
+
+    #[test]
+    fn test_max_chunk_len() {
+        let chunk = testing_chunk();
+        let bytes = chunk.as_bytes();
+
+        let options = ParseOptions {
+            max_chunk_len: 41,
+            ..ParseOptions::default()
+        };
+        let result = Chunk::parse_with(&bytes, &options);
+        assert!(matches!(
+            result,
+            Err(ChunkError::LengthLimitExceeded(42, 41))
+        ));
+
+        let options = ParseOptions {
+            max_chunk_len: 42,
+            ..ParseOptions::default()
+        };
+        assert!(Chunk::parse_with(&bytes, &options).is_ok());
+    }
+
+    #[test]
+    fn test_forged_length() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            Chunk::try_from(bytes.as_ref()),
+            Err(ChunkError::MaxLengthError)
+        ));
+
+        bytes[..4].copy_from_slice(&Chunk::MAX_LENGTH.to_be_bytes());
+        assert!(matches!(
+            Chunk::try_from(bytes.as_ref()),
+            Err(ChunkError::InvalidChunkData(46, _))
+        ));
+    }
+
+    #[test]
+    fn test_recompute_crc() {
+        let mut chunk = testing_chunk();
+        assert!(!chunk.recompute_crc());
+
+        chunk.crc = 1;
+        assert!(chunk.recompute_crc());
+        assert_eq!(chunk.crc(), 2882656334);
+        assert!(chunk.has_valid_crc());
+    }
+
+    #[test]
+    fn test_crc_implementations_agree() {
+        let chunk_type = ChunkType::from_str("IDAT").unwrap();
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i * 31 % 251) as u8).collect();
+        let bytes = [&chunk_type.bytes(), data.as_slice()].concat();
+
+        let expected = Crc::<u32, crc::Table<1>>::new(&CRC_32_ISO_HDLC).checksum(&bytes);
+        let sliced = Crc::<u32, crc::Table<16>>::new(&CRC_32_ISO_HDLC).checksum(&bytes);
+        assert_eq!(checksum(&chunk_type, &data), expected);
+        assert_eq!(sliced, expected);
+    }
+
+    #[test]
+    fn test_mutation_keeps_length_and_crc() {
+        let mut chunk = testing_chunk();
+        chunk.set_data(b"short".to_vec()).unwrap();
+        assert_eq!(chunk.length(), 5);
+        assert!(chunk.has_valid_crc());
+
+        chunk.append_data(b" and longer").unwrap();
+        assert_eq!(chunk.data(), b"short and longer");
+        assert_eq!(chunk.length(), 16);
+        assert_eq!(
+            chunk.crc(),
+            Chunk::new(*chunk.chunk_type(), chunk.data().to_vec()).crc()
+        );
+
+        chunk.set_type(ChunkType::from_str("ruSt").unwrap());
+        assert_eq!(chunk.chunk_type().to_string(), "ruSt");
+        assert!(chunk.has_valid_crc());
+        assert_eq!(
+            Chunk::try_from(chunk.as_bytes().as_ref()).unwrap().data(),
+            chunk.data()
+        );
+    }
+
+    #[test]
+    fn test_into_parts() {
+        let chunk = testing_chunk();
+        let expected = chunk.data().to_vec();
+        assert_eq!(chunk.clone().into_data(), expected);
+
+        let (chunk_type, data) = chunk.into_parts();
+        assert_eq!(chunk_type.to_string(), "RuSt");
+        assert_eq!(Chunk::new(chunk_type, data).crc(), 2882656334);
+    }
+
+    #[test]
+    fn test_write_to() {
+        let chunk = testing_chunk();
+        let mut written = Vec::new();
+        chunk.write_to(&mut written).unwrap();
+        assert_eq!(written, chunk.as_bytes());
+        assert_eq!(written.len(), 42 + 12);
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
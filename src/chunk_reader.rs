@@ -0,0 +1,124 @@
+use std::io::{self, Read};
+
+use crate::chunk::{Chunk, ChunkError, ParseOptions};
+
+/// Reads chunks one at a time from any `io::Read`, without buffering the whole stream.
+///
+/// The reader must be positioned at the start of a chunk, i.e. after the PNG signature.
+/// Use `Png::reader` to check the signature first.
+#[derive(Debug)]
+pub struct ChunkReader<R: Read> {
+    inner: R,
+    options: ParseOptions,
+    done: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_options(inner, ParseOptions::default())
+    }
+
+    pub fn with_options(inner: R, options: ParseOptions) -> Self {
+        Self {
+            inner,
+            options,
+            done: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Returns `Ok(None)` on a clean end of stream, or an error if it stops mid-chunk.
+    pub fn read_chunk(&mut self) -> Result<Option<Chunk>, ChunkError> {
+        let mut length_buf: [u8; 4] = [0; 4];
+        let mut filled = 0;
+        while filled < length_buf.len() {
+            match self.inner.read(&mut length_buf[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let length = u32::from_be_bytes(length_buf);
+        let chunk = Chunk::read_with_length(length, &mut self.inner, &self.options)?;
+        Ok(Some(chunk))
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk, ChunkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.read_chunk().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        ["FrSt", "miDl", "LASt"]
+            .iter()
+            .flat_map(|chunk_type| {
+                let chunk_type = ChunkType::from_str(chunk_type).unwrap();
+                Chunk::new(chunk_type, b"some data".to_vec()).as_bytes()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_read_all_chunks() {
+        let bytes = testing_bytes();
+        let chunks: Vec<Chunk> = ChunkReader::new(bytes.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].chunk_type().to_string(), "miDl");
+        assert_eq!(chunks[2].data(), b"some data");
+    }
+
+    #[test]
+    fn test_empty_stream() {
+        let mut reader = ChunkReader::new(&[][..]);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_truncated_stream() {
+        let bytes = testing_bytes();
+        let truncated = &bytes[..bytes.len() - 2];
+        let results: Vec<_> = ChunkReader::new(truncated).collect();
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_ok());
+        assert!(results[2].is_err());
+    }
+
+    #[test]
+    fn test_stops_after_error() {
+        let mut bytes = testing_bytes();
+        bytes[8] = b'1';
+        let mut reader = ChunkReader::new(bytes.as_slice());
+
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
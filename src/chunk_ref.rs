@@ -0,0 +1,180 @@
+use std::convert::TryFrom;
+use std::io;
+
+use crate::chunk::{self, Chunk, ChunkError, ParseOptions};
+use crate::chunk_type::ChunkType;
+
+/// A chunk borrowed from the buffer it was parsed from: no data is copied until
+/// `to_owned` is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+    chunk_type: ChunkType,
+    data: &'a [u8],
+    crc: u32,
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Parses the chunk at the start of `bytes`, returning it and the number of
+    /// bytes it spans, length and crc included.
+    pub fn parse(bytes: &'a [u8], options: &ParseOptions) -> Result<(Self, usize), ChunkError> {
+        // Running out of bytes fails the way reading a `Chunk` from them would.
+        let eof = || ChunkError::ReadError(io::ErrorKind::UnexpectedEof.into());
+        let field = |at: usize| {
+            bytes
+                .get(at..at + 4)
+                .map(|field| [field[0], field[1], field[2], field[3]])
+                .ok_or_else(eof)
+        };
+
+        let length = u32::from_be_bytes(field(0)?);
+        if length > Chunk::MAX_LENGTH {
+            return Err(ChunkError::MaxLengthError);
+        }
+        if length > options.max_chunk_len {
+            return Err(ChunkError::LengthLimitExceeded(
+                length,
+                options.max_chunk_len,
+            ));
+        }
+        let chunk_type = ChunkType::try_from(field(4)?)?;
+
+        let len = length as usize;
+        let data = bytes
+            .get(8..8 + len)
+            .ok_or(ChunkError::InvalidChunkData(bytes.len() - 8, len))?;
+        let crc = u32::from_be_bytes(field(8 + len)?);
+
+        let chunk = ChunkRef {
+            chunk_type,
+            data,
+            crc,
+        };
+        if options.verify_crc && !chunk.has_valid_crc() {
+            return Err(ChunkError::InvalidCrc);
+        }
+        Ok((chunk, 12 + len))
+    }
+
+    pub fn length(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    pub fn computed_crc(&self) -> u32 {
+        chunk::checksum(&self.chunk_type, self.data)
+    }
+
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc == self.computed_crc()
+    }
+
+    /// Copies the data into an owned `Chunk`, keeping the stored crc.
+    pub fn to_owned(&self) -> Chunk {
+        Chunk::with_crc(self.chunk_type, self.data.to_vec(), self.crc)
+    }
+}
+
+/// Iterates over the chunks in a buffer holding chunks back to back, such as a
+/// PNG file after its signature. Stops after the first error.
+#[derive(Debug, Clone)]
+pub struct ChunkRefs<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    options: ParseOptions,
+    failed: bool,
+}
+
+impl<'a> ChunkRefs<'a> {
+    pub fn new(bytes: &'a [u8], options: ParseOptions) -> Self {
+        ChunkRefs {
+            bytes,
+            offset: 0,
+            options,
+            failed: false,
+        }
+    }
+
+    /// Where the next chunk starts, relative to the buffer.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for ChunkRefs<'a> {
+    type Item = Result<ChunkRef<'a>, ChunkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.offset >= self.bytes.len() {
+            return None;
+        }
+        match ChunkRef::parse(&self.bytes[self.offset..], &self.options) {
+            Ok((chunk, len)) => {
+                self.offset += len;
+                Some(Ok(chunk))
+            }
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_bytes() -> Vec<u8> {
+        let mut bytes = Chunk::new(ChunkType::IHDR, vec![0; 13]).as_bytes();
+        bytes.extend(Chunk::new("ruSt".parse().unwrap(), b"secret".to_vec()).as_bytes());
+        bytes.extend(Chunk::new(ChunkType::IEND, Vec::new()).as_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_iterate_without_copying() {
+        let bytes = testing_bytes();
+        let chunks: Vec<ChunkRef> = ChunkRefs::new(&bytes, ParseOptions::default())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].data(), b"secret");
+        assert_eq!(chunks[1].data().as_ptr(), bytes[25 + 8..].as_ptr());
+
+        let owned = chunks[1].to_owned();
+        assert_eq!(owned.crc(), chunks[1].crc());
+        assert_eq!(owned.as_bytes(), bytes[25..25 + 18]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut bytes = testing_bytes();
+        bytes[25 + 8] ^= 1;
+        let mut chunks = ChunkRefs::new(&bytes, ParseOptions::default());
+        assert!(chunks.next().unwrap().is_ok());
+        assert!(matches!(chunks.next(), Some(Err(ChunkError::InvalidCrc))));
+        assert!(chunks.next().is_none());
+
+        let lenient: Vec<_> = ChunkRefs::new(&bytes, ParseOptions::lenient()).collect();
+        assert_eq!(lenient.len(), 3);
+        assert!(!lenient[1].as_ref().unwrap().has_valid_crc());
+
+        let truncated = &testing_bytes()[..25 + 8 + 3];
+        let results: Vec<_> = ChunkRefs::new(truncated, ParseOptions::default()).collect();
+        assert!(matches!(results[1], Err(ChunkError::InvalidChunkData(..))));
+        let truncated = &testing_bytes()[..25 + 8 + 6 + 2];
+        let results: Vec<_> = ChunkRefs::new(truncated, ParseOptions::default()).collect();
+        assert!(matches!(results[1], Err(ChunkError::ReadError(_))));
+    }
+}
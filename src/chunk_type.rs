@@ -4,6 +4,8 @@ use std::str::FromStr;
 
 use thiserror::Error;
 
+use crate::rng::Rng;
+
 #[derive(Error, Debug)]
 pub enum ChunkTypeError {
     #[error("ChunkTypeError Bad byte: {0}")]
@@ -14,12 +16,70 @@ pub enum ChunkTypeError {
 }
 
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Ordered by its bytes, so uppercase (critical) types sort before lowercase ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct ChunkType {
     data: [u8; 4],
 }
 
 impl ChunkType {
+    pub const IHDR: ChunkType = ChunkType::new(*b"IHDR");
+    pub const PLTE: ChunkType = ChunkType::new(*b"PLTE");
+    pub const IDAT: ChunkType = ChunkType::new(*b"IDAT");
+    pub const IEND: ChunkType = ChunkType::new(*b"IEND");
+    pub const TRNS: ChunkType = ChunkType::new(*b"tRNS");
+    pub const CHRM: ChunkType = ChunkType::new(*b"cHRM");
+    pub const GAMA: ChunkType = ChunkType::new(*b"gAMA");
+    pub const ICCP: ChunkType = ChunkType::new(*b"iCCP");
+    pub const SBIT: ChunkType = ChunkType::new(*b"sBIT");
+    pub const SRGB: ChunkType = ChunkType::new(*b"sRGB");
+    pub const CICP: ChunkType = ChunkType::new(*b"cICP");
+    pub const MDCV: ChunkType = ChunkType::new(*b"mDCV");
+    pub const CLLI: ChunkType = ChunkType::new(*b"cLLI");
+    pub const TEXT: ChunkType = ChunkType::new(*b"tEXt");
+    pub const ZTXT: ChunkType = ChunkType::new(*b"zTXt");
+    pub const ITXT: ChunkType = ChunkType::new(*b"iTXt");
+    pub const BKGD: ChunkType = ChunkType::new(*b"bKGD");
+    pub const HIST: ChunkType = ChunkType::new(*b"hIST");
+    pub const PHYS: ChunkType = ChunkType::new(*b"pHYs");
+    pub const SPLT: ChunkType = ChunkType::new(*b"sPLT");
+    pub const EXIF: ChunkType = ChunkType::new(*b"eXIf");
+    pub const TIME: ChunkType = ChunkType::new(*b"tIME");
+    pub const ACTL: ChunkType = ChunkType::new(*b"acTL");
+    pub const FCTL: ChunkType = ChunkType::new(*b"fcTL");
+    pub const FDAT: ChunkType = ChunkType::new(*b"fdAT");
+    pub const OFFS: ChunkType = ChunkType::new(*b"oFFs");
+    pub const PCAL: ChunkType = ChunkType::new(*b"pCAL");
+    pub const SCAL: ChunkType = ChunkType::new(*b"sCAL");
+    pub const STER: ChunkType = ChunkType::new(*b"sTER");
+    pub const GIFG: ChunkType = ChunkType::new(*b"gIFg");
+    pub const GIFX: ChunkType = ChunkType::new(*b"gIFx");
+    pub const DSIG: ChunkType = ChunkType::new(*b"dSIG");
+
+    /// Builds a chunk type in a const context, panicking, at compile time when
+    /// used in a const item, if any byte is not an ASCII letter.
+    pub const fn new(bytes: [u8; 4]) -> Self {
+        let mut i = 0;
+        while i < bytes.len() {
+            assert!(
+                bytes[i].is_ascii_alphabetic(),
+                "chunk type bytes must be ASCII letters"
+            );
+            i += 1;
+        }
+        ChunkType { data: bytes }
+    }
+
+    /// A random ancillary, private, safe-to-copy type, such as `ruSt`: the
+    /// kind of type any tool may add to an image and others carry along.
+    pub fn random_private(rng: &mut Rng) -> Self {
+        let mut letter = || b'a' + rng.below(26) as u8;
+        ChunkType {
+            data: [letter(), letter(), letter().to_ascii_uppercase(), letter()],
+        }
+    }
+
     pub fn bytes(&self) -> [u8; 4] {
         self.data
     }
@@ -118,6 +178,21 @@ mod tests {
         assert_eq!(expected, actual.bytes());
     }
 
+    #[test]
+    pub fn test_random_private() {
+        let mut rng = Rng::new(1);
+        let types: Vec<ChunkType> = (0..100)
+            .map(|_| ChunkType::random_private(&mut rng))
+            .collect();
+        for chunk_type in &types {
+            assert!(chunk_type.is_valid(), "{chunk_type}");
+            assert!(!chunk_type.is_critical());
+            assert!(!chunk_type.is_public());
+            assert!(chunk_type.is_safe_to_copy());
+        }
+        assert!(types.iter().any(|chunk_type| *chunk_type != types[0]));
+    }
+
     #[test]
     pub fn test_chunk_type_from_str() {
         let expected = ChunkType::try_from([82, 117, 83, 116]).unwrap();
@@ -188,6 +263,37 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_consts() {
+        assert_eq!(ChunkType::IDAT, ChunkType::from_str("IDAT").unwrap());
+        assert_eq!(ChunkType::TEXT.to_string(), "tEXt");
+        assert!(ChunkType::IHDR.is_critical());
+        assert!(!ChunkType::PHYS.is_critical());
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn test_chunk_type_new_rejects_digits() {
+        ChunkType::new(*b"Ru5t");
+    }
+
+    #[test]
+    pub fn test_chunk_type_as_map_key() {
+        use std::collections::{BTreeSet, HashMap};
+
+        let mut counts: HashMap<ChunkType, usize> = HashMap::new();
+        for chunk_type in [ChunkType::IDAT, ChunkType::TEXT, ChunkType::IDAT] {
+            *counts.entry(chunk_type).or_default() += 1;
+        }
+        assert_eq!(counts[&ChunkType::IDAT], 2);
+
+        let sorted: BTreeSet<ChunkType> = [ChunkType::TEXT, ChunkType::IEND, ChunkType::IDAT]
+            .into_iter()
+            .collect();
+        let names: Vec<String> = sorted.iter().map(ToString::to_string).collect();
+        assert_eq!(names, ["IDAT", "IEND", "tEXt"]);
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -0,0 +1,2976 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+
+use png_secret::archive::{Archive, Member};
+use png_secret::base64;
+use png_secret::builder::{Fill, PngBuilder};
+use png_secret::capacity::{self, Method};
+use png_secret::chunk::{Chunk, ChunkError, ParseOptions};
+use png_secret::chunk_type::ChunkType;
+use png_secret::diff::{self, Change, DiffError};
+use png_secret::envelope::{Compression, Envelope, EnvelopeError, FileInfo};
+use png_secret::exif::ExifChunk;
+use png_secret::fec::{self, FecError};
+use png_secret::glob::PathPattern;
+use png_secret::hex;
+use png_secret::ihdr::{ColorType, Ihdr};
+use png_secret::inspect::{self, ChunkSummary, TextEntry};
+use png_secret::json::Value;
+use png_secret::lsb::{self, LsbError, LsbOptions};
+use png_secret::ordering::{self, OrderingViolation};
+use png_secret::payload;
+use png_secret::png::{InsertPosition, Png, PngError};
+use png_secret::raster::{Raster, RasterError};
+use png_secret::redundancy::{self, Stored, Vote};
+use png_secret::rng::Rng;
+use png_secret::sha256;
+use png_secret::signatures::{self, Location, Marker};
+use png_secret::steganalysis::{self, Analysis};
+use png_secret::stripe::{self, StripeError};
+use png_secret::text::{CompressedTextChunk, InternationalTextChunk, TextChunk};
+use png_secret::time::TimeChunk;
+use png_secret::verify;
+use png_secret::zlib;
+
+use crate::args::{
+    Batch, CapacityArgs, CarrierSpec, CompareArgs, CopyChunksArgs, DecodeArgs, DetectArgs,
+    DiffArgs, EncodeArgs, ExifAction, ExifArgs, ExportChunksArgs, FixCrcArgs, Format, HexdumpArgs,
+    ImportChunksArgs, Input, InspectArgs, KeySource, ListArgs, RemoveArgs, ReorderArgs,
+    ReplaceArgs, ReportFormat, ScanArgs, ScrubArgs, Selection, StatsArgs, StripMetadataArgs,
+    TextKind, TextTarget, TouchTimeArgs, VerifyArgs, WatchAction, WatchArgs,
+};
+use crate::batch;
+use crate::output;
+use crate::progress::Progress;
+use crate::prompt;
+use crate::report::{self, print_table, Align};
+
+/// The file in an export directory that describes the chunk files next to it.
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// Supplies the passphrase wherever one would otherwise be prompted for.
+const PASSPHRASE_VAR: &str = "PNG_SECRET_PASSPHRASE";
+
+/// Read when `PASSPHRASE_VAR` isn't set.
+const PASSWORD_VAR: &str = "PNG_SECRET_PASSWORD";
+
+pub fn encode(mut args: EncodeArgs) -> Result<()> {
+    if let Some(files) = batch::files(&args.file, &args.batch)? {
+        if args.output.is_some() || args.generate_carrier.is_some() || !args.spread.is_empty() {
+            bail!("-o, --generate-carrier and --spread take a single image, not several");
+        }
+        args.key = read_key_once(args.key, true)?;
+        return batch::run(&files, &args.batch, false, |file| {
+            encode(EncodeArgs {
+                file: file.to_path_buf(),
+                batch: Batch::member(),
+                ..args.clone()
+            })
+        });
+    }
+    let (original, png) = match args.generate_carrier {
+        Some(spec) => (Vec::new(), generate_carrier(&spec, &args)?),
+        None => {
+            let original = read_png_bytes(&args.file)?;
+            let png = Png::from_bytes(&original)?;
+            (original, png)
+        }
+    };
+
+    let envelope = match &args.input {
+        Input::Message(message) => Envelope::new(decode_message(message.clone(), args.format)?),
+        Input::Files(paths) => match &paths[..] {
+            [path] => {
+                let (info, data) = read_input_file(path)?;
+                Envelope::new(data).with_file(info)
+            }
+            paths => {
+                let mut archive = Archive::new();
+                for path in paths {
+                    let (info, data) = read_input_file(path)?;
+                    archive.add(&info.name, info.mode, data)?;
+                }
+                Envelope::from_archive(&archive)
+            }
+        },
+    };
+    // A generated carrier leaves stdin free for a passphrase prompt.
+    let stdin_in_use = is_stdio(&args.file) && args.generate_carrier.is_none();
+    let key = read_key(args.key.as_ref(), true, stdin_in_use)?;
+    let envelope = envelope.with_metadata(args.metadata.clone());
+    let bytes = wrap_payload(envelope, &args, key.as_deref());
+    let bytes = match args.fec {
+        Some(scheme) => fec::encode(&bytes, scheme),
+        None => bytes,
+    };
+    // Every carrier is read before any is written.
+    let output = args.output.clone().unwrap_or_else(|| args.file.clone());
+    let mut carriers = vec![(original, png, output)];
+    for path in &args.spread {
+        let original = read_png_bytes(path)?;
+        let png = Png::from_bytes(&original)?;
+        carriers.push((original, png, path.clone()));
+    }
+    if args.random_type {
+        args.chunk_type = random_chunk_type(carriers.iter().map(|(_, png, _)| png));
+        let (_, _, output) = &carriers[0];
+        report(
+            output,
+            args.dry_run,
+            &format!(
+                "Stored in {} chunks, decode with that type",
+                args.chunk_type
+            ),
+        )?;
+    }
+    let pieces = match carriers.len() {
+        1 => vec![bytes],
+        count => stripe::split(&bytes, count, Rng::random_seed() as u32)?,
+    };
+    for ((original, png, output), piece) in carriers.into_iter().zip(pieces) {
+        store_payload(&args, key.clone(), &original, png, &output, &piece)?;
+    }
+    Ok(())
+}
+
+/// The bytes encode stores for `envelope`: the envelope itself, with its
+/// digest, unless `--raw` asks for the message alone. A text entry holds the
+/// message alone too, so other tools show it as it is, unless `--envelope`
+/// asks for the digest or the envelope has something more to record.
+fn wrap_payload(envelope: Envelope, args: &EncodeArgs, key: Option<&[u8]>) -> Vec<u8> {
+    let plain_text = args.text.is_some()
+        && !args.envelope
+        && key.is_none()
+        && args.compression.is_none()
+        && args.padding.is_none()
+        && envelope.file.is_none()
+        && !envelope.archive
+        && envelope.metadata.is_empty();
+    if args.raw || plain_text {
+        return envelope.body;
+    }
+    let envelope = envelope.with_compression(args.compression.unwrap_or_default());
+    let envelope = match args.padding {
+        Some(padding) => envelope.with_padding(padding, &mut Rng::new(Rng::random_seed())),
+        None => envelope,
+    };
+    envelope.to_bytes(key)
+}
+
+/// Hides `bytes` in `png`, as many times as `--redundancy` asks, and writes
+/// it to `output`.
+fn store_payload(
+    args: &EncodeArgs,
+    key: Option<Vec<u8>>,
+    original: &[u8],
+    mut png: Png,
+    output: &Path,
+    bytes: &[u8],
+) -> Result<()> {
+    let copies = match args.redundancy {
+        1 => vec![bytes.to_vec()],
+        count => redundancy::copies(bytes, count),
+    };
+
+    if let Some(options) = &args.lsb {
+        // The key that tags the payload also scatters its bits.
+        let options = LsbOptions {
+            key,
+            ..options.clone()
+        };
+        let mut progress = Progress::steps(3, args.batch.quiet);
+        progress.step("reading the pixels");
+        let mut raster = Raster::from_png(&png)?;
+        let promoted = promote_palette(&mut png, &mut raster)?;
+        let bytes = copies.concat();
+        progress.advance("hiding the payload");
+        lsb::embed(&mut raster, &bytes, &options)?;
+        progress.advance("compressing the pixels");
+        raster.store(&mut png)?;
+        drop(progress);
+        if promoted {
+            eoutln!(
+                "Warning: the indexed image was converted to {} for --lsb: {} -> {} bytes",
+                raster.ihdr().color_type,
+                original.len(),
+                png.as_bytes().len()
+            )?;
+        }
+        if args.dry_run {
+            outln!(
+                "Would hide {} bytes in the low bits of {} pixel samples",
+                bytes.len(),
+                ((bytes.len() + lsb::LENGTH_LEN) * 8)
+                    .div_ceil(usize::from(options.bits_per_sample))
+            )?;
+            report_dry_run(output, original.len(), &png)?;
+            return Ok(());
+        }
+        return write_png(output, &png, args.backup.as_deref());
+    }
+
+    let before = ordering::validate_ordering(png.chunks());
+    for (copy, bytes) in copies.iter().enumerate() {
+        let chunks = match &args.text {
+            Some(text) => vec![text_chunk(text, bytes)?],
+            None => {
+                let max_chunk_size = args.max_chunk_size.unwrap_or(payload::DEFAULT_SEGMENT_LEN);
+                payload::split(&args.chunk_type, bytes, max_chunk_size)?
+            }
+        };
+        // Copies after the first are spread out, so cutting the chunks around
+        // one copy leaves the others.
+        let position = match copy {
+            0 => args.position.clone(),
+            copy => COPY_POSITIONS[copy % COPY_POSITIONS.len()].clone(),
+        };
+        // Segments stay contiguous and in order from wherever the first one lands.
+        let first = png.position_index(&position)?;
+        for (index, chunk) in (first..).zip(chunks) {
+            if args.dry_run {
+                outln!(
+                    "Would add chunk {} ({}, {} bytes)",
+                    index,
+                    chunk.chunk_type(),
+                    chunk.length()
+                )?;
+            }
+            png.insert_chunk(chunk, &InsertPosition::Index(index))?;
+        }
+    }
+
+    warn_new_violations(&before, png.chunks())?;
+
+    if args.dry_run {
+        report_dry_run(output, original.len(), &png)?;
+        return Ok(());
+    }
+    write_png(output, &png, args.backup.as_deref())
+}
+
+/// A random private chunk type none of `pngs` has, nor is one tools are known
+/// by.
+fn random_chunk_type<'a>(pngs: impl Iterator<Item = &'a Png> + Clone) -> ChunkType {
+    let mut rng = Rng::new(Rng::random_seed());
+    loop {
+        let chunk_type = ChunkType::random_private(&mut rng);
+        let known = signatures::SIGNATURES
+            .iter()
+            .any(|signature| signature.marker == Marker::ChunkType(chunk_type.bytes()));
+        if !known
+            && pngs
+                .clone()
+                .all(|png| png.chunk_indices(chunk_type).is_empty())
+        {
+            return chunk_type;
+        }
+    }
+}
+
+/// Where `encode --redundancy` puts each copy after the first, in turn.
+const COPY_POSITIONS: [InsertPosition; 3] = [
+    InsertPosition::BeforeIend,
+    InsertPosition::BeforeType(ChunkType::IDAT),
+    InsertPosition::AfterIhdr,
+];
+
+/// Builds the image `encode --generate-carrier` asks for, printing the seed of
+/// random noise so the same carrier can be made again.
+fn generate_carrier(spec: &CarrierSpec, args: &EncodeArgs) -> Result<Png> {
+    let builder = PngBuilder::new(spec.width, spec.height)
+        .with_color(spec.color)
+        .with_bit_depth(spec.bit_depth);
+    let builder = match (spec.noise, spec.seed) {
+        (false, _) => builder,
+        (true, Some(seed)) => builder.with_fill(Fill::Noise(seed)),
+        (true, None) => {
+            let seed = Rng::random_seed();
+            let output = args.output.as_deref().unwrap_or(&args.file);
+            report(
+                output,
+                args.dry_run,
+                &format!("Generated a noise carrier with --seed {seed}"),
+            )?;
+            builder.with_fill(Fill::Noise(seed))
+        }
+    };
+    Ok(builder.build()?)
+}
+
+/// Builds the standard text chunk `text` asks for, holding `payload`.
+fn text_chunk(text: &TextTarget, payload: &[u8]) -> Result<Chunk> {
+    let keyword = &text.keyword;
+    Ok(match text.kind {
+        TextKind::Text => TextChunk::new(keyword, &payload::to_text(payload))?.to_chunk(),
+        TextKind::Compressed => {
+            CompressedTextChunk::new(keyword, &payload::to_text(payload))?.to_chunk()
+        }
+        TextKind::International => {
+            InternationalTextChunk::new(keyword, &payload::to_utf8_text(payload))?
+                .with_language(&text.language_tag, &text.translated_keyword)?
+                .to_chunk()
+        }
+    })
+}
+
+/// Reads a PNG, leniently if chunks fail their CRC check, since they may
+/// still hold a payload error correction can repair; the error is returned
+/// alongside for when they don't.
+fn read_damaged_png(path: &Path) -> Result<(Png, Option<PngError>)> {
+    let original = read_png_bytes(path)?;
+    Ok(match Png::from_bytes(&original) {
+        Err(err @ PngError::InValidChunk(ChunkError::InvalidCrc)) => (
+            Png::from_bytes_with(&original, &ParseOptions::lenient())?,
+            Some(err),
+        ),
+        result => (result?, None),
+    })
+}
+
+/// Reads the payload `decode` asks for from `png`, taking a vote if it
+/// was stored several times.
+fn read_payload(
+    args: &DecodeArgs,
+    png: &Png,
+    key: Option<Vec<u8>>,
+) -> Result<(Vec<u8>, Option<Vote>)> {
+    let stored = match &args.text_keyword {
+        Some(keyword) => {
+            let language = args.language.as_deref();
+            payload::find_text_stored(png.chunks(), keyword, language, args.nth)?
+        }
+        None => match &args.lsb {
+            Some(options) => {
+                // Without the key the sample order is unknown, so a keyed payload
+                // reads as none rather than asking for the passphrase later.
+                let options = LsbOptions {
+                    key,
+                    ..options.clone()
+                };
+                let mut progress = Progress::steps(2, args.batch.quiet);
+                progress.step("reading the pixels");
+                let raster = Raster::from_png(png)?;
+                progress.advance("reading the payload");
+                let extracted = lsb::extract(&raster, &options);
+                drop(progress);
+                Stored::from_joined(match extracted {
+                    Err(LsbError::NoPayload) if options.key.is_none() => {
+                        bail!("no LSB payload found; if it was hidden with a key, pass the key")
+                    }
+                    Err(LsbError::NoPayload) => {
+                        bail!("no LSB payload found with this key")
+                    }
+                    result => result?,
+                })
+            }
+            None => payload::join_nth_stored(&args.chunk_type, png.chunks(), args.nth)?,
+        },
+    };
+    let (bytes, vote) = stored.resolve();
+    if let Some(vote) = &vote {
+        let ties = match vote.ties {
+            0 => String::new(),
+            ties => format!(", {ties} of them ties settled by the first copy"),
+        };
+        eoutln!(
+            "Redundancy: voted over {} of {} copies, {} bytes disputed{ties}",
+            vote.found,
+            vote.count,
+            vote.disputed
+        )?;
+    }
+    Ok((bytes, vote))
+}
+
+/// Warns about the ordering rules `chunks` breaks that the original chunks,
+/// with the violations in `before`, did not already break.
+fn warn_new_violations(before: &[OrderingViolation], chunks: &[Chunk]) -> io::Result<()> {
+    let mut before: Vec<_> = before
+        .iter()
+        .map(|violation| (&violation.chunk_type, violation.rule))
+        .collect();
+    for violation in ordering::validate_ordering(chunks) {
+        let key = (&violation.chunk_type, violation.rule);
+        match before.iter().position(|old| *old == key) {
+            Some(position) => {
+                before.swap_remove(position);
+            }
+            None => eoutln!("Warning: {violation}")?,
+        }
+    }
+    Ok(())
+}
+
+pub fn decode(mut args: DecodeArgs) -> Result<()> {
+    if let Some(files) = batch::files(&args.file, &args.batch)? {
+        if args.output.is_some() || !args.spread.is_empty() {
+            bail!("-o and --spread take a single image, not several");
+        }
+        args.key = read_key_once(args.key, false)?;
+        return batch::run(&files, &args.batch, false, |file| {
+            decode(DecodeArgs {
+                file: file.to_path_buf(),
+                batch: Batch::member(),
+                ..args.clone()
+            })
+        });
+    }
+    let paths: Vec<&Path> = std::iter::once(&args.file)
+        .chain(&args.spread)
+        .map(PathBuf::as_path)
+        .collect();
+    let mut images = Vec::new();
+    for path in &paths {
+        images.push(read_damaged_png(path)?);
+    }
+    let stdin_in_use = is_stdio(&args.file);
+    let key = read_key(args.key.as_ref(), false, stdin_in_use)?;
+    let mut pieces = Vec::new();
+    let mut damage = None;
+    for (png, png_damage) in images {
+        let (bytes, vote) = read_payload(&args, &png, key.clone())?;
+        // Several copies outvote damage to one, unless the vote was a tie.
+        if vote.is_none_or(|vote| vote.found < 2 || vote.ties > 0) {
+            damage = damage.or(png_damage);
+        }
+        pieces.push(bytes);
+    }
+    let bytes = match stripe::join(&pieces) {
+        Ok(bytes) => bytes,
+        Err(StripeError::NotAPiece(_)) if pieces.len() == 1 => pieces.swap_remove(0),
+        Err(StripeError::NotAPiece(index)) => bail!(
+            "the payload in {} is no piece of a spread payload",
+            paths[index].display()
+        ),
+        Err(StripeError::Missing(index, count)) => bail!(
+            "the payload is spread over {count} images and piece {} is missing, \
+             pass the others with --spread",
+            index + 1
+        ),
+        Err(err) => return Err(err.into()),
+    };
+    let bytes = match (fec::decode(&bytes), damage) {
+        (Ok((data, correction)), _) => {
+            eoutln!(
+                "Error correction {}: repaired {} bytes in {} of {} blocks",
+                correction.fec,
+                correction.corrected,
+                correction.damaged_blocks,
+                correction.blocks
+            )?;
+            data
+        }
+        (Err(FecError::NotProtected), None) => bytes,
+        (Err(FecError::NotProtected), Some(err)) => return Err(err.into()),
+        (Err(err), _) => return Err(err.into()),
+    };
+    let (envelope, tagged) = match Envelope::from_bytes(&bytes, key.as_deref()) {
+        Err(EnvelopeError::KeyRequired) if key.is_none() => {
+            let key = read_key(Some(&KeySource::Prompt), false, stdin_in_use)?;
+            (Envelope::from_bytes(&bytes, key.as_deref())?, true)
+        }
+        result => (result?, key.is_some()),
+    };
+    if args.info {
+        print_envelope_info(&envelope, Envelope::is_envelope(&bytes), tagged)?;
+        return Ok(());
+    }
+    if let Some(expires) = envelope.metadata.expires {
+        if envelope.metadata.is_expired(TimeChunk::now()?) {
+            if !args.ignore_expiry {
+                bail!("payload expired at {expires}, pass --ignore-expiry to read it anyway");
+            }
+            eoutln!("Warning: payload expired at {expires}")?;
+        }
+    }
+
+    let archive = if envelope.archive {
+        Archive::from_bytes(&envelope.body)?
+    } else if let Some(file) = envelope.file {
+        let mut archive = Archive::new();
+        archive.add(&file.name, file.mode, envelope.body)?;
+        archive
+    } else {
+        if args.list || !args.extract.is_empty() || args.extract_to.is_some() {
+            bail!("payload is a message, not a file");
+        }
+        return print_payload(&args, envelope.body);
+    };
+
+    if args.list {
+        for member in archive.members() {
+            outln!(
+                "{:04o} {:>10} {}",
+                member.info.mode,
+                member.info.size,
+                member.info.name
+            )?;
+        }
+        return Ok(());
+    }
+
+    let members = match (&args.extract[..], &args.extract_to) {
+        ([], None) if args.output.is_some() || args.format != Format::Text => {
+            archive.members().iter().collect()
+        }
+        ([], None) => bail!(
+            "payload holds {} file(s), use --list, --extract <name> or --extract-to <dir>",
+            archive.members().len()
+        ),
+        ([], Some(_)) => archive.members().iter().collect(),
+        (names, _) => archive.select(names)?,
+    };
+    if args.output.is_some() || args.format != Format::Text {
+        let [member] = members[..] else {
+            bail!("payload holds several files, select one with --extract <name>");
+        };
+        return print_payload(&args, member.data.clone());
+    }
+    let dir = args.extract_to.as_deref().unwrap_or(Path::new("."));
+    for member in members {
+        extract_file(dir, member)?;
+    }
+
+    Ok(())
+}
+
+pub fn fix_crc(args: FixCrcArgs) -> Result<()> {
+    let bytes = read_png_bytes(&args.file)?;
+    let mut png = Png::from_bytes_with(&bytes, &ParseOptions::lenient())?;
+    let output = args.output.as_deref().unwrap_or(&args.file);
+    let report = |line: String| report(output, args.dry_run, &line);
+
+    let fixes = png.fix_crcs(|chunk| {
+        args.chunk_types.is_empty() || args.chunk_types.contains(chunk.chunk_type())
+    });
+    if fixes.is_empty() {
+        report("All selected chunks have valid crcs".to_string())?;
+        if is_stdio(output) && !args.dry_run {
+            write_png(output, &png, None)?;
+        }
+        return Ok(());
+    }
+
+    for fix in &fixes {
+        report(format!(
+            "Chunk {} ({}): crc {:08x} -> {:08x}",
+            fix.index, fix.chunk_type, fix.stored, fix.computed
+        ))?;
+    }
+
+    if args.dry_run {
+        report_dry_run(output, bytes.len(), &png)?;
+        return Ok(());
+    }
+    write_png(output, &png, args.backup.as_deref())?;
+    if !is_stdio(output) {
+        report(format!(
+            "Fixed {} chunk(s), wrote {}",
+            fixes.len(),
+            output.display()
+        ))?;
+    }
+
+    Ok(())
+}
+
+pub fn list(args: ListArgs) -> Result<()> {
+    let bytes = read_png_bytes(&args.file)?;
+    // Lenient, so chunks with a broken crc are listed rather than rejected.
+    let png = Png::from_bytes_with(&bytes, &ParseOptions::lenient())?;
+    let summaries = inspect::summarize(&png);
+
+    if args.format == ReportFormat::Table {
+        print_chunk_table(&summaries)?;
+    } else {
+        let records: Vec<Value> = summaries.iter().map(summary_json).collect();
+        report::print_structured(args.format, &Value::Array(records.clone()), &records)?;
+    }
+
+    Ok(())
+}
+
+pub fn inspect(args: InspectArgs) -> Result<()> {
+    let bytes = read_png_bytes(&args.file)?;
+    let png = Png::from_bytes_with(&bytes, &ParseOptions::lenient())?;
+    let summaries = inspect::summarize(&png);
+
+    if args.format != ReportFormat::Table {
+        let records: Vec<Value> = summaries
+            .iter()
+            .zip(png.chunks())
+            .map(|(summary, chunk)| {
+                let mut record = summary_json(summary);
+                if let Value::Object(fields) = &mut record {
+                    let entropy = inspect::entropy(chunk.data());
+                    fields.push((
+                        "entropy".to_string(),
+                        Value::from((entropy * 1000.0).round() / 1000.0),
+                    ));
+                    fields.push((
+                        "looks_random".to_string(),
+                        Value::from(inspect::looks_random(chunk)),
+                    ));
+                }
+                record
+            })
+            .collect();
+        let value = Value::object([
+            ("file", Value::from(args.file.display().to_string())),
+            ("size", Value::from(bytes.len())),
+            ("chunks", Value::Array(records.clone())),
+        ]);
+        report::print_structured(args.format, &value, &records)?;
+        return Ok(());
+    }
+
+    print_chunk_table(&summaries)?;
+    let texts: Vec<_> = summaries
+        .iter()
+        .filter_map(|summary| Some((summary, summary.text.as_ref()?)))
+        .collect();
+    let times: Vec<_> = summaries
+        .iter()
+        .filter_map(|summary| Some((summary, summary.time?)))
+        .collect();
+    let random: Vec<_> = summaries
+        .iter()
+        .zip(png.chunks())
+        .filter(|(_, chunk)| inspect::looks_random(chunk))
+        .collect();
+    if !texts.is_empty() || !times.is_empty() || !random.is_empty() {
+        outln!()?;
+    }
+    for (summary, time) in times {
+        outln!("{} {}: {time}", summary.index, summary.chunk_type)?;
+    }
+    for (summary, text) in texts {
+        let language = match (&text.language_tag[..], &text.translated_keyword[..]) {
+            ("", "") => String::new(),
+            (tag, "") => format!(" [{tag}]"),
+            (tag, translated) => format!(" [{tag}: {translated}]"),
+        };
+        outln!(
+            "{} {}: {}{}={}",
+            summary.index,
+            summary.chunk_type,
+            text.keyword,
+            language,
+            text.text
+        )?;
+    }
+    for (summary, chunk) in random {
+        outln!(
+            "{} {}: entropy {:.2} bits/byte, like compressed or encrypted data",
+            summary.index,
+            summary.chunk_type,
+            inspect::entropy(chunk.data())
+        )?;
+    }
+
+    Ok(())
+}
+
+fn summary_json(summary: &ChunkSummary) -> Value {
+    let chunk_type = &summary.chunk_type;
+    let text = summary.text.as_ref().map_or(Value::Null, |text| {
+        Value::object([
+            ("keyword", Value::from(text.keyword.as_str())),
+            ("text", Value::from(text.text.as_str())),
+            ("language_tag", Value::from(text.language_tag.as_str())),
+            (
+                "translated_keyword",
+                Value::from(text.translated_keyword.as_str()),
+            ),
+        ])
+    });
+    Value::object([
+        ("index", Value::from(summary.index)),
+        ("offset", Value::from(summary.offset)),
+        ("type", Value::from(chunk_type.to_string())),
+        ("length", Value::from(summary.length)),
+        ("crc", Value::from(format!("{:08x}", summary.crc))),
+        ("crc_valid", Value::from(summary.crc_valid)),
+        ("critical", Value::from(chunk_type.is_critical())),
+        ("public", Value::from(chunk_type.is_public())),
+        ("safe_to_copy", Value::from(chunk_type.is_safe_to_copy())),
+        ("text", text),
+        (
+            "time",
+            Value::from(summary.time.map(|time| time.to_string())),
+        ),
+    ])
+}
+
+fn print_chunk_table(summaries: &[ChunkSummary]) -> io::Result<()> {
+    let rows: Vec<Vec<String>> = summaries
+        .iter()
+        .map(|summary| {
+            vec![
+                summary.index.to_string(),
+                summary.offset.to_string(),
+                summary.chunk_type.to_string(),
+                summary.length.to_string(),
+                format!("{:08x}", summary.crc),
+                if summary.crc_valid { "ok" } else { "BAD" }.to_string(),
+                summary.flags(),
+            ]
+        })
+        .collect();
+    print_table(
+        &[
+            ("INDEX", Align::Right),
+            ("OFFSET", Align::Right),
+            ("TYPE", Align::Left),
+            ("LENGTH", Align::Right),
+            ("CRC", Align::Left),
+            ("STATUS", Align::Left),
+            ("FLAGS", Align::Left),
+        ],
+        &rows,
+    )
+}
+
+pub fn stats(args: StatsArgs) -> Result<()> {
+    let bytes = read_png_bytes(&args.file)?;
+    let png = Png::from_bytes_with(&bytes, &ParseOptions::lenient())?;
+    let stats = inspect::stats(&png);
+    let largest = stats.largest.map(|index| &png.chunks()[index]);
+
+    let types: Vec<Value> = stats
+        .types
+        .iter()
+        .map(|types| {
+            Value::object([
+                ("type", Value::from(types.chunk_type.to_string())),
+                ("count", Value::from(types.count)),
+                ("bytes", Value::from(types.bytes)),
+            ])
+        })
+        .collect();
+    if args.format != ReportFormat::Table {
+        let largest = largest.map_or(Value::Null, |chunk| {
+            Value::object([
+                ("index", Value::from(stats.largest)),
+                ("type", Value::from(chunk.chunk_type().to_string())),
+                ("length", Value::from(chunk.length())),
+            ])
+        });
+        let value = Value::object([
+            ("file", Value::from(args.file.display().to_string())),
+            ("size", Value::from(stats.file_size)),
+            ("chunks", Value::from(stats.chunk_count)),
+            ("critical_bytes", Value::from(stats.critical_bytes)),
+            ("ancillary_bytes", Value::from(stats.ancillary_bytes)),
+            ("ancillary_ratio", Value::from(stats.ancillary_ratio())),
+            ("largest", largest),
+            ("non_standard_bytes", Value::from(stats.non_standard_bytes)),
+            (
+                "non_standard_chunks",
+                Value::from(stats.non_standard_chunks),
+            ),
+            ("types", Value::Array(types.clone())),
+        ]);
+        report::print_structured(args.format, &value, &types)?;
+        return Ok(());
+    }
+
+    let percent = |bytes: u64| 100.0 * bytes as f64 / stats.file_size as f64;
+    let field = |label: &str, value: String| outln!("{:<20}{value}", format!("{label}:"));
+    field(
+        "File size",
+        format!("{} bytes in {} chunks", stats.file_size, stats.chunk_count),
+    )?;
+    field(
+        "Critical data",
+        format!(
+            "{} bytes ({:.1}%)",
+            stats.critical_bytes,
+            percent(stats.critical_bytes)
+        ),
+    )?;
+    field(
+        "Ancillary data",
+        format!(
+            "{} bytes ({:.1}%)",
+            stats.ancillary_bytes,
+            percent(stats.ancillary_bytes)
+        ),
+    )?;
+    if let Some(ratio) = stats.ancillary_ratio() {
+        field("Ancillary/critical", format!("{ratio:.3}"))?;
+    }
+    if let (Some(index), Some(chunk)) = (stats.largest, largest) {
+        field(
+            "Largest chunk",
+            format!("{index} ({}, {} bytes)", chunk.chunk_type(), chunk.length()),
+        )?;
+    }
+    field(
+        "Non-standard data",
+        format!(
+            "{} bytes in {} chunk(s)",
+            stats.non_standard_bytes, stats.non_standard_chunks
+        ),
+    )?;
+    outln!()?;
+
+    let rows: Vec<Vec<String>> = stats
+        .types
+        .iter()
+        .map(|types| {
+            vec![
+                types.chunk_type.to_string(),
+                types.count.to_string(),
+                types.bytes.to_string(),
+                if inspect::is_registered(&types.chunk_type) {
+                    String::new()
+                } else {
+                    "non-standard".to_string()
+                },
+            ]
+        })
+        .collect();
+    print_table(
+        &[
+            ("TYPE", Align::Left),
+            ("COUNT", Align::Right),
+            ("BYTES", Align::Right),
+            ("NOTE", Align::Left),
+        ],
+        &rows,
+    )?;
+
+    Ok(())
+}
+
+/// How much smaller deflate typically makes text, for the estimate printed
+/// without a payload to measure.
+const TEXT_DEFLATE_RATIO: u64 = 3;
+
+pub fn capacity(args: CapacityArgs) -> Result<()> {
+    let bytes = read_png_bytes(&args.file)?;
+    let mut png = Png::from_bytes_with(&bytes, &ParseOptions::lenient())?;
+    // Measured with the envelope encode really writes: raw messages go as they
+    // are, files and compressed payloads get a header.
+    let payload = match &args.payload {
+        Some(path) => {
+            let (info, data) = read_input_file(path)?;
+            let envelope = Envelope::new(data).with_file(info);
+            let plain = envelope.to_bytes(None).len();
+            let deflated = envelope
+                .with_compression(Compression::Deflate(zlib::DEFAULT_LEVEL))
+                .to_bytes(None)
+                .len();
+            Some((plain, deflated))
+        }
+        None => None,
+    };
+    let envelope_len = Envelope::new(Vec::new())
+        .with_compression(Compression::Deflate(zlib::DEFAULT_LEVEL))
+        .to_bytes(None)
+        .len() as u64;
+
+    let mut raster = None;
+    let methods = args
+        .method
+        .map_or(Method::ALL.to_vec(), |method| vec![method]);
+    let field = |label: &str, value: String| outln!("{:<20}{value}", format!("{label}:"));
+    for (n, method) in methods.into_iter().enumerate() {
+        if n > 0 {
+            outln!()?;
+        }
+        let max_chunk_size = args.max_chunk_size.unwrap_or(payload::DEFAULT_SEGMENT_LEN);
+        let limit = match method.channels() {
+            None => {
+                field(
+                    "Method",
+                    format!("{method}, in chunks of up to {max_chunk_size} bytes"),
+                )?;
+                capacity::chunk_capacity(max_chunk_size)
+            }
+            Some(channels) => {
+                let bits = args.bits_per_sample;
+                let plural = if bits == 1 { "" } else { "s" };
+                field("Method", format!("{method}, {bits} bit{plural} per sample"))?;
+                if raster.is_none() {
+                    let mut decoded = Raster::from_png(&png)?;
+                    if promote_palette(&mut png, &mut decoded)? {
+                        field(
+                            "Pixels",
+                            format!(
+                                "indexed, converted to {} as encode --lsb does",
+                                decoded.ihdr().color_type
+                            ),
+                        )?;
+                    }
+                    raster = Some(decoded);
+                }
+                let options = LsbOptions {
+                    channels,
+                    bits_per_sample: args.bits_per_sample,
+                    ..LsbOptions::default()
+                };
+                match lsb::capacity(raster.as_ref().expect("decoded above"), &options) {
+                    Ok(bytes) => bytes as u64,
+                    Err(LsbError::NoAlpha(color_type)) => {
+                        field(
+                            "Capacity",
+                            format!("none, {color_type} images have no alpha channel"),
+                        )?;
+                        continue;
+                    }
+                    Err(LsbError::Unsupported(format)) => {
+                        field(
+                            "Capacity",
+                            format!("none, {format} images are not supported"),
+                        )?;
+                        continue;
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        };
+
+        let practical = limit <= capacity::PRACTICAL_LIMIT;
+        match practical {
+            true => field("Capacity", format!("{limit} bytes"))?,
+            false => field("Capacity", "no practical limit".to_string())?,
+        }
+        match payload {
+            None if !practical => {}
+            None => field(
+                "Compressed text",
+                format!(
+                    "about {} bytes with --compress",
+                    limit.saturating_sub(envelope_len) * TEXT_DEFLATE_RATIO
+                ),
+            )?,
+            Some((plain, deflated)) => {
+                field(
+                    "Payload",
+                    format!("{plain} bytes to store, {deflated} with --compress"),
+                )?;
+                let fits = if plain as u64 <= limit {
+                    "yes"
+                } else if deflated as u64 <= limit {
+                    "only with --compress"
+                } else {
+                    "no"
+                };
+                field("Fits", fits.to_string())?;
+                if method == Method::Chunk {
+                    field(
+                        "File growth",
+                        format!(
+                            "{} bytes, {} with --compress",
+                            capacity::chunk_cost(plain, max_chunk_size),
+                            capacity::chunk_cost(deflated, max_chunk_size)
+                        ),
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn detect(args: DetectArgs) -> Result<()> {
+    let bytes = read_png_bytes(&args.file)?;
+    let png = Png::from_bytes_with(&bytes, &ParseOptions::lenient())?;
+    let ihdr = png.ihdr()?;
+    // Signatures still say something about images the statistics can't read,
+    // even those whose pixels can't be decoded.
+    let raster = Raster::from_png(&png);
+    let found = signatures::scan(&png, raster.as_ref().ok());
+    let measured = measure(&ihdr, &raster);
+    let analysis = measured.as_ref().ok();
+    let samples = u64::from(ihdr.width) * u64::from(ihdr.height);
+    let location = |found: &signatures::Match| match found.location {
+        Location::Chunk(index) => format!("chunk {index} ({})", png.chunks()[index].chunk_type()),
+        Location::Pixels => found.location.to_string(),
+    };
+
+    let channels: Vec<Value> = analysis
+        .iter()
+        .flat_map(|analysis| &analysis.channels)
+        .map(|channel| {
+            Value::object([
+                ("channel", Value::from(channel.channel)),
+                ("chi_square", Value::from(channel.chi_square)),
+                ("chi_square_extent", Value::from(channel.chi_square_extent)),
+                ("rs", Value::from(channel.rs)),
+                ("sample_pairs", Value::from(channel.sample_pairs)),
+                ("suspicion", Value::from(channel.suspicion())),
+            ])
+        })
+        .collect();
+    if args.format != ReportFormat::Table {
+        let signatures: Vec<Value> = found
+            .iter()
+            .map(|found| {
+                Value::object([
+                    ("tool", Value::from(found.signature.tool)),
+                    ("description", Value::from(found.signature.description)),
+                    ("location", Value::from(location(found))),
+                ])
+            })
+            .collect();
+        let value = Value::object([
+            ("file", Value::from(args.file.display().to_string())),
+            ("signatures", Value::Array(signatures)),
+            (
+                "score",
+                Value::from(analysis.map(|analysis| analysis.score())),
+            ),
+            (
+                "verdict",
+                Value::from(analysis.map(|analysis| analysis.verdict())),
+            ),
+            (
+                "payload_estimate",
+                Value::from(analysis.map(|analysis| analysis.payload_estimate(samples))),
+            ),
+            ("channels", Value::Array(channels.clone())),
+        ]);
+        report::print_structured(args.format, &value, &channels)?;
+        return Ok(());
+    }
+
+    let field = |label: &str, value: String| outln!("{:<20}{value}", format!("{label}:"));
+    if found.is_empty() {
+        field("Signatures", "none".to_string())?;
+    }
+    for (n, found) in found.iter().enumerate() {
+        let line = format!(
+            "{}: {} ({})",
+            location(found),
+            found.signature.description,
+            found.signature.tool
+        );
+        if n == 0 {
+            field("Signatures", line)?;
+        } else {
+            outln!("{:<20}{line}", "")?;
+        }
+    }
+    let analysis = match measured {
+        Ok(analysis) => analysis,
+        Err(reason) => {
+            field("Suspicion", format!("not measured, {reason}"))?;
+            return Ok(());
+        }
+    };
+    field(
+        "Suspicion",
+        format!("{:.0}% ({})", analysis.score() * 100.0, analysis.verdict()),
+    )?;
+    field(
+        "Payload estimate",
+        format!("about {} bytes", analysis.payload_estimate(samples)),
+    )?;
+    outln!()?;
+
+    let estimate = |estimate: Option<f64>| estimate.map_or("-".to_string(), |e| format!("{e:.3}"));
+    let rows: Vec<Vec<String>> = analysis
+        .channels
+        .iter()
+        .map(|channel| {
+            vec![
+                channel.channel.to_string(),
+                format!("{:.3}", channel.chi_square),
+                format!("{:.0}%", channel.chi_square_extent * 100.0),
+                estimate(channel.rs),
+                estimate(channel.sample_pairs),
+                format!("{:.0}%", channel.suspicion() * 100.0),
+            ]
+        })
+        .collect();
+    print_table(
+        &[
+            ("CHANNEL", Align::Left),
+            ("CHI-SQUARE", Align::Right),
+            ("EXTENT", Align::Right),
+            ("RS", Align::Right),
+            ("SAMPLE-PAIRS", Align::Right),
+            ("SUSPICION", Align::Right),
+        ],
+        &rows,
+    )?;
+    Ok(())
+}
+
+/// A payload `scan` found in an image.
+struct Finding {
+    location: String,
+    /// `None` when a trace tells of a payload but not how big it is.
+    bytes: Option<usize>,
+    payload: String,
+    tag: String,
+}
+
+/// The statistics `detect` gives on the pixels of `raster`, or why there are
+/// none.
+fn measure(ihdr: &Ihdr, raster: &Result<Raster, RasterError>) -> Result<Analysis, String> {
+    let raster = raster
+        .as_ref()
+        .map_err(|err| format!("the pixels can't be decoded ({err})"))?;
+    steganalysis::analyze(raster).map_err(|_| {
+        format!(
+            "only 8-bit grayscale and truecolor pixels are analyzed, not {}-bit {}",
+            ihdr.bit_depth, ihdr.color_type
+        )
+    })
+}
+
+pub fn scan(args: ScanArgs) -> Result<()> {
+    let files = batch::files_recursive(&args.file, &args.batch)?;
+    let passphrases: Vec<String> = match &args.passphrases {
+        Some(path) => fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let (mut rows, mut records) = (Vec::new(), Vec::new());
+    let mut holding = 0;
+    let mut warned = None;
+    let mut progress = Progress::new(files.len(), args.batch.quiet);
+    let scan = |_, file: &Path| scan_image(file, args.lsb, &passphrases);
+    batch::for_each(&files, args.batch.jobs, scan, |file, findings| {
+        progress.advance(&file.display().to_string());
+        let findings = match findings {
+            Ok(findings) => findings,
+            Err(err) => {
+                let warning = progress.suspend(|| eoutln!("Warning: {}: {err:#}", file.display()));
+                if let Err(err) = warning {
+                    warned.get_or_insert(err);
+                }
+                return;
+            }
+        };
+        if !findings.is_empty() {
+            holding += 1;
+        }
+        for finding in findings {
+            let name = file.display().to_string();
+            records.push(Value::object([
+                ("file", Value::from(name.clone())),
+                ("location", Value::from(finding.location.clone())),
+                ("bytes", Value::from(finding.bytes)),
+                ("payload", Value::from(finding.payload.clone())),
+                ("tag", Value::from(finding.tag.clone())),
+            ]));
+            rows.push(vec![
+                name,
+                finding.location,
+                finding
+                    .bytes
+                    .map_or_else(|| "-".to_string(), |bytes| bytes.to_string()),
+                finding.payload,
+                finding.tag,
+            ]);
+        }
+    });
+    drop(progress);
+    if let Some(err) = warned {
+        return Err(err.into());
+    }
+
+    if args.format != ReportFormat::Table {
+        report::print_structured(args.format, &Value::Array(records.clone()), &records)?;
+        return Ok(());
+    }
+    if !rows.is_empty() {
+        print_table(
+            &[
+                ("FILE", Align::Left),
+                ("LOCATION", Align::Left),
+                ("BYTES", Align::Right),
+                ("PAYLOAD", Align::Left),
+                ("TAG", Align::Left),
+            ],
+            &rows,
+        )?;
+        outln!()?;
+    }
+    outln!(
+        "{} payload(s) in {holding} of {} images",
+        rows.len(),
+        files.len()
+    )?;
+    Ok(())
+}
+
+/// The payloads in the image at `path`: png-secret's in full, one for every
+/// payload in a chunk type or text entry, and other tools' as their traces.
+fn scan_image(path: &Path, lsb: bool, passphrases: &[String]) -> Result<Vec<Finding>> {
+    let bytes = read_png_bytes(path)?;
+    let png = Png::from_bytes_with(&bytes, &ParseOptions::lenient())?;
+    // Pixels LSB tools can't write to hold nothing to find.
+    let raster = if lsb {
+        Raster::from_png(&png).ok()
+    } else {
+        None
+    };
+    let found = signatures::scan(&png, raster.as_ref());
+    let ours = |found: &signatures::Match| found.signature.tool == "png-secret";
+    // Chunk types other tools are known by, whose payloads we can't tell
+    // apart from theirs unless they are ours to identify.
+    let foreign_types: Vec<ChunkType> = found
+        .iter()
+        .filter_map(|found| match found.location {
+            Location::Chunk(index) if !ours(found) => Some(*png.chunks()[index].chunk_type()),
+            _ => None,
+        })
+        .collect();
+
+    let mut findings = Vec::new();
+    let mut done_types = Vec::new();
+    let mut done_chunks = Vec::new();
+    for found in found.iter().filter(|found| ours(found)) {
+        match found.location {
+            Location::Chunk(index) if !done_chunks.contains(&index) => {
+                done_chunks.push(index);
+                let chunk = &png.chunks()[index];
+                let chunk_type = *chunk.chunk_type();
+                if let Some(entry) = TextEntry::from_chunk(chunk) {
+                    let location = format!("{chunk_type} {}", entry.keyword);
+                    let payload = payload::from_text(&entry.text);
+                    findings.push(describe_payload(location, payload, None, passphrases));
+                } else if !done_types.contains(&chunk_type) {
+                    done_types.push(chunk_type);
+                    match payload::join_all(&chunk_type, png.chunks()) {
+                        Ok(payloads) => {
+                            let payloads = payloads
+                                .into_iter()
+                                .filter(|payload| {
+                                    signatures::identify(payload).is_some()
+                                        || !foreign_types.contains(&chunk_type)
+                                })
+                                .collect();
+                            findings.extend(describe_stored(
+                                &chunk_type.to_string(),
+                                redundancy::gather(payloads),
+                                passphrases,
+                            ));
+                        }
+                        Err(err) => findings.push(Finding {
+                            location: chunk_type.to_string(),
+                            bytes: None,
+                            payload: format!("incomplete payload: {err}"),
+                            tag: "-".to_string(),
+                        }),
+                    }
+                }
+            }
+            Location::Chunk(_) | Location::Pixels => {}
+        }
+    }
+    if let Some(raster) = &raster {
+        findings.extend(scan_pixels(raster, passphrases));
+    }
+    // Other tools' traces, but not the chunk type of a payload already found.
+    for found in found.iter().filter(|found| !ours(found)) {
+        let (location, bytes) = match found.location {
+            Location::Chunk(index) if done_chunks.contains(&index) => continue,
+            Location::Chunk(index) => {
+                let chunk = &png.chunks()[index];
+                (
+                    format!("chunk {index} ({})", chunk.chunk_type()),
+                    Some(chunk.data().len()),
+                )
+            }
+            Location::Pixels => ("pixels".to_string(), None),
+        };
+        findings.push(Finding {
+            location,
+            bytes,
+            payload: format!("{} ({})", found.signature.description, found.signature.tool),
+            tag: "-".to_string(),
+        });
+    }
+    Ok(findings)
+}
+
+/// A finding for every payload in `stored`, the copies of one taken as one.
+/// The payloads in the low bits of `raster`: one in sample order, and one in
+/// the order each of `passphrases` scatters the samples in.
+fn scan_pixels(raster: &Raster, passphrases: &[String]) -> Vec<Finding> {
+    // A plain payload leaves no trace in the pixels; the length read first
+    // is what tells it from untouched ones.
+    let mut findings = match lsb::extract(raster, &LsbOptions::default()) {
+        Ok(payload) => describe_stored("pixels", vec![Stored::from_joined(payload)], passphrases),
+        Err(_) => Vec::new(),
+    };
+    // Any order gives some length, so a keyed payload only counts if it is
+    // one of ours.
+    for (line, passphrase) in passphrases.iter().enumerate() {
+        let options = LsbOptions {
+            key: Some(passphrase.as_bytes().to_vec()),
+            ..LsbOptions::default()
+        };
+        let Ok(payload) = lsb::extract(raster, &options) else {
+            continue;
+        };
+        let (payload, vote) = Stored::from_joined(payload).resolve();
+        if signatures::identify(&payload).is_some() {
+            let location = format!("pixels, key on line {}", line + 1);
+            findings.push(describe_payload(location, payload, vote, passphrases));
+            break;
+        }
+    }
+    findings
+}
+
+fn describe_stored(location: &str, stored: Vec<Stored>, passphrases: &[String]) -> Vec<Finding> {
+    stored
+        .into_iter()
+        .map(|stored| {
+            let (payload, vote) = stored.resolve();
+            describe_payload(location.to_string(), payload, vote, passphrases)
+        })
+        .collect()
+}
+
+/// What `payload` is and whether one of `passphrases` opens its tag.
+fn describe_payload(
+    location: String,
+    payload: Vec<u8>,
+    vote: Option<Vote>,
+    passphrases: &[String],
+) -> Finding {
+    let mut what = signatures::identify(&payload)
+        .map_or("unknown data", |signature| signature.description)
+        .to_string();
+    if let Some(vote) = vote {
+        what = format!("{what}, {} of {} copies", vote.found, vote.count);
+    }
+    Finding {
+        location,
+        bytes: Some(payload.len()),
+        payload: what,
+        tag: tag_status(payload, passphrases),
+    }
+}
+
+/// Whether the envelope inside `payload` carries an integrity tag, and which
+/// of `passphrases`, counting lines from 1, it was made with.
+fn tag_status(payload: Vec<u8>, passphrases: &[String]) -> String {
+    let Some(envelope) = unwrap_envelope(payload) else {
+        return "-".to_string();
+    };
+    match Envelope::from_bytes(&envelope, None) {
+        Ok(_) => "none".to_string(),
+        Err(EnvelopeError::KeyRequired) if passphrases.is_empty() => "tagged".to_string(),
+        Err(EnvelopeError::KeyRequired) => passphrases
+            .iter()
+            .position(|passphrase| {
+                Envelope::from_bytes(&envelope, Some(passphrase.as_bytes())).is_ok()
+            })
+            .map_or_else(
+                || "tagged, no passphrase fits".to_string(),
+                |line| format!("tagged, passphrase on line {}", line + 1),
+            ),
+        Err(_) => "damaged".to_string(),
+    }
+}
+
+/// The envelope inside error correction, or `None` if there is none, as in
+/// a piece of a spread payload.
+fn unwrap_envelope(payload: Vec<u8>) -> Option<Vec<u8>> {
+    if fec::is_protected(&payload) {
+        return unwrap_envelope(fec::decode(&payload).ok()?.0);
+    }
+    Envelope::is_envelope(&payload).then_some(payload)
+}
+
+pub fn watch(args: WatchArgs) -> Result<()> {
+    if !args.dir.is_dir() {
+        bail!("{} is not a directory", args.dir.display());
+    }
+    let mut action = args.action;
+    if let WatchAction::Decode(decode) = &mut action {
+        decode.key = read_key_once(decode.key.take(), false)?;
+    }
+    let exclude = &action.batch().exclude;
+
+    // Images already run on or left alone, as they were then, and those
+    // waiting to be seen the same twice.
+    let mut done = HashMap::new();
+    let mut waiting = HashMap::new();
+    for (path, stamp) in watched_images(&args.dir, exclude)? {
+        if args.existing {
+            waiting.insert(path, stamp);
+        } else {
+            done.insert(path, stamp);
+        }
+    }
+    eoutln!("Watching {} for new images", args.dir.display())?;
+    loop {
+        thread::sleep(args.interval);
+        let images = watched_images(&args.dir, exclude)?;
+        // An image removed and added again is a new one.
+        done.retain(|path, _| images.contains_key(path));
+        waiting.retain(|path, _| images.contains_key(path));
+        for (path, stamp) in images {
+            // One changed since it was run on is new again.
+            if done.get(&path) == Some(&stamp) {
+                continue;
+            }
+            if waiting.get(&path) != Some(&stamp) {
+                waiting.insert(path, stamp);
+                continue;
+            }
+            waiting.remove(&path);
+            if let Err(err) = run_watch_action(&action, &path) {
+                eoutln!("Error: {}: {err:#}", path.display())?;
+            }
+            // What the command rewrote is no change to run on again.
+            let stamp = image_stamp(&path).unwrap_or(stamp);
+            done.insert(path, stamp);
+        }
+    }
+}
+
+/// How `watch` tells an image has changed: its size and when it was last
+/// modified.
+type Stamp = (u64, Option<SystemTime>);
+
+/// The PNG files in `dir` `watch` looks after, with their stamps.
+fn watched_images(dir: &Path, exclude: &[PathPattern]) -> Result<BTreeMap<PathBuf, Stamp>> {
+    let mut images = BTreeMap::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.')
+            || !batch::is_png_name(&path)
+            || batch::is_excluded(&path, exclude)
+        {
+            continue;
+        }
+        // Gone again, or not a file.
+        if let Some(stamp) = image_stamp(&path) {
+            images.insert(path, stamp);
+        }
+    }
+    Ok(images)
+}
+
+fn image_stamp(path: &Path) -> Option<Stamp> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => Some((metadata.len(), metadata.modified().ok())),
+        _ => None,
+    }
+}
+
+fn run_watch_action(action: &WatchAction, path: &Path) -> Result<()> {
+    let file = path.to_path_buf();
+    match action.clone() {
+        WatchAction::Decode(args) => {
+            outln!("{}:", path.display())?;
+            decode(DecodeArgs { file, ..args })
+        }
+        WatchAction::Verify(args) => verify(VerifyArgs { file, ..args }),
+        WatchAction::Scrub(args) => {
+            outln!("{}:", path.display())?;
+            scrub(ScrubArgs { file, ..args })
+        }
+    }
+}
+
+pub fn verify(args: VerifyArgs) -> Result<()> {
+    if let Some(files) = batch::files(&args.file, &args.batch)? {
+        return batch::run(&files, &args.batch, true, |file| {
+            verify(VerifyArgs {
+                file: file.to_path_buf(),
+                batch: Batch::member(),
+            })
+        });
+    }
+    let bytes = read_png_bytes(&args.file)?;
+    let problems = verify::verify(&bytes);
+    if problems.is_empty() {
+        outln!("{}: ok", args.file.display())?;
+        return Ok(());
+    }
+
+    for problem in &problems {
+        outln!("{}: {problem}", args.file.display())?;
+    }
+    bail!(
+        "{} problem(s) found in {}",
+        problems.len(),
+        args.file.display()
+    )
+}
+
+pub fn diff(args: DiffArgs) -> Result<()> {
+    let old = Png::from_bytes_with(&read_png_bytes(&args.old)?, &ParseOptions::lenient())?;
+    let new = Png::from_bytes_with(&read_png_bytes(&args.new)?, &ParseOptions::lenient())?;
+    let (old, new) = (old.chunks(), new.chunks());
+    let describe = |chunk: &Chunk| {
+        format!(
+            "{} ({} bytes, crc {:08x})",
+            chunk.chunk_type(),
+            chunk.length(),
+            chunk.crc()
+        )
+    };
+
+    let changes = diff::diff(old, new);
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    for change in &changes {
+        match *change {
+            Change::Same { old: i, .. } if args.all => outln!("  {i} {}", describe(&old[i]))?,
+            Change::Same { .. } => {}
+            Change::Removed { old: i } => {
+                removed += 1;
+                outln!("- {i} {}", describe(&old[i]))?;
+            }
+            Change::Added { new: j } => {
+                added += 1;
+                outln!("+ {j} {}", describe(&new[j]))?;
+            }
+            Change::Changed { old: i, new: j } => {
+                changed += 1;
+                let (old, new) = (&old[i], &new[j]);
+                outln!(
+                    "~ {i} -> {j} {}: {} -> {} bytes, crc {:08x} -> {:08x}",
+                    old.chunk_type(),
+                    old.length(),
+                    new.length(),
+                    old.crc(),
+                    new.crc()
+                )?;
+                if args.data {
+                    print_data_diff(old.data(), new.data())?;
+                }
+            }
+        }
+    }
+
+    if added + removed + changed == 0 {
+        outln!("No differences")?;
+    } else {
+        outln!("{added} added, {removed} removed, {changed} changed")?;
+    }
+    Ok(())
+}
+
+pub fn compare(args: CompareArgs) -> Result<()> {
+    let baseline =
+        Png::from_bytes_with(&read_png_bytes(&args.baseline)?, &ParseOptions::lenient())?;
+    let png = Png::from_bytes_with(&read_png_bytes(&args.file)?, &ParseOptions::lenient())?;
+    let field = |label: &str, value: String| outln!("{:<20}{value}", format!("{label}:"));
+    let more = |line: String| outln!("{:<20}{line}", "");
+
+    // IDAT changes whenever the pixels do; those are compared decoded below.
+    let (old, new) = (baseline.chunks(), png.chunks());
+    let changes: Vec<String> =
+        diff::diff(old, new)
+            .into_iter()
+            .filter_map(|change| match change {
+                Change::Same { .. } => None,
+                Change::Removed { old: i } if old[i].chunk_type() != &ChunkType::IDAT => Some(
+                    format!("- {i} {} ({} bytes)", old[i].chunk_type(), old[i].length()),
+                ),
+                Change::Added { new: j } if new[j].chunk_type() != &ChunkType::IDAT => Some(
+                    format!("+ {j} {} ({} bytes)", new[j].chunk_type(), new[j].length()),
+                ),
+                Change::Changed { old: i, new: j } if old[i].chunk_type() != &ChunkType::IDAT => {
+                    Some(format!(
+                        "~ {i} -> {j} {}: {} -> {} bytes",
+                        old[i].chunk_type(),
+                        old[i].length(),
+                        new[j].length()
+                    ))
+                }
+                _ => None,
+            })
+            .collect();
+    match changes.split_first() {
+        None => field("Chunks", "no differences besides IDAT".to_string())?,
+        Some((first, rest)) => {
+            field("Chunks", first.clone())?;
+            for line in rest {
+                more(line.clone())?;
+            }
+        }
+    }
+
+    let (old, new) = (Raster::from_png(&baseline)?, Raster::from_png(&png)?);
+    let pixels = match diff::diff_pixels(&old, &new) {
+        Ok(pixels) => pixels,
+        Err(DiffError::FormatMismatch(old, new)) => {
+            field("Pixels", format!("not compared, {old} against {new}"))?;
+            return Ok(());
+        }
+    };
+    if pixels.changed_samples == 0 {
+        field("Pixels", "identical".to_string())?;
+        return Ok(());
+    }
+    field(
+        "Pixels",
+        format!(
+            "{} of {} samples changed ({:.2}%), in {} pixels, {} bits in all",
+            pixels.changed_samples,
+            pixels.samples,
+            100.0 * pixels.changed_samples as f64 / pixels.samples as f64,
+            pixels.changed_pixels,
+            pixels.changed_bits()
+        ),
+    )?;
+    let bits: Vec<String> = pixels
+        .bits
+        .iter()
+        .enumerate()
+        .filter(|(_, &count)| count > 0)
+        .map(|(bit, count)| format!("bit {bit}: {count}"))
+        .collect();
+    field("Bits changed", bits.join(", "))?;
+    let names = old.ihdr().color_type.channel_names();
+    let channels: Vec<String> = names
+        .iter()
+        .zip(&pixels.channels)
+        .map(|(name, count)| format!("{name} {count}"))
+        .collect();
+    field("Channels", channels.join(", "))?;
+    if let Some((x0, y0, x1, y1)) = pixels.bounds {
+        field("Region", format!("x {x0}..={x1}, y {y0}..={y1}"))?;
+    }
+    const MAX_RUNS: usize = 8;
+    let mut runs: Vec<String> = pixels
+        .rows
+        .iter()
+        .take(MAX_RUNS)
+        .map(|&(first, last)| match first == last {
+            true => first.to_string(),
+            false => format!("{first}..={last}"),
+        })
+        .collect();
+    if pixels.rows.len() > MAX_RUNS {
+        runs.push(format!("and {} more runs", pixels.rows.len() - MAX_RUNS));
+    }
+    field("Rows", runs.join(", "))?;
+
+    let Some(lsb_bits) = pixels.lsb_bits() else {
+        let highest = pixels
+            .bits
+            .iter()
+            .rposition(|&count| count > 0)
+            .unwrap_or(0);
+        field(
+            "Pattern",
+            format!(
+                "not LSB embedding: bit {highest} changed and samples moved by up to {}",
+                pixels.max_delta
+            ),
+        )?;
+        return Ok(());
+    };
+    // Each payload bit matches the one it replaces half the time.
+    let expected = 1.0 - 0.5f64.powi(i32::from(lsb_bits));
+    let plural = if lsb_bits == 1 { "" } else { "s" };
+    field(
+        "Pattern",
+        format!("only the lowest {lsb_bits} bit{plural} changed, as LSB embedding does"),
+    )?;
+    more(format!(
+        "{:.0}% of the samples from the first change to the last differ, random payload bits would change {:.0}%",
+        pixels.density() * 100.0,
+        expected * 100.0
+    ))?;
+    // Sequential embedding starts with the payload length, whose high bits
+    // are mostly zero and may match the image, so the first change can come
+    // a little after the first sample.
+    if pixels
+        .span
+        .is_some_and(|(first, _)| first < (8 * lsb::LENGTH_LEN) as u64)
+    {
+        more("changes start at the first pixels, as sequential embedding does".to_string())?;
+    }
+    let payload_bits = pixels.changed_samples as f64 / expected * f64::from(lsb_bits);
+    field(
+        "Payload estimate",
+        format!("about {} bytes", (payload_bits / 8.0) as u64),
+    )?;
+    Ok(())
+}
+
+/// Prints the lines of the two data dumps that differ, old above new.
+fn print_data_diff(old: &[u8], new: &[u8]) -> io::Result<()> {
+    let old_dump = hex::dump(old, 0);
+    let new_dump = hex::dump(new, 0);
+    // Leave out the closing offset lines; the sizes are already reported.
+    let old_lines: Vec<&str> = old_dump.lines().filter(|line| line.len() > 8).collect();
+    let new_lines: Vec<&str> = new_dump.lines().filter(|line| line.len() > 8).collect();
+
+    for i in 0..old_lines.len().max(new_lines.len()) {
+        let (old_line, new_line) = (old_lines.get(i), new_lines.get(i));
+        if old_line == new_line {
+            continue;
+        }
+        if let Some(line) = old_line {
+            outln!("    - {line}")?;
+        }
+        if let Some(line) = new_line {
+            outln!("    + {line}")?;
+        }
+    }
+    Ok(())
+}
+
+pub fn hexdump(args: HexdumpArgs) -> Result<()> {
+    let bytes = read_png_bytes(&args.file)?;
+    let png = Png::from_bytes_with(&bytes, &ParseOptions::lenient())?;
+
+    let matching: Vec<_> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| *chunk.chunk_type() == args.chunk_type)
+        .collect();
+    let Some(chunk) = matching.get(args.nth - 1) else {
+        match matching.len() {
+            0 => bail!("no {} chunk found", args.chunk_type),
+            count => bail!(
+                "only {count} {} chunk(s) found, there is no chunk {}",
+                args.chunk_type,
+                args.nth
+            ),
+        }
+    };
+
+    let data = chunk.data();
+    let start = args.range.start;
+    let end = args.range.end.map_or(data.len(), |end| end.min(data.len()));
+    if start > data.len() {
+        bail!(
+            "range starts at {start} but the chunk has only {} bytes",
+            data.len()
+        );
+    }
+    out!("{}", hex::dump(&data[start..end], start))?;
+
+    Ok(())
+}
+
+pub fn remove(args: RemoveArgs) -> Result<()> {
+    if let Some(files) = batch::files(&args.file, &args.batch)? {
+        if args.output.is_some() {
+            bail!("-o takes a single image, not several");
+        }
+        return batch::run(&files, &args.batch, false, |file| {
+            remove(RemoveArgs {
+                file: file.to_path_buf(),
+                batch: Batch::member(),
+                ..args.clone()
+            })
+        });
+    }
+    let original = read_png_bytes(&args.file)?;
+    let png = Png::from_bytes(&original)?;
+    let output = args.output.as_deref().unwrap_or(&args.file);
+
+    let matching: Vec<usize> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| args.pattern.matches(&chunk.chunk_type().to_string()))
+        .map(|(index, _)| index)
+        .collect();
+    let selected = match args.selection {
+        Selection::First => matching.into_iter().take(1).collect(),
+        Selection::Nth(nth) => match matching.get(nth - 1) {
+            Some(&index) => vec![index],
+            None => bail!(
+                "only {} chunk(s) match {}, there is no chunk {nth}",
+                matching.len(),
+                args.pattern
+            ),
+        },
+        Selection::All => matching,
+    };
+    if selected.is_empty() {
+        bail!("no chunk matches {}", args.pattern);
+    }
+
+    for &index in &selected {
+        let chunk = &png.chunks()[index];
+        if chunk.chunk_type().is_critical() && !args.force {
+            bail!(
+                "refusing to remove critical chunk {index} ({}), use --force",
+                chunk.chunk_type()
+            );
+        }
+    }
+    let png = without_chunks(&png, &selected, output, args.dry_run)?;
+
+    if args.dry_run {
+        report_dry_run(output, original.len(), &png)?;
+        return Ok(());
+    }
+    write_png(output, &png, args.backup.as_deref())
+}
+
+pub fn reorder(args: ReorderArgs) -> Result<()> {
+    let original = read_png_bytes(&args.file)?;
+    let png = Png::from_bytes(&original)?;
+    let output = args.output.as_deref().unwrap_or(&args.file);
+
+    let order = ordering::canonical_order(png.chunks());
+    let verb = if args.dry_run { "Would move" } else { "Moved" };
+    let mut moved = 0;
+    for (new_index, &old_index) in order.iter().enumerate() {
+        if new_index != old_index {
+            moved += 1;
+            report(
+                output,
+                args.dry_run,
+                &format!(
+                    "{verb} chunk {old_index} ({}) to {new_index}",
+                    png.chunks()[old_index].chunk_type()
+                ),
+            )?;
+        }
+    }
+    if moved == 0 {
+        report(output, args.dry_run, "Chunks are already in order")?;
+        if is_stdio(output) && !args.dry_run {
+            write_png(output, &png, None)?;
+        }
+        return Ok(());
+    }
+    let png = Png::from_chunks(
+        order
+            .into_iter()
+            .map(|index| png.chunks()[index].clone())
+            .collect(),
+    );
+
+    if args.dry_run {
+        report_dry_run(output, original.len(), &png)?;
+        return Ok(());
+    }
+    write_png(output, &png, args.backup.as_deref())
+}
+
+pub fn touch_time(args: TouchTimeArgs) -> Result<()> {
+    let original = read_png_bytes(&args.file)?;
+    let mut png = Png::from_bytes(&original)?;
+    let stored = png
+        .chunk_by_type(TimeChunk::chunk_type())
+        .map(TimeChunk::try_from);
+
+    if args.show {
+        match stored {
+            Some(time) => outln!("{}", time?)?,
+            None => bail!("{} has no tIME chunk", args.file.display()),
+        }
+        return Ok(());
+    }
+
+    let time = match args.time {
+        Some(time) => time,
+        None => TimeChunk::now()?,
+    };
+    let output = args.output.as_deref().unwrap_or(&args.file);
+    let verb = if args.dry_run { "Would set" } else { "Set" };
+    match stored {
+        Some(Ok(old)) => report(
+            output,
+            args.dry_run,
+            &format!("{verb} tIME from {old} to {time}"),
+        )?,
+        _ => report(output, args.dry_run, &format!("{verb} tIME to {time}"))?,
+    }
+    if png.chunk_by_type(TimeChunk::chunk_type()).is_some() {
+        png.replace_chunk(&TimeChunk::chunk_type(), time.to_bytes().to_vec())?;
+    } else {
+        png.insert_chunk(time.to_chunk(), &InsertPosition::BeforeIend)?;
+    }
+
+    if args.dry_run {
+        report_dry_run(output, original.len(), &png)?;
+        return Ok(());
+    }
+    write_png(output, &png, args.backup.as_deref())
+}
+
+pub fn exif(args: ExifArgs) -> Result<()> {
+    let original = read_png_bytes(&args.file)?;
+    let png = Png::from_bytes(&original)?;
+    let output = args.output.as_deref().unwrap_or(&args.file);
+    let found: Vec<usize> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type() == &ExifChunk::chunk_type())
+        .map(|(index, _)| index)
+        .collect();
+    let first = found
+        .first()
+        .map(|&index| (index, ExifChunk::try_from(&png.chunks()[index])));
+
+    let png = match args.action {
+        ExifAction::List => {
+            let Some((index, exif)) = first else {
+                bail!("{} has no eXIf chunk", args.file.display());
+            };
+            let exif = exif?;
+            outln!(
+                "chunk {index}: {} bytes, {:?} byte order",
+                exif.data().len(),
+                exif.byte_order()
+            )?;
+            for entry in exif.entries()? {
+                if let Some(name) = entry.name() {
+                    match entry.text {
+                        Some(text) => outln!("  {name}: {text}")?,
+                        None => outln!("  {name}")?,
+                    }
+                }
+            }
+            return Ok(());
+        }
+        ExifAction::Extract(path) => {
+            let Some((_, exif)) = first else {
+                bail!("{} has no eXIf chunk", args.file.display());
+            };
+            return write_output(&path, exif?.data());
+        }
+        ExifAction::Strip => {
+            if found.is_empty() {
+                report(output, args.dry_run, "No eXIf chunk found")?;
+                if is_stdio(output) && !args.dry_run {
+                    write_png(output, &png, None)?;
+                }
+                return Ok(());
+            }
+            without_chunks(&png, &found, output, args.dry_run)?
+        }
+        ExifAction::Inject(path) => {
+            let data =
+                fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+            let exif = ExifChunk::new(data)?;
+            let verb = if args.dry_run {
+                "Would store"
+            } else {
+                "Stored"
+            };
+            report(
+                output,
+                args.dry_run,
+                &format!("{verb} {} bytes of Exif data", exif.data().len()),
+            )?;
+            // eXIf is unique, so every existing one makes way for the new blob.
+            let mut png = without_chunks(&png, &found, output, args.dry_run)?;
+            let position = match found.first() {
+                Some(&index) => InsertPosition::Index(index),
+                None => InsertPosition::BeforeType(ChunkType::IDAT),
+            };
+            png.insert_chunk(exif.to_chunk(), &position)?;
+            png
+        }
+    };
+
+    if args.dry_run {
+        report_dry_run(output, original.len(), &png)?;
+        return Ok(());
+    }
+    write_png(output, &png, args.backup.as_deref())
+}
+
+pub fn scrub(args: ScrubArgs) -> Result<()> {
+    if let Some(files) = batch::files(&args.file, &args.batch)? {
+        if args.output.is_some() {
+            bail!("-o takes a single image, not several");
+        }
+        return batch::run(&files, &args.batch, false, |file| {
+            scrub(ScrubArgs {
+                file: file.to_path_buf(),
+                batch: Batch::member(),
+                ..args.clone()
+            })
+        });
+    }
+    let original = read_png_bytes(&args.file)?;
+    let png = Png::from_bytes(&original)?;
+    let output = args.output.as_deref().unwrap_or(&args.file);
+
+    let selected: Vec<usize> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| {
+            let chunk_type = chunk.chunk_type();
+            !chunk_type.is_critical()
+                && !inspect::is_registered(chunk_type)
+                && !args.keep.contains(chunk_type)
+        })
+        .map(|(index, _)| index)
+        .collect();
+    if selected.is_empty() {
+        report(output, args.dry_run, "No non-standard chunks found")?;
+        if is_stdio(output) && !args.dry_run {
+            write_png(output, &png, None)?;
+        }
+        return Ok(());
+    }
+    let png = without_chunks(&png, &selected, output, args.dry_run)?;
+
+    if args.dry_run {
+        report_dry_run(output, original.len(), &png)?;
+        return Ok(());
+    }
+    write_png(output, &png, args.backup.as_deref())
+}
+
+pub fn strip_metadata(args: StripMetadataArgs) -> Result<()> {
+    let original = read_png_bytes(&args.file)?;
+    let png = Png::from_bytes(&original)?;
+    let output = args.output.as_deref().unwrap_or(&args.file);
+
+    let selected: Vec<usize> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| {
+            let chunk_type = chunk.chunk_type();
+            let kept_keyword = TextEntry::from_chunk(chunk)
+                .is_some_and(|text| args.keep_keywords.contains(&text.keyword));
+            inspect::is_metadata(chunk_type) && !args.keep.contains(chunk_type) && !kept_keyword
+        })
+        .map(|(index, _)| index)
+        .collect();
+    if selected.is_empty() {
+        report(output, args.dry_run, "No metadata chunks found")?;
+        if is_stdio(output) && !args.dry_run {
+            write_png(output, &png, None)?;
+        }
+        return Ok(());
+    }
+    let png = without_chunks(&png, &selected, output, args.dry_run)?;
+
+    if args.dry_run {
+        report_dry_run(output, original.len(), &png)?;
+        return Ok(());
+    }
+    write_png(output, &png, args.backup.as_deref())
+}
+
+pub fn copy_chunks(args: CopyChunksArgs) -> Result<()> {
+    if is_stdio(&args.from) && is_stdio(&args.to) {
+        bail!("only one of --from and --to can be read from stdin");
+    }
+    let source = Png::from_bytes(&read_png_bytes(&args.from)?)?;
+    let original = read_png_bytes(&args.to)?;
+    let mut png = Png::from_bytes(&original)?;
+    let output = args.output.as_deref().unwrap_or(&args.to);
+
+    let selected: Vec<(usize, &Chunk)> = source
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| args.chunk_types.contains(chunk.chunk_type()))
+        .collect();
+    if selected.is_empty() {
+        bail!("no chunk of the given types in {}", args.from.display());
+    }
+
+    let critical = |png: &Png| -> Vec<Chunk> {
+        png.chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().is_critical())
+            .cloned()
+            .collect()
+    };
+    let same_image = critical(&source)
+        .iter()
+        .map(Chunk::as_bytes)
+        .eq(critical(&png).iter().map(Chunk::as_bytes));
+    for (index, chunk) in &selected {
+        let chunk_type = chunk.chunk_type();
+        if chunk_type.is_critical() {
+            bail!("refusing to copy critical chunk {index} ({chunk_type})");
+        }
+        if !chunk_type.is_safe_to_copy() && !same_image && !args.force {
+            bail!(
+                "chunk {index} ({chunk_type}) is unsafe to copy into an image with other \
+                 critical chunks, use --force"
+            );
+        }
+    }
+
+    let first_idat = |png: &Png| {
+        png.chunks()
+            .iter()
+            .position(|chunk| &chunk.chunk_type().bytes() == b"IDAT")
+    };
+    let source_idat = first_idat(&source).unwrap_or(source.chunks().len());
+    let mut before_idat = match first_idat(&png) {
+        Some(index) => index,
+        None => png.position_index(&InsertPosition::BeforeIend)?,
+    };
+    let verb = if args.dry_run { "Would copy" } else { "Copied" };
+    for (index, chunk) in selected {
+        let position = if index < source_idat {
+            before_idat += 1;
+            InsertPosition::Index(before_idat - 1)
+        } else {
+            InsertPosition::BeforeIend
+        };
+        let inserted = png.insert_chunk(chunk.clone(), &position)?;
+        report(
+            output,
+            args.dry_run,
+            &format!(
+                "{verb} chunk {index} ({}, {} bytes) to index {inserted}",
+                chunk.chunk_type(),
+                chunk.length()
+            ),
+        )?;
+    }
+
+    if args.dry_run {
+        report_dry_run(output, original.len(), &png)?;
+        return Ok(());
+    }
+    write_png(output, &png, args.backup.as_deref())
+}
+
+pub fn export_chunks(args: ExportChunksArgs) -> Result<()> {
+    let bytes = read_png_bytes(&args.file)?;
+    let png = Png::from_bytes_with(&bytes, &ParseOptions::lenient())?;
+    let summaries = inspect::summarize(&png);
+
+    fs::create_dir_all(&args.dir)
+        .with_context(|| format!("failed to create {}", args.dir.display()))?;
+    let width = png
+        .chunks()
+        .len()
+        .saturating_sub(1)
+        .to_string()
+        .len()
+        .max(3);
+    let mut entries = Vec::new();
+    for (chunk, summary) in png.chunks().iter().zip(&summaries) {
+        let name = format!("{:0width$}_{}.bin", summary.index, summary.chunk_type);
+        let path = args.dir.join(&name);
+        let mut file = File::create_new(&path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        file.write_all(chunk.data())?;
+        file.sync_all()?;
+
+        entries.push(Value::object([
+            ("index", Value::from(summary.index)),
+            ("file", Value::from(name)),
+            ("type", Value::from(summary.chunk_type.to_string())),
+            ("offset", Value::from(summary.offset)),
+            ("length", Value::from(summary.length)),
+            ("crc", Value::from(format!("{:08x}", summary.crc))),
+            ("crc_valid", Value::from(summary.crc_valid)),
+        ]));
+    }
+
+    let manifest = Value::object([
+        ("source", Value::from(args.file.display().to_string())),
+        ("signature", Value::from(hex::encode(png.header()))),
+        ("chunks", Value::Array(entries)),
+    ]);
+    let path = args.dir.join(MANIFEST_NAME);
+    let mut file =
+        File::create_new(&path).with_context(|| format!("failed to create {}", path.display()))?;
+    writeln!(file, "{}", manifest.to_string_pretty())?;
+    file.sync_all()?;
+
+    outln!(
+        "Exported {} chunk(s) to {}",
+        summaries.len(),
+        args.dir.display()
+    )?;
+    Ok(())
+}
+
+pub fn import_chunks(args: ImportChunksArgs) -> Result<()> {
+    let chunks = read_manifest(&args.dir)?;
+
+    let (mut png, original_len, output) = match &args.into {
+        Some(into) => {
+            let original = read_png_bytes(into)?;
+            let output = args.output.as_deref().unwrap_or(into);
+            (Png::from_bytes(&original)?, original.len(), output)
+        }
+        None => (
+            Png::from_chunks(Vec::new()),
+            0,
+            args.output.as_deref().expect("required without --into"),
+        ),
+    };
+    let report = |line: String| report(output, args.dry_run, &line);
+
+    if args.into.is_none() {
+        report(format!("Read {} chunk(s)", chunks.len()))?;
+        png = Png::from_chunks(chunks);
+    } else {
+        let mut next = png.position_index(&args.position)?;
+        for chunk in chunks {
+            if chunk.chunk_type().is_critical() {
+                report(format!("Skipped critical chunk {}", chunk.chunk_type()))?;
+                continue;
+            }
+            report(format!(
+                "Inserted {} ({} bytes) at index {next}",
+                chunk.chunk_type(),
+                chunk.length()
+            ))?;
+            png.insert_chunk(chunk, &InsertPosition::Index(next))?;
+            next += 1;
+        }
+    }
+
+    if args.dry_run {
+        report_dry_run(output, original_len, &png)?;
+        return Ok(());
+    }
+    write_png(output, &png, args.backup.as_deref())
+}
+
+/// Reads the chunks an export manifest lists, from the files next to it.
+fn read_manifest(dir: &Path) -> Result<Vec<Chunk>> {
+    let path = dir.join(MANIFEST_NAME);
+    let text =
+        fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+    let manifest: Value = text
+        .parse()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    let entries = manifest
+        .get("chunks")
+        .and_then(Value::as_array)
+        .with_context(|| format!("{} has no chunks list", path.display()))?;
+
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let field = |name: &str| {
+                entry
+                    .get(name)
+                    .and_then(Value::as_str)
+                    .with_context(|| format!("chunk {i} of {} has no {name}", path.display()))
+            };
+            let chunk_type = field("type")?.parse()?;
+            let name = field("file")?;
+            // Only plain names, so a manifest cannot reach outside its directory.
+            let mut components = Path::new(name).components();
+            if !matches!(
+                (components.next(), components.next()),
+                (Some(Component::Normal(_)), None)
+            ) {
+                bail!(
+                    "chunk {i} of {} names a file outside it: {name}",
+                    path.display()
+                );
+            }
+            let data_path = dir.join(name);
+            let data = fs::read(&data_path)
+                .with_context(|| format!("failed to read {}", data_path.display()))?;
+            Ok(Chunk::new(chunk_type, data))
+        })
+        .collect()
+}
+
+pub fn replace(args: ReplaceArgs) -> Result<()> {
+    let original = read_png_bytes(&args.file)?;
+    let mut png = Png::from_bytes(&original)?;
+    let output = args.output.as_deref().unwrap_or(&args.file);
+
+    let data = match args.input {
+        Input::Message(message) => decode_message(message, args.format)?,
+        Input::Files(paths) => {
+            let [path] = &paths[..] else {
+                bail!("replace takes a single --file");
+            };
+            fs::read(path).with_context(|| format!("failed to read {}", path.display()))?
+        }
+    };
+
+    let matching: Vec<usize> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| *chunk.chunk_type() == args.chunk_type)
+        .map(|(index, _)| index)
+        .collect();
+    let Some(&index) = matching.get(args.nth - 1) else {
+        match matching.len() {
+            0 => bail!("no {} chunk found", args.chunk_type),
+            count => bail!(
+                "only {count} {} chunk(s) found, there is no chunk {}",
+                args.chunk_type,
+                args.nth
+            ),
+        }
+    };
+
+    let new_len = data.len();
+    let old = png.replace_chunk_at(index, data)?;
+    let verb = if args.dry_run {
+        "Would replace"
+    } else {
+        "Replaced"
+    };
+    report(
+        output,
+        args.dry_run,
+        &format!(
+            "{verb} chunk {index} ({}): {} -> {new_len} bytes",
+            args.chunk_type,
+            old.length()
+        ),
+    )?;
+
+    if args.dry_run {
+        report_dry_run(output, original.len(), &png)?;
+        return Ok(());
+    }
+    write_png(output, &png, args.backup.as_deref())
+}
+
+/// Copies `png` without the chunks at `selected`, reporting each one.
+fn without_chunks(png: &Png, selected: &[usize], output: &Path, dry_run: bool) -> io::Result<Png> {
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    for &index in selected {
+        let chunk = &png.chunks()[index];
+        report(
+            output,
+            dry_run,
+            &format!(
+                "{verb} chunk {index} ({}, {} bytes)",
+                chunk.chunk_type(),
+                chunk.length()
+            ),
+        )?;
+    }
+
+    let kept = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !selected.contains(index))
+        .map(|(_, chunk)| chunk.clone())
+        .collect();
+    Ok(Png::from_chunks(kept))
+}
+
+/// The bytes a message given on the command line spells out.
+fn decode_message(message: String, format: Format) -> Result<Vec<u8>> {
+    Ok(match format {
+        Format::Text => message.into_bytes(),
+        Format::Base64 => base64::decode(&message)?,
+        Format::Hex => hex::decode(&message)?,
+    })
+}
+
+/// Prints a status line, on stderr when stdout is taken by an image being written.
+fn report(output: &Path, dry_run: bool, line: &str) -> io::Result<()> {
+    if is_stdio(output) && !dry_run {
+        eoutln!("{line}")
+    } else {
+        outln!("{line}")
+    }
+}
+
+/// Turns an indexed image into truecolor, whose samples LSB mode can change,
+/// dropping the chunks that describe palette indices. Returns whether it was
+/// indexed.
+fn promote_palette(png: &mut Png, raster: &mut Raster) -> Result<bool> {
+    if raster.ihdr().color_type != ColorType::Indexed {
+        return Ok(false);
+    }
+    let palette = png
+        .palette()?
+        .context("indexed image without a PLTE chunk")?;
+    raster.promote_to_truecolor(&palette)?;
+    for chunk_type in [
+        ChunkType::PLTE,
+        ChunkType::TRNS,
+        ChunkType::BKGD,
+        ChunkType::HIST,
+        ChunkType::SBIT,
+    ] {
+        png.remove_all_chunks(chunk_type);
+    }
+    Ok(true)
+}
+
+/// Describes the write a mutating command would have made under `--dry-run`.
+fn report_dry_run(output: &Path, original_len: usize, png: &Png) -> io::Result<()> {
+    let destination = if is_stdio(output) {
+        "stdout".to_string()
+    } else {
+        output.display().to_string()
+    };
+    outln!(
+        "Would write {destination}: {original_len} -> {} bytes (dry run, nothing written)",
+        png.as_bytes().len()
+    )
+}
+
+/// Resolves the key for a payload's integrity tag, prompting if needed.
+/// `confirm` asks for a prompted passphrase twice, for commands that create a tag.
+/// `stdin_in_use` rules out reading the passphrase from stdin when the image is.
+fn read_key(
+    source: Option<&KeySource>,
+    confirm: bool,
+    stdin_in_use: bool,
+) -> Result<Option<Vec<u8>>> {
+    let key = match source {
+        None => return Ok(None),
+        Some(KeySource::Passphrase(passphrase)) => passphrase.as_bytes().to_vec(),
+        Some(KeySource::Keyfile(path)) => sha256::digest(&fs::read(path)?).to_vec(),
+        Some(KeySource::Fd(fd)) => prompt::read_passphrase_fd(*fd)?.into_bytes(),
+        Some(KeySource::Prompt) => match passphrase_from_env(|name| env::var(name).ok()) {
+            Some(passphrase) => passphrase.into_bytes(),
+            None if stdin_in_use => bail!(
+                "the image is read from stdin, give the passphrase with --passphrase-fd \
+                 or {PASSPHRASE_VAR}"
+            ),
+            _ => prompt::read_passphrase(confirm)?.into_bytes(),
+        },
+    };
+    Ok(Some(key))
+}
+
+/// The passphrase in `PASSPHRASE_VAR`, or else in `PASSWORD_VAR`, if either
+/// is set and not empty, `var` looking a variable up.
+fn passphrase_from_env(var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    [PASSPHRASE_VAR, PASSWORD_VAR]
+        .into_iter()
+        .find_map(|name| var(name).filter(|passphrase| !passphrase.is_empty()))
+}
+
+/// Reads a passphrase that can only be given once, from the terminal or a file
+/// descriptor, so a command run on several images asks for it only once.
+fn read_key_once(source: Option<KeySource>, confirm: bool) -> Result<Option<KeySource>> {
+    match source {
+        Some(KeySource::Prompt | KeySource::Fd(_)) => {
+            let key = read_key(source.as_ref(), confirm, false)?.unwrap_or_default();
+            let passphrase = String::from_utf8(key).context("passphrase is not valid UTF-8")?;
+            Ok(Some(KeySource::Passphrase(passphrase)))
+        }
+        source => Ok(source),
+    }
+}
+
+/// Reads a file to embed, along with the name and permissions to restore it with.
+fn read_input_file(path: &Path) -> Result<(FileInfo, Vec<u8>)> {
+    let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("{} has no usable file name", path.display()))?
+        .to_string();
+    FileInfo::validate_name(&name)?;
+
+    let info = FileInfo {
+        name,
+        size: data.len() as u64,
+        mode: file_mode(&fs::metadata(path)?),
+    };
+    Ok((info, data))
+}
+
+/// Restores an embedded file into `dir`, refusing to overwrite an existing file.
+fn extract_file(dir: &Path, member: &Member) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(&member.info.name);
+    let mut output =
+        File::create_new(&path).with_context(|| format!("failed to create {}", path.display()))?;
+    output.write_all(&member.data)?;
+    set_file_mode(&output, member.info.mode)?;
+    output.sync_all()?;
+
+    outln!("Extracted {} ({} bytes)", path.display(), member.data.len())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+#[cfg(unix)]
+fn set_file_mode(file: &File, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    file.set_permissions(fs::Permissions::from_mode(mode & 0o777))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_file_mode(file: &File, mode: u32) -> Result<()> {
+    let mut permissions = file.metadata()?.permissions();
+    permissions.set_readonly(mode & 0o200 == 0);
+    file.set_permissions(permissions)?;
+    Ok(())
+}
+
+/// What `decode --info` shows of a payload, leaving its body alone.
+fn print_envelope_info(envelope: &Envelope, wrapped: bool, tagged: bool) -> Result<()> {
+    let field = |label: &str, value: String| outln!("{:<20}{value}", format!("{label}:"));
+    let size = envelope.body.len();
+    let kind = if envelope.archive {
+        let files = Archive::from_bytes(&envelope.body)?.members().len();
+        format!("archive of {files} file(s), {size} bytes")
+    } else if envelope.file.is_some() {
+        format!("file, {size} bytes")
+    } else {
+        format!("message, {size} bytes")
+    };
+    field("Payload", kind)?;
+    if !wrapped {
+        field("Envelope", "none, raw bytes from another tool".to_string())?;
+    }
+    field("Compression", envelope.compression.to_string())?;
+    field(
+        "Integrity tag",
+        if tagged { "checked" } else { "none" }.to_string(),
+    )?;
+    if let Some(file) = &envelope.file {
+        field("File", format!("{} ({:04o})", file.name, file.mode))?;
+    }
+    if !envelope.padding.is_empty() {
+        field("Padding", format!("{} bytes", envelope.padding.len()))?;
+    }
+    let metadata = &envelope.metadata;
+    let texts = [
+        ("Creator", &metadata.creator),
+        ("Comment", &metadata.comment),
+        ("Content type", &metadata.content_type),
+    ];
+    for (label, text) in texts {
+        if let Some(text) = text {
+            field(label, text.clone())?;
+        }
+    }
+    if let Some(time) = metadata.created_at {
+        field("Created", time.to_string())?;
+    }
+    if let Some(time) = metadata.expires {
+        let expired = if metadata.is_expired(TimeChunk::now()?) {
+            " (expired)"
+        } else {
+            ""
+        };
+        field("Expires", format!("{time}{expired}"))?;
+    }
+    Ok(())
+}
+
+/// Writes a decoded message or file to `-o` or stdout in the requested format.
+/// Text only goes to a terminal when it is valid UTF-8.
+fn print_payload(args: &DecodeArgs, bytes: Vec<u8>) -> Result<()> {
+    let bytes = match args.format {
+        Format::Text => bytes,
+        Format::Base64 => (base64::encode(&bytes) + "\n").into_bytes(),
+        Format::Hex => hex::dump(&bytes, 0).into_bytes(),
+    };
+    match &args.output {
+        Some(output) => write_output(output, &bytes),
+        None if args.format == Format::Text => match String::from_utf8(bytes) {
+            Ok(message) => {
+                outln!("{message}")?;
+                Ok(())
+            }
+            Err(_) => bail!("payload is not valid UTF-8, use --base64 or -o <output>"),
+        },
+        None => write_output(Path::new("-"), &bytes),
+    }
+}
+
+/// Writes decoded bytes verbatim to `path`, or to stdout for `-`.
+fn write_output(path: &Path, bytes: &[u8]) -> Result<()> {
+    if is_stdio(path) {
+        let mut stdout = output::stdout();
+        stdout.write_all(bytes)?;
+        stdout.flush()?;
+    } else {
+        write_atomically(path, |writer| Ok(writer.write_all(bytes)?))?;
+    }
+    Ok(())
+}
+
+/// Whether `path` is `-`, standing for stdin or stdout.
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+fn read_png_bytes(path: &Path) -> Result<Vec<u8>> {
+    if is_stdio(path) {
+        let mut bytes = Vec::new();
+        io::stdin().lock().read_to_end(&mut bytes)?;
+        return Ok(bytes);
+    }
+    fs::read(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// Writes the image to `path`, first copying an existing file to `path` + `backup`.
+fn write_png(path: &Path, png: &Png, backup: Option<&str>) -> Result<()> {
+    if is_stdio(path) {
+        let mut stdout = io::stdout().lock();
+        png.write_to(&mut stdout)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+
+    if let Some(suffix) = backup {
+        if path.exists() {
+            let mut backup_path = path.as_os_str().to_owned();
+            backup_path.push(suffix);
+            let backup_path = PathBuf::from(backup_path);
+            fs::copy(path, &backup_path).with_context(|| {
+                format!(
+                    "failed to back up {} to {}",
+                    path.display(),
+                    backup_path.display()
+                )
+            })?;
+        }
+    }
+
+    write_atomically(path, |writer| Ok(png.write_to(writer)?))
+}
+
+/// Writes `path` through a temporary file in the same directory that is renamed over
+/// it once complete, so an interrupted write never leaves a truncated file behind.
+/// An existing file keeps its permissions.
+fn write_atomically<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> Result<()>,
+{
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .with_context(|| format!("{} is not a file path", path.display()))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(".{}.tmp", process::id()));
+    let temp_path = dir.join(temp_name);
+
+    let result = File::create_new(&temp_path)
+        .with_context(|| format!("failed to create {}", temp_path.display()))
+        .and_then(|file| {
+            if let Ok(metadata) = fs::metadata(path) {
+                file.set_permissions(metadata.permissions())?;
+            }
+            let mut writer = BufWriter::new(&file);
+            write(&mut writer)?;
+            writer.flush()?;
+            drop(writer);
+            Ok(file.sync_all()?)
+        })
+        .and_then(|()| {
+            fs::rename(&temp_path, path)
+                .with_context(|| format!("failed to replace {}", path.display()))
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+
+    // Make the rename itself durable; not every platform can open a directory.
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{self, Command};
+
+    fn encode_args(line: &str) -> EncodeArgs {
+        match args::parse(line.split_whitespace().map(str::to_string)) {
+            Ok(Command::Encode(args)) => *args,
+            other => panic!("not an encode: {other:?}"),
+        }
+    }
+
+    fn decode_args(line: &str) -> DecodeArgs {
+        match args::parse(line.split_whitespace().map(str::to_string)) {
+            Ok(Command::Decode(args)) => args,
+            other => panic!("not a decode: {other:?}"),
+        }
+    }
+
+    /// A directory of its own for a test that writes files, removed after.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("png-secret-{name}-{}", process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        /// `name` inside the directory, as a string for a command line.
+        fn file(&self, name: &str) -> String {
+            self.0.join(name).display().to_string()
+        }
+
+        /// The names of the files in the directory.
+        fn names(&self) -> Vec<String> {
+            let mut names: Vec<String> = fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn carrier_bytes() -> Vec<u8> {
+        PngBuilder::new(32, 32).build().unwrap().as_bytes()
+    }
+
+    #[test]
+    fn test_measure_corrupt_pixels() {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.replace_chunk(&ChunkType::IDAT, b"not zlib".to_vec())
+            .unwrap();
+        png.append_chunk(Chunk::new("ruSt".parse().unwrap(), b"hi".to_vec()));
+        let ihdr = png.ihdr().unwrap();
+        let raster = Raster::from_png(&png);
+        assert!(raster.is_err());
+        assert_eq!(
+            measure(&ihdr, &raster).unwrap_err(),
+            "the pixels can't be decoded (RasterError invalid image data)"
+        );
+        let found = signatures::scan(&png, raster.as_ref().ok());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].location, Location::Chunk(png.chunks().len() - 1));
+
+        let png = PngBuilder::new(4, 4).build().unwrap();
+        let raster = Raster::from_png(&png);
+        assert!(measure(&png.ihdr().unwrap(), &raster).is_ok());
+    }
+
+    #[test]
+    fn test_wrap_payload() {
+        let args = encode_args("encode in.png ruSt hello");
+        let bytes = wrap_payload(Envelope::new(b"hello".to_vec()), &args, None);
+        let chunk_type = args.chunk_type;
+        let mut chunks = payload::split(&chunk_type, &bytes, payload::DEFAULT_SEGMENT_LEN).unwrap();
+        let stored = payload::join(&chunk_type, &chunks).unwrap();
+        assert_eq!(Envelope::from_bytes(&stored, None).unwrap().body, b"hello");
+
+        // One byte of the message changed, the chunk's CRC made to match.
+        let mut data = chunks[0].data().to_vec();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        chunks[0] = Chunk::new(chunk_type, data);
+        let stored = payload::join(&chunk_type, &chunks).unwrap();
+        assert_eq!(
+            Envelope::from_bytes(&stored, None),
+            Err(EnvelopeError::DigestMismatch)
+        );
+
+        let args = encode_args("encode in.png ruSt hello --raw");
+        assert_eq!(
+            wrap_payload(Envelope::new(b"hello".to_vec()), &args, None),
+            b"hello"
+        );
+    }
+
+    #[test]
+    fn test_wrap_payload_text() {
+        let message = "hello there";
+        for mode in ["--as-text", "--as-ztxt", "--as-itxt"] {
+            let args = encode_args(&format!("encode in.png {mode} Comment hello"));
+            let bytes = wrap_payload(Envelope::new(message.as_bytes().to_vec()), &args, None);
+            let chunk = text_chunk(args.text.as_ref().unwrap(), &bytes).unwrap();
+            let entry = TextEntry::from_chunk(&chunk).unwrap();
+            assert_eq!(
+                (entry.keyword.as_str(), entry.text.as_str()),
+                ("Comment", message)
+            );
+
+            let args = encode_args(&format!("encode in.png {mode} Comment hello --envelope"));
+            let bytes = wrap_payload(Envelope::new(b"hello".to_vec()), &args, None);
+            assert_eq!(Envelope::from_bytes(&bytes, None).unwrap().body, b"hello");
+        }
+        let args = encode_args("encode in.png --as-text Comment hello --creator me");
+        let bytes = wrap_payload(
+            Envelope::new(b"hello".to_vec()).with_metadata(args.metadata.clone()),
+            &args,
+            None,
+        );
+        assert_ne!(bytes, b"hello");
+    }
+
+    #[test]
+    fn test_scan_pixels_keyed() {
+        let png = PngBuilder::new(64, 64).build().unwrap();
+        let mut raster = Raster::from_png(&png).unwrap();
+        let payload = Envelope::new(b"hidden".to_vec()).to_bytes(Some(b"pw"));
+        let options = LsbOptions {
+            key: Some(b"pw".to_vec()),
+            ..LsbOptions::default()
+        };
+        lsb::embed(&mut raster, &payload, &options).unwrap();
+
+        assert!(scan_pixels(&raster, &[]).is_empty());
+        let passphrases = ["other".to_string(), "pw".to_string()];
+        let findings = scan_pixels(&raster, &passphrases);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].location, "pixels, key on line 2");
+        assert_eq!(findings[0].bytes, Some(payload.len()));
+        assert_eq!(findings[0].tag, "tagged, passphrase on line 2");
+    }
+
+    #[test]
+    fn test_passphrase_from_env() {
+        let from = |vars: &[(&str, &str)]| {
+            let vars: HashMap<String, String> = vars
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            passphrase_from_env(|name| vars.get(name).cloned())
+        };
+        let password = (PASSWORD_VAR, "from password");
+        assert_eq!(from(&[password]).as_deref(), Some("from password"));
+        assert_eq!(
+            from(&[password, (PASSPHRASE_VAR, "from passphrase")]).as_deref(),
+            Some("from passphrase")
+        );
+        assert_eq!(
+            from(&[password, (PASSPHRASE_VAR, "")]).as_deref(),
+            Some("from password")
+        );
+        assert_eq!(from(&[(PASSPHRASE_VAR, "")]), None);
+        assert_eq!(from(&[]), None);
+    }
+
+    #[test]
+    fn test_write_atomically() {
+        let dir = TempDir::new("atomic");
+        let path = dir.0.join("image.png");
+        fs::write(&path, b"old").unwrap();
+
+        let result = write_atomically(&path, |writer| {
+            writer.write_all(b"half of it")?;
+            bail!("interrupted")
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        // The temporary file is gone with the failure, not left beside it.
+        assert_eq!(dir.names(), ["image.png"]);
+
+        write_atomically(&path, |writer| Ok(writer.write_all(b"new")?)).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(dir.names(), ["image.png"]);
+    }
+
+    #[test]
+    fn test_write_png_backup() {
+        let dir = TempDir::new("backup");
+        let path = dir.0.join("image.png");
+        fs::write(&path, b"old bytes").unwrap();
+        let png = Png::from_bytes(&carrier_bytes()).unwrap();
+        write_png(&path, &png, Some(".orig")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), png.as_bytes());
+        assert_eq!(
+            fs::read(dir.0.join("image.png.orig")).unwrap(),
+            b"old bytes"
+        );
+
+        let line = format!("encode {} ruSt hello --backup", dir.file("image.png"));
+        encode(encode_args(&line)).unwrap();
+        assert_eq!(
+            fs::read(dir.0.join("image.png.bak")).unwrap(),
+            png.as_bytes()
+        );
+        assert_eq!(
+            dir.names(),
+            ["image.png", "image.png.bak", "image.png.orig"]
+        );
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let dir = TempDir::new("dry-run");
+        let path = dir.0.join("image.png");
+        fs::write(&path, carrier_bytes()).unwrap();
+        let image = dir.file("image.png");
+        let out = dir.file("out.png");
+        for line in [
+            format!("encode {image} ruSt hello -n"),
+            format!("encode {image} ruSt hello --dry-run -o {out} --backup"),
+            format!("encode {image} --lsb hello -n"),
+            format!("encode {image} --as-itxt Comment hello -n"),
+        ] {
+            encode(encode_args(&line)).unwrap();
+        }
+        assert_eq!(fs::read(&path).unwrap(), carrier_bytes());
+        assert_eq!(dir.names(), ["image.png"]);
+    }
+
+    #[test]
+    fn test_decode_output() {
+        let dir = TempDir::new("decode-output");
+        fs::write(dir.0.join("image.png"), carrier_bytes()).unwrap();
+        let image = dir.file("image.png");
+        for (mode, decode_mode) in [
+            ("ruSt", "ruSt"),
+            ("--as-ztxt Secret", "--as-text Secret"),
+            ("--as-itxt Secret", "--as-text Secret"),
+        ] {
+            let out = dir.file("image.out.png");
+            encode(encode_args(&format!(
+                "encode {image} {mode} hello -o {out}"
+            )))
+            .unwrap();
+            let message = dir.file("message.txt");
+            decode(decode_args(&format!(
+                "decode {out} {decode_mode} -o {message}"
+            )))
+            .unwrap();
+            assert_eq!(fs::read(&message).unwrap(), b"hello", "{mode}");
+        }
+        assert_eq!(dir.names(), ["image.out.png", "image.png", "message.txt"]);
+    }
+}
@@ -0,0 +1,369 @@
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::ihdr::Ihdr;
+use crate::raster::Raster;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum DiffError {
+    #[error("DiffError the images differ in format: {0} against {1}")]
+    FormatMismatch(String, String),
+}
+
+/// How a chunk of one image relates to the other's. Indices are chunk indices,
+/// `old` into the first image and `new` into the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// The same type, data and crc in both.
+    Same {
+        old: usize,
+        new: usize,
+    },
+    Removed {
+        old: usize,
+    },
+    Added {
+        new: usize,
+    },
+    /// A chunk of the same type in the same place, with other data or crc.
+    Changed {
+        old: usize,
+        new: usize,
+    },
+}
+
+impl Change {
+    pub fn is_same(&self) -> bool {
+        matches!(self, Change::Same { .. })
+    }
+}
+
+/// Aligns the chunks of two images, keeping the longest run of identical chunks
+/// in order and pairing up the rest by type where they sit between the same
+/// identical chunks.
+pub fn diff(old: &[Chunk], new: &[Chunk]) -> Vec<Change> {
+    let same = |a: &Chunk, b: &Chunk| {
+        a.chunk_type() == b.chunk_type() && a.crc() == b.crc() && a.data() == b.data()
+    };
+
+    // lengths[i][j] is the longest common subsequence of old[i..] and new[j..].
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if same(&old[i], &new[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && same(&old[i], &new[j]) {
+            pair_up(old, new, &mut removed, &mut added, &mut changes);
+            changes.push(Change::Same { old: i, new: j });
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            added.push(j);
+            j += 1;
+        } else {
+            removed.push(i);
+            i += 1;
+        }
+    }
+    pair_up(old, new, &mut removed, &mut added, &mut changes);
+
+    changes
+}
+
+/// Turns the chunks removed and added between two identical ones into changes,
+/// matching each removed chunk with the first added one of its type.
+fn pair_up(
+    old: &[Chunk],
+    new: &[Chunk],
+    removed: &mut Vec<usize>,
+    added: &mut Vec<usize>,
+    changes: &mut Vec<Change>,
+) {
+    for old_index in removed.drain(..) {
+        let partner = added
+            .iter()
+            .position(|&new_index| new[new_index].chunk_type() == old[old_index].chunk_type());
+        match partner {
+            Some(position) => changes.push(Change::Changed {
+                old: old_index,
+                new: added.remove(position),
+            }),
+            None => changes.push(Change::Removed { old: old_index }),
+        }
+    }
+    changes.extend(added.drain(..).map(|new| Change::Added { new }));
+}
+
+/// How the pixels of two images of the same size and format differ, sample by
+/// sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelDiff {
+    pub samples: u64,
+    pub changed_samples: u64,
+    pub changed_pixels: u64,
+    /// Changed samples of each channel, in sample order.
+    pub channels: Vec<u64>,
+    /// How often each bit of a sample changed, lowest bit first.
+    pub bits: Vec<u64>,
+    pub max_delta: u16,
+    /// The first and last changed sample, counting in storage order.
+    pub span: Option<(u64, u64)>,
+    /// The smallest rectangle around the changed pixels, as `(x0, y0, x1, y1)`
+    /// with both corners inside.
+    pub bounds: Option<(u32, u32, u32, u32)>,
+    /// The runs of rows with changes, first and last row of each.
+    pub rows: Vec<(u32, u32)>,
+}
+
+impl PixelDiff {
+    pub fn changed_bits(&self) -> u64 {
+        self.bits.iter().sum()
+    }
+
+    /// How many of the lowest bits of each sample the changes fit in, when it
+    /// is at most half of them: what LSB embedding leaves, overwriting those
+    /// bits and nothing above. `None` without changes or when higher bits
+    /// changed too, as editing and filters do.
+    pub fn lsb_bits(&self) -> Option<u8> {
+        let highest = self.bits.iter().rposition(|&count| count > 0)?;
+        let bits = highest as u8 + 1;
+        (usize::from(bits) <= self.bits.len() / 2 && u32::from(self.max_delta) < 1 << bits)
+            .then_some(bits)
+    }
+
+    /// The share of samples between the first and last change that changed.
+    /// Random payload bits differ from the cover's about half the time per bit
+    /// used, so dense embedding lands near 1/2, or 3/4 with two bits.
+    pub fn density(&self) -> f64 {
+        self.span.map_or(0.0, |(first, last)| {
+            self.changed_samples as f64 / (last - first + 1) as f64
+        })
+    }
+}
+
+/// Compares the pixels of `old` and `new`, which must have the same size, color
+/// type and bit depth. Interlacing doesn't matter.
+pub fn diff_pixels(old: &Raster, new: &Raster) -> Result<PixelDiff, DiffError> {
+    let (ihdr, new_ihdr) = (old.ihdr(), new.ihdr());
+    let format = |ihdr: &Ihdr| {
+        format!(
+            "{}x{} {}-bit {}",
+            ihdr.width, ihdr.height, ihdr.bit_depth, ihdr.color_type
+        )
+    };
+    if (ihdr.width, ihdr.height, ihdr.color_type, ihdr.bit_depth)
+        != (
+            new_ihdr.width,
+            new_ihdr.height,
+            new_ihdr.color_type,
+            new_ihdr.bit_depth,
+        )
+    {
+        return Err(DiffError::FormatMismatch(format(ihdr), format(new_ihdr)));
+    }
+
+    let depth = usize::from(ihdr.bit_depth);
+    let channels = usize::from(ihdr.color_type.channels());
+    let width = ihdr.width as usize;
+    let mut diff = PixelDiff {
+        samples: u64::from(ihdr.width) * u64::from(ihdr.height) * channels as u64,
+        changed_samples: 0,
+        changed_pixels: 0,
+        channels: vec![0; channels],
+        bits: vec![0; depth],
+        max_delta: 0,
+        span: None,
+        bounds: None,
+        rows: Vec::new(),
+    };
+    let row_len = old.row_len();
+    let rows = old.data().chunks(row_len).zip(new.data().chunks(row_len));
+    for (y, (old_row, new_row)) in (0u32..).zip(rows) {
+        let mut row_changed = false;
+        for x in 0..width {
+            let mut pixel_changed = false;
+            for channel in 0..channels {
+                let index = x * channels + channel;
+                let (a, b) = (sample(old_row, index, depth), sample(new_row, index, depth));
+                if a == b {
+                    continue;
+                }
+                pixel_changed = true;
+                diff.changed_samples += 1;
+                diff.channels[channel] += 1;
+                let changed = a ^ b;
+                for (bit, count) in diff.bits.iter_mut().enumerate() {
+                    *count += u64::from(changed >> bit & 1);
+                }
+                diff.max_delta = diff.max_delta.max(a.abs_diff(b));
+                let position = u64::from(y) * (width * channels) as u64 + index as u64;
+                diff.span = Some(
+                    diff.span
+                        .map_or((position, position), |(first, _)| (first, position)),
+                );
+            }
+            if pixel_changed {
+                row_changed = true;
+                diff.changed_pixels += 1;
+                let x = x as u32;
+                diff.bounds = Some(diff.bounds.map_or((x, y, x, y), |(x0, y0, x1, _)| {
+                    (x0.min(x), y0, x1.max(x), y)
+                }));
+            }
+        }
+        if row_changed {
+            match diff.rows.last_mut() {
+                Some((_, last)) if *last + 1 == y => *last = y,
+                _ => diff.rows.push((y, y)),
+            }
+        }
+    }
+    Ok(diff)
+}
+
+/// The `index`th sample of `depth` bits in `row`; samples below 8 bits are
+/// packed from the highest bits of each byte.
+fn sample(row: &[u8], index: usize, depth: usize) -> u16 {
+    match depth {
+        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+        8 => u16::from(row[index]),
+        _ => {
+            let bit = index * depth;
+            u16::from(row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{Fill, PngBuilder};
+    use crate::ihdr::ColorType;
+
+    fn chunks(specs: &[(&str, &[u8])]) -> Vec<Chunk> {
+        specs
+            .iter()
+            .map(|(chunk_type, data)| Chunk::new(chunk_type.parse().unwrap(), data.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_identical() {
+        let old = chunks(&[("IHDR", &[0; 13]), ("IEND", &[])]);
+        let changes = diff(&old, &old);
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().all(Change::is_same));
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = chunks(&[
+            ("IHDR", &[0; 13]),
+            ("tEXt", b"a\0b"),
+            ("IDAT", &[1]),
+            ("ruSt", b"old"),
+            ("IEND", &[]),
+        ]);
+        let new = chunks(&[
+            ("IHDR", &[0; 13]),
+            ("IDAT", &[1]),
+            ("ruSt", b"new"),
+            ("abCd", b"extra"),
+            ("IEND", &[]),
+        ]);
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Change::Same { old: 0, new: 0 },
+                Change::Removed { old: 1 },
+                Change::Same { old: 2, new: 1 },
+                Change::Changed { old: 3, new: 2 },
+                Change::Added { new: 3 },
+                Change::Same { old: 4, new: 4 },
+            ]
+        );
+    }
+
+    fn raster(color_type: ColorType, bit_depth: u8) -> Raster {
+        let png = PngBuilder::new(10, 6)
+            .with_color_type(color_type)
+            .with_bit_depth(bit_depth)
+            .with_fill(Fill::Noise(4))
+            .build()
+            .unwrap();
+        Raster::from_png(&png).unwrap()
+    }
+
+    #[test]
+    fn test_pixels() {
+        let old = raster(ColorType::Rgb, 8);
+        let diff = diff_pixels(&old, &old).unwrap();
+        assert_eq!((diff.samples, diff.changed_samples), (180, 0));
+        assert_eq!(
+            (diff.span, diff.bounds, diff.lsb_bits()),
+            (None, None, None)
+        );
+
+        // Low bits of green in pixel (2, 1), blue in (7, 1) and red in (3, 4).
+        let mut new = old.clone();
+        for (offset, flip) in [(30 + 6 + 1, 1), (30 + 21 + 2, 3), (120 + 9, 2)] {
+            new.data_mut()[offset] ^= flip;
+        }
+        let diff = diff_pixels(&old, &new).unwrap();
+        assert_eq!(diff.changed_samples, 3);
+        assert_eq!(diff.changed_pixels, 3);
+        assert_eq!(diff.channels, [1, 1, 1]);
+        assert_eq!(diff.bits[..3], [2, 2, 0]);
+        assert_eq!(diff.changed_bits(), 4);
+        assert_eq!(diff.span, Some((37, 129)));
+        assert_eq!(diff.bounds, Some((2, 1, 7, 4)));
+        assert_eq!(diff.rows, [(1, 1), (4, 4)]);
+        assert_eq!(diff.lsb_bits(), Some(2));
+        assert!((diff.density() - 3.0 / 93.0).abs() < 1e-9);
+
+        new.data_mut()[0] ^= 0x40;
+        assert_eq!(diff_pixels(&old, &new).unwrap().lsb_bits(), None);
+    }
+
+    #[test]
+    fn test_pixel_formats() {
+        let old = raster(ColorType::Grayscale, 16);
+        let mut new = old.clone();
+        new.data_mut()[1] ^= 0xff;
+        let diff = diff_pixels(&old, &new).unwrap();
+        assert_eq!(diff.bits[7..9], [1, 0]);
+        assert_eq!(diff.lsb_bits(), Some(8));
+
+        let old = raster(ColorType::Grayscale, 2);
+        let mut new = old.clone();
+        new.data_mut()[0] ^= 0b0001_0000;
+        let diff = diff_pixels(&old, &new).unwrap();
+        assert_eq!(diff.bounds, Some((1, 0, 1, 0)));
+        assert_eq!(diff.lsb_bits(), Some(1));
+
+        assert_eq!(
+            diff_pixels(&old, &raster(ColorType::Grayscale, 4)),
+            Err(DiffError::FormatMismatch(
+                "10x6 2-bit grayscale".to_string(),
+                "10x6 4-bit grayscale".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_empty() {
+        let new = chunks(&[("IHDR", &[0; 13])]);
+        assert_eq!(diff(&[], &new), vec![Change::Added { new: 0 }]);
+        assert_eq!(diff(&new, &[]), vec![Change::Removed { old: 0 }]);
+    }
+}
@@ -0,0 +1,879 @@
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::archive::Archive;
+use crate::rng::Rng;
+use crate::sha256;
+use crate::time::{TimeChunk, TimeError};
+use crate::zlib::{self, ZlibError};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum EnvelopeError {
+    #[error("EnvelopeError payload is truncated")]
+    Truncated,
+    #[error("EnvelopeError unsupported envelope version {0}")]
+    UnsupportedVersion(u8),
+    #[error("EnvelopeError payload carries an integrity tag, a passphrase is required")]
+    KeyRequired,
+    #[error("EnvelopeError payload carries no integrity tag")]
+    NotAuthenticated,
+    #[error("EnvelopeError integrity check failed, wrong passphrase or tampered payload")]
+    BadTag,
+    #[error("EnvelopeError unsupported compression method {0}")]
+    UnsupportedCompression(u8),
+    #[error("EnvelopeError unknown compression {0}, expected none, deflate or deflate:<0-9>")]
+    UnknownCompression(String),
+    #[error("EnvelopeError failed to decompress body: {0}")]
+    Decompress(#[from] ZlibError),
+    #[error("EnvelopeError invalid file name {0:?}")]
+    InvalidFileName(String),
+    #[error("EnvelopeError file should be {0} bytes but the body is {1}")]
+    SizeMismatch(u64, usize),
+    #[error("EnvelopeError unknown padding {0}, expected <bytes> or <percent>%")]
+    UnknownPadding(String),
+    #[error("EnvelopeError body does not match its SHA-256 digest, the payload is corrupted")]
+    DigestMismatch,
+    #[error("EnvelopeError invalid time: {0}")]
+    InvalidTime(#[from] TimeError),
+}
+
+/// Marks a payload as an envelope rather than raw bytes written by another tool.
+pub const MAGIC: [u8; 4] = [0x89, b'p', b's', b'e'];
+
+pub const VERSION: u8 = 1;
+
+/// The most a compressed body that isn't a file of known size may inflate
+/// to, so a small payload can't expand to fill memory.
+pub const MAX_BODY_LEN: usize = 1 << 30;
+
+/// The most a raw payload that looks like a zlib stream is inflated to, to
+/// see whether it is one. Every payload read is tried, so this is kept well
+/// below `MAX_BODY_LEN`; a stream inflating further is left as it is.
+pub const MAX_RAW_LEN: usize = 64 << 20;
+
+/// An HMAC-SHA256 tag over everything before it is appended to the envelope.
+const FLAG_HMAC: u8 = 1;
+
+/// Magic, version, flags and a big-endian u16 field count.
+const HEADER_LEN: usize = 8;
+
+/// Field holding the compression method and level applied to the body.
+const FIELD_COMPRESSION: u8 = 1;
+
+/// Field describing the file the body was read from.
+const FIELD_FILE: u8 = 2;
+
+/// Empty field marking the body as an `Archive`.
+const FIELD_ARCHIVE: u8 = 3;
+
+/// Field holding the big-endian u32 length of the random padding after the body.
+const FIELD_PADDING: u8 = 4;
+
+/// Fields holding the UTF-8 `Metadata` texts.
+const FIELD_CREATOR: u8 = 5;
+const FIELD_COMMENT: u8 = 6;
+const FIELD_CONTENT_TYPE: u8 = 7;
+
+/// Fields holding the creation and expiry times as the 7 bytes of a tIME chunk.
+const FIELD_CREATED_AT: u8 = 8;
+const FIELD_EXPIRES: u8 = 9;
+
+/// Field holding the SHA-256 digest of the body, before compression. Every
+/// envelope has one, so damage shows even without an integrity tag.
+const FIELD_DIGEST: u8 = 10;
+
+/// The tag and length in front of every field's value.
+const FIELD_OVERHEAD: usize = 5;
+
+const METHOD_NONE: u8 = 0;
+const METHOD_DEFLATE: u8 = 1;
+
+/// How the body is compressed inside the envelope.
+///
+/// Deflate bodies are complete zlib streams (RFC 1950), so they can be inflated by
+/// stock zlib tooling or reused as zTXt data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// Deflate at a zlib level from 0 to 9.
+    Deflate(u8),
+}
+
+impl Compression {
+    fn method(self) -> u8 {
+        match self {
+            Self::None => METHOD_NONE,
+            Self::Deflate(_) => METHOD_DEFLATE,
+        }
+    }
+
+    fn compress(self, body: &[u8]) -> Vec<u8> {
+        match self {
+            Self::None => body.to_vec(),
+            Self::Deflate(level) => zlib::compress(body, level),
+        }
+    }
+
+    /// Inflates `body`, giving up past `max_len` bytes.
+    fn decompress(self, body: &[u8], max_len: usize) -> Result<Vec<u8>, EnvelopeError> {
+        match self {
+            Self::None => Ok(body.to_vec()),
+            Self::Deflate(_) => Ok(zlib::decompress_with_limit(body, max_len)?),
+        }
+    }
+}
+
+/// The spelling `FromStr` reads, with the level always given.
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => f.write_str("none"),
+            Self::Deflate(level) => write!(f, "deflate:{level}"),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = EnvelopeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unknown = || EnvelopeError::UnknownCompression(s.to_string());
+        match s.split_once(':') {
+            None if s == "none" => Ok(Self::None),
+            None if s == "deflate" => Ok(Self::Deflate(zlib::DEFAULT_LEVEL)),
+            Some(("deflate", level)) => match level.parse() {
+                Ok(level) if level <= zlib::MAX_LEVEL => Ok(Self::Deflate(level)),
+                _ => Err(unknown()),
+            },
+            _ => Err(unknown()),
+        }
+    }
+}
+
+/// How much random padding to add after the body, so payloads of about the
+/// same size don't come out exactly as long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    /// Pad to a random length up to this many bytes, not counting an
+    /// integrity tag. An envelope already as long gets none.
+    UpTo(u64),
+    /// Pad by a random amount up to this percentage of the envelope.
+    Percent(u32),
+}
+
+impl Padding {
+    /// A random amount of padding for an envelope of `len` bytes.
+    pub fn amount(self, len: usize, rng: &mut Rng) -> usize {
+        let most = match self {
+            Self::UpTo(bound) => bound.saturating_sub(len as u64),
+            Self::Percent(percent) => len as u64 * u64::from(percent) / 100,
+        };
+        rng.below(most.min(u64::from(u32::MAX)) + 1) as usize
+    }
+}
+
+impl FromStr for Padding {
+    type Err = EnvelopeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unknown = || EnvelopeError::UnknownPadding(s.to_string());
+        match s.strip_suffix('%') {
+            Some(percent) => percent.parse().map(Self::Percent).map_err(|_| unknown()),
+            None => s.parse().map(Self::UpTo).map_err(|_| unknown()),
+        }
+    }
+}
+
+/// What the sender chose to say about a payload, readable without
+/// extracting it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Metadata {
+    pub creator: Option<String>,
+    pub created_at: Option<TimeChunk>,
+    pub comment: Option<String>,
+    /// A media type such as `text/plain`.
+    pub content_type: Option<String>,
+    /// After this time decode refuses the payload unless told otherwise. Only
+    /// a request: nothing stops a reader ignoring it.
+    pub expires: Option<TimeChunk>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether the payload is past its expiry at `now`.
+    pub fn is_expired(&self, now: TimeChunk) -> bool {
+        self.expires.is_some_and(|expires| expires < now)
+    }
+
+    fn fields(&self) -> Vec<(u8, Vec<u8>)> {
+        let mut fields = Vec::new();
+        for (tag, text) in [
+            (FIELD_CREATOR, &self.creator),
+            (FIELD_COMMENT, &self.comment),
+            (FIELD_CONTENT_TYPE, &self.content_type),
+        ] {
+            if let Some(text) = text {
+                fields.push((tag, text.as_bytes().to_vec()));
+            }
+        }
+        for (tag, time) in [
+            (FIELD_CREATED_AT, &self.created_at),
+            (FIELD_EXPIRES, &self.expires),
+        ] {
+            if let Some(time) = time {
+                fields.push((tag, time.to_bytes().to_vec()));
+            }
+        }
+        fields
+    }
+
+    /// Takes the value of a metadata field, ignoring other tags.
+    fn read_field(&mut self, tag: u8, value: &[u8]) -> Result<(), EnvelopeError> {
+        let text = || Some(String::from_utf8_lossy(value).into_owned());
+        match tag {
+            FIELD_CREATOR => self.creator = text(),
+            FIELD_COMMENT => self.comment = text(),
+            FIELD_CONTENT_TYPE => self.content_type = text(),
+            FIELD_CREATED_AT => self.created_at = Some(TimeChunk::from_bytes(value)?),
+            FIELD_EXPIRES => self.expires = Some(TimeChunk::from_bytes(value)?),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// The original name, size and permissions of an embedded file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    /// A bare file name, never a path.
+    pub name: String,
+    pub size: u64,
+    /// Unix permission bits.
+    pub mode: u32,
+}
+
+impl FileInfo {
+    /// Checks that `name` can be joined onto a directory without escaping it.
+    pub fn validate_name(name: &str) -> Result<(), EnvelopeError> {
+        let valid =
+            !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\', '\0']);
+        if valid {
+            Ok(())
+        } else {
+            Err(EnvelopeError::InvalidFileName(name.to_string()))
+        }
+    }
+
+    /// Layout: `mode: u32 BE, size: u64 BE, name`.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(12 + self.name.len());
+        bytes.extend_from_slice(&self.mode.to_be_bytes());
+        bytes.extend_from_slice(&self.size.to_be_bytes());
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, EnvelopeError> {
+        if bytes.len() < 12 {
+            return Err(EnvelopeError::Truncated);
+        }
+        let (mode, rest) = bytes.split_at(4);
+        let (size, name) = rest.split_at(8);
+        let name = String::from_utf8_lossy(name).into_owned();
+        Self::validate_name(&name)?;
+        Ok(Self {
+            name,
+            size: u64::from_be_bytes(size.try_into().unwrap()),
+            mode: u32::from_be_bytes(mode.try_into().unwrap()),
+        })
+    }
+}
+
+/// A payload together with the metadata needed to read it back.
+///
+/// Layout: header, then `field count` fields of `tag: u8, len: u32 BE, value`, then the
+/// body, then any padding, then the tag if `FLAG_HMAC` is set. Readers skip fields they
+/// don't recognise.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Envelope {
+    pub body: Vec<u8>,
+    pub compression: Compression,
+    /// Set when the body is the contents of a file rather than a message.
+    pub file: Option<FileInfo>,
+    /// Set when the body is an `Archive` of several files.
+    pub archive: bool,
+    /// Random bytes stored after the body, covered by the integrity tag.
+    pub padding: Vec<u8>,
+    pub metadata: Metadata,
+}
+
+impl Envelope {
+    pub fn new(body: Vec<u8>) -> Self {
+        Self {
+            body,
+            compression: Compression::None,
+            file: None,
+            archive: false,
+            padding: Vec::new(),
+            metadata: Metadata::default(),
+        }
+    }
+
+    pub fn with_file(mut self, file: FileInfo) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    pub fn from_archive(archive: &Archive) -> Self {
+        Self {
+            archive: true,
+            ..Self::new(archive.to_bytes())
+        }
+    }
+
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Adds a random amount of random padding, measured against the envelope
+    /// as it is now. Set any compression first.
+    pub fn with_padding(mut self, padding: Padding, rng: &mut Rng) -> Self {
+        self.padding.clear();
+        let len = self.to_bytes(None).len() + FIELD_OVERHEAD + 4;
+        self.padding = vec![0; padding.amount(len, rng)];
+        rng.fill_bytes(&mut self.padding);
+        self
+    }
+
+    /// Whether `bytes` start like an envelope rather than a raw payload.
+    pub fn is_envelope(bytes: &[u8]) -> bool {
+        bytes.starts_with(&MAGIC)
+    }
+
+    /// Serializes the envelope, appending an HMAC-SHA256 tag keyed by `key` if given.
+    pub fn to_bytes(&self, key: Option<&[u8]>) -> Vec<u8> {
+        let flags = if key.is_some() { FLAG_HMAC } else { 0 };
+        let body = self.compression.compress(&self.body);
+
+        let mut fields = Vec::new();
+        if let Compression::Deflate(level) = self.compression {
+            fields.push((FIELD_COMPRESSION, vec![self.compression.method(), level]));
+        }
+        if let Some(file) = &self.file {
+            fields.push((FIELD_FILE, file.to_bytes()));
+        }
+        if self.archive {
+            fields.push((FIELD_ARCHIVE, Vec::new()));
+        }
+        fields.extend(self.metadata.fields());
+        fields.push((FIELD_DIGEST, sha256::digest(&self.body).to_vec()));
+        if !self.padding.is_empty() {
+            let len = self.padding.len() as u32;
+            fields.push((FIELD_PADDING, len.to_be_bytes().to_vec()));
+        }
+
+        let mut bytes =
+            Vec::with_capacity(HEADER_LEN + body.len() + self.padding.len() + sha256::DIGEST_LEN);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.push(flags);
+        bytes.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+        for (tag, value) in &fields {
+            bytes.push(*tag);
+            bytes.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(value);
+        }
+        bytes.extend_from_slice(&body);
+        bytes.extend_from_slice(&self.padding);
+
+        if let Some(key) = key {
+            let tag = sha256::hmac(key, &bytes);
+            bytes.extend_from_slice(&tag);
+        }
+        bytes
+    }
+
+    /// Parses an envelope, checking its integrity tag against `key`.
+    ///
+    /// Bytes without the envelope magic are treated as a raw payload, as written by
+    /// older versions or other tools. A raw payload that is a complete zlib stream is
+    /// decompressed.
+    pub fn from_bytes(bytes: &[u8], key: Option<&[u8]>) -> Result<Self, EnvelopeError> {
+        if !Self::is_envelope(bytes) {
+            return match key {
+                Some(_) => Err(EnvelopeError::NotAuthenticated),
+                None => Ok(Self::from_raw(bytes)),
+            };
+        }
+
+        if bytes.len() < HEADER_LEN {
+            return Err(EnvelopeError::Truncated);
+        }
+        let version = bytes[4];
+        if version != VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(version));
+        }
+        let flags = bytes[5];
+
+        let content = if flags & FLAG_HMAC != 0 {
+            let key = key.ok_or(EnvelopeError::KeyRequired)?;
+            let split = bytes
+                .len()
+                .checked_sub(sha256::DIGEST_LEN)
+                .filter(|&split| split >= HEADER_LEN)
+                .ok_or(EnvelopeError::Truncated)?;
+            let (content, tag) = bytes.split_at(split);
+            if !sha256::verify_hmac(key, content, tag) {
+                return Err(EnvelopeError::BadTag);
+            }
+            content
+        } else if key.is_some() {
+            return Err(EnvelopeError::NotAuthenticated);
+        } else {
+            bytes
+        };
+
+        let field_count = u16::from_be_bytes([content[6], content[7]]);
+        let mut rest = &content[HEADER_LEN..];
+        let mut compression = Compression::None;
+        let mut file = None;
+        let mut archive = false;
+        let mut padding_len = 0;
+        let mut metadata = Metadata::default();
+        let mut digest = None;
+        for _ in 0..field_count {
+            let (tag, value, remaining) = read_field(rest)?;
+            match tag {
+                FIELD_COMPRESSION => compression = read_compression(value)?,
+                FIELD_FILE => file = Some(FileInfo::from_bytes(value)?),
+                FIELD_ARCHIVE => archive = true,
+                FIELD_PADDING => padding_len = read_padding_len(value)?,
+                FIELD_DIGEST => digest = Some(value),
+                tag => metadata.read_field(tag, value)?,
+            }
+            rest = remaining;
+        }
+
+        let split = rest
+            .len()
+            .checked_sub(padding_len)
+            .ok_or(EnvelopeError::Truncated)?;
+        let (rest, padding) = rest.split_at(split);
+        // A file's body can't be any longer than the file was.
+        let max_len = match &file {
+            Some(file) => usize::try_from(file.size).unwrap_or(usize::MAX),
+            None => MAX_BODY_LEN,
+        };
+        let body = compression.decompress(rest, max_len)?;
+        if digest.is_some_and(|digest| digest != sha256::digest(&body)) {
+            return Err(EnvelopeError::DigestMismatch);
+        }
+        if let Some(file) = &file {
+            if file.size != body.len() as u64 {
+                return Err(EnvelopeError::SizeMismatch(file.size, body.len()));
+            }
+        }
+        Ok(Self {
+            body,
+            compression,
+            file,
+            archive,
+            padding: padding.to_vec(),
+            metadata,
+        })
+    }
+
+    fn from_raw(bytes: &[u8]) -> Self {
+        // Only a stream that inflates with a matching checksum counts, so text that
+        // happens to start like a zlib header is left alone.
+        if let Some(level) = zlib::header_level(bytes) {
+            if let Ok(body) = zlib::decompress_with_limit(bytes, MAX_RAW_LEN) {
+                return Self::new(body).with_compression(Compression::Deflate(level));
+            }
+        }
+        Self::new(bytes.to_vec())
+    }
+}
+
+fn read_compression(value: &[u8]) -> Result<Compression, EnvelopeError> {
+    match *value {
+        [METHOD_NONE, ..] => Ok(Compression::None),
+        [METHOD_DEFLATE, level, ..] => Ok(Compression::Deflate(level)),
+        [METHOD_DEFLATE] => Ok(Compression::Deflate(zlib::DEFAULT_LEVEL)),
+        [method, ..] => Err(EnvelopeError::UnsupportedCompression(method)),
+        [] => Err(EnvelopeError::Truncated),
+    }
+}
+
+fn read_padding_len(value: &[u8]) -> Result<usize, EnvelopeError> {
+    let len: [u8; 4] = value.try_into().map_err(|_| EnvelopeError::Truncated)?;
+    Ok(u32::from_be_bytes(len) as usize)
+}
+
+fn read_field(bytes: &[u8]) -> Result<(u8, &[u8], &[u8]), EnvelopeError> {
+    if bytes.len() < 5 {
+        return Err(EnvelopeError::Truncated);
+    }
+    let tag = bytes[0];
+    let len = u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as usize;
+    let rest = &bytes[5..];
+    if rest.len() < len {
+        return Err(EnvelopeError::Truncated);
+    }
+    let (value, rest) = rest.split_at(len);
+    Ok((tag, value, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let envelope = Envelope::new(b"secret".to_vec());
+        let bytes = envelope.to_bytes(None);
+        assert!(Envelope::is_envelope(&bytes));
+        assert_eq!(Envelope::from_bytes(&bytes, None).unwrap(), envelope);
+    }
+
+    #[test]
+    fn test_raw_payload() {
+        let envelope = Envelope::from_bytes(b"plain text", None).unwrap();
+        assert_eq!(envelope.body, b"plain text");
+        assert_eq!(
+            Envelope::from_bytes(b"plain text", Some(b"key")),
+            Err(EnvelopeError::NotAuthenticated)
+        );
+    }
+
+    #[test]
+    fn test_raw_zlib_payload() {
+        let body = b"compressed by another tool".to_vec();
+        let envelope = Envelope::from_bytes(&zlib::compress(&body, 9), None).unwrap();
+        assert_eq!(envelope.body, body);
+        assert_eq!(envelope.compression, Compression::Deflate(9));
+
+        // "x^" is a valid zlib header, but the rest doesn't inflate.
+        let envelope = Envelope::from_bytes(b"x^2 + y^2", None).unwrap();
+        assert_eq!(envelope.body, b"x^2 + y^2");
+        assert_eq!(envelope.compression, Compression::None);
+
+        // Nor does a stream inflating past the limit.
+        let bomb = zlib::compress(&vec![0; MAX_RAW_LEN + 1], 1);
+        let envelope = Envelope::from_bytes(&bomb, None).unwrap();
+        assert_eq!(envelope.body, bomb);
+        assert_eq!(envelope.compression, Compression::None);
+    }
+
+    #[test]
+    fn test_hmac_round_trip() {
+        let envelope = Envelope::new(b"secret".to_vec());
+        let bytes = envelope.to_bytes(Some(b"passphrase"));
+        assert_eq!(
+            bytes.len(),
+            HEADER_LEN + FIELD_OVERHEAD + 2 * sha256::DIGEST_LEN + 6
+        );
+        assert_eq!(
+            Envelope::from_bytes(&bytes, Some(b"passphrase")).unwrap(),
+            envelope
+        );
+    }
+
+    #[test]
+    fn test_hmac_failures() {
+        let bytes = Envelope::new(b"secret".to_vec()).to_bytes(Some(b"passphrase"));
+        assert_eq!(
+            Envelope::from_bytes(&bytes, None),
+            Err(EnvelopeError::KeyRequired)
+        );
+        assert_eq!(
+            Envelope::from_bytes(&bytes, Some(b"wrong")),
+            Err(EnvelopeError::BadTag)
+        );
+
+        let mut tampered = bytes.clone();
+        tampered[HEADER_LEN] ^= 1;
+        assert_eq!(
+            Envelope::from_bytes(&tampered, Some(b"passphrase")),
+            Err(EnvelopeError::BadTag)
+        );
+
+        let untagged = Envelope::new(b"secret".to_vec()).to_bytes(None);
+        assert_eq!(
+            Envelope::from_bytes(&untagged, Some(b"passphrase")),
+            Err(EnvelopeError::NotAuthenticated)
+        );
+    }
+
+    #[test]
+    fn test_unknown_fields_are_skipped() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[VERSION, 0, 0, 1]);
+        bytes.extend_from_slice(&[200, 0, 0, 0, 2, 0xaa, 0xbb]);
+        bytes.extend_from_slice(b"body");
+
+        let envelope = Envelope::from_bytes(&bytes, None).unwrap();
+        assert_eq!(envelope.body, b"body");
+
+        bytes.truncate(HEADER_LEN + 3);
+        assert_eq!(
+            Envelope::from_bytes(&bytes, None),
+            Err(EnvelopeError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_compression_round_trip() {
+        let body = b"compressible ".repeat(100);
+        for compression in [Compression::Deflate(0), Compression::Deflate(9)] {
+            let envelope = Envelope::new(body.clone()).with_compression(compression);
+            let bytes = envelope.to_bytes(Some(b"passphrase"));
+            assert_eq!(
+                Envelope::from_bytes(&bytes, Some(b"passphrase")).unwrap(),
+                envelope
+            );
+        }
+
+        let bytes = Envelope::new(body.clone())
+            .with_compression(Compression::Deflate(6))
+            .to_bytes(None);
+        assert!(bytes.len() < body.len() / 10);
+
+        let stream = &bytes[HEADER_LEN + 7 + FIELD_OVERHEAD + sha256::DIGEST_LEN..];
+        assert_eq!(zlib::decompress(stream).unwrap(), body);
+    }
+
+    #[test]
+    fn test_compression_errors() {
+        let mut bytes = Envelope::new(b"body".to_vec())
+            .with_compression(Compression::Deflate(6))
+            .to_bytes(None);
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert_eq!(
+            Envelope::from_bytes(&bytes, None),
+            Err(EnvelopeError::Decompress(ZlibError::ChecksumMismatch))
+        );
+
+        bytes[HEADER_LEN + 5] = 7;
+        assert_eq!(
+            Envelope::from_bytes(&bytes, None),
+            Err(EnvelopeError::UnsupportedCompression(7))
+        );
+    }
+
+    #[test]
+    fn test_digest() {
+        let mut bytes = Envelope::new(b"secret".to_vec()).to_bytes(None);
+        let digest = &bytes[HEADER_LEN + FIELD_OVERHEAD..][..sha256::DIGEST_LEN];
+        assert_eq!(digest, sha256::digest(b"secret"));
+
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert_eq!(
+            Envelope::from_bytes(&bytes, None),
+            Err(EnvelopeError::DigestMismatch)
+        );
+    }
+
+    #[test]
+    fn test_compression_from_str() {
+        assert_eq!("none".parse(), Ok(Compression::None));
+        assert_eq!(
+            "deflate".parse(),
+            Ok(Compression::Deflate(zlib::DEFAULT_LEVEL))
+        );
+        assert_eq!("deflate:9".parse(), Ok(Compression::Deflate(9)));
+        assert_eq!(Compression::Deflate(9).to_string(), "deflate:9");
+        for bad in ["zstd", "deflate:10", "deflate:", "none:1"] {
+            assert_eq!(
+                bad.parse::<Compression>(),
+                Err(EnvelopeError::UnknownCompression(bad.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let file = FileInfo {
+            name: "secret.pdf".to_string(),
+            size: 4,
+            mode: 0o600,
+        };
+        let envelope = Envelope::new(b"%PDF".to_vec())
+            .with_compression(Compression::Deflate(6))
+            .with_file(file);
+        let bytes = envelope.to_bytes(None);
+        assert_eq!(Envelope::from_bytes(&bytes, None).unwrap(), envelope);
+
+        let mut wrong_size = envelope.clone();
+        wrong_size.file.as_mut().unwrap().size = 5;
+        assert_eq!(
+            Envelope::from_bytes(&wrong_size.to_bytes(None), None),
+            Err(EnvelopeError::SizeMismatch(5, 4))
+        );
+        // A body inflating past the file's size is cut off there.
+        wrong_size.file.as_mut().unwrap().size = 3;
+        assert_eq!(
+            Envelope::from_bytes(&wrong_size.to_bytes(None), None),
+            Err(EnvelopeError::Decompress(ZlibError::OutputTooLarge(3)))
+        );
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let mut archive = Archive::new();
+        archive.add("a.txt", 0o644, b"a".to_vec()).unwrap();
+        archive.add("b.txt", 0o644, b"b".to_vec()).unwrap();
+
+        let envelope = Envelope::from_archive(&archive).with_compression(Compression::Deflate(6));
+        let parsed = Envelope::from_bytes(&envelope.to_bytes(None), None).unwrap();
+        assert!(parsed.archive);
+        assert_eq!(Archive::from_bytes(&parsed.body).unwrap(), archive);
+    }
+
+    #[test]
+    fn test_padding_round_trip() {
+        let mut rng = Rng::new(7);
+        let envelope = Envelope::new(b"secret".to_vec())
+            .with_compression(Compression::Deflate(6))
+            .with_padding(Padding::UpTo(1000), &mut rng);
+        let bytes = envelope.to_bytes(Some(b"passphrase"));
+        assert!(bytes.len() <= 1000 + sha256::DIGEST_LEN);
+        let parsed = Envelope::from_bytes(&bytes, Some(b"passphrase")).unwrap();
+        assert_eq!(parsed, envelope);
+        assert_eq!(parsed.body, b"secret");
+
+        // Header, digest, padding length field and body.
+        let unpadded = HEADER_LEN + 2 * FIELD_OVERHEAD + sha256::DIGEST_LEN + 4 + 6;
+        let lengths: Vec<usize> = (0..8)
+            .map(|_| {
+                Envelope::new(b"secret".to_vec())
+                    .with_padding(Padding::Percent(400), &mut rng)
+                    .to_bytes(None)
+                    .len()
+            })
+            .collect();
+        assert!(lengths.iter().any(|&len| len != lengths[0]));
+        assert!(lengths.iter().all(|&len| len <= unpadded * 5));
+
+        // Already past the bound, so nothing is added.
+        let envelope = Envelope::new(vec![0; 100]).with_padding(Padding::UpTo(10), &mut rng);
+        assert!(envelope.padding.is_empty());
+
+        let mut bytes = Envelope::new(b"body".to_vec())
+            .with_padding(Padding::UpTo(200), &mut rng)
+            .to_bytes(None);
+        bytes.truncate(HEADER_LEN + FIELD_OVERHEAD + 4 + 2);
+        assert_eq!(
+            Envelope::from_bytes(&bytes, None),
+            Err(EnvelopeError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let metadata = Metadata {
+            creator: Some("Ada".to_string()),
+            created_at: Some(TimeChunk::new(2024, 3, 1, 12, 34, 56).unwrap()),
+            comment: Some("for the archive".to_string()),
+            content_type: Some("text/plain".to_string()),
+            expires: Some(TimeChunk::new(2025, 12, 31, 23, 59, 59).unwrap()),
+        };
+        assert!(!metadata.is_expired(TimeChunk::new(2025, 12, 31, 23, 59, 59).unwrap()));
+        assert!(metadata.is_expired(TimeChunk::new(2026, 1, 1, 0, 0, 0).unwrap()));
+        assert!(!Metadata::default().is_expired(TimeChunk::new(9999, 1, 1, 0, 0, 0).unwrap()));
+        let envelope = Envelope::new(b"secret".to_vec())
+            .with_compression(Compression::Deflate(6))
+            .with_metadata(metadata.clone());
+        let parsed = Envelope::from_bytes(&envelope.to_bytes(None), None).unwrap();
+        assert_eq!(parsed.metadata, metadata);
+        assert_eq!(parsed.body, b"secret");
+        assert!(Envelope::new(Vec::new()).metadata.is_empty());
+
+        let bad_time = Metadata {
+            created_at: Some(TimeChunk {
+                month: 13,
+                ..metadata.created_at.unwrap()
+            }),
+            ..Metadata::default()
+        };
+        let bytes = Envelope::new(Vec::new())
+            .with_metadata(bad_time)
+            .to_bytes(None);
+        assert_eq!(
+            Envelope::from_bytes(&bytes, None),
+            Err(EnvelopeError::InvalidTime(TimeError::OutOfRange("month")))
+        );
+    }
+
+    #[test]
+    fn test_padding_from_str() {
+        assert_eq!("4096".parse(), Ok(Padding::UpTo(4096)));
+        assert_eq!("20%".parse(), Ok(Padding::Percent(20)));
+        for bad in ["", "%", "-1", "big", "20 %"] {
+            assert_eq!(
+                bad.parse::<Padding>(),
+                Err(EnvelopeError::UnknownPadding(bad.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_file_names() {
+        assert!(FileInfo::validate_name("notes.txt").is_ok());
+        assert!(FileInfo::validate_name(".hidden").is_ok());
+        for bad in [
+            "",
+            ".",
+            "..",
+            "../etc/passwd",
+            "/etc/passwd",
+            "a\\b",
+            "a\0b",
+        ] {
+            assert_eq!(
+                FileInfo::validate_name(bad),
+                Err(EnvelopeError::InvalidFileName(bad.to_string()))
+            );
+        }
+
+        let file = FileInfo {
+            name: "../escape".to_string(),
+            size: 0,
+            mode: 0o644,
+        };
+        let bytes = Envelope::new(Vec::new()).with_file(file).to_bytes(None);
+        assert_eq!(
+            Envelope::from_bytes(&bytes, None),
+            Err(EnvelopeError::InvalidFileName("../escape".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_bad_header() {
+        assert_eq!(
+            Envelope::from_bytes(&MAGIC, None),
+            Err(EnvelopeError::Truncated)
+        );
+
+        let mut bytes = Envelope::new(Vec::new()).to_bytes(None);
+        bytes[4] = 9;
+        assert_eq!(
+            Envelope::from_bytes(&bytes, None),
+            Err(EnvelopeError::UnsupportedVersion(9))
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use std::convert::TryFrom;
+
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ExifError {
+    #[error("ExifError not an eXIf chunk: {0}")]
+    WrongType(String),
+    #[error("ExifError data does not start with a TIFF header")]
+    BadHeader,
+    #[error("ExifError IFD at offset {0} lies outside the data")]
+    BadOffset(usize),
+}
+
+/// The TIFF byte order an Exif blob is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// `II`, little-endian.
+    Intel,
+    /// `MM`, big-endian.
+    Motorola,
+}
+
+impl ByteOrder {
+    fn u16(self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        match self {
+            ByteOrder::Intel => u16::from_le_bytes(bytes),
+            ByteOrder::Motorola => u16::from_be_bytes(bytes),
+        }
+    }
+
+    fn u32(self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self {
+            ByteOrder::Intel => u32::from_le_bytes(bytes),
+            ByteOrder::Motorola => u32::from_be_bytes(bytes),
+        }
+    }
+}
+
+/// One entry of the first image file directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExifEntry {
+    pub tag: u16,
+    /// The TIFF field type, e.g. 2 for ASCII.
+    pub field_type: u16,
+    pub count: u32,
+    /// The text of an ASCII entry, without its trailing NUL.
+    pub text: Option<String>,
+}
+
+impl ExifEntry {
+    /// The name of the well-known tags that say where an image came from.
+    pub fn name(&self) -> Option<&'static str> {
+        Some(match self.tag {
+            0x010e => "ImageDescription",
+            0x010f => "Make",
+            0x0110 => "Model",
+            0x0112 => "Orientation",
+            0x0131 => "Software",
+            0x0132 => "DateTime",
+            0x013b => "Artist",
+            0x8298 => "Copyright",
+            0x8769 => "ExifIFD",
+            0x8825 => "GPSInfo",
+            _ => return None,
+        })
+    }
+}
+
+/// An Exif blob: a TIFF header followed by image file directories, as stored
+/// in an eXIf chunk without the `Exif\0\0` prefix JPEG files use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExifChunk {
+    data: Vec<u8>,
+}
+
+impl ExifChunk {
+    pub const TYPE: &'static str = "eXIf";
+
+    /// Prefix of the APP1 segment Exif data comes in inside JPEG files.
+    pub const JPEG_PREFIX: &'static [u8] = b"Exif\0\0";
+
+    /// Wraps `data`, dropping a JPEG `Exif\0\0` prefix if it has one.
+    pub fn new(data: Vec<u8>) -> Result<Self, ExifError> {
+        let data = match data.strip_prefix(Self::JPEG_PREFIX) {
+            Some(tiff) => tiff.to_vec(),
+            None => data,
+        };
+        byte_order(&data)?;
+        Ok(ExifChunk { data })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn byte_order(&self) -> ByteOrder {
+        byte_order(&self.data).expect("checked by new")
+    }
+
+    /// The entries of the first image file directory (IFD0).
+    pub fn entries(&self) -> Result<Vec<ExifEntry>, ExifError> {
+        let order = self.byte_order();
+        let data = &self.data;
+        let ifd = order.u32(&data[4..8]) as usize;
+        let count = data
+            .get(ifd..ifd + 2)
+            .map(|bytes| order.u16(bytes) as usize)
+            .ok_or(ExifError::BadOffset(ifd))?;
+        let entries = data
+            .get(ifd + 2..ifd + 2 + count * 12)
+            .ok_or(ExifError::BadOffset(ifd))?;
+
+        Ok(entries
+            .chunks_exact(12)
+            .map(|entry| {
+                let field_type = order.u16(&entry[2..4]);
+                let count = order.u32(&entry[4..8]);
+                let text = (field_type == 2)
+                    .then(|| {
+                        // Values over 4 bytes are stored elsewhere, at an offset.
+                        let len = count as usize;
+                        let value = if len <= 4 {
+                            entry.get(8..8 + len)
+                        } else {
+                            let offset = order.u32(&entry[8..12]) as usize;
+                            data.get(offset..offset.checked_add(len)?)
+                        }?;
+                        let value = value.strip_suffix(&[0]).unwrap_or(value);
+                        Some(String::from_utf8_lossy(value).into_owned())
+                    })
+                    .flatten();
+                ExifEntry {
+                    tag: order.u16(&entry[0..2]),
+                    field_type,
+                    count,
+                    text,
+                }
+            })
+            .collect())
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(Self::chunk_type(), self.data.clone())
+    }
+
+    pub fn chunk_type() -> ChunkType {
+        ChunkType::EXIF
+    }
+}
+
+impl TryFrom<&Chunk> for ExifChunk {
+    type Error = ExifError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if chunk.chunk_type() != &Self::chunk_type() {
+            return Err(ExifError::WrongType(chunk.chunk_type().to_string()));
+        }
+        byte_order(chunk.data())?;
+        Ok(ExifChunk {
+            data: chunk.data().to_vec(),
+        })
+    }
+}
+
+fn byte_order(data: &[u8]) -> Result<ByteOrder, ExifError> {
+    match data.get(..4) {
+        Some(b"II*\0") if data.len() >= 8 => Ok(ByteOrder::Intel),
+        Some(b"MM\0*") if data.len() >= 8 => Ok(ByteOrder::Motorola),
+        _ => Err(ExifError::BadHeader),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A little-endian blob with Make (inline) and Software (at an offset).
+    fn testing_exif() -> Vec<u8> {
+        let mut data = b"II*\0\x08\0\0\0".to_vec();
+        data.extend(2u16.to_le_bytes());
+        data.extend([0x0f, 0x01, 2, 0, 4, 0, 0, 0]);
+        data.extend(b"Cam\0");
+        data.extend([0x31, 0x01, 2, 0, 9, 0, 0, 0]);
+        data.extend(38u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(b"GIMP 2.0\0");
+        data
+    }
+
+    #[test]
+    fn test_entries() {
+        let exif = ExifChunk::new(testing_exif()).unwrap();
+        assert_eq!(exif.byte_order(), ByteOrder::Intel);
+        let entries = exif.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name(), Some("Make"));
+        assert_eq!(entries[0].text.as_deref(), Some("Cam"));
+        assert_eq!(entries[1].name(), Some("Software"));
+        assert_eq!(entries[1].text.as_deref(), Some("GIMP 2.0"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut jpeg = ExifChunk::JPEG_PREFIX.to_vec();
+        jpeg.extend(testing_exif());
+        let exif = ExifChunk::new(jpeg).unwrap();
+        assert_eq!(exif.data(), testing_exif());
+        assert_eq!(ExifChunk::try_from(&exif.to_chunk()), Ok(exif));
+
+        assert_eq!(ExifChunk::new(b"JFIF".to_vec()), Err(ExifError::BadHeader));
+        let mut truncated = testing_exif();
+        truncated[4] = 200;
+        assert_eq!(
+            ExifChunk::new(truncated).unwrap().entries(),
+            Err(ExifError::BadOffset(200))
+        );
+    }
+}
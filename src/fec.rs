@@ -0,0 +1,464 @@
+//! Reed-Solomon forward error correction over GF(2^8), so a payload survives
+//! a few damaged bytes: a corrupted chunk or low bits changed by an edit.
+//!
+//! Layout: a header codeword holding the magic, the parity per block and the
+//! data length, then the data in blocks of at most `255 - parity` bytes, each
+//! followed by its parity. The blocks are interleaved byte by byte, so a run
+//! of damaged bytes spreads over every block rather than overwhelming one.
+
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum FecError {
+    #[error("FecError unknown error correction {0}, expected rs:<1-128>")]
+    UnknownScheme(String),
+    #[error("FecError payload carries no error correction")]
+    NotProtected,
+    #[error("FecError payload should be {0} bytes but is {1}")]
+    Truncated(usize, usize),
+    #[error("FecError block {0} has more damaged bytes than its parity corrects")]
+    Uncorrectable(usize),
+}
+
+/// Marks a payload protected by `encode`.
+pub const MAGIC: [u8; 4] = [0x89, b'p', b's', b'r'];
+
+/// Magic, parity and a big-endian u32 data length.
+const HEADER_DATA_LEN: usize = 9;
+
+/// The parity of the header codeword, which corrects 4 damaged header bytes.
+const HEADER_PARITY: usize = 8;
+
+pub const HEADER_LEN: usize = HEADER_DATA_LEN + HEADER_PARITY;
+
+/// The longest codeword over GF(2^8).
+const MAX_CODEWORD: usize = 255;
+
+/// The most parity bytes per block `--fec` takes.
+pub const MAX_PARITY: u8 = 128;
+
+/// Reed-Solomon with this many parity bytes per block, correcting up to half
+/// as many damaged bytes in each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fec {
+    pub parity: u8,
+}
+
+impl Fec {
+    /// The number of blocks `len` bytes of data are split into.
+    fn blocks(self, len: usize) -> usize {
+        len.div_ceil(MAX_CODEWORD - usize::from(self.parity))
+    }
+
+    /// The size of `len` bytes of data once protected.
+    pub fn encoded_len(self, len: usize) -> usize {
+        let blocks = self.blocks(len);
+        let block_len = if blocks == 0 { 0 } else { len.div_ceil(blocks) };
+        HEADER_LEN + blocks * (block_len + usize::from(self.parity))
+    }
+}
+
+impl FromStr for Fec {
+    type Err = FecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("rs:").map(str::parse) {
+            Some(Ok(parity)) if (1..=MAX_PARITY).contains(&parity) => Ok(Self { parity }),
+            _ => Err(FecError::UnknownScheme(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Fec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rs:{}", self.parity)
+    }
+}
+
+/// What `decode` had to repair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Correction {
+    pub fec: Fec,
+    pub blocks: usize,
+    /// Blocks with at least one damaged byte.
+    pub damaged_blocks: usize,
+    /// Damaged bytes repaired, the header's included.
+    pub corrected: usize,
+}
+
+/// Protects `data` with `fec`.
+pub fn encode(data: &[u8], fec: Fec) -> Vec<u8> {
+    let parity = usize::from(fec.parity);
+    let mut header = Vec::with_capacity(HEADER_DATA_LEN);
+    header.extend_from_slice(&MAGIC);
+    header.push(fec.parity);
+    header.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let mut bytes = Vec::with_capacity(fec.encoded_len(data.len()));
+    bytes.extend_from_slice(&header);
+    bytes.extend_from_slice(&parity_of(&header, HEADER_PARITY));
+
+    let blocks = fec.blocks(data.len());
+    if blocks == 0 {
+        return bytes;
+    }
+    let block_len = data.len().div_ceil(blocks);
+    let codewords: Vec<Vec<u8>> = (0..blocks)
+        .map(|block| {
+            let start = (block * block_len).min(data.len());
+            let end = ((block + 1) * block_len).min(data.len());
+            // The last block is padded with zeros, which are not stored.
+            let mut codeword = data[start..end].to_vec();
+            codeword.resize(block_len, 0);
+            let parity = parity_of(&codeword, parity);
+            codeword.extend_from_slice(&parity);
+            codeword
+        })
+        .collect();
+    for i in 0..block_len + parity {
+        bytes.extend(codewords.iter().map(|codeword| codeword[i]));
+    }
+    bytes
+}
+
+/// Whether `bytes` start with a header `encode` wrote, damaged or not.
+pub fn is_protected(bytes: &[u8]) -> bool {
+    read_header(bytes).is_ok()
+}
+
+/// Repairs and returns the data `encode` protected, with what it repaired.
+/// Bytes past the protected payload are ignored.
+pub fn decode(bytes: &[u8]) -> Result<(Vec<u8>, Correction), FecError> {
+    let (fec, len, header_corrected) = read_header(bytes)?;
+    let expected = fec.encoded_len(len);
+    if bytes.len() < expected {
+        return Err(FecError::Truncated(expected, bytes.len()));
+    }
+    let mut correction = Correction {
+        fec,
+        blocks: fec.blocks(len),
+        damaged_blocks: 0,
+        corrected: header_corrected,
+    };
+    if correction.blocks == 0 {
+        return Ok((Vec::new(), correction));
+    }
+
+    let blocks = correction.blocks;
+    let body = &bytes[HEADER_LEN..expected];
+    let codeword_len = body.len() / blocks;
+    let mut data = Vec::with_capacity(blocks * codeword_len);
+    for block in 0..blocks {
+        let mut codeword: Vec<u8> = body.iter().skip(block).step_by(blocks).copied().collect();
+        let corrected = correct(&mut codeword, usize::from(fec.parity))
+            .ok_or(FecError::Uncorrectable(block))?;
+        if corrected > 0 {
+            correction.damaged_blocks += 1;
+            correction.corrected += corrected;
+        }
+        data.extend_from_slice(&codeword[..codeword_len - usize::from(fec.parity)]);
+    }
+    data.truncate(len);
+    Ok((data, correction))
+}
+
+/// The scheme and data length from the header, and the header bytes repaired.
+fn read_header(bytes: &[u8]) -> Result<(Fec, usize, usize), FecError> {
+    let mut header = bytes
+        .get(..HEADER_LEN)
+        .ok_or(FecError::NotProtected)?
+        .to_vec();
+    let corrected = correct(&mut header, HEADER_PARITY).ok_or(FecError::NotProtected)?;
+    if header[..MAGIC.len()] != MAGIC || !(1..=MAX_PARITY).contains(&header[4]) {
+        return Err(FecError::NotProtected);
+    }
+    let len = u32::from_be_bytes(header[5..9].try_into().unwrap()) as usize;
+    Ok((Fec { parity: header[4] }, len, corrected))
+}
+
+/// Log and antilog tables of GF(2^8) with the polynomial
+/// x^8 + x^4 + x^3 + x^2 + 1 and generator 2. The antilog table is doubled so
+/// sums of two logs index it directly.
+struct Tables {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+static TABLES: Tables = {
+    let mut exp = [0; 512];
+    let mut log = [0; 256];
+    let mut value: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = value as u8;
+        exp[i + 255] = value as u8;
+        log[value as usize] = i as u8;
+        value <<= 1;
+        if value & 0x100 != 0 {
+            value ^= 0x11d;
+        }
+        i += 1;
+    }
+    Tables { exp, log }
+};
+
+fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    TABLES.exp[usize::from(TABLES.log[usize::from(a)]) + usize::from(TABLES.log[usize::from(b)])]
+}
+
+fn div(a: u8, b: u8) -> u8 {
+    if a == 0 {
+        return 0;
+    }
+    let log =
+        usize::from(TABLES.log[usize::from(a)]) + 255 - usize::from(TABLES.log[usize::from(b)]);
+    TABLES.exp[log]
+}
+
+/// 2 raised to `power`, which may be negative.
+fn alpha(power: isize) -> u8 {
+    TABLES.exp[power.rem_euclid(255) as usize]
+}
+
+/// Evaluates a polynomial stored lowest degree first at `x`.
+fn eval(poly: &[u8], x: u8) -> u8 {
+    poly.iter().rev().fold(0, |acc, &coef| mul(acc, x) ^ coef)
+}
+
+/// The parity bytes for `message`: the remainder of dividing it, shifted up by
+/// `parity` places, by the generator with roots 2^0 to 2^(parity - 1). The
+/// message and the parity are stored highest degree first.
+fn parity_of(message: &[u8], parity: usize) -> Vec<u8> {
+    let mut generator = vec![1u8];
+    for i in 0..parity {
+        // Multiplies by (x + 2^i), highest degree first.
+        let root = alpha(i as isize);
+        let mut next = vec![0; generator.len() + 1];
+        for (j, &coef) in generator.iter().enumerate() {
+            next[j] ^= coef;
+            next[j + 1] ^= mul(coef, root);
+        }
+        generator = next;
+    }
+
+    let mut remainder = vec![0; parity];
+    for &byte in message {
+        let factor = byte ^ remainder[0];
+        remainder.remove(0);
+        remainder.push(0);
+        for (r, &g) in remainder.iter_mut().zip(&generator[1..]) {
+            *r ^= mul(g, factor);
+        }
+    }
+    remainder
+}
+
+/// Repairs `codeword`, message then `parity` parity bytes, in place, returning
+/// the number of bytes repaired or `None` if there are too many to repair.
+fn correct(codeword: &mut [u8], parity: usize) -> Option<usize> {
+    // The codeword is stored highest degree first, so byte p has degree n - 1 - p.
+    let len = codeword.len();
+    let degree = move |position: usize| (len - 1 - position) as isize;
+    let syndromes = |codeword: &[u8]| -> Vec<u8> {
+        (0..parity)
+            .map(|i| {
+                let x = alpha(i as isize);
+                codeword.iter().fold(0, |acc, &byte| mul(acc, x) ^ byte)
+            })
+            .collect()
+    };
+    let syndrome = syndromes(codeword);
+    if syndrome.iter().all(|&s| s == 0) {
+        return Some(0);
+    }
+
+    // Berlekamp-Massey finds the error locator, the polynomial whose roots are
+    // the inverses of 2^degree for each damaged byte, lowest degree first.
+    let (mut locator, mut previous) = (vec![1u8], vec![1u8]);
+    let (mut errors, mut shift, mut last_discrepancy) = (0, 1, 1u8);
+    for n in 0..parity {
+        let discrepancy = (1..=errors).fold(syndrome[n], |acc, i| {
+            acc ^ mul(locator.get(i).copied().unwrap_or(0), syndrome[n - i])
+        });
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+        let scale = div(discrepancy, last_discrepancy);
+        let mut next = locator.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (i, &coef) in previous.iter().enumerate() {
+            next[i + shift] ^= mul(coef, scale);
+        }
+        if 2 * errors <= n {
+            previous = std::mem::replace(&mut locator, next);
+            errors = n + 1 - errors;
+            last_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            locator = next;
+            shift += 1;
+        }
+    }
+    if 2 * errors > parity {
+        return None;
+    }
+
+    // The damaged bytes are where the locator has its roots.
+    let positions: Vec<usize> = (0..codeword.len())
+        .filter(|&position| eval(&locator, alpha(-degree(position))) == 0)
+        .collect();
+    if positions.len() != errors {
+        return None;
+    }
+
+    // Forney: the error evaluator is the syndromes times the locator, cut to
+    // the parity length; each error is 2^degree * evaluator / locator' at the
+    // root.
+    let mut evaluator = vec![0u8; parity];
+    for (i, &s) in syndrome.iter().enumerate() {
+        for (j, &l) in locator.iter().enumerate().take(parity - i) {
+            evaluator[i + j] ^= mul(s, l);
+        }
+    }
+    // The formal derivative keeps the odd powers, each down one degree.
+    let derivative: Vec<u8> = locator
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, &coef)| if i % 2 == 1 { coef } else { 0 })
+        .collect();
+    for &position in &positions {
+        let root = alpha(-degree(position));
+        let denominator = eval(&derivative, root);
+        if denominator == 0 {
+            return None;
+        }
+        let magnitude = mul(
+            alpha(degree(position)),
+            div(eval(&evaluator, root), denominator),
+        );
+        codeword[position] ^= magnitude;
+    }
+
+    syndromes(codeword)
+        .iter()
+        .all(|&s| s == 0)
+        .then_some(positions.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    fn random(len: usize, seed: u64) -> Vec<u8> {
+        let mut bytes = vec![0; len];
+        Rng::new(seed).fill_bytes(&mut bytes);
+        bytes
+    }
+
+    /// Overwrites `count` distinct bytes picked by `rng` with different values.
+    fn damage(bytes: &mut [u8], count: usize, rng: &mut Rng) {
+        let mut positions: Vec<usize> = (0..bytes.len()).collect();
+        rng.shuffle(&mut positions);
+        for &position in &positions[..count] {
+            bytes[position] ^= 1 + rng.below(255) as u8;
+        }
+    }
+
+    #[test]
+    fn test_fec() {
+        assert_eq!("rs:16".parse(), Ok(Fec { parity: 16 }));
+        assert_eq!(Fec { parity: 16 }.to_string(), "rs:16");
+        for spec in ["rs:0", "rs:129", "rs", "hamming:4"] {
+            assert_eq!(
+                spec.parse::<Fec>(),
+                Err(FecError::UnknownScheme(spec.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_correct() {
+        let mut rng = Rng::new(1);
+        for (len, parity) in [(1, 2), (10, 4), (100, 16), (223, 32), (127, 128)] {
+            let message = random(len, len as u64);
+            let mut codeword = message.clone();
+            codeword.extend(parity_of(&message, parity));
+            let original = codeword.clone();
+            assert_eq!(correct(&mut codeword, parity), Some(0));
+
+            for errors in 1..=parity / 2 {
+                let mut damaged = original.clone();
+                damage(&mut damaged, errors, &mut rng);
+                assert_eq!(
+                    correct(&mut damaged, parity),
+                    Some(errors),
+                    "{len} {parity}"
+                );
+                assert_eq!(damaged, original);
+            }
+        }
+
+        // Too much damage is refused rather than silently miscorrected, at
+        // least most of the time.
+        let message = random(50, 2);
+        let mut codeword = message.clone();
+        codeword.extend(parity_of(&message, 8));
+        damage(&mut codeword, 20, &mut rng);
+        assert_eq!(correct(&mut codeword, 8), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = Rng::new(3);
+        for (len, parity) in [(0, 4), (5, 4), (251, 4), (252, 4), (1000, 16)] {
+            let fec = Fec { parity };
+            let data = random(len, 7);
+            let mut bytes = encode(&data, fec);
+            assert_eq!(bytes.len(), fec.encoded_len(len));
+            assert!(is_protected(&bytes));
+            let (decoded, correction) = decode(&bytes).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(correction.corrected, 0);
+
+            // A run of damage as long as the parity corrects in every block.
+            let run = fec.blocks(len) * usize::from(parity) / 2;
+            for byte in &mut bytes[HEADER_LEN..HEADER_LEN + run] {
+                *byte ^= 0x5a;
+            }
+            damage(&mut bytes[..HEADER_LEN], 3, &mut rng);
+            let (decoded, correction) = decode(&bytes).unwrap();
+            assert_eq!(decoded, data, "{len} {parity}");
+            assert_eq!(correction.corrected, run + 3);
+        }
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(!is_protected(b"plain message, no header here"));
+        assert_eq!(decode(b"short"), Err(FecError::NotProtected));
+
+        let fec = Fec { parity: 16 };
+        let bytes = encode(&random(300, 1), fec);
+        assert_eq!(
+            decode(&bytes[..100]),
+            Err(FecError::Truncated(bytes.len(), 100))
+        );
+
+        let mut bytes = bytes;
+        let blocks = fec.blocks(300);
+        // Nine damaged bytes of block 1 for a parity correcting eight.
+        for i in 0..9 {
+            bytes[HEADER_LEN + 1 + i * blocks] ^= 0xff;
+        }
+        assert_eq!(decode(&bytes), Err(FecError::Uncorrectable(1)));
+    }
+}
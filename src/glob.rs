@@ -0,0 +1,336 @@
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum GlobError {
+    #[error("GlobError unclosed [ in pattern {0}")]
+    UnclosedClass(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    /// `?`, any one character.
+    Any,
+    /// `*`, any run of characters, including none.
+    Star,
+    /// `[...]` or `[!...]`, one character in (or not in) the inclusive ranges.
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// A shell-style wildcard pattern: `?`, `*`, `[abc]`, `[a-z]` and `[!abc]`.
+/// A backslash matches the next character literally.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    source: String,
+    tokens: Vec<Token>,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self, GlobError> {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::Any,
+                '*' => Token::Star,
+                '\\' => Token::Literal(chars.next().unwrap_or('\\')),
+                '[' => {
+                    let negated = chars.next_if(|&c| c == '!' || c == '^').is_some();
+                    let mut ranges = Vec::new();
+                    loop {
+                        let start = match chars.next() {
+                            // A `]` straight after the `[` is part of the class.
+                            Some(']') if !ranges.is_empty() => break,
+                            Some(c) => c,
+                            None => return Err(GlobError::UnclosedClass(pattern.to_string())),
+                        };
+                        let end = match chars.next_if_eq(&'-') {
+                            Some(_) => match chars.next_if(|&c| c != ']') {
+                                Some(end) => end,
+                                None => {
+                                    // A trailing `-` is literal, as in `[a-]`.
+                                    ranges.push((start, start));
+                                    '-'
+                                }
+                            },
+                            None => start,
+                        };
+                        let (start, end) = if start <= end {
+                            (start, end)
+                        } else {
+                            (end, start)
+                        };
+                        ranges.push((start, end));
+                    }
+                    Token::Class { negated, ranges }
+                }
+                c => Token::Literal(c),
+            };
+            tokens.push(token);
+        }
+
+        Ok(Self {
+            source: pattern.to_string(),
+            tokens,
+        })
+    }
+
+    /// Whether the pattern matches the whole of `text`.
+    pub fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let (mut t, mut p) = (0, 0);
+        // Where to resume after the last `*` if the rest fails to match.
+        let mut backtrack: Option<(usize, usize)> = None;
+
+        while t < text.len() {
+            match self.tokens.get(p) {
+                Some(Token::Star) => {
+                    backtrack = Some((p, t));
+                    p += 1;
+                    continue;
+                }
+                Some(token) if token.matches(text[t]) => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            match backtrack {
+                Some((star, start)) => {
+                    p = star + 1;
+                    t = start + 1;
+                    backtrack = Some((star, start + 1));
+                }
+                None => return false,
+            }
+        }
+        self.tokens[p..].iter().all(|token| token == &Token::Star)
+    }
+
+    /// Whether the pattern contains any wildcards, or only matches itself.
+    pub fn is_literal(&self) -> bool {
+        self.tokens
+            .iter()
+            .all(|token| matches!(token, Token::Literal(_)))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Literal(literal) => *literal == c,
+            Token::Any => true,
+            Token::Star => false,
+            Token::Class { negated, ranges } => {
+                ranges
+                    .iter()
+                    .any(|&(start, end)| (start..=end).contains(&c))
+                    != *negated
+            }
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = GlobError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// A pattern over `/`-separated paths, each component a `Pattern`, where a
+/// `**` component stands for any number of directories, none included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathPattern {
+    source: String,
+    /// `None` for `**`. Empty components, as in `a//b`, are left out.
+    components: Vec<Option<Pattern>>,
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Result<Self, GlobError> {
+        let components = pattern
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .map(|component| match component {
+                "**" => Ok(None),
+                component => Pattern::new(component).map(Some),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            source: pattern.to_string(),
+            components,
+        })
+    }
+
+    /// Whether the pattern matches the whole of the `/`-separated `path`.
+    pub fn matches(&self, path: &str) -> bool {
+        let path: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        matches_components(&self.components, &path)
+    }
+
+    /// The directory to search for matches from: the leading components
+    /// without wildcards, as written, or an empty string for none.
+    pub fn base(&self) -> &str {
+        let parts: Vec<&str> = self.source.split('/').collect();
+        let mut end = 0;
+        // The last component names what is searched for, so never counts.
+        for (i, part) in parts[..parts.len() - 1].iter().enumerate() {
+            let literal = part.is_empty()
+                || (*part != "**" && Pattern::new(part).is_ok_and(|part| part.is_literal()));
+            if !literal {
+                break;
+            }
+            end = parts[..=i].iter().map(|part| part.len() + 1).sum::<usize>() - 1;
+        }
+        match &self.source[..end] {
+            "" if self.source.starts_with('/') => "/",
+            base => base,
+        }
+    }
+
+    /// How many directories below `base` a match can be, or `None` if `**`
+    /// lets it be any number.
+    pub fn depth(&self) -> Option<usize> {
+        let below = self.components.iter().skip(self.base_components());
+        below
+            .map(|component| component.as_ref().map(|_| 1))
+            .sum::<Option<usize>>()
+            .map(|count| count.saturating_sub(1))
+    }
+
+    fn base_components(&self) -> usize {
+        self.base().split('/').filter(|c| !c.is_empty()).count()
+    }
+
+    /// Whether the pattern contains any wildcards, or only matches itself.
+    pub fn is_literal(&self) -> bool {
+        self.components
+            .iter()
+            .all(|component| component.as_ref().is_some_and(Pattern::is_literal))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+fn matches_components(pattern: &[Option<Pattern>], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((None, rest)) => (0..=path.len()).any(|skip| matches_components(rest, &path[skip..])),
+        Some((Some(component), rest)) => path.split_first().is_some_and(|(first, path)| {
+            component.matches(first) && matches_components(rest, path)
+        }),
+    }
+}
+
+impl FromStr for PathPattern {
+    type Err = GlobError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).unwrap().matches(text)
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert!(matches("ru??", "ruSt"));
+        assert!(!matches("ru??", "ruS"));
+        assert!(!matches("ru??", "RuSt"));
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("*.png", "image.png"));
+        assert!(!matches("*.png", "image.png.bak"));
+        assert!(matches("a*b*c", "aXXbYYbc"));
+        assert!(!matches("a*b*c", "aXXbYYb"));
+        assert!(matches("**x", "x"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(matches("[tz]EXt", "tEXt"));
+        assert!(matches("[tz]EXt", "zEXt"));
+        assert!(!matches("[tz]EXt", "iEXt"));
+        assert!(matches("[a-z]???", "ruSt"));
+        assert!(!matches("[a-z]???", "RuSt"));
+        assert!(matches("[!A-Z]*", "ruSt"));
+        assert!(!matches("[!A-Z]*", "IDAT"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "x"));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert_eq!(
+            Pattern::new("ru[St"),
+            Err(GlobError::UnclosedClass("ru[St".to_string()))
+        );
+        assert!(Pattern::new("[]").is_err());
+    }
+
+    #[test]
+    fn test_path_patterns() {
+        let pattern = PathPattern::new("imgs/**/*.png").unwrap();
+        assert!(pattern.matches("imgs/a.png"));
+        assert!(pattern.matches("imgs/2024/march/a.png"));
+        assert!(!pattern.matches("imgs/a.jpg"));
+        assert!(!pattern.matches("other/a.png"));
+        assert_eq!(pattern.base(), "imgs");
+        assert_eq!(pattern.depth(), None);
+
+        let pattern = PathPattern::new("/home/me/*/*.png").unwrap();
+        assert!(pattern.matches("/home/me/x/a.png"));
+        assert!(!pattern.matches("/home/me/a.png"));
+        assert_eq!(pattern.base(), "/home/me");
+        assert_eq!(pattern.depth(), Some(1));
+
+        let pattern = PathPattern::new("*.png").unwrap();
+        assert_eq!((pattern.base(), pattern.depth()), ("", Some(0)));
+        assert!(!pattern.is_literal());
+        assert_eq!(PathPattern::new("/*.png").unwrap().base(), "/");
+        assert!(PathPattern::new("a/b.png").unwrap().is_literal());
+        assert!(PathPattern::new("**").unwrap().matches("any/thing"));
+        assert!(PathPattern::new("a/[b/c").is_err());
+    }
+
+    #[test]
+    fn test_is_literal() {
+        assert!(Pattern::new("IDAT").unwrap().is_literal());
+        assert!(!Pattern::new("ID?T").unwrap().is_literal());
+    }
+}
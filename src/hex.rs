@@ -0,0 +1,111 @@
+use std::fmt::Write;
+
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum HexError {
+    #[error("HexError invalid hex digit {0:?} at offset {1}")]
+    InvalidDigit(char, usize),
+    #[error("HexError odd number of hex digits")]
+    OddLength,
+}
+
+/// Bytes shown on each line of a dump.
+pub const DUMP_WIDTH: usize = 16;
+
+/// Encodes `data` as lowercase hex without separators.
+pub fn encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len() * 2);
+    for byte in data {
+        write!(encoded, "{byte:02x}").unwrap();
+    }
+    encoded
+}
+
+/// Decodes hex digits of either case, ignoring whitespace between bytes.
+pub fn decode(encoded: &str) -> Result<Vec<u8>, HexError> {
+    let mut decoded = Vec::with_capacity(encoded.len() / 2);
+    let mut high = None;
+    for (offset, c) in encoded.char_indices() {
+        if c.is_ascii_whitespace() && high.is_none() {
+            continue;
+        }
+        let digit = c.to_digit(16).ok_or(HexError::InvalidDigit(c, offset))? as u8;
+        match high.take() {
+            Some(high) => decoded.push(high << 4 | digit),
+            None => high = Some(digit),
+        }
+    }
+    match high {
+        Some(_) => Err(HexError::OddLength),
+        None => Ok(decoded),
+    }
+}
+
+/// Formats `data` like `hexdump -C`: an offset, sixteen bytes in hex and the same bytes
+/// as ASCII on each line, then the offset just past the end. Offsets start at `start`,
+/// so a slice can be dumped with the offsets of the data it was cut from.
+pub fn dump(data: &[u8], start: usize) -> String {
+    let mut out = String::new();
+    for (i, line) in data.chunks(DUMP_WIDTH).enumerate() {
+        write!(out, "{:08x} ", start + i * DUMP_WIDTH).unwrap();
+        for j in 0..DUMP_WIDTH {
+            if j % 8 == 0 {
+                out.push(' ');
+            }
+            match line.get(j) {
+                Some(byte) => write!(out, "{byte:02x} ").unwrap(),
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str(" |");
+        for &byte in line {
+            out.push(if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            });
+        }
+        out.push_str("|\n");
+    }
+    writeln!(out, "{:08x}", start + data.len()).unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(encode(&[0x00, 0xab, 0xff]), "00abff");
+        assert_eq!(decode(&encode(&data)).unwrap(), data);
+        assert_eq!(decode("DE ad\nbe EF").unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert_eq!(decode("abc"), Err(HexError::OddLength));
+        assert_eq!(decode("zz"), Err(HexError::InvalidDigit('z', 0)));
+        assert_eq!(decode("a b"), Err(HexError::InvalidDigit(' ', 1)));
+    }
+
+    #[test]
+    fn test_dump() {
+        let expected = "\
+00000000  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52  |.PNG........IHDR|
+00000010  68 69 21                                          |hi!|
+00000013
+";
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        data.extend_from_slice(b"hi!");
+        assert_eq!(dump(&data, 0), expected);
+
+        assert_eq!(
+            dump(b"abc", 0x20),
+            "00000020  61 62 63                                          |abc|\n00000023\n"
+        );
+        assert_eq!(dump(b"", 0), "00000000\n");
+    }
+}
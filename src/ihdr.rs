@@ -0,0 +1,312 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum IhdrError {
+    #[error("IhdrError not an IHDR chunk: {0}")]
+    WrongType(String),
+    #[error("IhdrError data is {0} bytes, expected 13")]
+    InvalidLength(usize),
+    #[error("IhdrError width and height must be at least 1")]
+    ZeroDimension,
+    #[error("IhdrError dimension {0} exceeds 2^31 - 1")]
+    DimensionTooLarge(u32),
+    #[error("IhdrError invalid color type {0}")]
+    InvalidColorType(u8),
+    #[error("IhdrError bit depth {0} is not allowed for color type {1}")]
+    InvalidBitDepth(u8, ColorType),
+    #[error("IhdrError unknown compression method {0}")]
+    UnknownCompression(u8),
+    #[error("IhdrError unknown filter method {0}")]
+    UnknownFilter(u8),
+    #[error("IhdrError unknown interlace method {0}")]
+    UnknownInterlace(u8),
+}
+
+/// How pixels are made up, from the IHDR color type byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale = 0,
+    Rgb = 2,
+    Indexed = 3,
+    GrayscaleAlpha = 4,
+    Rgba = 6,
+}
+
+impl ColorType {
+    /// Samples per pixel; an indexed pixel is a single palette index.
+    pub fn channels(self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// What each sample of a pixel holds, in order.
+    pub fn channel_names(self) -> &'static [&'static str] {
+        match self {
+            ColorType::Grayscale => &["gray"],
+            ColorType::Indexed => &["index"],
+            ColorType::GrayscaleAlpha => &["gray", "alpha"],
+            ColorType::Rgb => &["red", "green", "blue"],
+            ColorType::Rgba => &["red", "green", "blue", "alpha"],
+        }
+    }
+
+    /// The bit depths the spec allows with this color type.
+    pub fn allowed_bit_depths(self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+
+    pub fn has_alpha(self) -> bool {
+        matches!(self, ColorType::GrayscaleAlpha | ColorType::Rgba)
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = IhdrError;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        match byte {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(IhdrError::InvalidColorType(byte)),
+        }
+    }
+}
+
+impl fmt::Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "rgb",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale+alpha",
+            ColorType::Rgba => "rgba",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interlace {
+    None = 0,
+    Adam7 = 1,
+}
+
+/// The image header: dimensions and pixel format.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.IHDR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    /// Always 0, deflate, in a valid header.
+    pub compression_method: u8,
+    /// Always 0, adaptive filtering, in a valid header.
+    pub filter_method: u8,
+    pub interlace: Interlace,
+}
+
+impl Ihdr {
+    pub const LENGTH: usize = 13;
+    /// The spec limits both dimensions to 2^31 - 1.
+    pub const MAX_DIMENSION: u32 = (1 << 31) - 1;
+
+    /// A non-interlaced header with the standard compression and filter methods.
+    pub fn new(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: ColorType,
+    ) -> Result<Self, IhdrError> {
+        let ihdr = Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlace: Interlace::None,
+        };
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+
+    /// Parses and validates the 13 bytes of IHDR data.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, IhdrError> {
+        let data: &[u8; Self::LENGTH] = data
+            .try_into()
+            .map_err(|_| IhdrError::InvalidLength(data.len()))?;
+        let u32_at =
+            |at: usize| u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+
+        let ihdr = Ihdr {
+            width: u32_at(0),
+            height: u32_at(4),
+            bit_depth: data[8],
+            color_type: ColorType::try_from(data[9])?,
+            compression_method: data[10],
+            filter_method: data[11],
+            interlace: match data[12] {
+                0 => Interlace::None,
+                1 => Interlace::Adam7,
+                other => return Err(IhdrError::UnknownInterlace(other)),
+            },
+        };
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+
+    /// Checks the combinations the spec allows.
+    pub fn validate(&self) -> Result<(), IhdrError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(IhdrError::ZeroDimension);
+        }
+        for dimension in [self.width, self.height] {
+            if dimension > Self::MAX_DIMENSION {
+                return Err(IhdrError::DimensionTooLarge(dimension));
+            }
+        }
+        if !self
+            .color_type
+            .allowed_bit_depths()
+            .contains(&self.bit_depth)
+        {
+            return Err(IhdrError::InvalidBitDepth(self.bit_depth, self.color_type));
+        }
+        if self.compression_method != 0 {
+            return Err(IhdrError::UnknownCompression(self.compression_method));
+        }
+        if self.filter_method != 0 {
+            return Err(IhdrError::UnknownFilter(self.filter_method));
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let mut bytes = [0; Self::LENGTH];
+        bytes[..4].copy_from_slice(&self.width.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.height.to_be_bytes());
+        bytes[8] = self.bit_depth;
+        bytes[9] = self.color_type as u8;
+        bytes[10] = self.compression_method;
+        bytes[11] = self.filter_method;
+        bytes[12] = self.interlace as u8;
+        bytes
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(Self::chunk_type(), self.to_bytes().to_vec())
+    }
+
+    fn chunk_type() -> ChunkType {
+        ChunkType::IHDR
+    }
+
+    pub fn bits_per_pixel(&self) -> u32 {
+        u32::from(self.bit_depth) * u32::from(self.color_type.channels())
+    }
+
+    /// Bytes in one row of pixels, without the filter type byte in front of it.
+    pub fn row_bytes(&self) -> u64 {
+        (u64::from(self.width) * u64::from(self.bits_per_pixel())).div_ceil(8)
+    }
+
+    /// Bytes of the decompressed image data of a non-interlaced image, each row
+    /// with its filter type byte.
+    pub fn raw_data_len(&self) -> u64 {
+        (self.row_bytes() + 1) * u64::from(self.height)
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = IhdrError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if chunk.chunk_type() != &Self::chunk_type() {
+            return Err(IhdrError::WrongType(chunk.chunk_type().to_string()));
+        }
+        Self::from_bytes(chunk.data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_bytes(color_type: u8, bit_depth: u8) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(640u32.to_be_bytes());
+        bytes.extend(480u32.to_be_bytes());
+        bytes.extend([bit_depth, color_type, 0, 0, 1]);
+        bytes
+    }
+
+    #[test]
+    fn test_parse() {
+        let ihdr = Ihdr::from_bytes(&header_bytes(6, 8)).unwrap();
+        assert_eq!(ihdr.width, 640);
+        assert_eq!(ihdr.height, 480);
+        assert_eq!(ihdr.color_type, ColorType::Rgba);
+        assert_eq!(ihdr.interlace, Interlace::Adam7);
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+        assert_eq!(ihdr.row_bytes(), 2560);
+        assert_eq!(ihdr.to_bytes().to_vec(), header_bytes(6, 8));
+    }
+
+    #[test]
+    fn test_round_trip_chunk() {
+        let ihdr = Ihdr::new(3, 2, 1, ColorType::Grayscale).unwrap();
+        assert_eq!(ihdr.row_bytes(), 1);
+        assert_eq!(ihdr.raw_data_len(), 4);
+        assert_eq!(Ihdr::try_from(&ihdr.to_chunk()), Ok(ihdr));
+
+        let chunk = Chunk::new("ruSt".parse().unwrap(), ihdr.to_bytes().to_vec());
+        assert_eq!(
+            Ihdr::try_from(&chunk),
+            Err(IhdrError::WrongType("ruSt".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            Ihdr::from_bytes(&[0; 12]),
+            Err(IhdrError::InvalidLength(12))
+        );
+        assert_eq!(
+            Ihdr::from_bytes(&header_bytes(1, 8)),
+            Err(IhdrError::InvalidColorType(1))
+        );
+        assert_eq!(
+            Ihdr::from_bytes(&header_bytes(2, 4)),
+            Err(IhdrError::InvalidBitDepth(4, ColorType::Rgb))
+        );
+        assert_eq!(
+            Ihdr::new(0, 1, 8, ColorType::Rgb),
+            Err(IhdrError::ZeroDimension)
+        );
+        let mut bytes = header_bytes(3, 8);
+        bytes[12] = 2;
+        assert_eq!(
+            Ihdr::from_bytes(&bytes),
+            Err(IhdrError::UnknownInterlace(2))
+        );
+    }
+}
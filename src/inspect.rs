@@ -0,0 +1,437 @@
+use std::convert::TryFrom;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::text::{CompressedTextChunk, InternationalTextChunk, TextChunk};
+use crate::time::TimeChunk;
+
+/// Where a chunk sits in the file and whether it is intact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSummary {
+    pub index: usize,
+    /// Byte offset of the chunk's length field from the start of the file.
+    pub offset: usize,
+    pub chunk_type: ChunkType,
+    pub length: u32,
+    pub crc: u32,
+    pub crc_valid: bool,
+    /// The contents of a tEXt, zTXt or iTXt chunk that could be decoded.
+    pub text: Option<TextEntry>,
+    /// The modification time of a well-formed tIME chunk.
+    pub time: Option<TimeChunk>,
+}
+
+/// A keyword and its text, as stored by the PNG textual chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEntry {
+    pub keyword: String,
+    pub text: String,
+    /// The language of an iTXt entry, empty when not given or for other chunks.
+    pub language_tag: String,
+    /// The keyword translated into `language_tag`, empty when not given.
+    pub translated_keyword: String,
+}
+
+impl TextEntry {
+    /// Decodes a tEXt, zTXt or iTXt chunk, decompressing its text if needed.
+    /// Returns `None` for other chunk types and for malformed contents.
+    pub fn from_chunk(chunk: &Chunk) -> Option<Self> {
+        let entry = |keyword: &str, text: &str| TextEntry {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+        };
+
+        match &chunk.chunk_type().bytes() {
+            b"tEXt" => {
+                let text = TextChunk::try_from(chunk).ok()?;
+                Some(entry(text.keyword(), text.text()))
+            }
+            b"zTXt" => {
+                let text = CompressedTextChunk::try_from(chunk).ok()?;
+                Some(entry(text.keyword(), text.text()))
+            }
+            b"iTXt" => {
+                let text = InternationalTextChunk::try_from(chunk).ok()?;
+                Some(TextEntry {
+                    language_tag: text.language_tag().to_string(),
+                    translated_keyword: text.translated_keyword().to_string(),
+                    ..entry(text.keyword(), text.text())
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ChunkSummary {
+    /// The chunk's property bits spelled out, e.g. `critical public unsafe-to-copy`.
+    pub fn flags(&self) -> String {
+        let chunk_type = &self.chunk_type;
+        let mut flags = vec![
+            if chunk_type.is_critical() {
+                "critical"
+            } else {
+                "ancillary"
+            },
+            if chunk_type.is_public() {
+                "public"
+            } else {
+                "private"
+            },
+            if chunk_type.is_safe_to_copy() {
+                "safe-to-copy"
+            } else {
+                "unsafe-to-copy"
+            },
+        ];
+        if !chunk_type.is_reserved_bit_valid() {
+            flags.push("reserved-bit-set");
+        }
+        flags.join(" ")
+    }
+}
+
+/// Chunk types defined by the PNG specification and its registered extensions.
+pub const REGISTERED_TYPES: &[&str] = &[
+    "IHDR", "PLTE", "IDAT", "IEND", "tRNS", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV",
+    "cLLI", "tEXt", "zTXt", "iTXt", "bKGD", "hIST", "pHYs", "sPLT", "eXIf", "tIME", "acTL", "fcTL",
+    "fdAT", "oFFs", "pCAL", "sCAL", "sTER", "gIFg", "gIFx", "dSIG",
+];
+
+/// Standard chunks that describe the image or its origin rather than how it is
+/// displayed: text, timestamps, Exif and signatures.
+pub const METADATA_TYPES: &[&str] = &["tEXt", "zTXt", "iTXt", "tIME", "eXIf", "dSIG"];
+
+/// Whether `chunk_type` is one of [`METADATA_TYPES`].
+pub fn is_metadata(chunk_type: &ChunkType) -> bool {
+    METADATA_TYPES
+        .iter()
+        .any(|metadata| metadata.as_bytes() == chunk_type.bytes())
+}
+
+/// Whether `chunk_type` is one a standard decoder may know about. Anything else
+/// is private or made up, and is where hidden data tends to live.
+pub fn is_registered(chunk_type: &ChunkType) -> bool {
+    REGISTERED_TYPES
+        .iter()
+        .any(|registered| registered.as_bytes() == chunk_type.bytes())
+}
+
+/// Ancillary chunks whose data is compressed by design, so high entropy says
+/// nothing about them.
+pub const COMPRESSED_TYPES: &[&str] = &["zTXt", "iTXt", "iCCP", "eXIf"];
+
+/// Below this many bytes even random data has too few distinct values to tell
+/// it from anything else.
+pub const MIN_ENTROPY_LEN: usize = 32;
+
+/// The share of the highest possible entropy above which data looks random:
+/// 7.2 bits per byte for long data, where English text has about 4.5 and
+/// deflate output well over 7.
+pub const RANDOM_ENTROPY_RATIO: f64 = 0.9;
+
+/// The Shannon entropy of `data` in bits per byte, from 0 for a single repeated
+/// value to 8 for every value equally often.
+pub fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0u64; 256];
+    for &byte in data {
+        counts[usize::from(byte)] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| count as f64 / len)
+        .fold(0.0, |sum, p| sum - p * p.log2())
+}
+
+/// Whether `chunk` is an ancillary chunk holding what looks like compressed
+/// or encrypted data though its type doesn't call for any, a strong sign of a
+/// hidden payload. The entropy is compared with the most `data.len()` bytes
+/// can have, so shorter chunks aren't held to the 8 bits of long ones.
+pub fn looks_random(chunk: &Chunk) -> bool {
+    let (chunk_type, data) = (chunk.chunk_type(), chunk.data());
+    if chunk_type.is_critical()
+        || data.len() < MIN_ENTROPY_LEN
+        || COMPRESSED_TYPES
+            .iter()
+            .any(|compressed| compressed.as_bytes() == chunk_type.bytes())
+    {
+        return false;
+    }
+    let max = (data.len().min(256) as f64).log2();
+    entropy(data) >= max * RANDOM_ENTROPY_RATIO
+}
+
+/// How many chunks of one type there are and how much data they hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeStats {
+    pub chunk_type: ChunkType,
+    pub count: usize,
+    /// Data bytes, without the 12 bytes of length, type and crc per chunk.
+    pub bytes: u64,
+}
+
+/// Totals over the whole file, to spot images that carry more than pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// Bytes of the serialized image, signature included.
+    pub file_size: u64,
+    pub chunk_count: usize,
+    /// In order of first appearance.
+    pub types: Vec<TypeStats>,
+    pub critical_bytes: u64,
+    pub ancillary_bytes: u64,
+    /// Index of the chunk with the most data, the first one on a tie.
+    pub largest: Option<usize>,
+    /// Data in chunks whose type is not registered.
+    pub non_standard_bytes: u64,
+    pub non_standard_chunks: usize,
+}
+
+impl Stats {
+    /// Ancillary data bytes per critical data byte, `None` without critical data.
+    pub fn ancillary_ratio(&self) -> Option<f64> {
+        (self.critical_bytes > 0).then(|| self.ancillary_bytes as f64 / self.critical_bytes as f64)
+    }
+}
+
+/// Computes [`Stats`] for `png`.
+pub fn stats(png: &Png) -> Stats {
+    let mut stats = Stats {
+        file_size: png.header().len() as u64,
+        chunk_count: png.chunks().len(),
+        types: Vec::new(),
+        critical_bytes: 0,
+        ancillary_bytes: 0,
+        largest: None,
+        non_standard_bytes: 0,
+        non_standard_chunks: 0,
+    };
+
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let chunk_type = chunk.chunk_type();
+        let bytes = chunk.data().len() as u64;
+        stats.file_size += 12 + bytes;
+
+        match stats
+            .types
+            .iter_mut()
+            .find(|types| types.chunk_type == *chunk_type)
+        {
+            Some(types) => {
+                types.count += 1;
+                types.bytes += bytes;
+            }
+            None => stats.types.push(TypeStats {
+                chunk_type: *chunk_type,
+                count: 1,
+                bytes,
+            }),
+        }
+
+        if chunk_type.is_critical() {
+            stats.critical_bytes += bytes;
+        } else {
+            stats.ancillary_bytes += bytes;
+        }
+        if !is_registered(chunk_type) {
+            stats.non_standard_bytes += bytes;
+            stats.non_standard_chunks += 1;
+        }
+        let largest = stats
+            .largest
+            .map(|largest| png.chunks()[largest].data().len());
+        if largest.is_none_or(|largest| chunk.data().len() > largest) {
+            stats.largest = Some(index);
+        }
+    }
+
+    stats
+}
+
+/// Summarizes every chunk of `png`, in file order.
+pub fn summarize(png: &Png) -> Vec<ChunkSummary> {
+    let mut offset = png.header().len();
+    png.chunks()
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let summary = ChunkSummary {
+                index,
+                offset,
+                chunk_type: *chunk.chunk_type(),
+                length: chunk.length(),
+                crc: chunk.crc(),
+                crc_valid: chunk.has_valid_crc(),
+                text: TextEntry::from_chunk(chunk),
+                time: TimeChunk::try_from(chunk).ok(),
+            };
+            offset += 12 + chunk.data().len();
+            summary
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zlib;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(chunk_type.parse().unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_summarize() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("ruSt", b"secret"),
+            chunk("tIME", &[0x07, 0xe8, 3, 1, 12, 0, 0]),
+            chunk("IEND", &[]),
+        ]);
+        let summaries = summarize(&png);
+
+        let offsets: Vec<usize> = summaries.iter().map(|summary| summary.offset).collect();
+        assert_eq!(offsets, vec![8, 8 + 25, 8 + 25 + 18, 8 + 25 + 18 + 19]);
+        assert_eq!(summaries[1].chunk_type.to_string(), "ruSt");
+        assert_eq!(summaries[1].length, 6);
+        assert_eq!(summaries[1].time, None);
+        assert_eq!(
+            summaries[2].time.map(|time| time.to_string()),
+            Some("2024-03-01T12:00:00Z".to_string())
+        );
+        assert!(summaries.iter().all(|summary| summary.crc_valid));
+    }
+
+    #[test]
+    fn test_flags() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[]),
+            chunk("ruSt", &[]),
+            chunk("abcd", &[]),
+        ]);
+        let summaries = summarize(&png);
+        assert_eq!(summaries[0].flags(), "critical public unsafe-to-copy");
+        assert_eq!(summaries[1].flags(), "ancillary private safe-to-copy");
+        assert_eq!(
+            summaries[2].flags(),
+            "ancillary private safe-to-copy reserved-bit-set"
+        );
+    }
+
+    #[test]
+    fn test_text_entries() {
+        let mut ztxt = b"Comment\0\0".to_vec();
+        ztxt.extend(zlib::compress(b"squeezed", zlib::DEFAULT_LEVEL));
+        let mut itxt = b"Title\0\x01\0de\0Titel\0".to_vec();
+        itxt.extend(zlib::compress("Grüße".as_bytes(), zlib::DEFAULT_LEVEL));
+
+        let png = Png::from_chunks(vec![
+            chunk("tEXt", b"Author\0Andr\xe9"),
+            chunk("zTXt", &ztxt),
+            chunk("iTXt", &itxt),
+            chunk("tEXt", b"no separator"),
+            chunk("ruSt", b"key\0value"),
+        ]);
+        let texts: Vec<Option<(String, String)>> = summarize(&png)
+            .into_iter()
+            .map(|summary| summary.text.map(|text| (text.keyword, text.text)))
+            .collect();
+        let entry = |keyword: &str, text: &str| Some((keyword.to_string(), text.to_string()));
+        assert_eq!(
+            texts,
+            vec![
+                entry("Author", "André"),
+                entry("Comment", "squeezed"),
+                entry("Title", "Grüße"),
+                None,
+                None,
+            ]
+        );
+
+        let title = TextEntry::from_chunk(&png.chunks()[2]).unwrap();
+        assert_eq!(title.language_tag, "de");
+        assert_eq!(title.translated_keyword, "Titel");
+    }
+
+    #[test]
+    fn test_type_classes() {
+        let chunk_type = |s: &str| s.parse::<ChunkType>().unwrap();
+        assert!(is_registered(&chunk_type("pHYs")));
+        assert!(!is_registered(&chunk_type("ruSt")));
+        assert!(is_metadata(&chunk_type("tIME")));
+        assert!(!is_metadata(&chunk_type("gAMA")));
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert!(entropy(&[]).is_sign_positive());
+        assert!(entropy(&[7; 100]).is_sign_positive());
+        assert_eq!(entropy(&[7; 100]), 0.0);
+        assert_eq!(entropy(&[0, 1, 0, 1]), 1.0);
+        let all: Vec<u8> = (0..=255).collect();
+        assert_eq!(entropy(&all), 8.0);
+
+        let mut random = vec![0; 4096];
+        crate::rng::Rng::new(1).fill_bytes(&mut random);
+        assert!(looks_random(&chunk("ruSt", &random)));
+        assert!(looks_random(&chunk("ruSt", &random[..40])));
+        assert!(!looks_random(&chunk("ruSt", &random[..16])));
+        assert!(!looks_random(&chunk("IDAT", &random)));
+        assert!(!looks_random(&chunk("zTXt", &random)));
+        let words = [
+            "hidden", "in", "plain", "sight", "a", "message", "of", "no", "length",
+        ];
+        let mut rng = crate::rng::Rng::new(2);
+        let text = (0..2000)
+            .map(|_| words[rng.below(words.len() as u64) as usize])
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert!(!looks_random(&chunk("tEXt", text.as_bytes())));
+        assert!(looks_random(&chunk(
+            "ruSt",
+            &zlib::compress(text.as_bytes(), 9)
+        )));
+    }
+
+    #[test]
+    fn test_stats() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("IDAT", &[0; 100]),
+            chunk("tEXt", b"a\0b"),
+            chunk("ruSt", &[0; 40]),
+            chunk("IDAT", &[0; 100]),
+            chunk("IEND", &[]),
+        ]);
+        let stats = stats(&png);
+
+        assert_eq!(stats.file_size, png.as_bytes().len() as u64);
+        assert_eq!(stats.chunk_count, 6);
+        let types: Vec<(String, usize, u64)> = stats
+            .types
+            .iter()
+            .map(|types| (types.chunk_type.to_string(), types.count, types.bytes))
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                ("IHDR".to_string(), 1, 13),
+                ("IDAT".to_string(), 2, 200),
+                ("tEXt".to_string(), 1, 3),
+                ("ruSt".to_string(), 1, 40),
+                ("IEND".to_string(), 1, 0),
+            ]
+        );
+        assert_eq!(stats.critical_bytes, 213);
+        assert_eq!(stats.ancillary_bytes, 43);
+        assert_eq!(stats.largest, Some(1));
+        assert_eq!(stats.non_standard_bytes, 40);
+        assert_eq!(stats.non_standard_chunks, 1);
+        assert_eq!(stats.ancillary_ratio(), Some(43.0 / 213.0));
+    }
+}
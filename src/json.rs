@@ -0,0 +1,513 @@
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum JsonError {
+    #[error("JsonError unexpected {0:?} at offset {1}")]
+    Unexpected(char, usize),
+    #[error("JsonError unexpected end of input")]
+    UnexpectedEnd,
+    #[error("JsonError invalid number at offset {0}")]
+    InvalidNumber(usize),
+    #[error("JsonError invalid escape at offset {0}")]
+    InvalidEscape(usize),
+    #[error("JsonError nesting deeper than {MAX_DEPTH} levels")]
+    TooDeep,
+}
+
+/// How deeply arrays and objects may nest before parsing gives up, so that
+/// hostile input cannot exhaust the stack.
+pub const MAX_DEPTH: usize = 128;
+
+/// A JSON value. Objects keep their keys in insertion order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Builds an object from `(key, value)` pairs.
+    pub fn object<K: Into<String>>(pairs: impl IntoIterator<Item = (K, Value)>) -> Self {
+        Value::Object(pairs.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Looks up `key` if this is an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The number, if it is a whole one that fits a `u64`.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Number(n) if n >= 0.0 && n.fract() == 0.0 && n <= u64::MAX as f64 => {
+                Some(n as u64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Serializes with two-space indentation and a key per line.
+    pub fn to_string_pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, indent: usize) {
+        let pad = |out: &mut String, indent: usize| out.extend(std::iter::repeat_n(' ', indent));
+        match self {
+            Value::Array(items) if !items.is_empty() => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    pad(out, indent + 2);
+                    item.write_pretty(out, indent + 2);
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                pad(out, indent);
+                out.push(']');
+            }
+            Value::Object(pairs) if !pairs.is_empty() => {
+                out.push_str("{\n");
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    pad(out, indent + 2);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    value.write_pretty(out, indent + 2);
+                    out.push_str(if i + 1 < pairs.len() { ",\n" } else { "\n" });
+                }
+                pad(out, indent);
+                out.push('}');
+            }
+            value => write!(out, "{value}").unwrap(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    /// Compact serialization without any whitespace.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{b}"),
+            // JSON has no NaN or infinity.
+            Value::Number(n) if !n.is_finite() => f.write_str("null"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::String(s) => {
+                let mut out = String::with_capacity(s.len() + 2);
+                write_string(&mut out, s);
+                f.write_str(&out)
+            }
+            Value::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(pairs) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    let mut out = String::new();
+                    write_string(&mut out, key);
+                    write!(f, "{out}:{value}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => write!(out, "\\u{:04x}", u32::from(c)).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl FromStr for Value {
+    type Err = JsonError;
+
+    /// Parses a single JSON document, surrounded by nothing but whitespace.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { input: s, pos: 0 };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            Some(c) => Err(JsonError::Unexpected(c, parser.pos)),
+            None => Ok(value),
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Result<char, JsonError> {
+        let c = self.peek().ok_or(JsonError::UnexpectedEnd)?;
+        self.pos += c.len_utf8();
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        let pos = self.pos;
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => Err(JsonError::Unexpected(c, pos)),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, JsonError> {
+        if depth > MAX_DEPTH {
+            return Err(JsonError::TooDeep);
+        }
+        self.skip_whitespace();
+        match self.peek().ok_or(JsonError::UnexpectedEnd)? {
+            '{' => self.object(depth),
+            '[' => self.array(depth),
+            '"' => self.string().map(Value::String),
+            '-' | '0'..='9' => self.number(),
+            _ => self.literal(),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Value, JsonError> {
+        for (word, value) in [
+            ("true", Value::Bool(true)),
+            ("false", Value::Bool(false)),
+            ("null", Value::Null),
+        ] {
+            if self.input[self.pos..].starts_with(word) {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        Err(JsonError::Unexpected(self.peek().unwrap(), self.pos))
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError> {
+        let start = self.pos;
+        let rest = &self.input[start..];
+        let len = rest
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(rest.len());
+        let text = &rest[..len];
+        // Rust accepts forms JSON does not, such as "1." or leading zeros.
+        let digits = text.strip_prefix('-').unwrap_or(text);
+        let int_len = digits
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(digits.len());
+        let (int, rest) = digits.split_at(int_len);
+        let valid = !int.is_empty()
+            && (int == "0" || !int.starts_with('0'))
+            && rest
+                .strip_prefix('.')
+                .is_none_or(|frac| frac.starts_with(|c: char| c.is_ascii_digit()));
+        match text.parse() {
+            Ok(n) if valid => {
+                self.pos += len;
+                Ok(Value::Number(n))
+            }
+            _ => Err(JsonError::InvalidNumber(start)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            let pos = self.pos;
+            match self.next()? {
+                '"' => return Ok(out),
+                '\\' => {
+                    let c = match self.next()? {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => self.unicode_escape(pos)?,
+                        _ => return Err(JsonError::InvalidEscape(pos)),
+                    };
+                    out.push(c);
+                }
+                c if u32::from(c) < 0x20 => return Err(JsonError::Unexpected(c, pos)),
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// Decodes the digits after `\u`, joining a surrogate pair if one follows.
+    fn unicode_escape(&mut self, pos: usize) -> Result<char, JsonError> {
+        let high = self.hex4(pos)?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.input[self.pos..].starts_with("\\u") {
+                return Err(JsonError::InvalidEscape(pos));
+            }
+            self.pos += 2;
+            let low = self.hex4(pos)?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(JsonError::InvalidEscape(pos));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or(JsonError::InvalidEscape(pos))
+    }
+
+    fn hex4(&mut self, pos: usize) -> Result<u32, JsonError> {
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .ok_or(JsonError::InvalidEscape(pos))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| JsonError::InvalidEscape(pos))?;
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(JsonError::InvalidEscape(pos));
+        }
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, JsonError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            let pos = self.pos;
+            match self.next()? {
+                ',' => {}
+                ']' => return Ok(Value::Array(items)),
+                c => return Err(JsonError::Unexpected(c, pos)),
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, JsonError> {
+        self.expect('{')?;
+        let mut pairs = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Object(pairs));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            pairs.push((key, self.value(depth + 1)?));
+            self.skip_whitespace();
+            let pos = self.pos;
+            match self.next()? {
+                ',' => {}
+                '}' => return Ok(Value::Object(pairs)),
+                c => return Err(JsonError::Unexpected(c, pos)),
+            }
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+macro_rules! impl_from_number {
+    ($($t:ty),*) => {
+        $(impl From<$t> for Value {
+            fn from(n: $t) -> Self {
+                Value::Number(n as f64)
+            }
+        })*
+    };
+}
+
+impl_from_number!(u8, u16, u32, u64, usize, i32, i64, f64);
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Value {
+        Value::object([
+            ("type", Value::from("tEXt")),
+            ("length", Value::from(42u32)),
+            ("valid", Value::from(true)),
+            ("text", Value::from("line\n\"quoted\"\u{1}")),
+            ("missing", Value::Null),
+            ("ratio", Value::from(0.5)),
+            ("items", Value::from(vec![1u8, 2])),
+            ("empty", Value::Array(Vec::new())),
+        ])
+    }
+
+    #[test]
+    fn test_compact() {
+        assert_eq!(
+            sample().to_string(),
+            r#"{"type":"tEXt","length":42,"valid":true,"text":"line\n\"quoted\"\u0001","missing":null,"ratio":0.5,"items":[1,2],"empty":[]}"#
+        );
+        assert_eq!(Value::Number(f64::NAN).to_string(), "null");
+    }
+
+    #[test]
+    fn test_pretty() {
+        let value = Value::object([
+            ("a", Value::from(1u8)),
+            ("b", Value::from(vec!["x"])),
+            ("c", Value::Object(Vec::new())),
+        ]);
+        assert_eq!(
+            value.to_string_pretty(),
+            "{\n  \"a\": 1,\n  \"b\": [\n    \"x\"\n  ],\n  \"c\": {}\n}"
+        );
+    }
+
+    #[test]
+    fn test_get() {
+        assert_eq!(sample().get("length"), Some(&Value::Number(42.0)));
+        assert_eq!(sample().get("nope"), None);
+        assert_eq!(Value::Null.get("length"), None);
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        let value = sample();
+        assert_eq!(value.to_string().parse::<Value>(), Ok(value.clone()));
+        assert_eq!(value.to_string_pretty().parse::<Value>(), Ok(value));
+    }
+
+    #[test]
+    fn test_parse() {
+        let value: Value = r#" {"a": [1, -2.5e3, true, null], "b": "\u00e9\ud83d\ude00\/"} "#
+            .parse()
+            .unwrap();
+        assert_eq!(
+            value,
+            Value::object([
+                (
+                    "a",
+                    Value::Array(vec![
+                        Value::Number(1.0),
+                        Value::Number(-2500.0),
+                        Value::Bool(true),
+                        Value::Null,
+                    ])
+                ),
+                ("b", Value::from("é😀/")),
+            ])
+        );
+        assert_eq!(
+            value.get("a").unwrap().as_array().unwrap()[0].as_u64(),
+            Some(1)
+        );
+        assert_eq!(value.get("b").unwrap().as_str(), Some("é😀/"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |s: &str| s.parse::<Value>();
+        assert_eq!(parse(""), Err(JsonError::UnexpectedEnd));
+        assert_eq!(parse("[1,]"), Err(JsonError::Unexpected(']', 3)));
+        assert_eq!(parse("{\"a\" 1}"), Err(JsonError::Unexpected('1', 5)));
+        assert_eq!(parse("01"), Err(JsonError::InvalidNumber(0)));
+        assert_eq!(parse("1."), Err(JsonError::InvalidNumber(0)));
+        assert_eq!(parse(r#""\x""#), Err(JsonError::InvalidEscape(1)));
+        assert_eq!(parse(r#""\ud800""#), Err(JsonError::InvalidEscape(1)));
+        assert_eq!(parse("nul"), Err(JsonError::Unexpected('n', 0)));
+        assert_eq!(parse("1 2"), Err(JsonError::Unexpected('2', 2)));
+        assert_eq!(parse(&"[".repeat(MAX_DEPTH + 2)), Err(JsonError::TooDeep));
+    }
+}
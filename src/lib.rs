@@ -1,3 +1,34 @@
+pub mod ancillary;
+pub mod archive;
+pub mod base64;
+pub mod builder;
+pub mod capacity;
 pub mod chunk;
+pub mod chunk_reader;
+pub mod chunk_ref;
 pub mod chunk_type;
+pub mod diff;
+pub mod envelope;
+pub mod exif;
+pub mod fec;
+pub mod glob;
+pub mod hex;
+pub mod ihdr;
+pub mod inspect;
+pub mod json;
+pub mod lsb;
+pub mod ordering;
+pub mod palette;
+pub mod payload;
 pub mod png;
+pub mod raster;
+pub mod redundancy;
+pub mod rng;
+pub mod sha256;
+pub mod signatures;
+pub mod steganalysis;
+pub mod stripe;
+pub mod text;
+pub mod time;
+pub mod verify;
+pub mod zlib;
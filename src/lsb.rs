@@ -0,0 +1,407 @@
+//! Hiding a payload in the least significant bits of the pixel samples, where
+//! it keeps the image looking the same and leaves no extra chunk behind.
+//!
+//! The bits go into the lowest bit or bits of each sample, most significant bit
+//! of each byte first, behind a big-endian u32 length. Samples are taken in
+//! scanline order, or with a key in an order only the key reproduces,
+//! scattering the bits over the image.
+
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::ihdr::{ColorType, Ihdr};
+use crate::raster::{Raster, RasterError};
+use crate::rng::Rng;
+use crate::sha256;
+
+#[derive(Error, Debug)]
+pub enum LsbError {
+    #[error("LsbError {0} images are not supported")]
+    Unsupported(String),
+    #[error("LsbError payload is {0} bytes, the image holds at most {1}")]
+    TooLarge(usize, usize),
+    #[error("LsbError no payload found in the image")]
+    NoPayload,
+    #[error("LsbError {0} images have no alpha channel")]
+    NoAlpha(ColorType),
+    #[error("LsbError unknown channels {0}, expected all, color, alpha or opaque")]
+    UnknownChannels(String),
+    #[error("LsbError {0} bits per sample, expected 1 to 8")]
+    InvalidBitsPerSample(u8),
+    #[error("LsbError invalid image data")]
+    Raster(#[from] RasterError),
+}
+
+/// Bytes taken by the length in front of the payload.
+pub const LENGTH_LEN: usize = 4;
+
+/// Which samples carry payload bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Channels {
+    #[default]
+    All,
+    /// Everything but alpha.
+    Color,
+    /// Only alpha, leaving colors exactly as they were.
+    Alpha,
+    /// The colors of fully opaque pixels, so no change can show through a
+    /// translucent one. Alpha is left alone, which keeps the same pixels opaque
+    /// for extraction.
+    Opaque,
+}
+
+impl FromStr for Channels {
+    type Err = LsbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "color" => Ok(Self::Color),
+            "alpha" => Ok(Self::Alpha),
+            "opaque" => Ok(Self::Opaque),
+            _ => Err(LsbError::UnknownChannels(s.to_string())),
+        }
+    }
+}
+
+/// How a payload is laid out in the pixels; extraction needs the same options
+/// as embedding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LsbOptions {
+    pub channels: Channels,
+    /// Shuffles the samples with a generator seeded from this key.
+    pub key: Option<Vec<u8>>,
+    /// How many of the lowest bits of each sample hold payload, 1 to 8. Each
+    /// bit more doubles the capacity, and the change, which 16-bit samples hide
+    /// far better than 8-bit ones.
+    pub bits_per_sample: u8,
+}
+
+impl LsbOptions {
+    pub const MAX_BITS_PER_SAMPLE: u8 = 8;
+}
+
+impl Default for LsbOptions {
+    fn default() -> Self {
+        LsbOptions {
+            channels: Channels::default(),
+            key: None,
+            bits_per_sample: 1,
+        }
+    }
+}
+
+/// Binds the sample order to this use of a key, so it says nothing about the
+/// key's HMAC tags.
+const ORDER_CONTEXT: &[u8] = b"png-secret lsb sample order";
+
+/// Checks that the samples of `ihdr` are ones whose low bits can be changed
+/// freely: whole bytes and not palette indices.
+pub fn check_supported(ihdr: &Ihdr) -> Result<(), LsbError> {
+    if ihdr.color_type == ColorType::Indexed {
+        return Err(LsbError::Unsupported(ihdr.color_type.to_string()));
+    }
+    if !matches!(ihdr.bit_depth, 8 | 16) {
+        return Err(LsbError::Unsupported(format!("{}-bit", ihdr.bit_depth)));
+    }
+    Ok(())
+}
+
+/// The positions in `raster.data()` of the low bytes of the samples `options`
+/// selects, in the order bits go into them.
+pub fn slots(raster: &Raster, options: &LsbOptions) -> Result<Vec<usize>, LsbError> {
+    let ihdr = raster.ihdr();
+    check_supported(ihdr)?;
+    if !(1..=LsbOptions::MAX_BITS_PER_SAMPLE).contains(&options.bits_per_sample) {
+        return Err(LsbError::InvalidBitsPerSample(options.bits_per_sample));
+    }
+    let channels = usize::from(ihdr.color_type.channels());
+    let has_alpha = ihdr.color_type.has_alpha();
+    if options.channels == Channels::Alpha && !has_alpha {
+        return Err(LsbError::NoAlpha(ihdr.color_type));
+    }
+
+    // Samples are big-endian, so the low byte is the last one.
+    let sample_len = usize::from(ihdr.bit_depth / 8);
+    let pixel_len = channels * sample_len;
+    let data = raster.data();
+    let mut slots = Vec::with_capacity(data.len() / sample_len);
+    for (pixel, samples) in data.chunks_exact(pixel_len).enumerate() {
+        let low_byte = |channel: usize| pixel * pixel_len + (channel + 1) * sample_len - 1;
+        let color_channels = if has_alpha { channels - 1 } else { channels };
+        let opaque = !has_alpha
+            || samples[pixel_len - sample_len..]
+                .iter()
+                .all(|&b| b == u8::MAX);
+        match options.channels {
+            Channels::All => slots.extend((0..channels).map(low_byte)),
+            Channels::Color => slots.extend((0..color_channels).map(low_byte)),
+            Channels::Alpha => slots.push(low_byte(channels - 1)),
+            Channels::Opaque if opaque => slots.extend((0..color_channels).map(low_byte)),
+            Channels::Opaque => {}
+        }
+    }
+    if let Some(key) = &options.key {
+        Rng::from_seed(sha256::hmac(key, ORDER_CONTEXT)).shuffle(&mut slots);
+    }
+    Ok(slots)
+}
+
+/// The largest payload `embed` can fit in `raster`, in bytes.
+pub fn capacity(raster: &Raster, options: &LsbOptions) -> Result<usize, LsbError> {
+    Ok(capacity_of(&slots(raster, options)?, options))
+}
+
+fn capacity_of(slots: &[usize], options: &LsbOptions) -> usize {
+    (slots.len() * usize::from(options.bits_per_sample) / 8).saturating_sub(LENGTH_LEN)
+}
+
+/// The bit positions within a low byte that hold payload, highest first.
+fn positions(options: &LsbOptions) -> impl Iterator<Item = u8> + Clone {
+    (0..options.bits_per_sample).rev()
+}
+
+/// Writes `payload` into the low bits of `raster`.
+pub fn embed(raster: &mut Raster, payload: &[u8], options: &LsbOptions) -> Result<(), LsbError> {
+    let slots = slots(raster, options)?;
+    let capacity = capacity_of(&slots, options);
+    if payload.len() > capacity {
+        return Err(LsbError::TooLarge(payload.len(), capacity));
+    }
+
+    let length = u32::try_from(payload.len()).expect("capacity fits in u32");
+    let bytes = length
+        .to_be_bytes()
+        .into_iter()
+        .chain(payload.iter().copied());
+    let bits = bytes.flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1));
+    let targets = slots
+        .iter()
+        .flat_map(|&slot| positions(options).map(move |position| (slot, position)));
+    let data = raster.data_mut();
+    for ((slot, position), bit) in targets.zip(bits) {
+        data[slot] = (data[slot] & !(1 << position)) | bit << position;
+    }
+    Ok(())
+}
+
+/// Reads back a payload written by `embed`. An empty payload can't be told
+/// from none at all and reads as `NoPayload`.
+pub fn extract(raster: &Raster, options: &LsbOptions) -> Result<Vec<u8>, LsbError> {
+    let slots = slots(raster, options)?;
+    let capacity = capacity_of(&slots, options);
+    let data = raster.data();
+    let mut bits = slots
+        .iter()
+        .flat_map(|&slot| positions(options).map(move |position| (data[slot] >> position) & 1));
+    let mut bytes = std::iter::from_fn(move || {
+        let mut byte = 0;
+        for _ in 0..8 {
+            byte = byte << 1 | bits.next()?;
+        }
+        Some(byte)
+    });
+
+    let length: Vec<u8> = bytes.by_ref().take(LENGTH_LEN).collect();
+    let length = match <[u8; LENGTH_LEN]>::try_from(length) {
+        Ok(length) => u32::from_be_bytes(length) as usize,
+        Err(_) => return Err(LsbError::NoPayload),
+    };
+    // Untouched images tend to have runs of equal low bits, which read as an
+    // empty or impossibly long payload.
+    if length == 0 || length > capacity {
+        return Err(LsbError::NoPayload);
+    }
+    Ok(bytes.take(length).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{Fill, PngBuilder};
+    use crate::png::Png;
+
+    fn noise(width: u32, height: u32, color_type: ColorType) -> Png {
+        PngBuilder::new(width, height)
+            .with_color_type(color_type)
+            .with_fill(Fill::Noise(1))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        let mut png = noise(16, 16, ColorType::Rgb);
+        let mut raster = Raster::from_png(&png).unwrap();
+        let original = raster.data().to_vec();
+        assert_eq!(
+            capacity(&raster, &LsbOptions::default()).unwrap(),
+            16 * 16 * 3 / 8 - 4
+        );
+
+        embed(
+            &mut raster,
+            b"hidden in plain sight",
+            &LsbOptions::default(),
+        )
+        .unwrap();
+        let changed = original
+            .iter()
+            .zip(raster.data())
+            .filter(|(a, b)| a != b)
+            .count();
+        assert!(changed > 0);
+        assert!(original
+            .iter()
+            .zip(raster.data())
+            .all(|(a, b)| a >> 1 == b >> 1));
+
+        raster.store(&mut png).unwrap();
+        let png = Png::from_bytes(&png.as_bytes()).unwrap();
+        let raster = Raster::from_png(&png).unwrap();
+        assert_eq!(
+            extract(&raster, &LsbOptions::default()).unwrap(),
+            b"hidden in plain sight"
+        );
+    }
+
+    #[test]
+    fn test_channels() {
+        let png = noise(8, 8, ColorType::Rgba);
+        let mut raster = Raster::from_png(&png).unwrap();
+        // Make every other pixel translucent.
+        for alpha in raster.data_mut().iter_mut().skip(3).step_by(8) {
+            *alpha = 128;
+        }
+        let original = raster.data().to_vec();
+        let count = |channels| {
+            let options = LsbOptions {
+                channels,
+                ..LsbOptions::default()
+            };
+            slots(&raster, &options).unwrap().len()
+        };
+        assert_eq!(count(Channels::All), 256);
+        assert_eq!(count(Channels::Color), 192);
+        assert_eq!(count(Channels::Alpha), 64);
+        assert_eq!(count(Channels::Opaque), 96);
+
+        let alpha = LsbOptions {
+            channels: Channels::Alpha,
+            ..LsbOptions::default()
+        };
+        embed(&mut raster, b"a", &alpha).unwrap();
+        assert_eq!(extract(&raster, &alpha).unwrap(), b"a");
+        let changed: Vec<usize> = (0..original.len())
+            .filter(|&i| original[i] != raster.data()[i])
+            .collect();
+        assert!(!changed.is_empty());
+        assert!(changed.iter().all(|i| i % 4 == 3));
+
+        let opaque = LsbOptions {
+            channels: Channels::Opaque,
+            ..LsbOptions::default()
+        };
+        let mut raster = Raster::from_png(&png).unwrap();
+        embed(&mut raster, b"opaque", &opaque).unwrap();
+        assert_eq!(extract(&raster, &opaque).unwrap(), b"opaque");
+        assert!(raster.data().iter().skip(3).step_by(4).all(|&a| a == 255));
+
+        let rgb = Raster::from_png(&noise(4, 4, ColorType::Rgb)).unwrap();
+        assert!(matches!(
+            slots(&rgb, &alpha),
+            Err(LsbError::NoAlpha(ColorType::Rgb))
+        ));
+        assert!("alpha".parse::<Channels>().is_ok());
+        assert!("red".parse::<Channels>().is_err());
+    }
+
+    #[test]
+    fn test_bits_per_sample() {
+        let png = PngBuilder::new(16, 16)
+            .with_color_type(ColorType::Rgba)
+            .with_bit_depth(16)
+            .with_fill(Fill::Noise(2))
+            .build()
+            .unwrap();
+        let mut raster = Raster::from_png(&png).unwrap();
+        let original = raster.data().to_vec();
+        let options = LsbOptions {
+            bits_per_sample: 3,
+            ..LsbOptions::default()
+        };
+        assert_eq!(
+            capacity(&raster, &options).unwrap(),
+            16 * 16 * 4 * 3 / 8 - 4
+        );
+
+        let payload: Vec<u8> = (0..=255).collect();
+        embed(&mut raster, &payload, &options).unwrap();
+        assert_eq!(extract(&raster, &options).unwrap(), payload);
+        // Only the low 3 bits of each low byte may change.
+        for (i, (a, b)) in original.iter().zip(raster.data()).enumerate() {
+            match i % 2 {
+                0 => assert_eq!(a, b),
+                _ => assert_eq!(a >> 3, b >> 3),
+            }
+        }
+
+        for bits_per_sample in [0, 9] {
+            let options = LsbOptions {
+                bits_per_sample,
+                ..LsbOptions::default()
+            };
+            assert!(matches!(
+                capacity(&raster, &options),
+                Err(LsbError::InvalidBitsPerSample(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_keyed_order() {
+        let mut raster = Raster::from_png(&noise(32, 32, ColorType::Rgb)).unwrap();
+        let keyed = LsbOptions {
+            key: Some(b"hunter2".to_vec()),
+            ..LsbOptions::default()
+        };
+        let sequential = slots(&raster, &LsbOptions::default()).unwrap();
+        let scattered = slots(&raster, &keyed).unwrap();
+        assert_ne!(sequential, scattered);
+        assert_eq!(scattered, slots(&raster, &keyed).unwrap());
+        let mut sorted = scattered.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, sequential);
+
+        embed(&mut raster, b"scattered", &keyed).unwrap();
+        assert_eq!(extract(&raster, &keyed).unwrap(), b"scattered");
+        let wrong = LsbOptions {
+            key: Some(b"hunter3".to_vec()),
+            ..LsbOptions::default()
+        };
+        assert!(!matches!(extract(&raster, &wrong), Ok(payload) if payload == b"scattered"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut raster = Raster::from_png(&noise(4, 4, ColorType::Grayscale)).unwrap();
+        assert_eq!(capacity(&raster, &LsbOptions::default()).unwrap(), 0);
+        assert!(matches!(
+            embed(&mut raster, b"x", &LsbOptions::default()),
+            Err(LsbError::TooLarge(1, 0))
+        ));
+
+        let raster = Raster::from_png(&PngBuilder::new(32, 32).build().unwrap()).unwrap();
+        assert!(matches!(
+            extract(&raster, &LsbOptions::default()),
+            Err(LsbError::NoPayload)
+        ));
+
+        let indexed = Raster::from_png(&noise(4, 4, ColorType::Indexed)).unwrap();
+        assert!(matches!(
+            capacity(&indexed, &LsbOptions::default()),
+            Err(LsbError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,57 @@
+#[macro_use]
+mod output;
+
+mod args;
+mod batch;
+mod commands;
+mod progress;
+mod prompt;
+mod report;
+
+use std::process;
+
+use args::Command;
+
+fn main() {
+    if let Err(err) = run() {
+        // Whatever read the output has all it wanted, as with `| head`.
+        if output::is_broken_pipe(&err) {
+            return;
+        }
+        let _ = eoutln!("Error: {err:#}");
+        process::exit(1);
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    match args::parse(std::env::args().skip(1))? {
+        Command::Encode(args) => commands::encode(*args),
+        Command::Decode(args) => commands::decode(args),
+        Command::FixCrc(args) => commands::fix_crc(args),
+        Command::List(args) => commands::list(args),
+        Command::Inspect(args) => commands::inspect(args),
+        Command::Stats(args) => commands::stats(args),
+        Command::Capacity(args) => commands::capacity(args),
+        Command::Detect(args) => commands::detect(args),
+        Command::Scan(args) => commands::scan(args),
+        Command::Watch(args) => commands::watch(args),
+        Command::Verify(args) => commands::verify(args),
+        Command::Diff(args) => commands::diff(args),
+        Command::Compare(args) => commands::compare(args),
+        Command::Hexdump(args) => commands::hexdump(args),
+        Command::Remove(args) => commands::remove(args),
+        Command::Replace(args) => commands::replace(args),
+        Command::Reorder(args) => commands::reorder(args),
+        Command::TouchTime(args) => commands::touch_time(args),
+        Command::Exif(args) => commands::exif(args),
+        Command::Scrub(args) => commands::scrub(args),
+        Command::StripMetadata(args) => commands::strip_metadata(args),
+        Command::CopyChunks(args) => commands::copy_chunks(args),
+        Command::ExportChunks(args) => commands::export_chunks(args),
+        Command::ImportChunks(args) => commands::import_chunks(args),
+        Command::Help => {
+            out!("{}", args::USAGE)?;
+            Ok(())
+        }
+    }
+}
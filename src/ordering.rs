@@ -0,0 +1,305 @@
+//! The positional rules the PNG specification sets for its standard chunks.
+
+use std::fmt;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+/// Where a chunk type must sit relative to the critical chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Placement {
+    Ihdr,
+    /// After IHDR and before PLTE and IDAT, like gAMA or sRGB.
+    BeforePlte,
+    Plte,
+    /// After PLTE and before IDAT, like tRNS or bKGD.
+    AfterPlte,
+    /// Anywhere before IDAT, like pHYs.
+    BeforeIdat,
+    Idat,
+    Iend,
+}
+
+impl Placement {
+    /// The rule for `chunk_type`, or `None` for a type that may go anywhere
+    /// between IHDR and IEND, which includes all the types the spec does not know.
+    pub fn of(chunk_type: &ChunkType) -> Option<Self> {
+        let placement = match &chunk_type.bytes() {
+            b"IHDR" => Self::Ihdr,
+            b"cHRM" | b"gAMA" | b"iCCP" | b"sBIT" | b"sRGB" | b"cICP" | b"mDCV" | b"cLLI" => {
+                Self::BeforePlte
+            }
+            b"PLTE" => Self::Plte,
+            b"bKGD" | b"hIST" | b"tRNS" => Self::AfterPlte,
+            b"pHYs" | b"sPLT" | b"oFFs" | b"pCAL" | b"sCAL" | b"sTER" | b"eXIf" | b"acTL" => {
+                Self::BeforeIdat
+            }
+            b"IDAT" => Self::Idat,
+            b"IEND" => Self::Iend,
+            _ => return None,
+        };
+        Some(placement)
+    }
+}
+
+/// Whether the spec allows at most one chunk of `chunk_type`.
+pub fn is_unique(chunk_type: &ChunkType) -> bool {
+    matches!(
+        &chunk_type.bytes(),
+        b"IHDR"
+            | b"PLTE"
+            | b"IEND"
+            | b"cHRM"
+            | b"gAMA"
+            | b"iCCP"
+            | b"sBIT"
+            | b"sRGB"
+            | b"cICP"
+            | b"mDCV"
+            | b"cLLI"
+            | b"bKGD"
+            | b"hIST"
+            | b"tRNS"
+            | b"pHYs"
+            | b"oFFs"
+            | b"pCAL"
+            | b"sCAL"
+            | b"sTER"
+            | b"eXIf"
+            | b"tIME"
+            | b"acTL"
+    )
+}
+
+/// The positional rule a chunk breaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// IHDR must be the first chunk.
+    IhdrFirst,
+    /// Nothing may follow IEND.
+    AfterIend,
+    /// A type that may appear only once appears again.
+    Unique,
+    /// A type that must precede PLTE follows it.
+    BeforePlte,
+    /// A type that must follow PLTE precedes it.
+    AfterPlte,
+    /// A type that must precede the image data follows an IDAT.
+    BeforeIdat,
+    /// IDAT chunks must form a single run.
+    ConsecutiveIdat,
+}
+
+/// A chunk out of place, by its index in the slice given to [`validate_ordering`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderingViolation {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub rule: Rule,
+}
+
+impl fmt::Display for OrderingViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (index, chunk_type) = (self.index, &self.chunk_type);
+        match self.rule {
+            Rule::IhdrFirst => write!(f, "IHDR is chunk {index}, not the first"),
+            Rule::AfterIend => write!(f, "{chunk_type} chunk {index} comes after IEND"),
+            Rule::Unique => write!(f, "{chunk_type} chunk {index} is not the only one"),
+            Rule::BeforePlte => write!(f, "{chunk_type} chunk {index} comes after PLTE"),
+            Rule::AfterPlte => write!(f, "{chunk_type} chunk {index} comes before PLTE"),
+            Rule::BeforeIdat => write!(f, "{chunk_type} chunk {index} comes after IDAT"),
+            Rule::ConsecutiveIdat => {
+                write!(
+                    f,
+                    "IDAT chunk {index} is separated from the IDATs before it"
+                )
+            }
+        }
+    }
+}
+
+/// Checks `chunks` against the spec's positional rules for the standard chunk
+/// types, returning every chunk that breaks one, in order. Missing chunks are
+/// not reported; an empty slice has nothing out of place.
+pub fn validate_ordering(chunks: &[Chunk]) -> Vec<OrderingViolation> {
+    let mut violations = Vec::new();
+    let first_plte = chunks
+        .iter()
+        .position(|chunk| &chunk.chunk_type().bytes() == b"PLTE");
+    let mut seen: Vec<&ChunkType> = Vec::new();
+    let mut seen_iend = false;
+    let mut seen_idat = false;
+    let mut idat_ended = false;
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        let chunk_type = chunk.chunk_type();
+        let mut violation = |rule| {
+            violations.push(OrderingViolation {
+                index,
+                chunk_type: *chunk_type,
+                rule,
+            })
+        };
+
+        let placement = Placement::of(chunk_type);
+        if seen_iend {
+            violation(Rule::AfterIend);
+        }
+        if is_unique(chunk_type) && seen.contains(&chunk_type) {
+            violation(Rule::Unique);
+        }
+        match placement {
+            Some(Placement::Ihdr) if index > 0 && !seen.contains(&chunk_type) => {
+                violation(Rule::IhdrFirst)
+            }
+            Some(Placement::BeforePlte) if first_plte.is_some_and(|plte| plte < index) => {
+                violation(Rule::BeforePlte)
+            }
+            Some(Placement::AfterPlte) if first_plte.is_some_and(|plte| plte > index) => {
+                violation(Rule::AfterPlte)
+            }
+            Some(Placement::Idat) if idat_ended => {
+                violation(Rule::ConsecutiveIdat);
+                // Once per gap, not for every IDAT after it.
+                idat_ended = false;
+            }
+            _ => {}
+        }
+        let before_idat = matches!(
+            placement,
+            Some(
+                Placement::BeforePlte
+                    | Placement::Plte
+                    | Placement::AfterPlte
+                    | Placement::BeforeIdat
+            )
+        );
+        if before_idat && seen_idat {
+            violation(Rule::BeforeIdat);
+        }
+
+        match placement {
+            Some(Placement::Idat) => seen_idat = true,
+            Some(Placement::Iend) => seen_iend = true,
+            _ if seen_idat => idat_ended = true,
+            _ => {}
+        }
+        seen.push(chunk_type);
+    }
+
+    violations
+}
+
+/// The order the chunks would have in a spec-compliant layout, as indices into
+/// `chunks`.
+///
+/// Chunks with a [`Placement`] are sorted by it and otherwise keep their order.
+/// A chunk that may go anywhere stays just behind the chunk with a placement it
+/// followed, so text chunks stay near where they were, but leave a run of IDAT
+/// chunks and never end up before IHDR or after IEND.
+pub fn canonical_order(chunks: &[Chunk]) -> Vec<usize> {
+    let mut previous = Placement::Ihdr;
+    let mut keys: Vec<(usize, usize)> = chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let rank = match Placement::of(chunk.chunk_type()) {
+                Some(placement) => {
+                    previous = placement;
+                    placement as usize * 2
+                }
+                None => previous.min(Placement::Idat) as usize * 2 + 1,
+            };
+            (rank, index)
+        })
+        .collect();
+    keys.sort();
+    keys.into_iter().map(|(_, index)| index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(types: &[&str]) -> Vec<Chunk> {
+        types
+            .iter()
+            .map(|chunk_type| Chunk::new(chunk_type.parse().unwrap(), Vec::new()))
+            .collect()
+    }
+
+    fn reordered(types: &[&str]) -> Vec<String> {
+        let chunks = chunks(types);
+        canonical_order(&chunks)
+            .into_iter()
+            .map(|index| chunks[index].chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_canonical_order_keeps_valid_files() {
+        let types = [
+            "IHDR", "gAMA", "tEXt", "PLTE", "tRNS", "pHYs", "IDAT", "IDAT", "ruSt", "IEND",
+        ];
+        assert_eq!(reordered(&types), types);
+    }
+
+    fn violations(types: &[&str]) -> Vec<(usize, Rule)> {
+        validate_ordering(&chunks(types))
+            .into_iter()
+            .map(|violation| (violation.index, violation.rule))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_ordering() {
+        let valid = [
+            "IHDR", "gAMA", "tEXt", "PLTE", "tRNS", "pHYs", "IDAT", "IDAT", "ruSt", "IEND",
+        ];
+        assert_eq!(violations(&valid), []);
+        assert_eq!(violations(&[]), []);
+
+        assert_eq!(
+            violations(&[
+                "tEXt", "IHDR", "tRNS", "PLTE", "gAMA", "IDAT", "tEXt", "IDAT", "pHYs", "IEND",
+                "IEND",
+            ]),
+            [
+                (1, Rule::IhdrFirst),
+                (2, Rule::AfterPlte),
+                (4, Rule::BeforePlte),
+                (7, Rule::ConsecutiveIdat),
+                (8, Rule::BeforeIdat),
+                (10, Rule::AfterIend),
+                (10, Rule::Unique),
+            ]
+        );
+        assert_eq!(
+            violations(&["IHDR", "IHDR", "sRGB", "sRGB", "tEXt", "tEXt"]),
+            [(1, Rule::Unique), (3, Rule::Unique)]
+        );
+    }
+
+    #[test]
+    fn test_canonical_order_is_valid() {
+        let types = [
+            "tEXt", "IHDR", "IDAT", "ruSt", "IDAT", "pHYs", "tRNS", "PLTE", "gAMA", "IEND", "tIME",
+        ];
+        let chunks = chunks(&types);
+        let reordered: Vec<Chunk> = canonical_order(&chunks)
+            .into_iter()
+            .map(|index| chunks[index].clone())
+            .collect();
+        assert_eq!(validate_ordering(&reordered), []);
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        assert_eq!(
+            reordered(&[
+                "tEXt", "IHDR", "IDAT", "ruSt", "IDAT", "pHYs", "PLTE", "gAMA", "IEND", "tIME",
+            ]),
+            ["IHDR", "tEXt", "gAMA", "PLTE", "pHYs", "IDAT", "IDAT", "ruSt", "tIME", "IEND",]
+        );
+    }
+}
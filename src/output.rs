@@ -0,0 +1,175 @@
+//! Standard output and error that can be held back: images a batch runs on
+//! at once print their output only when done, one image after another,
+//! instead of interleaving it, and none of it runs into the progress line.
+//! `out!`, `outln!`, `eout!` and `eoutln!` are the `print!`, `println!`,
+//! `eprint!` and `eprintln!` of the modules declared after this one; they
+//! return the error of a failed write rather than panicking.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+
+thread_local! {
+    static HELD: RefCell<Option<Held>> = const { RefCell::new(None) };
+}
+
+/// What `hold` kept back of each stream.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Held {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Standard output or error, or the buffer `hold` keeps for it on this
+/// thread.
+pub struct Stream {
+    stderr: bool,
+}
+
+/// Where commands write what goes to standard output.
+pub fn stdout() -> Stream {
+    Stream { stderr: false }
+}
+
+/// Where commands write what goes to standard error.
+pub fn stderr() -> Stream {
+    Stream { stderr: true }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let held = HELD.with_borrow_mut(|held| {
+            let Some(held) = held else {
+                return false;
+            };
+            match self.stderr {
+                true => held.stderr.extend_from_slice(buf),
+                false => held.stdout.extend_from_slice(buf),
+            }
+            true
+        });
+        match (held, self.stderr) {
+            (true, _) => Ok(buf.len()),
+            (false, true) => io::stderr().lock().write(buf),
+            (false, false) => io::stdout().lock().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.stderr {
+            true => io::stderr().flush(),
+            false => io::stdout().flush(),
+        }
+    }
+}
+
+/// Runs `f` with what it writes to standard output and error kept back, and
+/// returns that along with its result.
+pub fn hold<T>(f: impl FnOnce() -> T) -> (T, Held) {
+    let outer = HELD.replace(Some(Held::default()));
+    let result = f();
+    let held = HELD.replace(outer).unwrap_or_default();
+    (result, held)
+}
+
+/// What `out!` does, to `stdout`.
+pub fn print(args: fmt::Arguments) -> io::Result<()> {
+    stdout()
+        .write_fmt(args)
+        .map_err(|err| failed("stdout", err))
+}
+
+/// What `eout!` does, to `stderr`.
+pub fn eprint(args: fmt::Arguments) -> io::Result<()> {
+    stderr()
+        .write_fmt(args)
+        .map_err(|err| failed("stderr", err))
+}
+
+/// Prints what `hold` kept back, standard output first.
+pub fn print_held(held: &Held) -> io::Result<()> {
+    stdout()
+        .write_all(&held.stdout)
+        .map_err(|err| failed("stdout", err))?;
+    stderr()
+        .write_all(&held.stderr)
+        .map_err(|err| failed("stderr", err))
+}
+
+/// `err` saying which stream it came of, of the same kind still.
+fn failed(stream: &str, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("failed printing to {stream}: {err}"))
+}
+
+/// Whether `err` comes of writing to a pipe whose reader has gone, as it
+/// does when the output is piped into `head`.
+pub fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
+
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::output::print(format_args!($($arg)*))
+    };
+}
+
+macro_rules! outln {
+    () => {
+        $crate::output::print(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+macro_rules! eout {
+    ($($arg:tt)*) => {
+        $crate::output::eprint(format_args!($($arg)*))
+    };
+}
+
+macro_rules! eoutln {
+    () => {
+        $crate::output::eprint(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::eprint(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hold() {
+        let (result, held) = hold(|| {
+            outln!("one {}", 1).unwrap();
+            eoutln!("warning").unwrap();
+            let (_, inner) = hold(|| out!("inner").unwrap());
+            assert_eq!(inner.stdout, b"inner");
+            out!("two").unwrap();
+            2
+        });
+        assert_eq!(result, 2);
+        assert_eq!(
+            held,
+            Held {
+                stdout: b"one 1\ntwo".to_vec(),
+                stderr: b"warning\n".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_is_broken_pipe() {
+        let err = anyhow::Error::new(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert!(is_broken_pipe(&err.context("failed to write")));
+        let err = anyhow::Error::new(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(!is_broken_pipe(&err));
+    }
+}
@@ -0,0 +1,229 @@
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PaletteError {
+    #[error("PaletteError PLTE data is {0} bytes, not a multiple of 3")]
+    InvalidLength(usize),
+    #[error("PaletteError a palette needs at least one color")]
+    Empty,
+    #[error("PaletteError {0} colors, at most {1} are allowed")]
+    TooManyColors(usize, usize),
+    #[error("PaletteError tRNS has {0} entries for a palette of {1} colors")]
+    TooManyAlphas(usize, usize),
+    #[error("PaletteError color index {0} is out of range for {1} colors")]
+    IndexOutOfRange(usize, usize),
+}
+
+/// One palette entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+}
+
+/// The colors of PLTE and, for indexed images, the alpha of each from tRNS.
+///
+/// Entries missing from tRNS are fully opaque, so only the alphas up to the
+/// last translucent color are stored.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.PLTE
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Palette {
+    colors: Vec<Rgb>,
+    alphas: Vec<u8>,
+}
+
+impl Palette {
+    /// The most entries a palette can have, for a bit depth of 8.
+    pub const MAX_COLORS: usize = 256;
+
+    pub fn new(colors: Vec<Rgb>) -> Result<Self, PaletteError> {
+        if colors.is_empty() {
+            return Err(PaletteError::Empty);
+        }
+        if colors.len() > Self::MAX_COLORS {
+            return Err(PaletteError::TooManyColors(colors.len(), Self::MAX_COLORS));
+        }
+        Ok(Palette {
+            colors,
+            alphas: Vec::new(),
+        })
+    }
+
+    /// Parses PLTE data and the data of the image's tRNS chunk, if any.
+    pub fn from_data(plte: &[u8], trns: Option<&[u8]>) -> Result<Self, PaletteError> {
+        if !plte.len().is_multiple_of(3) {
+            return Err(PaletteError::InvalidLength(plte.len()));
+        }
+        let mut palette = Self::new(
+            plte.chunks_exact(3)
+                .map(|rgb| Rgb::new(rgb[0], rgb[1], rgb[2]))
+                .collect(),
+        )?;
+        if let Some(trns) = trns {
+            if trns.len() > palette.len() {
+                return Err(PaletteError::TooManyAlphas(trns.len(), palette.len()));
+            }
+            palette.alphas = trns.to_vec();
+            palette.trim_alphas();
+        }
+        Ok(palette)
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    pub fn colors(&self) -> &[Rgb] {
+        &self.colors
+    }
+
+    /// The colors, for changing in place; their alphas stay with their index.
+    pub fn colors_mut(&mut self) -> &mut [Rgb] {
+        &mut self.colors
+    }
+
+    /// The alpha of entry `index`, 255 for opaque.
+    pub fn alpha(&self, index: usize) -> Option<u8> {
+        (index < self.len()).then(|| self.alphas.get(index).copied().unwrap_or(u8::MAX))
+    }
+
+    pub fn set_alpha(&mut self, index: usize, alpha: u8) -> Result<(), PaletteError> {
+        if index >= self.len() {
+            return Err(PaletteError::IndexOutOfRange(index, self.len()));
+        }
+        if index >= self.alphas.len() {
+            self.alphas.resize(index + 1, u8::MAX);
+        }
+        self.alphas[index] = alpha;
+        self.trim_alphas();
+        Ok(())
+    }
+
+    /// Whether any entry is not fully opaque, so a tRNS chunk is needed.
+    pub fn has_transparency(&self) -> bool {
+        !self.alphas.is_empty()
+    }
+
+    /// Adds a color at the end, returning its index.
+    pub fn push(&mut self, color: Rgb, alpha: u8) -> Result<usize, PaletteError> {
+        if self.len() == Self::MAX_COLORS {
+            return Err(PaletteError::TooManyColors(
+                self.len() + 1,
+                Self::MAX_COLORS,
+            ));
+        }
+        self.colors.push(color);
+        let index = self.len() - 1;
+        self.set_alpha(index, alpha)?;
+        Ok(index)
+    }
+
+    /// Exchanges two entries along with their alphas. Pixels keep their indexes,
+    /// so the image data must be remapped to keep its look.
+    pub fn swap(&mut self, a: usize, b: usize) -> Result<(), PaletteError> {
+        for index in [a, b] {
+            if index >= self.len() {
+                return Err(PaletteError::IndexOutOfRange(index, self.len()));
+            }
+        }
+        let (alpha_a, alpha_b) = (self.alpha(a), self.alpha(b));
+        self.colors.swap(a, b);
+        self.set_alpha(a, alpha_b.unwrap_or(u8::MAX))?;
+        self.set_alpha(b, alpha_a.unwrap_or(u8::MAX))
+    }
+
+    /// Drops the entries from `len` on, keeping at least one.
+    pub fn truncate(&mut self, len: usize) {
+        self.colors.truncate(len.max(1));
+        self.alphas.truncate(len.max(1));
+        self.trim_alphas();
+    }
+
+    pub fn plte_data(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .flat_map(|color| [color.r, color.g, color.b])
+            .collect()
+    }
+
+    /// tRNS data for an indexed image, or `None` if every entry is opaque.
+    pub fn trns_data(&self) -> Option<Vec<u8>> {
+        self.has_transparency().then(|| self.alphas.clone())
+    }
+
+    pub fn plte_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::PLTE, self.plte_data())
+    }
+
+    pub fn trns_chunk(&self) -> Option<Chunk> {
+        self.trns_data()
+            .map(|data| Chunk::new(ChunkType::TRNS, data))
+    }
+
+    fn trim_alphas(&mut self) {
+        while self.alphas.last() == Some(&u8::MAX) {
+            self.alphas.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_data() {
+        let palette = Palette::from_data(&[255, 0, 0, 0, 255, 0, 0, 0, 255], Some(&[0])).unwrap();
+        assert_eq!(palette.colors()[1], Rgb::new(0, 255, 0));
+        assert_eq!(palette.alpha(0), Some(0));
+        assert_eq!(palette.alpha(2), Some(255));
+        assert_eq!(palette.alpha(3), None);
+        assert_eq!(palette.trns_data(), Some(vec![0]));
+
+        assert_eq!(
+            Palette::from_data(&[1, 2], None),
+            Err(PaletteError::InvalidLength(2))
+        );
+        assert_eq!(
+            Palette::from_data(&[1, 2, 3], Some(&[0, 0])),
+            Err(PaletteError::TooManyAlphas(2, 1))
+        );
+    }
+
+    #[test]
+    fn test_alphas_stay_in_sync() {
+        let mut palette = Palette::new(vec![Rgb::new(1, 1, 1), Rgb::new(2, 2, 2)]).unwrap();
+        assert!(palette.trns_chunk().is_none());
+
+        palette.push(Rgb::new(3, 3, 3), 128).unwrap();
+        assert_eq!(palette.trns_data(), Some(vec![255, 255, 128]));
+
+        palette.swap(0, 2).unwrap();
+        assert_eq!(palette.colors()[0], Rgb::new(3, 3, 3));
+        assert_eq!(palette.trns_data(), Some(vec![128]));
+
+        palette.colors_mut()[1].g = 9;
+        assert_eq!(palette.plte_data(), [3, 3, 3, 2, 9, 2, 1, 1, 1]);
+
+        palette.set_alpha(0, 255).unwrap();
+        assert!(!palette.has_transparency());
+        assert_eq!(
+            palette.set_alpha(3, 0),
+            Err(PaletteError::IndexOutOfRange(3, 3))
+        );
+    }
+}
@@ -0,0 +1,426 @@
+use std::convert::TryFrom;
+
+use thiserror::Error;
+
+use crate::base64;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::envelope::Envelope;
+use crate::fec;
+use crate::inspect::TextEntry;
+use crate::redundancy::{self, Stored};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PayloadError {
+    #[error("PayloadError no {0} chunk found")]
+    NotFound(String),
+    #[error("PayloadError segment {0} of {1} is missing")]
+    MissingSegment(u16, u16),
+    #[error("PayloadError unexpected segment {0} of {1}")]
+    UnexpectedSegment(u16, u16),
+    #[error("PayloadError payload needs {0} segments, more than the maximum of {max}", max = u16::MAX)]
+    TooManySegments(usize),
+    #[error("PayloadError no {0} payload number {1}, there are only {2}")]
+    NthNotFound(String, usize, usize),
+}
+
+/// Marks chunk data as one piece of a payload split across several chunks.
+pub const SEGMENT_MAGIC: [u8; 4] = [0x89, b'p', b's', b'q'];
+
+/// Magic, then big-endian u16 index and u16 count.
+pub const SEGMENT_HEADER_LEN: usize = 8;
+
+/// Payloads up to this size are stored in a single chunk.
+pub const DEFAULT_SEGMENT_LEN: usize = 1 << 20;
+
+/// The longest segment that still fits in a chunk, header and all.
+pub const MAX_SEGMENT_LEN: usize = Chunk::MAX_LENGTH as usize - SEGMENT_HEADER_LEN;
+
+/// Splits `payload` into chunks of `chunk_type`.
+///
+/// A payload that fits in `max_segment_len` bytes becomes a single chunk holding exactly
+/// those bytes, so small secrets stay readable by tools that know nothing about segments.
+/// Larger payloads are cut into segments, each prefixed with its index and the total count.
+pub fn split(
+    chunk_type: &ChunkType,
+    payload: &[u8],
+    max_segment_len: usize,
+) -> Result<Vec<Chunk>, PayloadError> {
+    if payload.len() <= max_segment_len && !payload.starts_with(&SEGMENT_MAGIC) {
+        return Ok(vec![Chunk::new(*chunk_type, payload.to_vec())]);
+    }
+
+    let pieces: Vec<&[u8]> = payload.chunks(max_segment_len.max(1)).collect();
+    let count =
+        u16::try_from(pieces.len()).map_err(|_| PayloadError::TooManySegments(pieces.len()))?;
+
+    Ok(pieces
+        .into_iter()
+        .enumerate()
+        .map(|(index, piece)| {
+            let mut data = Vec::with_capacity(SEGMENT_HEADER_LEN + piece.len());
+            data.extend_from_slice(&SEGMENT_MAGIC);
+            data.extend_from_slice(&(index as u16).to_be_bytes());
+            data.extend_from_slice(&count.to_be_bytes());
+            data.extend_from_slice(piece);
+            Chunk::new(*chunk_type, data)
+        })
+        .collect())
+}
+
+/// Reassembles every payload stored in `chunks` of `chunk_type`, in file order.
+///
+/// Chunks of other types are skipped, so this can be handed all chunks of a png.
+pub fn join_all<'a, I>(chunk_type: &ChunkType, chunks: I) -> Result<Vec<Vec<u8>>, PayloadError>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    let mut payloads = Vec::new();
+    let mut pending: Option<(u16, u16, Vec<u8>)> = None;
+
+    for chunk in chunks
+        .into_iter()
+        .filter(|chunk| chunk.chunk_type() == chunk_type)
+    {
+        let segment = parse_segment(chunk.data());
+
+        if let Some((next, count, mut payload)) = pending.take() {
+            match segment {
+                Some((index, segment_count, piece)) if index == next && segment_count == count => {
+                    payload.extend_from_slice(piece);
+                    if index + 1 == count {
+                        payloads.push(payload);
+                    } else {
+                        pending = Some((index + 1, count, payload));
+                    }
+                    continue;
+                }
+                _ => return Err(PayloadError::MissingSegment(next, count)),
+            }
+        }
+
+        match segment {
+            Some((0, count, piece)) if count > 1 => pending = Some((1, count, piece.to_vec())),
+            Some((0, _, piece)) => payloads.push(piece.to_vec()),
+            Some((index, count, _)) => return Err(PayloadError::UnexpectedSegment(index, count)),
+            None => payloads.push(chunk.data().to_vec()),
+        }
+    }
+
+    if let Some((next, count, _)) = pending {
+        return Err(PayloadError::MissingSegment(next, count));
+    }
+
+    Ok(payloads)
+}
+
+/// Reassembles the first payload stored in `chunks` of `chunk_type`.
+pub fn join<'a, I>(chunk_type: &ChunkType, chunks: I) -> Result<Vec<u8>, PayloadError>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    join_nth(chunk_type, chunks, 1)
+}
+
+/// Reassembles the `nth` payload, counting from 1, stored in `chunks` of `chunk_type`.
+pub fn join_nth<'a, I>(
+    chunk_type: &ChunkType,
+    chunks: I,
+    nth: usize,
+) -> Result<Vec<u8>, PayloadError>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    let payloads = join_all(chunk_type, chunks)?;
+    pick(
+        payloads,
+        nth,
+        chunk_type.to_string(),
+        chunk_type.to_string(),
+    )
+}
+
+/// Like `join_nth`, but counts the copies of a payload `redundancy::copies` made
+/// as one payload, returning them all.
+pub fn join_nth_stored<'a, I>(
+    chunk_type: &ChunkType,
+    chunks: I,
+    nth: usize,
+) -> Result<Stored, PayloadError>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    let stored = redundancy::gather(join_all(chunk_type, chunks)?);
+    pick(stored, nth, chunk_type.to_string(), chunk_type.to_string())
+}
+
+/// Spells `payload` as the text of a text chunk entry.
+///
+/// A UTF-8 message whose characters all fit in Latin-1 is stored as that text, so other
+/// tools show it as is. Anything else is stored as base64 of an envelope, wrapping the
+/// payload in one first if needed, so `from_text` can tell the two apart; a payload
+/// protected by `fec` or a copy `redundancy` made is stored as base64 of itself, and
+/// so found by its own header.
+pub fn to_text(payload: &[u8]) -> String {
+    text_or_base64(payload, |c| u32::from(c) <= 0xff)
+}
+
+/// Like `to_text`, but for iTXt entries, which can hold any UTF-8 message as is.
+pub fn to_utf8_text(payload: &[u8]) -> String {
+    text_or_base64(payload, |_| true)
+}
+
+fn text_or_base64(payload: &[u8], allowed: impl Fn(char) -> bool) -> String {
+    if Envelope::is_envelope(payload)
+        || fec::is_protected(payload)
+        || payload.starts_with(&redundancy::COPY_MAGIC)
+    {
+        return base64::encode(payload);
+    }
+    match std::str::from_utf8(payload) {
+        Ok(text) if text.chars().all(|c| c != '\0' && allowed(c)) => text.to_string(),
+        _ => base64::encode(&Envelope::new(payload.to_vec()).to_bytes(None)),
+    }
+}
+
+/// Recovers the payload `to_text` stored as `text`.
+pub fn from_text(text: &str) -> Vec<u8> {
+    match base64::decode(text) {
+        Ok(bytes)
+            if Envelope::is_envelope(&bytes)
+                || fec::is_protected(&bytes)
+                || bytes.starts_with(&redundancy::COPY_MAGIC) =>
+        {
+            bytes
+        }
+        _ => text.as_bytes().to_vec(),
+    }
+}
+
+/// Finds the `nth` text entry, counting from 1, stored under `keyword` in `chunks`,
+/// only counting iTXt entries tagged with `language` if one is given.
+pub fn find_text<'a, I>(
+    chunks: I,
+    keyword: &str,
+    language: Option<&str>,
+    nth: usize,
+) -> Result<String, PayloadError>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    let entries = text_entries(chunks, keyword, language);
+    pick(
+        entries,
+        nth,
+        format!("{keyword:?} text"),
+        format!("{keyword:?}"),
+    )
+}
+
+/// Like `find_text`, but returns the payload `from_text` recovers, counting the
+/// copies of a payload `redundancy::copies` made as one payload.
+pub fn find_text_stored<'a, I>(
+    chunks: I,
+    keyword: &str,
+    language: Option<&str>,
+    nth: usize,
+) -> Result<Stored, PayloadError>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    let payloads = text_entries(chunks, keyword, language)
+        .iter()
+        .map(|text| from_text(text))
+        .collect();
+    pick(
+        redundancy::gather(payloads),
+        nth,
+        format!("{keyword:?} text"),
+        format!("{keyword:?}"),
+    )
+}
+
+fn text_entries<'a, I>(chunks: I, keyword: &str, language: Option<&str>) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    chunks
+        .into_iter()
+        .filter_map(TextEntry::from_chunk)
+        .filter(|entry| entry.keyword == keyword)
+        .filter(|entry| language.is_none_or(|language| entry.language_tag == language))
+        .map(|entry| entry.text)
+        .collect()
+}
+
+/// The `nth` of `items`, counting from 1, which are `name` payloads, or
+/// `not_found` ones when there are none.
+fn pick<T>(
+    mut items: Vec<T>,
+    nth: usize,
+    not_found: String,
+    name: String,
+) -> Result<T, PayloadError> {
+    match items.len() {
+        0 => Err(PayloadError::NotFound(not_found)),
+        count if nth == 0 || nth > count => Err(PayloadError::NthNotFound(name, nth, count)),
+        _ => Ok(items.swap_remove(nth - 1)),
+    }
+}
+
+fn parse_segment(data: &[u8]) -> Option<(u16, u16, &[u8])> {
+    if data.len() < SEGMENT_HEADER_LEN || data[..4] != SEGMENT_MAGIC {
+        return None;
+    }
+
+    let index = u16::from_be_bytes([data[4], data[5]]);
+    let count = u16::from_be_bytes([data[6], data[7]]);
+    Some((index, count, &data[SEGMENT_HEADER_LEN..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn ru_st() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    fn testing_payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        assert_eq!(to_text(b"caf\xc3\xa9"), "café");
+        assert_eq!(to_utf8_text("世界".as_bytes()), "世界");
+        assert_eq!(from_text("café"), "café".as_bytes());
+
+        for payload in [&b"\xff\x00binary"[..], "世界".as_bytes()] {
+            let text = to_text(payload);
+            let envelope = Envelope::from_bytes(&from_text(&text), None).unwrap();
+            assert_eq!(envelope.body, payload);
+        }
+
+        let protected = fec::encode(b"message", fec::Fec { parity: 4 });
+        assert_eq!(from_text(&to_text(&protected)), protected);
+    }
+
+    #[test]
+    fn test_find_text() {
+        use crate::text::{InternationalTextChunk, TextChunk};
+
+        let chunks: Vec<Chunk> = [("Title", "one"), ("Secret", "two"), ("Secret", "three")]
+            .iter()
+            .map(|(keyword, text)| TextChunk::new(keyword, text).unwrap().to_chunk())
+            .collect();
+        assert_eq!(find_text(&chunks, "Secret", None, 2).unwrap(), "three");
+        assert_eq!(
+            find_text(&chunks, "Secret", None, 3),
+            Err(PayloadError::NthNotFound("\"Secret\"".to_string(), 3, 2))
+        );
+        assert!(find_text(&chunks, "Author", None, 1).is_err());
+
+        let mut chunks = chunks;
+        let itxt = InternationalTextChunk::new("Secret", "vier")
+            .unwrap()
+            .with_language("de", "Geheimnis")
+            .unwrap();
+        chunks.push(itxt.to_chunk());
+        assert_eq!(find_text(&chunks, "Secret", Some("de"), 1).unwrap(), "vier");
+        assert!(find_text(&chunks, "Secret", Some("fr"), 1).is_err());
+    }
+
+    #[test]
+    fn test_small_payload_is_a_single_raw_chunk() {
+        let chunks = split(&ru_st(), b"hello", 16).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].data(), b"hello");
+        assert_eq!(join(&ru_st(), &chunks).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_large_payload_round_trip() {
+        let payload = testing_payload(100);
+        let chunks = split(&ru_st(), &payload, 30).unwrap();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[3].data().len(), SEGMENT_HEADER_LEN + 10);
+        assert_eq!(join(&ru_st(), &chunks).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_join_skips_other_chunk_types() {
+        let payload = testing_payload(50);
+        let mut chunks = split(&ru_st(), &payload, 20).unwrap();
+        let other = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"noise".to_vec());
+        chunks.insert(1, other.clone());
+        chunks.insert(0, other);
+
+        assert_eq!(join(&ru_st(), &chunks).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_join_all_finds_every_payload() {
+        let first = testing_payload(50);
+        let mut chunks = split(&ru_st(), &first, 20).unwrap();
+        chunks.extend(split(&ru_st(), b"second", 20).unwrap());
+        chunks.extend(split(&ru_st(), &first, 20).unwrap());
+
+        let payloads = join_all(&ru_st(), &chunks).unwrap();
+        assert_eq!(payloads, vec![first.clone(), b"second".to_vec(), first]);
+    }
+
+    #[test]
+    fn test_join_nth() {
+        let mut chunks = split(&ru_st(), &testing_payload(50), 20).unwrap();
+        chunks.extend(split(&ru_st(), b"second", 20).unwrap());
+
+        assert_eq!(join_nth(&ru_st(), &chunks, 1).unwrap(), testing_payload(50));
+        assert_eq!(join_nth(&ru_st(), &chunks, 2).unwrap(), b"second");
+        assert_eq!(
+            join_nth(&ru_st(), &chunks, 3),
+            Err(PayloadError::NthNotFound("ruSt".to_string(), 3, 2))
+        );
+        assert_eq!(
+            join_nth(&ru_st(), &chunks, 0),
+            Err(PayloadError::NthNotFound("ruSt".to_string(), 0, 2))
+        );
+    }
+
+    #[test]
+    fn test_missing_segment() {
+        let mut chunks = split(&ru_st(), &testing_payload(100), 30).unwrap();
+        chunks.remove(2);
+        assert_eq!(
+            join(&ru_st(), &chunks),
+            Err(PayloadError::MissingSegment(2, 4))
+        );
+
+        chunks.truncate(2);
+        assert_eq!(
+            join(&ru_st(), &chunks),
+            Err(PayloadError::MissingSegment(2, 4))
+        );
+
+        assert_eq!(
+            join(&ru_st(), &chunks[1..]),
+            Err(PayloadError::UnexpectedSegment(1, 4))
+        );
+    }
+
+    #[test]
+    fn test_payload_that_looks_like_a_segment() {
+        let payload = [&SEGMENT_MAGIC[..], b"\x00\x00\x00\x01abc"].concat();
+        let chunks = split(&ru_st(), &payload, 1024).unwrap();
+        assert_eq!(join(&ru_st(), &chunks).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_not_found() {
+        assert_eq!(
+            join(&ru_st(), &[]),
+            Err(PayloadError::NotFound("ruSt".to_string()))
+        );
+    }
+}
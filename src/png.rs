@@ -1,14 +1,25 @@
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
 use std::fs;
-use std::io::{BufReader, Read};
+use std::io::{self, BufReader, Read, Write};
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
 
 use thiserror::Error;
 
+use crate::ancillary::{
+    AncillaryChunk, AncillaryError, Chromaticities, Gamma, PhysicalDimensions, RenderingIntent,
+};
 use crate::chunk;
-use crate::chunk::Chunk;
-use crate::chunk_type::ChunkType;
+use crate::chunk::{Chunk, ParseOptions};
+use crate::chunk_reader::ChunkReader;
+use crate::chunk_ref::ChunkRefs;
+use crate::chunk_type::{ChunkType, ChunkTypeError};
+use crate::ihdr::{ColorType, Ihdr, IhdrError};
+use crate::ordering::Placement;
+use crate::palette::{Palette, PaletteError};
 
 #[derive(Error, Debug)]
 pub enum PngError {
@@ -20,12 +31,78 @@ pub enum PngError {
     ReadError(#[from] std::io::Error),
     #[error("PngError invalid chunk")]
     InValidChunk(#[from] chunk::ChunkError),
+    #[error("PngError invalid chunk type")]
+    InvalidChunkType(#[from] ChunkTypeError),
+    #[error("PngError invalid insert position {0}")]
+    InvalidPosition(String),
+    #[error("PngError chunk index {0} is out of range for {1} chunks")]
+    IndexOutOfRange(usize, usize),
+    #[error("PngError invalid IHDR")]
+    InvalidIhdr(#[from] IhdrError),
+    #[error("PngError invalid ancillary chunk")]
+    InvalidAncillary(#[from] AncillaryError),
+    #[error("PngError invalid palette")]
+    InvalidPalette(#[from] PaletteError),
+}
+
+/// Where `Png::insert_chunk` places a new chunk.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum InsertPosition {
+    /// Just before IEND, where the spec allows ancillary chunks; at the end if there is
+    /// no IEND.
+    #[default]
+    BeforeIend,
+    /// Right after IHDR, ahead of the image data.
+    AfterIhdr,
+    /// After the last chunk of the given type.
+    AfterType(ChunkType),
+    /// Before the first chunk of the given type.
+    BeforeType(ChunkType),
+    /// At exactly this index, from 0 up to the number of chunks.
+    Index(usize),
+}
+
+impl FromStr for InsertPosition {
+    type Err = PngError;
+
+    /// Parses `before-iend`, `after-ihdr`, `after:<type>`, `before:<type>` or a chunk
+    /// index.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "before-iend" => return Ok(Self::BeforeIend),
+            "after-ihdr" => return Ok(Self::AfterIhdr),
+            _ => {}
+        }
+        if let Some(chunk_type) = s.strip_prefix("after:") {
+            return Ok(Self::AfterType(chunk_type.parse()?));
+        }
+        if let Some(chunk_type) = s.strip_prefix("before:") {
+            return Ok(Self::BeforeType(chunk_type.parse()?));
+        }
+        s.parse()
+            .map(Self::Index)
+            .map_err(|_| PngError::InvalidPosition(s.to_string()))
+    }
+}
+
+/// A chunk whose stored crc was replaced by `Png::fix_crcs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrcFix {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub stored: u32,
+    pub computed: u32,
 }
 
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Contents.html
+///
+/// Lookups by type go through an index of where each type sits, built on first use,
+/// kept in step by single inserts and removals and dropped by anything that may
+/// reorder or retype chunks wholesale.
 #[derive(Debug)]
 pub struct Png {
     chunks: Vec<Chunk>,
+    index: OnceLock<HashMap<ChunkType, Vec<usize>>>,
 }
 
 impl Png {
@@ -33,7 +110,50 @@ impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
     pub fn from_chunks(chunks: Vec<Chunk>) -> Self {
-        Self { chunks }
+        Self {
+            chunks,
+            index: OnceLock::new(),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PngError> {
+        Self::try_from(bytes)
+    }
+
+    pub fn from_bytes_with(bytes: &[u8], options: &ParseOptions) -> Result<Self, PngError> {
+        let reader = Self::reader_with(BufReader::new(bytes), options.clone())?;
+        let chunks = reader.collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::from_chunks(chunks))
+    }
+
+    /// Checks the PNG signature and returns a reader that yields the chunks that follow it.
+    pub fn reader<R: Read>(inner: R) -> Result<ChunkReader<R>, PngError> {
+        Self::reader_with(inner, ParseOptions::default())
+    }
+
+    pub fn reader_with<R: Read>(
+        mut inner: R,
+        options: ParseOptions,
+    ) -> Result<ChunkReader<R>, PngError> {
+        let mut header: [u8; 8] = [0; 8];
+        inner.read_exact(&mut header)?;
+        if header != Self::STANDARD_HEADER {
+            return Err(PngError::InvalidHeader);
+        }
+
+        Ok(ChunkReader::with_options(inner, options))
+    }
+
+    /// Checks the PNG signature and borrows the chunks that follow it straight
+    /// from `bytes`, for scanning files without copying their data.
+    pub fn chunk_refs(bytes: &[u8], options: ParseOptions) -> Result<ChunkRefs<'_>, PngError> {
+        match bytes.split_at_checked(Self::STANDARD_HEADER.len()) {
+            Some((header, rest)) if header == Self::STANDARD_HEADER => {
+                Ok(ChunkRefs::new(rest, options))
+            }
+            _ => Err(PngError::InvalidHeader),
+        }
     }
 
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PngError> {
@@ -42,17 +162,160 @@ impl Png {
     }
 
     pub fn append_chunk(&mut self, chunk: Chunk) {
+        if let Some(index) = self.index.get_mut() {
+            index
+                .entry(*chunk.chunk_type())
+                .or_default()
+                .push(self.chunks.len());
+        }
         self.chunks.push(chunk);
     }
 
+    /// Inserts `chunk` at `position`, returning the index it ended up at.
+    pub fn insert_chunk(
+        &mut self,
+        chunk: Chunk,
+        position: &InsertPosition,
+    ) -> Result<usize, PngError> {
+        let index = self.position_index(position)?;
+        self.insert_at(index, chunk);
+        Ok(index)
+    }
+
+    /// Inserts `chunk` just before the first chunk of `chunk_type`, e.g. ahead of
+    /// the image data with `IDAT`, returning the index it ended up at.
+    pub fn insert_before(
+        &mut self,
+        chunk_type: &ChunkType,
+        chunk: Chunk,
+    ) -> Result<usize, PngError> {
+        self.insert_chunk(chunk, &InsertPosition::BeforeType(*chunk_type))
+    }
+
+    /// Inserts `chunk` just after the last chunk of `chunk_type`, so after a whole
+    /// run of `IDAT` chunks rather than inside it.
+    pub fn insert_after(
+        &mut self,
+        chunk_type: &ChunkType,
+        chunk: Chunk,
+    ) -> Result<usize, PngError> {
+        self.insert_chunk(chunk, &InsertPosition::AfterType(*chunk_type))
+    }
+
+    /// The index a chunk inserted at `position` would get.
+    pub fn position_index(&self, position: &InsertPosition) -> Result<usize, PngError> {
+        let after_last = |chunk_type: ChunkType| {
+            self.chunk_indices(chunk_type)
+                .last()
+                .map(|index| index + 1)
+                .ok_or(PngError::PngChunkNotFound(chunk_type.to_string()))
+        };
+
+        match position {
+            InsertPosition::BeforeIend => Ok(self
+                .chunk_indices(ChunkType::IEND)
+                .last()
+                .copied()
+                .unwrap_or(self.chunks.len())),
+            InsertPosition::AfterIhdr => after_last(ChunkType::IHDR),
+            InsertPosition::AfterType(chunk_type) => after_last(*chunk_type),
+            InsertPosition::BeforeType(chunk_type) => self
+                .chunk_indices(*chunk_type)
+                .first()
+                .copied()
+                .ok_or(PngError::PngChunkNotFound(chunk_type.to_string())),
+            InsertPosition::Index(index) if *index <= self.chunks.len() => Ok(*index),
+            InsertPosition::Index(index) => Err(PngError::InvalidPosition(format!(
+                "{index}, the image has {} chunks",
+                self.chunks.len()
+            ))),
+        }
+    }
+
     pub fn remove_first_chunk(&mut self, chunk_type: ChunkType) -> Result<Chunk, PngError> {
-        let index = self
-            .chunks
-            .iter()
-            .position(|chunk| chunk.chunk_type() == &chunk_type)
+        let index = *self
+            .chunk_indices(chunk_type)
+            .first()
             .ok_or(PngError::PngChunkNotFound(chunk_type.to_string()))?;
 
-        Ok(self.chunks.remove(index))
+        Ok(self.remove_at(index))
+    }
+
+    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, PngError> {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        self.remove_first_chunk(chunk_type)
+    }
+
+    /// Removes every chunk of `chunk_type`, returning them in file order; empty if
+    /// there were none.
+    pub fn remove_all_chunks(&mut self, chunk_type: ChunkType) -> Vec<Chunk> {
+        let mut removed: Vec<Chunk> = self
+            .chunk_indices(chunk_type)
+            .to_vec()
+            .into_iter()
+            .rev()
+            .map(|index| self.remove_at(index))
+            .collect();
+        removed.reverse();
+        removed
+    }
+
+    /// Removes the chunk at `index`, shifting the ones after it down.
+    pub fn remove_chunk_at(&mut self, index: usize) -> Result<Chunk, PngError> {
+        if index >= self.chunks.len() {
+            return Err(PngError::IndexOutOfRange(index, self.chunks.len()));
+        }
+        Ok(self.remove_at(index))
+    }
+
+    /// Swaps the data of the first chunk of `chunk_type` for `data`, keeping its place
+    /// in the file, and returns the chunk it replaced.
+    pub fn replace_chunk(
+        &mut self,
+        chunk_type: &ChunkType,
+        data: Vec<u8>,
+    ) -> Result<Chunk, PngError> {
+        let index = *self
+            .chunk_indices(*chunk_type)
+            .first()
+            .ok_or(PngError::PngChunkNotFound(chunk_type.to_string()))?;
+        self.replace_chunk_at(index, data)
+    }
+
+    /// Like `replace_chunk`, for the chunk at `index`. The new chunk gets a fresh
+    /// length and crc.
+    pub fn replace_chunk_at(&mut self, index: usize, data: Vec<u8>) -> Result<Chunk, PngError> {
+        let len = self.chunks.len();
+        let chunk = self
+            .chunks
+            .get_mut(index)
+            .ok_or(PngError::IndexOutOfRange(index, len))?;
+        let replacement = Chunk::new(*chunk.chunk_type(), data);
+        Ok(std::mem::replace(chunk, replacement))
+    }
+
+    /// Recomputes the crc of every chunk accepted by `select`, reporting the ones that changed.
+    pub fn fix_crcs<F>(&mut self, mut select: F) -> Vec<CrcFix>
+    where
+        F: FnMut(&Chunk) -> bool,
+    {
+        let mut fixes = Vec::new();
+        for (index, chunk) in self.chunks.iter_mut().enumerate() {
+            if !select(chunk) {
+                continue;
+            }
+
+            let stored = chunk.crc();
+            if chunk.recompute_crc() {
+                fixes.push(CrcFix {
+                    index,
+                    chunk_type: *chunk.chunk_type(),
+                    stored,
+                    computed: chunk.crc(),
+                });
+            }
+        }
+        fixes
     }
 
     pub fn header(&self) -> &[u8; 8] {
@@ -63,19 +326,246 @@ impl Png {
         self.chunks.as_slice()
     }
 
+    /// The chunks, for changing in place, e.g. with `Chunk::set_data`.
+    pub fn chunks_mut(&mut self) -> &mut [Chunk] {
+        self.index.take();
+        self.chunks.as_mut_slice()
+    }
+
+    /// Keeps only the chunks for which `keep` returns true, in one pass.
+    pub fn retain<F>(&mut self, keep: F)
+    where
+        F: FnMut(&Chunk) -> bool,
+    {
+        self.index.take();
+        self.chunks.retain(keep);
+    }
+
+    /// Removes the chunks for which `remove` returns true and returns them in
+    /// file order.
+    pub fn remove_where<F>(&mut self, mut remove: F) -> Vec<Chunk>
+    where
+        F: FnMut(&Chunk) -> bool,
+    {
+        self.index.take();
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk| remove(chunk));
+        self.chunks = kept;
+        removed
+    }
+
     pub fn chunk_by_type(&self, chunk_type: ChunkType) -> Option<&Chunk> {
-        self.chunks
+        self.chunks_by_type(chunk_type).next()
+    }
+
+    /// Every chunk of `chunk_type`, in file order.
+    pub fn chunks_by_type(&self, chunk_type: ChunkType) -> impl Iterator<Item = &Chunk> + '_ {
+        self.chunk_indices(chunk_type)
             .iter()
-            .find(|chunk| chunk.chunk_type() == &chunk_type)
+            .map(|&index| &self.chunks[index])
+    }
+
+    /// The indices of the chunks of `chunk_type`, ascending.
+    pub fn chunk_indices(&self, chunk_type: ChunkType) -> &[usize] {
+        self.type_index()
+            .get(&chunk_type)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn type_index(&self) -> &HashMap<ChunkType, Vec<usize>> {
+        self.index.get_or_init(|| {
+            let mut index: HashMap<ChunkType, Vec<usize>> = HashMap::new();
+            for (position, chunk) in self.chunks.iter().enumerate() {
+                index.entry(*chunk.chunk_type()).or_default().push(position);
+            }
+            index
+        })
+    }
+
+    fn insert_at(&mut self, position: usize, chunk: Chunk) {
+        if let Some(index) = self.index.get_mut() {
+            for positions in index.values_mut() {
+                positions
+                    .iter_mut()
+                    .filter(|p| **p >= position)
+                    .for_each(|p| *p += 1);
+            }
+            let positions = index.entry(*chunk.chunk_type()).or_default();
+            let at = positions.partition_point(|&p| p < position);
+            positions.insert(at, position);
+        }
+        self.chunks.insert(position, chunk);
+    }
+
+    fn remove_at(&mut self, position: usize) -> Chunk {
+        let chunk = self.chunks.remove(position);
+        if let Some(index) = self.index.get_mut() {
+            if let Some(positions) = index.get_mut(chunk.chunk_type()) {
+                positions.retain(|&p| p != position);
+                if positions.is_empty() {
+                    index.remove(chunk.chunk_type());
+                }
+            }
+            for positions in index.values_mut() {
+                positions
+                    .iter_mut()
+                    .filter(|p| **p > position)
+                    .for_each(|p| *p -= 1);
+            }
+        }
+        chunk
+    }
+
+    /// Parses the image header from the first IHDR chunk.
+    pub fn ihdr(&self) -> Result<Ihdr, PngError> {
+        let chunk = self
+            .chunk_by_type(ChunkType::IHDR)
+            .ok_or(PngError::PngChunkNotFound(ChunkType::IHDR.to_string()))?;
+        Ok(Ihdr::try_from(chunk)?)
+    }
+
+    /// Parses the first chunk of type `T::TYPE`, or `None` if there is none.
+    pub fn ancillary<T: AncillaryChunk>(&self) -> Result<Option<T>, PngError> {
+        self.chunk_by_type(T::chunk_type())
+            .map(|chunk| T::from_data(chunk.data()))
+            .transpose()
+            .map_err(PngError::from)
+    }
+
+    /// Stores `value`, replacing the first chunk of its type in place, or else
+    /// inserting it where the spec wants it: before PLTE for the color chunks and
+    /// before the first IDAT otherwise.
+    pub fn set_ancillary<T: AncillaryChunk>(&mut self, value: &T) {
+        let chunk_type = T::chunk_type();
+        if let Some(&index) = self.chunk_indices(chunk_type).first() {
+            self.chunks[index] = value.to_chunk();
+            return;
+        }
+
+        let before_plte = Placement::of(&chunk_type) == Some(Placement::BeforePlte);
+        let index = self
+            .chunks
+            .iter()
+            .position(|chunk| match &chunk.chunk_type().bytes() {
+                b"IDAT" | b"IEND" => true,
+                b"PLTE" => before_plte,
+                _ => false,
+            })
+            .unwrap_or(self.chunks.len());
+        self.insert_at(index, value.to_chunk());
+    }
+
+    /// Pixels per unit from pHYs.
+    pub fn physical_dimensions(&self) -> Result<Option<PhysicalDimensions>, PngError> {
+        self.ancillary()
+    }
+
+    pub fn set_physical_dimensions(&mut self, dimensions: PhysicalDimensions) {
+        self.set_ancillary(&dimensions);
+    }
+
+    /// Image gamma from gAMA.
+    pub fn gamma(&self) -> Result<Option<Gamma>, PngError> {
+        self.ancillary()
+    }
+
+    pub fn set_gamma(&mut self, gamma: Gamma) {
+        self.set_ancillary(&gamma);
+    }
+
+    /// The rendering intent of an sRGB image.
+    pub fn rendering_intent(&self) -> Result<Option<RenderingIntent>, PngError> {
+        self.ancillary()
+    }
+
+    pub fn set_rendering_intent(&mut self, intent: RenderingIntent) {
+        self.set_ancillary(&intent);
+    }
+
+    /// White point and primaries from cHRM.
+    pub fn chromaticities(&self) -> Result<Option<Chromaticities>, PngError> {
+        self.ancillary()
+    }
+
+    pub fn set_chromaticities(&mut self, chromaticities: Chromaticities) {
+        self.set_ancillary(&chromaticities);
+    }
+
+    /// The PLTE colors along with, for an indexed image, their alphas from tRNS.
+    /// In other images tRNS holds a single transparent color instead, so it is
+    /// left out.
+    pub fn palette(&self) -> Result<Option<Palette>, PngError> {
+        let Some(plte) = self.chunk_by_type(ChunkType::PLTE) else {
+            return Ok(None);
+        };
+        let trns = match self.ihdr()?.color_type {
+            ColorType::Indexed => self.chunk_by_type(ChunkType::TRNS),
+            _ => None,
+        };
+        let palette = Palette::from_data(plte.data(), trns.map(|chunk| chunk.data()))?;
+        Ok(Some(palette))
+    }
+
+    /// Stores `palette` in PLTE, in place or ahead of the chunks that must follow
+    /// it. For an indexed image tRNS is rewritten to match, or removed when every
+    /// color is opaque, and the palette must fit the bit depth.
+    pub fn set_palette(&mut self, palette: &Palette) -> Result<(), PngError> {
+        let ihdr = self.ihdr()?;
+        let indexed = ihdr.color_type == ColorType::Indexed;
+        if indexed && palette.len() > 1 << ihdr.bit_depth {
+            return Err(PaletteError::TooManyColors(palette.len(), 1 << ihdr.bit_depth).into());
+        }
+
+        let plte = match self.chunk_indices(ChunkType::PLTE).first().copied() {
+            Some(index) => {
+                self.chunks[index] = palette.plte_chunk();
+                index
+            }
+            None => {
+                let index = self
+                    .chunks
+                    .iter()
+                    .position(|chunk| {
+                        let placement = Placement::of(chunk.chunk_type());
+                        placement.is_some_and(|placement| placement > Placement::Plte)
+                            && placement != Some(Placement::BeforeIdat)
+                    })
+                    .unwrap_or(self.chunks.len());
+                self.insert_at(index, palette.plte_chunk());
+                index
+            }
+        };
+
+        if indexed {
+            let trns_index = self.chunk_indices(ChunkType::TRNS).first().copied();
+            match (trns_index, palette.trns_chunk()) {
+                (Some(index), Some(trns)) => self.chunks[index] = trns,
+                (Some(index), None) => {
+                    self.remove_at(index);
+                }
+                (None, Some(trns)) => self.insert_at(plte + 1, trns),
+                (None, None) => {}
+            }
+        }
+        Ok(())
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
-        bytes.extend_from_slice(self.header());
+        self.write_to(&mut bytes)
+            .expect("writing to a Vec<u8> cannot fail");
+        bytes
+    }
+
+    /// Writes the signature followed by every chunk into `writer`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.header())?;
         for chunk in self.chunks() {
-            bytes.extend_from_slice(chunk.as_bytes().as_ref());
+            chunk.write_to(writer)?;
         }
-        bytes
+        Ok(())
     }
 }
 
@@ -83,27 +573,7 @@ impl TryFrom<&[u8]> for Png {
     type Error = PngError;
 
     fn try_from(bytes: &[u8]) -> Result<Png, PngError> {
-        let mut reader = BufReader::new(bytes);
-
-        let mut header: [u8; 8] = [0; 8];
-        reader.read_exact(&mut header)?;
-        if header != Png::STANDARD_HEADER {
-            return Err(PngError::InvalidHeader);
-        }
-
-        let mut chunks = Vec::new();
-        let mut length_buf: [u8; 4] = [0; 4];
-
-        while let Ok(()) = reader.read_exact(&mut length_buf) {
-            let length = 4 + u32::from_be_bytes(length_buf) + 4;
-            let mut buffer = vec![0; length as usize];
-            reader.read_exact(&mut buffer)?;
-            let all_bytes = [&length_buf, buffer.as_slice()].concat();
-            let chunk = Chunk::try_from(all_bytes.as_ref())?;
-            chunks.push(chunk);
-        }
-
-        Ok(Png::from_chunks(chunks))
+        Png::from_bytes_with(bytes, &ParseOptions::default())
     }
 }
 
@@ -121,7 +591,7 @@ impl fmt::Display for Png {
 mod tests {
     use super::*;
     use crate::chunk::Chunk;
-    use crate::chunk_type::{ChunkType, ChunkTypeError};
+    use crate::palette::Rgb;
     use std::convert::TryFrom;
 
     fn testing_chunks() -> Vec<Chunk> {
@@ -138,8 +608,6 @@ mod tests {
     }
 
     fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk, ChunkTypeError> {
-        use std::str::FromStr;
-
         let chunk_type = ChunkType::from_str(chunk_type)?;
         let data: Vec<u8> = data.bytes().collect();
 
@@ -236,6 +704,321 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = Png::from_bytes(&PNG_FILE[..]).unwrap();
+        let count = png.chunks().len();
+        let chunk = || chunk_from_strings("ruSt", "secret").unwrap();
+
+        let index = png
+            .insert_chunk(chunk(), &InsertPosition::BeforeIend)
+            .unwrap();
+        assert_eq!(index, count - 1);
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+
+        let index = png
+            .insert_chunk(chunk(), &InsertPosition::AfterIhdr)
+            .unwrap();
+        assert_eq!(index, 1);
+
+        let position = InsertPosition::AfterType("ruSt".parse().unwrap());
+        let index = png.insert_chunk(chunk(), &position).unwrap();
+        assert_eq!(index, count + 1);
+
+        let index = png
+            .insert_chunk(chunk(), &InsertPosition::Index(0))
+            .unwrap();
+        assert_eq!(index, 0);
+
+        let position = InsertPosition::Index(png.chunks().len() + 1);
+        assert!(png.insert_chunk(chunk(), &position).is_err());
+        let position = InsertPosition::AfterType("zzZz".parse().unwrap());
+        assert!(png.insert_chunk(chunk(), &position).is_err());
+    }
+
+    #[test]
+    fn test_insert_before_and_after() {
+        let mut png = Png::from_chunks(
+            ["IHDR", "IDAT", "IDAT", "IEND"]
+                .iter()
+                .map(|chunk_type| chunk_from_strings(chunk_type, "").unwrap())
+                .collect(),
+        );
+        let idat = ChunkType::from_str("IDAT").unwrap();
+
+        let chunk = chunk_from_strings("ruSt", "before").unwrap();
+        assert_eq!(png.insert_before(&idat, chunk).unwrap(), 1);
+        let chunk = chunk_from_strings("ruSt", "after").unwrap();
+        assert_eq!(png.insert_after(&idat, chunk).unwrap(), 4);
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "ruSt", "IDAT", "IDAT", "ruSt", "IEND"]);
+
+        let missing = ChunkType::from_str("PLTE").unwrap();
+        let chunk = chunk_from_strings("ruSt", "x").unwrap();
+        assert!(png.insert_before(&missing, chunk).is_err());
+    }
+
+    #[test]
+    fn test_retain_and_remove_where() {
+        let mut png = testing_png();
+        for chunk in png.chunks_mut() {
+            if chunk.chunk_type().to_string() == "FrSt" {
+                chunk.set_data(b"changed".to_vec()).unwrap();
+            }
+        }
+        assert_eq!(
+            png.chunk_by_type(ChunkType::from_str("FrSt").unwrap())
+                .unwrap()
+                .data(),
+            b"changed"
+        );
+
+        let removed = png.remove_where(|chunk| !chunk.chunk_type().is_critical());
+        assert_eq!(removed.len(), 1);
+        assert!(removed
+            .iter()
+            .all(|chunk| !chunk.chunk_type().is_critical()));
+        assert!(png
+            .chunks()
+            .iter()
+            .all(|chunk| chunk.chunk_type().is_critical()));
+
+        let count = png.chunks().len();
+        png.retain(|chunk| chunk.chunk_type().to_string() != "LASt");
+        assert_eq!(png.chunks().len(), count - 1);
+    }
+
+    #[test]
+    fn test_chunks_by_type_index() {
+        let mut png = testing_png();
+        let middle = ChunkType::from_str("miDl").unwrap();
+        assert_eq!(png.chunk_indices(middle), [1]);
+
+        png.append_chunk(chunk_from_strings("miDl", "appended").unwrap());
+        png.insert_chunk(
+            chunk_from_strings("miDl", "inserted").unwrap(),
+            &InsertPosition::Index(0),
+        )
+        .unwrap();
+        assert_eq!(png.chunk_indices(middle), [0, 2, 4]);
+        assert_eq!(png.chunk_indices(ChunkType::from_str("LASt").unwrap()), [3]);
+        let data: Vec<&[u8]> = png.chunks_by_type(middle).map(Chunk::data).collect();
+        assert_eq!(data, [&b"inserted"[..], b"I am another chunk", b"appended"]);
+
+        png.remove_first_chunk(middle).unwrap();
+        assert_eq!(png.chunk_indices(middle), [1, 3]);
+        assert_eq!(png.chunk_indices(ChunkType::from_str("FrSt").unwrap()), [0]);
+
+        png.chunks_mut()[0].set_type(middle);
+        assert_eq!(png.chunk_indices(middle), [0, 1, 3]);
+        assert!(png
+            .chunk_indices(ChunkType::from_str("FrSt").unwrap())
+            .is_empty());
+        assert_eq!(png.chunks_by_type(ChunkType::IDAT).count(), 0);
+    }
+
+    #[test]
+    fn test_remove_all_chunks_and_at() {
+        let mut png = testing_png();
+        let middle = ChunkType::from_str("miDl").unwrap();
+        png.append_chunk(chunk_from_strings("miDl", "appended").unwrap());
+
+        let removed = png.remove_all_chunks(middle);
+        let data: Vec<&[u8]> = removed.iter().map(Chunk::data).collect();
+        assert_eq!(data, [&b"I am another chunk"[..], b"appended"]);
+        assert_eq!(png.chunks().len(), 2);
+        assert!(png.remove_all_chunks(middle).is_empty());
+
+        let removed = png.remove_chunk_at(1).unwrap();
+        assert_eq!(removed.chunk_type().to_string(), "LASt");
+        assert!(png
+            .chunk_by_type(ChunkType::from_str("LASt").unwrap())
+            .is_none());
+        assert!(matches!(
+            png.remove_chunk_at(1),
+            Err(PngError::IndexOutOfRange(1, 1))
+        ));
+    }
+
+    #[test]
+    fn test_chunk_refs() {
+        let refs = Png::chunk_refs(&PNG_FILE, ParseOptions::default()).unwrap();
+        let types: Vec<String> = refs
+            .map(|chunk| chunk.unwrap().chunk_type().to_string())
+            .collect();
+        let png = Png::from_bytes(&PNG_FILE[..]).unwrap();
+        let expected: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, expected);
+        assert!(Png::chunk_refs(&PNG_FILE[1..], ParseOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_ihdr() {
+        let png = Png::from_bytes(&PNG_FILE[..]).unwrap();
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!(ihdr.to_bytes().to_vec(), png.chunks()[0].data());
+        assert!(testing_png().ihdr().is_err());
+    }
+
+    #[test]
+    fn test_ancillary_accessors() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "").unwrap(),
+            chunk_from_strings("PLTE", "").unwrap(),
+            chunk_from_strings("IDAT", "").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        assert_eq!(png.gamma().unwrap(), None);
+
+        png.set_physical_dimensions(PhysicalDimensions::from_dpi(300.0));
+        png.set_gamma(Gamma(45455));
+        png.set_rendering_intent(RenderingIntent::Perceptual);
+        png.set_chromaticities(Chromaticities::SRGB);
+        png.set_gamma(Gamma(100000));
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            types,
+            ["IHDR", "gAMA", "sRGB", "cHRM", "PLTE", "pHYs", "IDAT", "IEND"]
+        );
+        assert_eq!(png.gamma().unwrap(), Some(Gamma(100000)));
+        assert_eq!(
+            png.rendering_intent().unwrap(),
+            Some(RenderingIntent::Perceptual)
+        );
+        assert_eq!(png.chromaticities().unwrap(), Some(Chromaticities::SRGB));
+        assert_eq!(
+            png.physical_dimensions().unwrap(),
+            Some(PhysicalDimensions::from_dpi(300.0))
+        );
+
+        png.replace_chunk(&"gAMA".parse().unwrap(), vec![1])
+            .unwrap();
+        assert!(png.gamma().is_err());
+    }
+
+    #[test]
+    fn test_palette() {
+        let header = Ihdr::new(4, 4, 2, ColorType::Indexed).unwrap();
+        let mut png = Png::from_chunks(vec![
+            header.to_chunk(),
+            chunk_from_strings("gAMA", "").unwrap(),
+            chunk_from_strings("IDAT", "").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        assert_eq!(png.palette().unwrap(), None);
+
+        let mut palette = Palette::new(vec![Rgb::new(0, 0, 0), Rgb::new(255, 255, 255)]).unwrap();
+        palette.set_alpha(1, 0).unwrap();
+        png.set_palette(&palette).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "IEND"]);
+        assert_eq!(png.palette().unwrap(), Some(palette.clone()));
+
+        palette.set_alpha(1, 255).unwrap();
+        palette.colors_mut()[0] = Rgb::new(9, 9, 9);
+        png.set_palette(&palette).unwrap();
+        assert_eq!(png.chunks().len(), 5);
+        assert_eq!(png.chunks()[2].data(), [9, 9, 9, 255, 255, 255]);
+
+        for _ in 0..3 {
+            palette.push(Rgb::default(), 255).unwrap();
+        }
+        assert!(matches!(
+            png.set_palette(&palette),
+            Err(PngError::InvalidPalette(PaletteError::TooManyColors(5, 4)))
+        ));
+    }
+
+    #[test]
+    fn test_replace_chunk() {
+        let mut png = Png::from_bytes(&PNG_FILE[..]).unwrap();
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let index = png
+            .chunks()
+            .iter()
+            .position(|chunk| chunk.chunk_type() == &chunk_type)
+            .unwrap();
+
+        let old = png
+            .replace_chunk(&chunk_type, b"replaced".to_vec())
+            .unwrap();
+        assert_eq!(old.data(), b"hey");
+        let chunk = &png.chunks()[index];
+        assert_eq!(chunk.data(), b"replaced");
+        assert_eq!(chunk.length(), 8);
+        assert!(chunk.has_valid_crc());
+
+        assert!(png
+            .replace_chunk(&ChunkType::from_str("ruSt").unwrap(), Vec::new())
+            .is_err());
+        assert!(matches!(
+            png.replace_chunk_at(100, Vec::new()),
+            Err(PngError::IndexOutOfRange(100, _))
+        ));
+    }
+
+    #[test]
+    fn test_insert_before_missing_iend() {
+        let mut png = testing_png();
+        let chunk = chunk_from_strings("ruSt", "secret").unwrap();
+        let index = png
+            .insert_chunk(chunk, &InsertPosition::BeforeIend)
+            .unwrap();
+        assert_eq!(index, 3);
+        assert!(png
+            .insert_chunk(
+                chunk_from_strings("ruSt", "x").unwrap(),
+                &InsertPosition::AfterIhdr
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_insert_position_from_str() {
+        assert_eq!(
+            InsertPosition::from_str("before-iend").unwrap(),
+            InsertPosition::BeforeIend
+        );
+        assert_eq!(
+            InsertPosition::from_str("after-ihdr").unwrap(),
+            InsertPosition::AfterIhdr
+        );
+        assert_eq!(
+            InsertPosition::from_str("after:tEXt").unwrap(),
+            InsertPosition::AfterType("tEXt".parse().unwrap())
+        );
+        assert_eq!(
+            InsertPosition::from_str("3").unwrap(),
+            InsertPosition::Index(3)
+        );
+        assert_eq!(
+            InsertPosition::from_str("before:IDAT").unwrap(),
+            InsertPosition::BeforeType("IDAT".parse().unwrap())
+        );
+        assert!(InsertPosition::from_str("after:t3Xt").is_err());
+        assert!(InsertPosition::from_str("middle").is_err());
+    }
+
     #[test]
     fn test_remove_first_chunk() {
         let mut png = testing_png();
@@ -245,6 +1028,67 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_remove_chunk() {
+        let mut png = testing_png();
+        let chunk = png.remove_chunk("miDl").unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "I am another chunk");
+        assert_eq!(png.chunks().len(), 2);
+        assert!(png.remove_chunk("miDl").is_err());
+        assert!(png.remove_chunk("mi1l").is_err());
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let png = Png::from_bytes(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.header(), &Png::STANDARD_HEADER);
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_from_bytes_with_lenient_crc() {
+        let mut bytes = PNG_FILE.to_vec();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        assert!(Png::from_bytes(&bytes).is_err());
+
+        let png = Png::from_bytes_with(&bytes, &ParseOptions::lenient()).unwrap();
+        let iend = png.chunks().last().unwrap();
+        assert!(!iend.has_valid_crc());
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_fix_crcs() {
+        let mut bytes = PNG_FILE.to_vec();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        let mut png = Png::from_bytes_with(&bytes, &ParseOptions::lenient()).unwrap();
+
+        let fixes = png.fix_crcs(|chunk| chunk.chunk_type().to_string() != "IEND");
+        assert!(fixes.is_empty());
+
+        let fixes = png.fix_crcs(|_| true);
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(fixes[0].index, png.chunks().len() - 1);
+        assert_eq!(fixes[0].chunk_type.to_string(), "IEND");
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_reader() {
+        let chunks: Vec<Chunk> = Png::reader(&PNG_FILE[..])
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(chunks.len(), png.chunks().len());
+        assert_eq!(chunks.last().unwrap().chunk_type().to_string(), "IEND");
+
+        assert!(Png::reader(&PNG_FILE[1..]).is_err());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -259,6 +1103,14 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_write_to() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut written = Vec::new();
+        png.write_to(&mut written).unwrap();
+        assert_eq!(written, PNG_FILE.to_vec());
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -0,0 +1,172 @@
+//! A progress line on stderr for work that takes a while: running on many
+//! images, or hiding a payload in the pixels of a large one. It is only
+//! drawn when both stdout and stderr are terminals, and only one at a time.
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Whether a progress line is being drawn already.
+static DRAWING: AtomicBool = AtomicBool::new(false);
+
+/// The least time between redrawing the line.
+const REDRAW: Duration = Duration::from_millis(100);
+
+/// The widest the line gets, message and all.
+const WIDTH: usize = 79;
+
+const BAR_WIDTH: usize = 20;
+
+pub struct Progress {
+    total: usize,
+    done: usize,
+    /// What is being worked on.
+    message: String,
+    /// Count images, whose rate tells how long is left, rather than steps.
+    estimate: bool,
+    started: Instant,
+    drawn: Option<Instant>,
+    /// Whether this is the line being drawn.
+    shown: bool,
+}
+
+impl Progress {
+    /// Progress through `total` images, with an estimate of the time left.
+    pub fn new(total: usize, quiet: bool) -> Self {
+        Self::start(total, true, quiet)
+    }
+
+    /// Progress through `total` steps of one job, as different in length as
+    /// the steps of hiding a payload in the pixels.
+    pub fn steps(total: usize, quiet: bool) -> Self {
+        Self::start(total, false, quiet)
+    }
+
+    fn start(total: usize, estimate: bool, quiet: bool) -> Self {
+        let shown = !quiet
+            && io::stdout().is_terminal()
+            && io::stderr().is_terminal()
+            && !DRAWING.swap(true, Ordering::Relaxed);
+        Self {
+            total,
+            done: 0,
+            message: String::new(),
+            estimate,
+            started: Instant::now(),
+            drawn: None,
+            shown,
+        }
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.shown
+    }
+
+    /// Starts on the step `message` describes.
+    pub fn step(&mut self, message: &str) {
+        self.message = message.to_string();
+        self.draw(true);
+    }
+
+    /// Counts one more done, `message` naming it.
+    pub fn advance(&mut self, message: &str) {
+        self.done += 1;
+        self.message = message.to_string();
+        self.draw(false);
+    }
+
+    /// Takes the line away while `f` prints, then draws it again.
+    pub fn suspend<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        self.clear();
+        let result = f();
+        self.draw(true);
+        result
+    }
+
+    fn draw(&mut self, now: bool) {
+        if !self.shown || !now && self.drawn.is_some_and(|drawn| drawn.elapsed() < REDRAW) {
+            return;
+        }
+        self.drawn = Some(Instant::now());
+        let line = line(
+            self.done,
+            self.total,
+            self.started.elapsed(),
+            self.estimate,
+            &self.message,
+        );
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[K{line}");
+        let _ = stderr.flush();
+    }
+
+    fn clear(&self) {
+        if self.shown && self.drawn.is_some() {
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[K");
+            let _ = stderr.flush();
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.shown {
+            self.clear();
+            DRAWING.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The progress line: a bar, the count, the time taken and, when
+/// `estimate` says the rate so far is a fair guess, the time left.
+fn line(done: usize, total: usize, elapsed: Duration, estimate: bool, message: &str) -> String {
+    let filled = (done * BAR_WIDTH).checked_div(total).unwrap_or(0);
+    let mut line = format!(
+        "[{}{}] {done}/{total} {}",
+        "#".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled.min(BAR_WIDTH)),
+        minutes(elapsed),
+    );
+    if estimate && done > 0 && done < total {
+        let left = elapsed.mul_f64((total - done) as f64 / done as f64);
+        line.push_str(&format!(" ({} left)", minutes(left)));
+    }
+    if !message.is_empty() {
+        line.push(' ');
+        line.push_str(message);
+    }
+    match line.char_indices().nth(WIDTH) {
+        Some((end, _)) => line[..end].to_string(),
+        None => line,
+    }
+}
+
+/// `duration` as minutes and seconds.
+fn minutes(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line() {
+        assert_eq!(
+            line(5, 20, Duration::from_secs(30), true, "a.png"),
+            "[#####---------------] 5/20 0:30 (1:30 left) a.png"
+        );
+        assert_eq!(
+            line(1, 3, Duration::from_secs(2), false, "hiding the payload"),
+            "[######--------------] 1/3 0:02 hiding the payload"
+        );
+        assert_eq!(
+            line(0, 0, Duration::ZERO, true, ""),
+            "[--------------------] 0/0 0:00"
+        );
+        let long = line(1, 2, Duration::ZERO, true, &"x".repeat(100));
+        assert_eq!(long.chars().count(), WIDTH);
+    }
+}
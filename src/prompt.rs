@@ -0,0 +1,100 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+#[cfg(unix)]
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+/// Asks for a passphrase on the terminal without echoing it.
+///
+/// When stdin is not a terminal the passphrase is read from its first line instead,
+/// so it can be piped in by scripts.
+pub fn read_passphrase(confirm: bool) -> Result<String> {
+    let passphrase = if io::stdin().is_terminal() {
+        let passphrase = prompt_hidden("Passphrase: ")?;
+        if confirm && prompt_hidden("Confirm passphrase: ")? != passphrase {
+            bail!("passphrases do not match");
+        }
+        passphrase
+    } else {
+        read_line()?
+    };
+
+    if passphrase.is_empty() {
+        bail!("passphrase is empty");
+    }
+    Ok(passphrase)
+}
+
+/// Reads a passphrase from the first line of an inherited file descriptor,
+/// so it never appears in the process arguments or environment.
+pub fn read_passphrase_fd(fd: u32) -> Result<String> {
+    let file = File::open(format!("/dev/fd/{fd}"))
+        .with_context(|| format!("cannot read passphrase from file descriptor {fd}"))?;
+    let passphrase = first_line(BufReader::new(file))?;
+    if passphrase.is_empty() {
+        bail!("passphrase is empty");
+    }
+    Ok(passphrase)
+}
+
+fn prompt_hidden(prompt: &str) -> Result<String> {
+    eout!("{prompt}")?;
+    io::stderr().flush()?;
+
+    let echo = EchoGuard::disable();
+    let line = read_line();
+    drop(echo);
+    eoutln!()?;
+
+    line
+}
+
+fn read_line() -> Result<String> {
+    first_line(io::stdin().lock())
+}
+
+fn first_line<R: BufRead>(mut reader: R) -> Result<String> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let trimmed = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(trimmed);
+    Ok(line)
+}
+
+/// Turns terminal echo off for as long as it is alive.
+struct EchoGuard {
+    restore: bool,
+}
+
+impl EchoGuard {
+    fn disable() -> Self {
+        Self {
+            restore: stty("-echo"),
+        }
+    }
+}
+
+impl Drop for EchoGuard {
+    fn drop(&mut self) {
+        if self.restore {
+            stty("echo");
+        }
+    }
+}
+
+#[cfg(unix)]
+fn stty(setting: &str) -> bool {
+    Command::new("stty")
+        .arg(setting)
+        .stdin(Stdio::inherit())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// There is no portable way to hide input without platform bindings, so input is echoed.
+#[cfg(not(unix))]
+fn stty(_setting: &str) -> bool {
+    false
+}
@@ -0,0 +1,579 @@
+//! The decoded pixels of an image: IDAT data inflated and unfiltered into plain
+//! scanlines, and back.
+
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr, Interlace};
+use crate::palette::{Palette, PaletteError};
+use crate::png::{InsertPosition, Png, PngError};
+use crate::zlib::{self, ZlibError};
+
+#[derive(Error, Debug)]
+pub enum RasterError {
+    #[error("RasterError no IDAT chunk found")]
+    NoImageData,
+    #[error("RasterError image data is {0} bytes, expected {1}")]
+    InvalidLength(usize, u64),
+    #[error("RasterError unknown filter type {0} on row {1}")]
+    UnknownFilter(u8, u32),
+    #[error("RasterError invalid palette")]
+    InvalidPalette(#[from] PaletteError),
+    #[error("RasterError invalid image")]
+    InvalidPng(#[from] PngError),
+    #[error("RasterError invalid image data")]
+    Zlib(#[from] ZlibError),
+}
+
+/// The scanline filters of http://www.libpng.org/pub/png/spec/1.2/PNG-Filters.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4,
+}
+
+impl Filter {
+    fn from_byte(byte: u8) -> Option<Self> {
+        Some(match byte {
+            0 => Filter::None,
+            1 => Filter::Sub,
+            2 => Filter::Up,
+            3 => Filter::Average,
+            4 => Filter::Paeth,
+            _ => return None,
+        })
+    }
+
+    /// The value this filter predicts for a byte from the one `left` of it, the
+    /// one `up` above it and the one above that, `up_left`.
+    fn predict(self, left: u8, up: u8, up_left: u8) -> u8 {
+        match self {
+            Filter::None => 0,
+            Filter::Sub => left,
+            Filter::Up => up,
+            Filter::Average => ((u16::from(left) + u16::from(up)) / 2) as u8,
+            Filter::Paeth => {
+                let p = i16::from(left) + i16::from(up) - i16::from(up_left);
+                let (pa, pb, pc) = (
+                    (p - i16::from(left)).abs(),
+                    (p - i16::from(up)).abs(),
+                    (p - i16::from(up_left)).abs(),
+                );
+                if pa <= pb && pa <= pc {
+                    left
+                } else if pb <= pc {
+                    up
+                } else {
+                    up_left
+                }
+            }
+        }
+    }
+}
+
+/// The unfiltered scanlines of an image, with the filter each stored row had so
+/// that storing them again changes as little as possible. Interlaced images are
+/// put together from their passes, so the scanlines are always the whole image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Raster {
+    ihdr: Ihdr,
+    data: Vec<u8>,
+    filters: Vec<Filter>,
+    /// The compression level the image data advertised.
+    level: u8,
+}
+
+impl Raster {
+    /// The IDAT size libpng writes, so a tiny original doesn't turn into many chunks.
+    const MIN_IDAT_LEN: usize = 8192;
+
+    /// Decodes the image data of `png`.
+    pub fn from_png(png: &Png) -> Result<Self, RasterError> {
+        let ihdr = png.ihdr()?;
+        let compressed: Vec<u8> = png
+            .chunks_by_type(ChunkType::IDAT)
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect();
+        if compressed.is_empty() {
+            return Err(RasterError::NoImageData);
+        }
+        let passes = Pass::of(&ihdr);
+        let bits = ihdr.bits_per_pixel();
+        let expected: u64 = passes
+            .iter()
+            .map(|pass| (pass.row_len(bits) as u64 + 1) * u64::from(pass.height))
+            .sum();
+        let limit = usize::try_from(expected).unwrap_or(usize::MAX);
+        let filtered = zlib::decompress_with_limit(&compressed, limit)?;
+        if filtered.len() as u64 != expected {
+            return Err(RasterError::InvalidLength(filtered.len(), expected));
+        }
+
+        let bpp = Self::filter_distance(&ihdr);
+        let mut data = vec![0; ihdr.row_bytes() as usize * ihdr.height as usize];
+        let mut filters = Vec::with_capacity(ihdr.height as usize);
+        let mut rest = filtered.as_slice();
+        for pass in &passes {
+            let row_len = pass.row_len(bits);
+            let (lines, after) = rest.split_at((row_len + 1) * pass.height as usize);
+            rest = after;
+            let pixels = unfilter(lines, row_len, bpp, &mut filters)?;
+            match ihdr.interlace {
+                Interlace::None => data = pixels,
+                Interlace::Adam7 => pass.scatter(&ihdr, &pixels, &mut data),
+            }
+        }
+
+        Ok(Raster {
+            ihdr,
+            data,
+            filters,
+            level: zlib::header_level(&compressed).unwrap_or(zlib::DEFAULT_LEVEL),
+        })
+    }
+
+    pub fn ihdr(&self) -> &Ihdr {
+        &self.ihdr
+    }
+
+    /// The scanlines one after another, without filter type bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn row_len(&self) -> usize {
+        self.ihdr.row_bytes() as usize
+    }
+
+    /// Switches between plain scanlines and Adam7 passes for `store`. The old
+    /// filters don't fit the new rows, so every row gets the one the spec
+    /// suggests: none below 8 bits and for palette indices, Paeth otherwise.
+    pub fn set_interlace(&mut self, interlace: Interlace) {
+        self.ihdr.interlace = interlace;
+        let filter = if self.ihdr.bit_depth < 8 || self.ihdr.color_type == ColorType::Indexed {
+            Filter::None
+        } else {
+            Filter::Paeth
+        };
+        let rows = Pass::of(&self.ihdr)
+            .iter()
+            .map(|pass| pass.height as usize)
+            .sum();
+        self.filters = vec![filter; rows];
+    }
+
+    /// Replaces palette indices with the colors of `palette` as 8-bit RGB, or
+    /// RGBA if any entry is translucent. Other images are left as they are.
+    /// PLTE and the chunks that describe indices stay in the image for the
+    /// caller to drop, since `store` only writes IHDR and IDAT.
+    pub fn promote_to_truecolor(&mut self, palette: &Palette) -> Result<(), RasterError> {
+        if self.ihdr.color_type != ColorType::Indexed {
+            return Ok(());
+        }
+        let alpha = palette.has_transparency();
+        let (width, depth) = (self.ihdr.width as usize, self.ihdr.bit_depth as usize);
+        let mask = ((1u16 << depth) - 1) as u8;
+        let channels = if alpha { 4 } else { 3 };
+        let mut data = Vec::with_capacity(width * self.ihdr.height as usize * channels);
+        for row in self.data.chunks(self.row_len()) {
+            for bit in (0..width).map(|x| x * depth) {
+                let index = usize::from((row[bit / 8] >> (8 - depth - bit % 8)) & mask);
+                let color = palette
+                    .colors()
+                    .get(index)
+                    .ok_or(PaletteError::IndexOutOfRange(index, palette.len()))?;
+                data.extend([color.r, color.g, color.b]);
+                if alpha {
+                    data.extend(palette.alpha(index));
+                }
+            }
+        }
+
+        self.ihdr.color_type = if alpha {
+            ColorType::Rgba
+        } else {
+            ColorType::Rgb
+        };
+        self.ihdr.bit_depth = 8;
+        self.data = data;
+        self.set_interlace(self.ihdr.interlace);
+        Ok(())
+    }
+
+    /// Filters and compresses the scanlines, as passes if the image is
+    /// interlaced, into zlib data for IDAT.
+    pub fn to_image_data(&self) -> Vec<u8> {
+        let bits = self.ihdr.bits_per_pixel();
+        let bpp = Self::filter_distance(&self.ihdr);
+        let mut filtered = Vec::with_capacity(self.data.len() + self.filters.len());
+        let mut filters = self.filters.as_slice();
+        for pass in Pass::of(&self.ihdr) {
+            let (pass_filters, rest) = filters.split_at(pass.height as usize);
+            filters = rest;
+            let row_len = pass.row_len(bits);
+            match self.ihdr.interlace {
+                Interlace::None => filter(&self.data, row_len, bpp, pass_filters, &mut filtered),
+                Interlace::Adam7 => {
+                    let pixels = pass.gather(&self.ihdr, &self.data);
+                    filter(&pixels, row_len, bpp, pass_filters, &mut filtered);
+                }
+            }
+        }
+        zlib::compress(&filtered, self.level)
+    }
+
+    /// Replaces the IDAT chunks of `png` with the scanlines, where the first one
+    /// was and cut to the size of the largest one before, and IHDR with the
+    /// header, in case the interlacing changed.
+    pub fn store(&self, png: &mut Png) -> Result<(), RasterError> {
+        let first = *png
+            .chunk_indices(ChunkType::IDAT)
+            .first()
+            .ok_or(RasterError::NoImageData)?;
+        png.replace_chunk(&ChunkType::IHDR, self.ihdr.to_bytes().to_vec())?;
+        let removed = png.remove_all_chunks(ChunkType::IDAT);
+        let segment_len = removed
+            .iter()
+            .map(|chunk| chunk.data().len())
+            .max()
+            .unwrap_or_default()
+            .max(Self::MIN_IDAT_LEN);
+
+        let data = self.to_image_data();
+        for (index, segment) in (first..).zip(data.chunks(segment_len)) {
+            png.insert_chunk(
+                Chunk::new(ChunkType::IDAT, segment.to_vec()),
+                &InsertPosition::Index(index),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// How many bytes back the byte a filter calls `left` is: one pixel, or one
+    /// byte for depths below 8.
+    fn filter_distance(ihdr: &Ihdr) -> usize {
+        (ihdr.bits_per_pixel() as usize).div_ceil(8)
+    }
+}
+
+/// The Adam7 passes as the column and row each starts at and the distances
+/// between the pixels it takes.
+const ADAM7: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// A sub-image the image data is stored as: every `dx`th pixel of every `dy`th
+/// row starting from `x0`, `y0`. That is the whole image without interlacing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pass {
+    x0: u32,
+    y0: u32,
+    dx: u32,
+    dy: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Pass {
+    /// The passes of `ihdr`'s image in storage order, leaving out empty ones,
+    /// which have no rows in the image data at all.
+    fn of(ihdr: &Ihdr) -> Vec<Pass> {
+        let pass = |(x0, y0, dx, dy): (u32, u32, u32, u32)| Pass {
+            x0,
+            y0,
+            dx,
+            dy,
+            width: ihdr.width.saturating_sub(x0).div_ceil(dx),
+            height: ihdr.height.saturating_sub(y0).div_ceil(dy),
+        };
+        match ihdr.interlace {
+            Interlace::None => vec![pass((0, 0, 1, 1))],
+            Interlace::Adam7 => ADAM7
+                .into_iter()
+                .map(pass)
+                .filter(|pass| pass.width > 0 && pass.height > 0)
+                .collect(),
+        }
+    }
+
+    fn row_len(&self, bits_per_pixel: u32) -> usize {
+        (u64::from(self.width) * u64::from(bits_per_pixel)).div_ceil(8) as usize
+    }
+
+    /// Bit offsets of each pixel of the pass in the pass's own rows and in the
+    /// whole image.
+    fn pixel_offsets(&self, ihdr: &Ihdr) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let bits = ihdr.bits_per_pixel() as usize;
+        let (row_len, image_row_len) = (
+            self.row_len(ihdr.bits_per_pixel()),
+            ihdr.row_bytes() as usize,
+        );
+        (0..self.height as usize).flat_map(move |y| {
+            let image_y = self.y0 as usize + y * self.dy as usize;
+            (0..self.width as usize).map(move |x| {
+                let image_x = self.x0 as usize + x * self.dx as usize;
+                (
+                    y * row_len * 8 + x * bits,
+                    image_y * image_row_len * 8 + image_x * bits,
+                )
+            })
+        })
+    }
+
+    /// Copies the pixels of the pass into their places in `image`.
+    fn scatter(&self, ihdr: &Ihdr, pixels: &[u8], image: &mut [u8]) {
+        let bits = ihdr.bits_per_pixel() as usize;
+        for (from, to) in self.pixel_offsets(ihdr) {
+            copy_pixel(pixels, from, image, to, bits);
+        }
+    }
+
+    /// The rows of the pass, taken out of `image`.
+    fn gather(&self, ihdr: &Ihdr, image: &[u8]) -> Vec<u8> {
+        let bits = ihdr.bits_per_pixel() as usize;
+        let mut pixels = vec![0; self.row_len(ihdr.bits_per_pixel()) * self.height as usize];
+        for (to, from) in self.pixel_offsets(ihdr) {
+            copy_pixel(image, from, &mut pixels, to, bits);
+        }
+        pixels
+    }
+}
+
+/// Copies the pixel of `bits` bits at bit offset `from` in `src` to bit offset
+/// `to` in `dst`. Pixels below 8 bits never straddle a byte.
+fn copy_pixel(src: &[u8], from: usize, dst: &mut [u8], to: usize, bits: usize) {
+    if bits.is_multiple_of(8) {
+        let len = bits / 8;
+        dst[to / 8..to / 8 + len].copy_from_slice(&src[from / 8..from / 8 + len]);
+        return;
+    }
+    let mask = ((1u16 << bits) - 1) as u8;
+    let value = (src[from / 8] >> (8 - bits - from % 8)) & mask;
+    let shift = 8 - bits - to % 8;
+    dst[to / 8] = dst[to / 8] & !(mask << shift) | value << shift;
+}
+
+/// Undoes the filters of `lines`, rows of `row_len` bytes each behind its
+/// filter type byte, adding the filters to `filters`.
+fn unfilter(
+    lines: &[u8],
+    row_len: usize,
+    bpp: usize,
+    filters: &mut Vec<Filter>,
+) -> Result<Vec<u8>, RasterError> {
+    let mut data = vec![0; lines.len() / (row_len + 1) * row_len];
+    for (y, line) in lines.chunks(row_len + 1).enumerate() {
+        let filter = Filter::from_byte(line[0])
+            .ok_or(RasterError::UnknownFilter(line[0], filters.len() as u32))?;
+        filters.push(filter);
+        let (done, rest) = data.split_at_mut(y * row_len);
+        let previous = (y > 0).then(|| &done[(y - 1) * row_len..]);
+        let row = &mut rest[..row_len];
+        for x in 0..row_len {
+            let prediction = predict(filter, row, previous, x, bpp);
+            row[x] = line[1 + x].wrapping_add(prediction);
+        }
+    }
+    Ok(data)
+}
+
+/// Filters `data`, rows of `row_len` bytes, with one of `filters` per row onto
+/// the end of `filtered`.
+fn filter(data: &[u8], row_len: usize, bpp: usize, filters: &[Filter], filtered: &mut Vec<u8>) {
+    for (y, &filter) in filters.iter().enumerate() {
+        let row = &data[y * row_len..(y + 1) * row_len];
+        let previous = (y > 0).then(|| &data[(y - 1) * row_len..y * row_len]);
+        filtered.push(filter as u8);
+        for x in 0..row_len {
+            let prediction = predict(filter, row, previous, x, bpp);
+            filtered.push(row[x].wrapping_sub(prediction));
+        }
+    }
+}
+
+/// What `filter` predicts for byte `x` of `row`, from the unfiltered bytes before
+/// it and the `previous` row, if there is one.
+fn predict(filter: Filter, row: &[u8], previous: Option<&[u8]>, x: usize, bpp: usize) -> u8 {
+    let left = if x >= bpp { row[x - bpp] } else { 0 };
+    let (up, up_left) = match previous {
+        Some(previous) if x >= bpp => (previous[x], previous[x - bpp]),
+        Some(previous) => (previous[x], 0),
+        None => (0, 0),
+    };
+    filter.predict(left, up, up_left)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{Fill, PngBuilder};
+    use crate::ihdr::ColorType;
+
+    #[test]
+    fn test_round_trip_filters() {
+        let png = PngBuilder::new(17, 9)
+            .with_color_type(ColorType::Rgba)
+            .with_fill(Fill::Noise(3))
+            .build()
+            .unwrap();
+        let mut raster = Raster::from_png(&png).unwrap();
+        assert_eq!(raster.data().len(), 17 * 4 * 9);
+        let pixels = raster.data().to_vec();
+
+        // Each filter has to undo itself exactly.
+        for (y, filter) in raster.filters.iter_mut().enumerate() {
+            *filter = Filter::from_byte(y as u8 % 5).unwrap();
+        }
+        let mut stored = PngBuilder::new(17, 9)
+            .with_color_type(ColorType::Rgba)
+            .build()
+            .unwrap();
+        raster.store(&mut stored).unwrap();
+        let decoded = Raster::from_png(&stored).unwrap();
+        assert_eq!(decoded.data(), pixels);
+        assert_eq!(decoded.filters, raster.filters);
+    }
+
+    #[test]
+    fn test_interlace() {
+        for (color_type, bit_depth, width, height) in [
+            (ColorType::Rgb, 8, 13, 11),
+            (ColorType::Rgba, 16, 9, 3),
+            (ColorType::Grayscale, 2, 11, 7),
+            (ColorType::Indexed, 4, 1, 1),
+            (ColorType::Grayscale, 1, 5, 9),
+        ] {
+            let mut png = PngBuilder::new(width, height)
+                .with_color_type(color_type)
+                .with_bit_depth(bit_depth)
+                .with_fill(Fill::Noise(5))
+                .build()
+                .unwrap();
+            let mut raster = Raster::from_png(&png).unwrap();
+            let pixels = raster.data().to_vec();
+
+            raster.set_interlace(Interlace::Adam7);
+            raster.store(&mut png).unwrap();
+            assert_eq!(png.ihdr().unwrap().interlace, Interlace::Adam7);
+            let expected: u64 = Pass::of(png.ihdr().as_ref().unwrap())
+                .iter()
+                .map(|pass| {
+                    (pass.row_len(raster.ihdr().bits_per_pixel()) as u64 + 1)
+                        * u64::from(pass.height)
+                })
+                .sum();
+            let stored =
+                zlib::decompress(png.chunk_by_type(ChunkType::IDAT).unwrap().data()).unwrap();
+            assert_eq!(stored.len() as u64, expected);
+
+            let mut interlaced = Raster::from_png(&png).unwrap();
+            assert_eq!(interlaced.data(), pixels, "{color_type} at {bit_depth}");
+
+            interlaced.set_interlace(Interlace::None);
+            interlaced.store(&mut png).unwrap();
+            assert_eq!(Raster::from_png(&png).unwrap().data(), pixels);
+        }
+    }
+
+    #[test]
+    fn test_promote_to_truecolor() {
+        for (bit_depth, interlace) in [(2, Interlace::None), (8, Interlace::Adam7)] {
+            let mut png = PngBuilder::new(7, 5)
+                .with_color_type(ColorType::Indexed)
+                .with_bit_depth(bit_depth)
+                .with_fill(Fill::Noise(9))
+                .build()
+                .unwrap();
+            let mut raster = Raster::from_png(&png).unwrap();
+            raster.set_interlace(interlace);
+            let mut palette = png.palette().unwrap().unwrap();
+            let indices: Vec<usize> = raster
+                .data()
+                .chunks(raster.row_len())
+                .flat_map(|row| {
+                    let depth = usize::from(bit_depth);
+                    (0..7).map(move |x| {
+                        let bit = x * depth;
+                        usize::from(row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1)
+                    })
+                })
+                .collect();
+
+            let mut rgb = raster.clone();
+            rgb.promote_to_truecolor(&palette).unwrap();
+            assert_eq!(rgb.ihdr().color_type, ColorType::Rgb);
+            assert_eq!(rgb.ihdr().interlace, interlace);
+            let colors: Vec<u8> = indices
+                .iter()
+                .flat_map(|&index| {
+                    let color = palette.colors()[index];
+                    [color.r, color.g, color.b]
+                })
+                .collect();
+            assert_eq!(rgb.data(), colors);
+            rgb.store(&mut png).unwrap();
+            assert_eq!(Raster::from_png(&png).unwrap().data(), colors);
+
+            palette.set_alpha(indices[0], 10).unwrap();
+            let mut rgba = raster.clone();
+            rgba.promote_to_truecolor(&palette).unwrap();
+            assert_eq!(rgba.ihdr().color_type, ColorType::Rgba);
+            assert_eq!(rgba.data()[..4], [colors[0], colors[1], colors[2], 10]);
+            assert_eq!(rgba.data().len(), 7 * 5 * 4);
+        }
+
+        let png = PngBuilder::new(2, 2)
+            .with_color_type(ColorType::Indexed)
+            .build()
+            .unwrap();
+        let mut raster = Raster::from_png(&png).unwrap();
+        raster.data_mut().fill(3);
+        let palette = png.palette().unwrap().unwrap();
+        assert!(matches!(
+            raster.promote_to_truecolor(&palette),
+            Err(RasterError::InvalidPalette(PaletteError::IndexOutOfRange(
+                3, 1
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.remove_all_chunks(ChunkType::IDAT);
+        assert!(matches!(
+            Raster::from_png(&png),
+            Err(RasterError::NoImageData)
+        ));
+
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.replace_chunk(&ChunkType::IDAT, zlib::compress(&[1, 2, 3], 6))
+            .unwrap();
+        assert!(matches!(
+            Raster::from_png(&png),
+            Err(RasterError::InvalidLength(3, 52))
+        ));
+
+        let mut png = PngBuilder::new(1, 1).build().unwrap();
+        png.replace_chunk(&ChunkType::IDAT, zlib::compress(&[7, 0, 0, 0], 6))
+            .unwrap();
+        assert!(matches!(
+            Raster::from_png(&png),
+            Err(RasterError::UnknownFilter(7, 0))
+        ));
+    }
+}
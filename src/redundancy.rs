@@ -0,0 +1,304 @@
+//! Several copies of one payload, stored apart so losing or damaging one
+//! doesn't lose the payload: reading them back takes a majority vote on every
+//! byte.
+
+/// Marks a payload as one of several copies.
+pub const COPY_MAGIC: [u8; 4] = [0x89, b'p', b's', b'c'];
+
+/// Magic, u8 index and count, then the big-endian u32 length of the copy.
+pub const COPY_HEADER_LEN: usize = 10;
+
+/// The most copies `copies` makes.
+pub const MAX_COPIES: u8 = 16;
+
+/// One copy as stored: which of how many, and its bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadCopy<'a> {
+    pub index: u8,
+    pub count: u8,
+    /// Shorter than the header says if the copy was cut off.
+    pub data: &'a [u8],
+}
+
+impl<'a> PayloadCopy<'a> {
+    /// Parses a copy `copies` made, or `None` for anything else.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        let (index, count, len) = header(bytes)?;
+        let data = &bytes[COPY_HEADER_LEN..];
+        Some(Self {
+            index,
+            count,
+            data: &data[..len.min(data.len())],
+        })
+    }
+}
+
+/// The index, count and length in a copy header.
+fn header(bytes: &[u8]) -> Option<(u8, u8, usize)> {
+    if bytes.len() < COPY_HEADER_LEN || bytes[..4] != COPY_MAGIC {
+        return None;
+    }
+    let (index, count) = (bytes[4], bytes[5]);
+    if count == 0 || index >= count {
+        return None;
+    }
+    let len = u32::from_be_bytes(bytes[6..10].try_into().unwrap()) as usize;
+    Some((index, count, len))
+}
+
+/// `count` copies of `payload`, each with a header naming it.
+pub fn copies(payload: &[u8], count: u8) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|index| {
+            let mut copy = Vec::with_capacity(COPY_HEADER_LEN + payload.len());
+            copy.extend_from_slice(&COPY_MAGIC);
+            copy.push(index);
+            copy.push(count);
+            copy.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            copy.extend_from_slice(payload);
+            copy
+        })
+        .collect()
+}
+
+/// Splits the copies `copies` made and stored one after another, as in the
+/// low bits of the pixels. The copies are all as long, so any one intact
+/// header tells where each starts, as do damaged ones outvoted.
+pub fn split_joined(bytes: &[u8]) -> Vec<PayloadCopy<'_>> {
+    for count in 1..=MAX_COPIES {
+        let stride = bytes.len() / usize::from(count);
+        if stride <= COPY_HEADER_LEN || stride * usize::from(count) != bytes.len() {
+            continue;
+        }
+        let expected = |index: usize| Some((index as u8, count, stride - COPY_HEADER_LEN));
+        let intact = bytes
+            .chunks(stride)
+            .enumerate()
+            .any(|(index, copy)| header(copy) == expected(index));
+        let headers: Vec<Vec<u8>> = bytes
+            .chunks(stride)
+            .enumerate()
+            .map(|(index, copy)| {
+                // Headers differ only in the index.
+                let mut header = copy[..COPY_HEADER_LEN].to_vec();
+                header[4] ^= index as u8;
+                header
+            })
+            .collect();
+        if intact || header(&vote(&headers).0) == expected(0) {
+            return bytes
+                .chunks(stride)
+                .enumerate()
+                .map(|(index, copy)| PayloadCopy {
+                    index: index as u8,
+                    count,
+                    data: &copy[COPY_HEADER_LEN..],
+                })
+                .collect();
+        }
+    }
+    Vec::new()
+}
+
+/// A payload as found in an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stored {
+    /// A payload stored once.
+    Single(Vec<u8>),
+    /// The copies found of a payload stored `count` times.
+    Copies { count: u8, copies: Vec<Vec<u8>> },
+}
+
+/// What the vote over a payload's copies found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vote {
+    /// The number of copies stored.
+    pub count: u8,
+    pub found: usize,
+    /// Bytes on which the copies disagreed.
+    pub disputed: usize,
+    /// Disputed bytes no value won a majority of, taken from the first copy
+    /// that has them.
+    pub ties: usize,
+}
+
+impl Stored {
+    /// The copies `split_joined` finds in `bytes`, or the bytes as a single
+    /// payload if they are no copies.
+    pub fn from_joined(bytes: Vec<u8>) -> Self {
+        let copies = split_joined(&bytes);
+        match copies.first() {
+            Some(first) => Stored::Copies {
+                count: first.count,
+                copies: copies.iter().map(|copy| copy.data.to_vec()).collect(),
+            },
+            None => Stored::Single(bytes),
+        }
+    }
+
+    /// The payload, with the vote that settled it for copies.
+    pub fn resolve(self) -> (Vec<u8>, Option<Vote>) {
+        match self {
+            Stored::Single(payload) => (payload, None),
+            Stored::Copies { count, copies } => {
+                let (payload, disputed, ties) = vote(&copies);
+                let vote = Vote {
+                    count,
+                    found: copies.len(),
+                    disputed,
+                    ties,
+                };
+                (payload, Some(vote))
+            }
+        }
+    }
+}
+
+/// Gathers the copies among `payloads`, in the order they were found, into
+/// one `Stored::Copies` where the first of them was.
+pub fn gather(payloads: Vec<Vec<u8>>) -> Vec<Stored> {
+    let mut stored = Vec::new();
+    // The indices each set of copies has so far, by where it is in `stored`.
+    let mut sets: Vec<(usize, Vec<u8>)> = Vec::new();
+    for payload in payloads {
+        let Some(copy) = PayloadCopy::parse(&payload) else {
+            stored.push(Stored::Single(payload));
+            continue;
+        };
+        // A copy joins the first set of the same count still missing its index.
+        let set = sets.iter_mut().find(|(position, indices)| {
+            matches!(stored[*position], Stored::Copies { count, .. } if count == copy.count)
+                && !indices.contains(&copy.index)
+        });
+        match set {
+            Some((position, indices)) => {
+                indices.push(copy.index);
+                if let Stored::Copies { copies, .. } = &mut stored[*position] {
+                    copies.push(copy.data.to_vec());
+                }
+            }
+            None => {
+                sets.push((stored.len(), vec![copy.index]));
+                stored.push(Stored::Copies {
+                    count: copy.count,
+                    copies: vec![copy.data.to_vec()],
+                });
+            }
+        }
+    }
+    stored
+}
+
+/// The value most of `copies` hold at every byte, with the number of bytes
+/// they disagreed on and of those that were ties.
+pub fn vote(copies: &[Vec<u8>]) -> (Vec<u8>, usize, usize) {
+    let mut lengths: Vec<usize> = copies.iter().map(Vec::len).collect();
+    lengths.sort_unstable();
+    // The length of the copies sorted in the middle, so one cut short or
+    // damaged doesn't decide it.
+    let len = lengths.get(lengths.len() / 2).copied().unwrap_or(0);
+
+    let (mut disputed, mut ties) = (0, 0);
+    let payload = (0..len)
+        .map(|i| {
+            let values: Vec<u8> = copies
+                .iter()
+                .filter_map(|copy| copy.get(i).copied())
+                .collect();
+            if values.iter().all(|&value| value == values[0]) {
+                return values[0];
+            }
+            disputed += 1;
+            let mut votes = [0usize; 256];
+            for &value in &values {
+                votes[usize::from(value)] += 1;
+            }
+            let best = votes.iter().copied().max().unwrap_or(0);
+            if votes.iter().filter(|&&count| count == best).count() > 1 {
+                ties += 1;
+            }
+            values
+                .into_iter()
+                .find(|&value| votes[usize::from(value)] == best)
+                .unwrap()
+        })
+        .collect();
+    (payload, disputed, ties)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copies() {
+        let copies = copies(b"payload", 3);
+        assert_eq!(copies.len(), 3);
+        let copy = PayloadCopy::parse(&copies[2]).unwrap();
+        assert_eq!((copy.index, copy.count, copy.data), (2, 3, &b"payload"[..]));
+        assert_eq!(PayloadCopy::parse(b"payload"), None);
+
+        let mut joined = copies.concat();
+        joined[0] ^= 0xff;
+        joined[COPY_HEADER_LEN + 7 + 2] ^= 0xff;
+        let split = split_joined(&joined);
+        assert_eq!(split.len(), 3);
+        assert_eq!((split[1].index, split[1].count), (1, 3));
+        assert_eq!(split[2].data, b"payload");
+        assert_eq!(split_joined(&joined[..joined.len() - 2]), []);
+    }
+
+    #[test]
+    fn test_vote() {
+        let copies = |copies: &[&[u8]]| copies.iter().map(|copy| copy.to_vec()).collect::<Vec<_>>();
+        assert_eq!(
+            vote(&copies(&[b"hello", b"jello", b"help"])),
+            (b"hello".to_vec(), 2, 0)
+        );
+        assert_eq!(vote(&copies(&[b"ab", b"ac"])), (b"ab".to_vec(), 1, 1));
+        assert_eq!(vote(&copies(&[b"same"])), (b"same".to_vec(), 0, 0));
+    }
+
+    #[test]
+    fn test_gather() {
+        let first = copies(b"one", 3);
+        let second = copies(b"two", 2);
+        let payloads = vec![
+            first[0].clone(),
+            b"plain".to_vec(),
+            second[0].clone(),
+            first[2].clone(),
+            second[1].clone(),
+        ];
+        let stored = gather(payloads);
+        assert_eq!(
+            stored,
+            [
+                Stored::Copies {
+                    count: 3,
+                    copies: vec![b"one".to_vec(), b"one".to_vec()]
+                },
+                Stored::Single(b"plain".to_vec()),
+                Stored::Copies {
+                    count: 2,
+                    copies: vec![b"two".to_vec(), b"two".to_vec()]
+                },
+            ]
+        );
+        let (payload, vote) = stored[0].clone().resolve();
+        assert_eq!(payload, b"one");
+        assert_eq!(
+            vote,
+            Some(Vote {
+                count: 3,
+                found: 2,
+                disputed: 0,
+                ties: 0
+            })
+        );
+
+        // A copy whose index its set already has starts another set.
+        let stored = gather(vec![first[0].clone(), first[0].clone()]);
+        assert_eq!(stored.len(), 2);
+    }
+}
@@ -0,0 +1,286 @@
+//! Renders the results of the listing commands as a table, JSON, CSV or YAML.
+
+use std::fmt::Write;
+use std::io;
+
+use png_secret::json::Value;
+
+use crate::args::ReportFormat;
+
+#[derive(Clone, Copy)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// Prints rows under a header line, padding every column to its widest cell.
+pub fn print_table(columns: &[(&str, Align)], rows: &[Vec<String>]) -> io::Result<()> {
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, (header, _))| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .chain([header.len()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let headers: Vec<String> = columns
+        .iter()
+        .map(|(header, _)| header.to_string())
+        .collect();
+    for row in [&headers].into_iter().chain(rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(columns.iter().zip(&widths))
+            .map(|(cell, ((_, align), &width))| match align {
+                Align::Left => format!("{cell:<width$}"),
+                Align::Right => format!("{cell:>width$}"),
+            })
+            .collect();
+        outln!("{}", cells.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+/// Prints `value` in one of the structured formats. `records` is the list of
+/// objects that becomes the rows of a CSV file, which cannot hold anything
+/// around them; it is usually `value` itself or one of its fields.
+pub fn print_structured(format: ReportFormat, value: &Value, records: &[Value]) -> io::Result<()> {
+    match format {
+        ReportFormat::Table | ReportFormat::Json => outln!("{}", value.to_string_pretty()),
+        ReportFormat::Csv => out!("{}", to_csv(records)),
+        ReportFormat::Yaml => out!("{}", to_yaml(value)),
+    }
+}
+
+/// One CSV line per object. Nested objects are flattened into `parent.child`
+/// columns, and the header collects every column in order of appearance, so a
+/// field that is null in one record and an object in another still lines up.
+pub fn to_csv(records: &[Value]) -> String {
+    let rows: Vec<Vec<(String, String)>> = records
+        .iter()
+        .map(|record| {
+            let mut cells = Vec::new();
+            flatten("", record, &mut cells);
+            cells
+        })
+        .collect();
+
+    let mut columns: Vec<&str> = Vec::new();
+    for (key, _) in rows.iter().flatten() {
+        if !columns.contains(&key.as_str()) {
+            columns.push(key);
+        }
+    }
+    let nested = |column: &str| {
+        columns
+            .iter()
+            .any(|other| other.len() > column.len() && other.starts_with(&format!("{column}.")))
+    };
+    let columns: Vec<&str> = columns.iter().copied().filter(|c| !nested(c)).collect();
+
+    let mut out = String::new();
+    if columns.is_empty() {
+        return out;
+    }
+    let header: Vec<String> = columns.iter().map(|column| csv_field(column)).collect();
+    writeln!(out, "{}", header.join(",")).unwrap();
+    for row in &rows {
+        let cells: Vec<String> = columns
+            .iter()
+            .map(|column| {
+                row.iter()
+                    .find(|(key, _)| key == column)
+                    .map_or(String::new(), |(_, cell)| csv_field(cell))
+            })
+            .collect();
+        writeln!(out, "{}", cells.join(",")).unwrap();
+    }
+    out
+}
+
+fn flatten(prefix: &str, value: &Value, cells: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(pairs) => {
+            for (key, value) in pairs {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(&key, value, cells);
+            }
+        }
+        Value::Null => cells.push((prefix.to_string(), String::new())),
+        Value::String(s) => cells.push((prefix.to_string(), s.clone())),
+        value => cells.push((prefix.to_string(), value.to_string())),
+    }
+}
+
+/// Quotes a field that holds a separator, a quote or a line break (RFC 4180).
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Block-style YAML. Strings are double-quoted unless they can only be read
+/// back as the same string.
+pub fn to_yaml(value: &Value) -> String {
+    let mut out = String::new();
+    match value {
+        Value::Array(items) if !items.is_empty() => yaml_block(&mut out, value, 0),
+        Value::Object(pairs) if !pairs.is_empty() => yaml_block(&mut out, value, 0),
+        scalar => writeln!(out, "{}", yaml_scalar(scalar)).unwrap(),
+    }
+    out
+}
+
+/// Writes a non-empty array or object, one entry per line at `indent`.
+fn yaml_block(out: &mut String, value: &Value, indent: usize) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                write!(out, "{pad}- ").unwrap();
+                match item {
+                    // The first key shares the line with the dash.
+                    Value::Object(pairs) if !pairs.is_empty() => {
+                        for (i, (key, value)) in pairs.iter().enumerate() {
+                            if i > 0 {
+                                write!(out, "{pad}  ").unwrap();
+                            }
+                            yaml_entry(out, key, value, indent + 2);
+                        }
+                    }
+                    Value::Array(items) if !items.is_empty() => {
+                        out.push('\n');
+                        yaml_block(out, item, indent + 2);
+                    }
+                    scalar => writeln!(out, "{}", yaml_scalar(scalar)).unwrap(),
+                }
+            }
+        }
+        Value::Object(pairs) => {
+            for (key, value) in pairs {
+                out.push_str(&pad);
+                yaml_entry(out, key, value, indent);
+            }
+        }
+        scalar => writeln!(out, "{pad}{}", yaml_scalar(scalar)).unwrap(),
+    }
+}
+
+fn yaml_entry(out: &mut String, key: &str, value: &Value, indent: usize) {
+    write!(out, "{}:", yaml_string(key)).unwrap();
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            yaml_block(out, value, indent + 2);
+        }
+        Value::Object(pairs) if !pairs.is_empty() => {
+            out.push('\n');
+            yaml_block(out, value, indent + 2);
+        }
+        scalar => writeln!(out, " {}", yaml_scalar(scalar)).unwrap(),
+    }
+}
+
+fn yaml_scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::String(s) => yaml_string(s),
+        Value::Array(_) => "[]".to_string(),
+        Value::Object(_) => "{}".to_string(),
+        value => value.to_string(),
+    }
+}
+
+fn yaml_string(s: &str) -> String {
+    const KEYWORDS: &[&str] = &["true", "false", "null", "yes", "no", "on", "off", "y", "n"];
+
+    let plain = s.starts_with(|c: char| c.is_ascii_alphabetic())
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | ' '))
+        && !s.ends_with(' ')
+        && !KEYWORDS.contains(&s.to_ascii_lowercase().as_str());
+    if plain {
+        s.to_string()
+    } else {
+        // A JSON string is also a valid double-quoted YAML scalar.
+        Value::from(s).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<Value> {
+        vec![
+            Value::object([
+                ("type", Value::from("tEXt")),
+                ("length", Value::from(12u32)),
+                (
+                    "text",
+                    Value::object([
+                        ("keyword", Value::from("Comment")),
+                        ("text", Value::from("a, \"b\"")),
+                    ]),
+                ),
+            ]),
+            Value::object([
+                ("type", Value::from("IEND")),
+                ("length", Value::from(0u32)),
+                ("text", Value::Null),
+            ]),
+        ]
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            to_csv(&records()),
+            "type,length,text.keyword,text.text\n\
+             tEXt,12,Comment,\"a, \"\"b\"\"\"\n\
+             IEND,0,,\n"
+        );
+        let reversed: Vec<Value> = records().into_iter().rev().collect();
+        assert_eq!(
+            to_csv(&reversed).lines().next(),
+            Some("type,length,text.keyword,text.text")
+        );
+        assert_eq!(to_csv(&[]), "");
+    }
+
+    #[test]
+    fn test_yaml() {
+        let value = Value::object([
+            ("file", Value::from("in.png")),
+            ("empty", Value::Array(Vec::new())),
+            ("chunks", Value::Array(records())),
+        ]);
+        assert_eq!(
+            to_yaml(&value),
+            "file: in.png\n\
+             empty: []\n\
+             chunks:\n  \
+               - type: tEXt\n    \
+                 length: 12\n    \
+                 text:\n      \
+                   keyword: Comment\n      \
+                   text: \"a, \\\"b\\\"\"\n  \
+               - type: IEND\n    \
+                 length: 0\n    \
+                 text: null\n"
+        );
+        assert_eq!(yaml_string("1e3f88b1"), "\"1e3f88b1\"");
+        assert_eq!(yaml_string("No"), "\"No\"");
+        assert_eq!(yaml_string("critical public"), "critical public");
+    }
+}
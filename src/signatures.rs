@@ -0,0 +1,343 @@
+//! Traces known tools leave in images: chunk types they pick, the magic bytes
+//! of what they store and the headers they write into the pixels.
+
+use std::fmt;
+
+use crate::base64;
+use crate::envelope;
+use crate::fec;
+use crate::inspect::TextEntry;
+use crate::lsb::{self, LsbOptions};
+use crate::payload;
+use crate::png::Png;
+use crate::raster::Raster;
+use crate::redundancy;
+use crate::stripe;
+
+/// What a tool leaves behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Marker {
+    /// A chunk of this type.
+    ChunkType([u8; 4]),
+    /// Data starting with these bytes: the data of a chunk, the text of a text
+    /// chunk, or that text decoded from base64.
+    Prefix(&'static [u8]),
+    /// These bytes `offset` bytes into the lowest bits of the pixel samples,
+    /// taken in order, in either bit order.
+    Lsb { offset: usize, bytes: &'static [u8] },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    pub tool: &'static str,
+    pub description: &'static str,
+    pub marker: Marker,
+}
+
+/// The traces `scan` looks for.
+pub const SIGNATURES: &[Signature] = &[
+    Signature {
+        tool: "png-secret",
+        description: "payload envelope",
+        marker: Marker::Prefix(&envelope::MAGIC),
+    },
+    Signature {
+        tool: "png-secret",
+        description: "segment of a split payload",
+        marker: Marker::Prefix(&payload::SEGMENT_MAGIC),
+    },
+    Signature {
+        tool: "png-secret",
+        description: "payload with error correction",
+        marker: Marker::Prefix(&fec::MAGIC),
+    },
+    Signature {
+        tool: "png-secret",
+        description: "copy of a payload stored several times",
+        marker: Marker::Prefix(&redundancy::COPY_MAGIC),
+    },
+    Signature {
+        tool: "png-secret",
+        description: "piece of a payload spread over several images",
+        marker: Marker::Prefix(&stripe::STRIPE_MAGIC),
+    },
+    Signature {
+        tool: "png-secret",
+        description: "payload envelope hidden with --lsb",
+        marker: Marker::Lsb {
+            offset: lsb::LENGTH_LEN,
+            bytes: &envelope::MAGIC,
+        },
+    },
+    Signature {
+        tool: "png-secret",
+        description: "payload with error correction hidden with --lsb",
+        marker: Marker::Lsb {
+            offset: lsb::LENGTH_LEN,
+            bytes: &fec::MAGIC,
+        },
+    },
+    Signature {
+        tool: "png-secret",
+        description: "copies of a payload hidden with --lsb",
+        marker: Marker::Lsb {
+            offset: lsb::LENGTH_LEN,
+            bytes: &redundancy::COPY_MAGIC,
+        },
+    },
+    Signature {
+        tool: "png-secret",
+        description: "piece of a spread payload hidden with --lsb",
+        marker: Marker::Lsb {
+            offset: lsb::LENGTH_LEN,
+            bytes: &stripe::STRIPE_MAGIC,
+        },
+    },
+    Signature {
+        tool: "pngme",
+        description: "the example chunk type of the pngme book",
+        marker: Marker::ChunkType(*b"RuSt"),
+    },
+    Signature {
+        tool: "pngme",
+        description: "the chunk type of the pngme book's encode example",
+        marker: Marker::ChunkType(*b"ruSt"),
+    },
+    Signature {
+        tool: "OpenStego",
+        description: "data header stamp",
+        marker: Marker::Lsb {
+            offset: 0,
+            bytes: b"OPENSTEGO",
+        },
+    },
+    Signature {
+        tool: "openssl enc",
+        description: "salted encrypted data",
+        marker: Marker::Prefix(b"Salted__"),
+    },
+    Signature {
+        tool: "age",
+        description: "encrypted file",
+        marker: Marker::Prefix(b"age-encryption.org/v1"),
+    },
+    Signature {
+        tool: "gpg",
+        description: "ASCII-armored message",
+        marker: Marker::Prefix(b"-----BEGIN PGP MESSAGE-----"),
+    },
+    Signature {
+        tool: "zip",
+        description: "archive",
+        marker: Marker::Prefix(b"PK\x03\x04"),
+    },
+    Signature {
+        tool: "7-Zip",
+        description: "archive",
+        marker: Marker::Prefix(b"7z\xbc\xaf\x27\x1c"),
+    },
+    Signature {
+        tool: "RAR",
+        description: "archive",
+        marker: Marker::Prefix(b"Rar!\x1a\x07"),
+    },
+    Signature {
+        tool: "gzip",
+        description: "compressed file",
+        marker: Marker::Prefix(b"\x1f\x8b\x08"),
+    },
+];
+
+/// The first of `SIGNATURES` whose `Marker::Prefix` starts `bytes`, naming
+/// what a payload read out of an image is.
+pub fn identify(bytes: &[u8]) -> Option<&'static Signature> {
+    SIGNATURES.iter().find(
+        |signature| matches!(signature.marker, Marker::Prefix(prefix) if bytes.starts_with(prefix)),
+    )
+}
+
+/// Where a signature was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// The chunk at this index, or its text.
+    Chunk(usize),
+    Pixels,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Chunk(index) => write!(f, "chunk {index}"),
+            Location::Pixels => f.write_str("pixels"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub signature: &'static Signature,
+    pub location: Location,
+}
+
+/// The longest `Marker::Lsb` reaches, in bytes of low bits.
+const LSB_SCAN_LEN: usize = 32;
+
+/// Looks for every one of `SIGNATURES` in `png`, in the pixels too if they
+/// were decoded into `raster`.
+pub fn scan(png: &Png, raster: Option<&Raster>) -> Vec<Match> {
+    let mut matches = Vec::new();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let text = TextEntry::from_chunk(chunk).map(|entry| entry.text);
+        // Enough base64 for any prefix, cut to whole groups of four.
+        let decoded = text.as_deref().and_then(|text| {
+            let len = text.len().min(64) / 4 * 4;
+            base64::decode(text.get(..len)?).ok()
+        });
+        for signature in SIGNATURES {
+            let found = match signature.marker {
+                Marker::ChunkType(chunk_type) => chunk.chunk_type().bytes() == chunk_type,
+                Marker::Prefix(prefix) => {
+                    chunk.data().starts_with(prefix)
+                        || text
+                            .as_ref()
+                            .is_some_and(|text| text.as_bytes().starts_with(prefix))
+                        || decoded
+                            .as_ref()
+                            .is_some_and(|decoded| decoded.starts_with(prefix))
+                }
+                Marker::Lsb { .. } => false,
+            };
+            if found {
+                matches.push(Match {
+                    signature,
+                    location: Location::Chunk(index),
+                });
+            }
+        }
+    }
+
+    let Some(streams) = raster.and_then(low_bit_streams) else {
+        return matches;
+    };
+    for signature in SIGNATURES {
+        if let Marker::Lsb { offset, bytes } = signature.marker {
+            if streams.iter().any(|stream| {
+                stream
+                    .get(offset..)
+                    .is_some_and(|rest| rest.starts_with(bytes))
+            }) {
+                matches.push(Match {
+                    signature,
+                    location: Location::Pixels,
+                });
+            }
+        }
+    }
+    matches
+}
+
+/// The first bytes of the lowest bits of every sample, packed highest and
+/// lowest bit first. `None` for images LSB tools can't write to.
+fn low_bit_streams(raster: &Raster) -> Option<[Vec<u8>; 2]> {
+    let slots = lsb::slots(raster, &LsbOptions::default()).ok()?;
+    let data = raster.data();
+    let mut streams = [Vec::new(), Vec::new()];
+    for bits in slots.chunks_exact(8).take(LSB_SCAN_LEN) {
+        let (mut msb_first, mut lsb_first) = (0u8, 0u8);
+        for (i, &slot) in bits.iter().enumerate() {
+            let bit = data[slot] & 1;
+            msb_first |= bit << (7 - i);
+            lsb_first |= bit << i;
+        }
+        streams[0].push(msb_first);
+        streams[1].push(lsb_first);
+    }
+    Some(streams)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PngBuilder;
+    use crate::chunk::Chunk;
+    use crate::envelope::Envelope;
+    use crate::png::InsertPosition;
+    use crate::text::TextChunk;
+
+    fn found(matches: &[Match]) -> Vec<(&'static str, Location)> {
+        matches
+            .iter()
+            .map(|found| (found.signature.description, found.location))
+            .collect()
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        assert_eq!(scan(&png, None), []);
+
+        let envelope = Envelope::new(b"hi".to_vec()).to_bytes(None);
+        let text = payload::to_text(&envelope);
+        for chunk in [
+            Chunk::new("ruSt".parse().unwrap(), envelope),
+            TextChunk::new("Comment", &text).unwrap().to_chunk(),
+            Chunk::new("prIv".parse().unwrap(), b"Salted__12345678".to_vec()),
+        ] {
+            png.insert_chunk(chunk, &InsertPosition::BeforeIend)
+                .unwrap();
+        }
+        assert_eq!(
+            found(&scan(&png, None)),
+            [
+                ("payload envelope", Location::Chunk(2)),
+                (
+                    "the chunk type of the pngme book's encode example",
+                    Location::Chunk(2)
+                ),
+                ("payload envelope", Location::Chunk(3)),
+                ("salted encrypted data", Location::Chunk(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identify() {
+        let envelope = Envelope::new(b"hi".to_vec()).to_bytes(None);
+        let name = |bytes: &[u8]| identify(bytes).map(|signature| signature.description);
+        assert_eq!(name(&envelope), Some("payload envelope"));
+        assert_eq!(
+            name(&fec::encode(&envelope, fec::Fec { parity: 4 })),
+            Some("payload with error correction")
+        );
+        assert_eq!(name(b"PK\x03\x04rest"), Some("archive"));
+        assert_eq!(name(b"plain"), None);
+    }
+
+    #[test]
+    fn test_pixels() {
+        let png = PngBuilder::new(16, 16).build().unwrap();
+        let mut raster = Raster::from_png(&png).unwrap();
+        assert_eq!(scan(&png, Some(&raster)), []);
+
+        let envelope = Envelope::new(b"hi".to_vec()).to_bytes(None);
+        lsb::embed(&mut raster, &envelope, &LsbOptions::default()).unwrap();
+        assert_eq!(
+            found(&scan(&png, Some(&raster))),
+            [("payload envelope hidden with --lsb", Location::Pixels)]
+        );
+
+        // Lowest bit first, as other tools may write.
+        let mut raster = Raster::from_png(&png).unwrap();
+        let slots = lsb::slots(&raster, &LsbOptions::default()).unwrap();
+        let bits = b"OPENSTEGO"
+            .iter()
+            .flat_map(|byte| (0..8).map(move |i| byte >> i & 1));
+        for (slot, bit) in slots.into_iter().zip(bits) {
+            raster.data_mut()[slot] = raster.data()[slot] & !1 | bit;
+        }
+        assert_eq!(
+            found(&scan(&png, Some(&raster))),
+            [("data header stamp", Location::Pixels)]
+        );
+    }
+}
@@ -0,0 +1,492 @@
+//! Statistical tests for data hidden in the low bits of pixel samples.
+//!
+//! Each test looks at one channel at a time, as LSB tools embed into all of
+//! them alike but the statistics of, say, red and alpha have nothing in common.
+//! - The chi-square attack (Westfeld and Pfitzmann, 1999): overwriting low bits
+//!   evens out the counts of each pair of values 2k and 2k+1. It sees payloads
+//!   written from the start of the image and how far they reach.
+//! - RS analysis (Fridrich, Goljan and Du, 2001): flipping low bits makes small
+//!   groups of samples smoother or noisier in a way that changes predictably
+//!   with how many were already flipped, giving the share of samples changed.
+//! - Sample pair analysis (Dumitrescu, Wu and Wang, 2003): the same estimate
+//!   from counts of neighbouring sample pairs.
+//!
+//! None of them proves anything: noise, dithering and earlier processing can
+//! look like embedding, and a small payload looks like nothing at all.
+
+use thiserror::Error;
+
+use crate::ihdr::ColorType;
+use crate::raster::Raster;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SteganalysisError {
+    #[error("SteganalysisError {0} images are not supported")]
+    Unsupported(String),
+}
+
+/// The results of every test on one channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelAnalysis {
+    pub channel: &'static str,
+    /// The chi-square attack's probability that the whole channel carries
+    /// embedded bits, from 0 to 1.
+    pub chi_square: f64,
+    /// How much of the channel, from its first sample, looks embedded to the
+    /// chi-square attack, from 0 to 1.
+    pub chi_square_extent: f64,
+    /// RS analysis's estimate of the share of samples carrying payload, if the
+    /// channel has enough texture to tell.
+    pub rs: Option<f64>,
+    /// Sample pair analysis's estimate of the same.
+    pub sample_pairs: Option<f64>,
+}
+
+impl ChannelAnalysis {
+    /// How likely the channel is to carry a payload, from 0 to 1: the mean of
+    /// the RS and sample pair estimates. Smooth histograms fool the chi-square
+    /// attack into seeing even pairs everywhere, so it only decides when both
+    /// others fail.
+    pub fn suspicion(&self) -> f64 {
+        let estimates: Vec<f64> = [self.rs, self.sample_pairs]
+            .into_iter()
+            .flatten()
+            .map(|estimate| estimate.clamp(0.0, 1.0))
+            .collect();
+        if estimates.is_empty() {
+            return self.chi_square;
+        }
+        estimates.iter().sum::<f64>() / estimates.len() as f64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub channels: Vec<ChannelAnalysis>,
+}
+
+impl Analysis {
+    /// The suspicion of the most suspicious channel, since tools may embed in
+    /// only one of them.
+    pub fn score(&self) -> f64 {
+        self.channels
+            .iter()
+            .map(ChannelAnalysis::suspicion)
+            .fold(0.0, f64::max)
+    }
+
+    /// A word for the score: natural images stay well below 0.1, and a score
+    /// of 0.3 means about every third sample looks changed.
+    pub fn verdict(&self) -> &'static str {
+        match self.score() {
+            score if score < 0.1 => "unlikely",
+            score if score < 0.3 => "possible",
+            _ => "likely",
+        }
+    }
+
+    /// About how many payload bytes the estimates add up to, `samples` per
+    /// channel at one bit each.
+    pub fn payload_estimate(&self, samples: u64) -> u64 {
+        let bits: f64 = self
+            .channels
+            .iter()
+            .map(|channel| channel.suspicion() * samples as f64)
+            .sum();
+        (bits / 8.0) as u64
+    }
+}
+
+/// The number of steps the chi-square attack takes through each channel to
+/// find how far an embedded payload reaches.
+const EXTENT_STEPS: usize = 20;
+
+/// The probability above which the chi-square attack calls samples embedded.
+const EMBEDDED_PROBABILITY: f64 = 0.5;
+
+/// Runs every test on every channel of an 8-bit grayscale or truecolor image.
+pub fn analyze(raster: &Raster) -> Result<Analysis, SteganalysisError> {
+    let ihdr = raster.ihdr();
+    if ihdr.color_type == ColorType::Indexed {
+        return Err(SteganalysisError::Unsupported(ihdr.color_type.to_string()));
+    }
+    let names = ihdr.color_type.channel_names();
+    if ihdr.bit_depth != 8 {
+        return Err(SteganalysisError::Unsupported(format!(
+            "{}-bit",
+            ihdr.bit_depth
+        )));
+    }
+
+    let width = ihdr.width as usize;
+    let channels = names
+        .iter()
+        .enumerate()
+        .map(|(channel, &name)| {
+            let samples: Vec<u8> = raster
+                .data()
+                .iter()
+                .skip(channel)
+                .step_by(names.len())
+                .copied()
+                .collect();
+            analyze_channel(name, &samples, width)
+        })
+        .collect();
+    Ok(Analysis { channels })
+}
+
+/// The tests on the `samples` of one channel, rows of `width` each.
+fn analyze_channel(channel: &'static str, samples: &[u8], width: usize) -> ChannelAnalysis {
+    let chi_square = chi_square(samples);
+    let mut chi_square_extent = 0.0;
+    for step in 1..=EXTENT_STEPS {
+        let len = samples.len() * step / EXTENT_STEPS;
+        if self::chi_square(&samples[..len]) <= EMBEDDED_PROBABILITY {
+            break;
+        }
+        chi_square_extent = step as f64 / EXTENT_STEPS as f64;
+    }
+    ChannelAnalysis {
+        channel,
+        chi_square,
+        chi_square_extent,
+        rs: rs_estimate(samples, width),
+        sample_pairs: sample_pair_estimate(samples, width),
+    }
+}
+
+/// The chi-square attack's probability that the low bits of `samples` were
+/// replaced by random ones: near 1 when every pair of values 2k and 2k+1 is
+/// about equally common, near 0 otherwise.
+pub fn chi_square(samples: &[u8]) -> f64 {
+    let mut histogram = [0u64; 256];
+    for &sample in samples {
+        histogram[usize::from(sample)] += 1;
+    }
+
+    let mut statistic = 0.0;
+    let mut categories = 0;
+    for pair in histogram.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected > 0.0 {
+            statistic += (pair[0] as f64 - expected).powi(2) / expected;
+            categories += 1;
+        }
+    }
+    if categories < 2 {
+        return 0.0;
+    }
+    upper_gamma_ratio(f64::from(categories - 1) / 2.0, statistic / 2.0)
+}
+
+/// The samples in a group RS analysis looks at, and the mask of which of them
+/// it flips.
+const RS_MASK: [bool; 4] = [false, true, true, false];
+
+/// RS analysis's estimate of the share of `samples`, rows of `width`, whose
+/// low bits carry payload. `None` when rows are too short for the groups or the
+/// counts give no answer.
+pub fn rs_estimate(samples: &[u8], width: usize) -> Option<f64> {
+    let flipped: Vec<u8> = samples.iter().map(|sample| sample ^ 1).collect();
+    let [r_m, s_m, r_neg, s_neg] = rs_counts(samples, width)?;
+    let [r_m1, s_m1, r_neg1, s_neg1] = rs_counts(&flipped, width)?;
+
+    let d0 = r_m - s_m;
+    let d1 = r_m1 - s_m1;
+    let d_neg0 = r_neg - s_neg;
+    let d_neg1 = r_neg1 - s_neg1;
+    let a = 2.0 * (d1 + d0);
+    let b = d_neg0 - d_neg1 - d1 - 3.0 * d0;
+    let c = d0 - d_neg0;
+    let Some(x) = smaller_root(a, b, c) else {
+        // Once every low bit is random the mask no longer tells regular from
+        // singular groups and the equation flattens out, while the negative
+        // mask still does.
+        return (d0.abs() < d_neg0 * 0.1).then_some(1.0);
+    };
+    let estimate = x / (x - 0.5);
+    estimate.is_finite().then_some(estimate)
+}
+
+/// The shares of regular and singular groups under the flipping mask and its
+/// negative: groups that get noisier or smoother when flipped.
+fn rs_counts(samples: &[u8], width: usize) -> Option<[f64; 4]> {
+    let mut counts = [0u64; 4];
+    let mut groups = 0;
+    for row in samples.chunks(width.max(1)) {
+        for group in row.chunks_exact(RS_MASK.len()) {
+            let group: Vec<i16> = group.iter().map(|&sample| i16::from(sample)).collect();
+            let before = smoothness(&group);
+            for (negative, counts) in [false, true].into_iter().zip(counts.chunks_mut(2)) {
+                let after = smoothness(&flip_group(&group, negative));
+                if after > before {
+                    counts[0] += 1;
+                } else if after < before {
+                    counts[1] += 1;
+                }
+            }
+            groups += 1;
+        }
+    }
+    (groups > 0).then(|| counts.map(|count| count as f64 / groups as f64))
+}
+
+/// How much neighbouring samples of `group` differ.
+fn smoothness(group: &[i16]) -> i16 {
+    group.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum()
+}
+
+/// Applies the flipping `RS_MASK` selects: 2k and 2k+1 swapped, or for the
+/// negative mask 2k-1 and 2k.
+fn flip_group(group: &[i16], negative: bool) -> Vec<i16> {
+    group
+        .iter()
+        .zip(RS_MASK)
+        .map(|(&sample, flip)| match (flip, negative) {
+            (false, _) => sample,
+            (true, false) => sample ^ 1,
+            (true, true) => ((sample + 1) ^ 1) - 1,
+        })
+        .collect()
+}
+
+/// Sample pair analysis's estimate of the share of `samples`, rows of `width`,
+/// whose low bits carry payload, from horizontally neighbouring pairs.
+pub fn sample_pair_estimate(samples: &[u8], width: usize) -> Option<f64> {
+    // x: pairs the embedding pushes apart, y: pairs it pulls together, both
+    // without the ones that differ only in the low bit, w, and equal ones, z.
+    let (mut x, mut y, mut w, mut z, mut pairs) = (0u64, 0u64, 0u64, 0u64, 0u64);
+    for row in samples.chunks(width.max(1)) {
+        for pair in row.windows(2) {
+            let (u, v) = (pair[0], pair[1]);
+            pairs += 1;
+            if u == v {
+                z += 1;
+            }
+            if u >> 1 == v >> 1 && u != v {
+                w += 1;
+            }
+            let even = v.is_multiple_of(2);
+            if (even && u < v) || (!even && u > v) {
+                x += 1;
+            }
+            if (even && u > v) || (!even && u < v) {
+                y += 1;
+            }
+        }
+    }
+    if pairs == 0 {
+        return None;
+    }
+    let a = (w + z) as f64 / 2.0;
+    let b = 2.0 * x as f64 - pairs as f64;
+    let c = y as f64 - x as f64;
+    let estimate = smaller_root(a, b, c)?;
+    estimate.is_finite().then_some(estimate)
+}
+
+/// The root of `a`x² + `b`x + `c` closest to 0, or the one of `b`x + `c` when
+/// `a` is 0.
+fn smaller_root(a: f64, b: f64, c: f64) -> Option<f64> {
+    if a.abs() < f64::EPSILON {
+        return (b.abs() >= f64::EPSILON).then(|| -c / b);
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    // Fully embedded samples put the two roots together, and rounding can push
+    // them just apart into complex ones.
+    if discriminant < -(b * b) * 0.05 {
+        return None;
+    }
+    let root = discriminant.max(0.0).sqrt();
+    let (plus, minus) = ((-b + root) / (2.0 * a), (-b - root) / (2.0 * a));
+    Some(if plus.abs() <= minus.abs() {
+        plus
+    } else {
+        minus
+    })
+}
+
+/// The regularized upper incomplete gamma function Q(a, x), which gives the
+/// chi-square distribution's tail: P(X² > 2x) for 2a degrees of freedom.
+/// Numerical Recipes 6.2: a series below a + 1, a continued fraction above.
+fn upper_gamma_ratio(a: f64, x: f64) -> f64 {
+    const ITERATIONS: usize = 500;
+    const EPSILON: f64 = 1e-12;
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let prefix = (a * x.ln() - x - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let (mut term, mut sum) = (1.0 / a, 1.0 / a);
+        for n in 1..ITERATIONS {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        return (1.0 - sum * prefix).clamp(0.0, 1.0);
+    }
+
+    // Lentz's method.
+    let tiny = f64::MIN_POSITIVE / EPSILON;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for n in 1..ITERATIONS {
+        let an = -(n as f64) * (n as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    (prefix * h).clamp(0.0, 1.0)
+}
+
+/// ln Γ(x) for x > 0, by the Lanczos approximation (g = 7, n = 9).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // Reflection, for the halves below 1 that one degree of freedom gives.
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    /// A smooth gradient with mild noise, like a photo of a clean surface.
+    fn natural(width: usize, height: usize) -> Vec<u8> {
+        let mut rng = Rng::new(11);
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f64, (i / width) as f64);
+                let base = 120.0 + 60.0 * (x / 23.0).sin() + 50.0 * (y / 31.0).cos();
+                (base + rng.next_f64() * 3.0).round() as u8
+            })
+            .collect()
+    }
+
+    /// Replaces the low bits of about `share` of `samples` with random ones.
+    fn embed(samples: &mut [u8], share: f64, seed: u64) {
+        let mut rng = Rng::new(seed);
+        for sample in samples.iter_mut() {
+            if rng.next_f64() < share {
+                *sample = *sample & !1 | (rng.next_u32() & 1) as u8;
+            }
+        }
+    }
+
+    #[test]
+    fn test_gamma() {
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-10);
+        assert!((ln_gamma(0.5) - std::f64::consts::PI.sqrt().ln()).abs() < 1e-10);
+        // P(X² > 3.841) = 0.05 with one degree of freedom, and
+        // P(X² > 18.307) = 0.05 with ten.
+        assert!((upper_gamma_ratio(0.5, 3.841 / 2.0) - 0.05).abs() < 1e-3);
+        assert!((upper_gamma_ratio(5.0, 18.307 / 2.0) - 0.05).abs() < 1e-3);
+        assert_eq!(upper_gamma_ratio(2.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn test_estimates() {
+        let (width, height) = (200, 150);
+        let clean = natural(width, height);
+        for share in [0.0, 0.25, 0.5, 1.0] {
+            let mut samples = clean.clone();
+            embed(&mut samples, share, 1);
+            let analysis = analyze_channel("gray", &samples, width);
+            for estimate in [analysis.rs, analysis.sample_pairs] {
+                assert!((estimate.unwrap() - share).abs() < 0.1, "{analysis:?}");
+            }
+            assert!((analysis.suspicion() - share).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_chi_square() {
+        // Only even values, as if an earlier step had cleared the low bits.
+        let (width, height) = (200, 150);
+        let clean: Vec<u8> = natural(width, height)
+            .into_iter()
+            .map(|sample| sample & !1)
+            .collect();
+        let analysis = analyze_channel("gray", &clean, width);
+        assert!(analysis.chi_square < 0.01, "{analysis:?}");
+        assert_eq!(analysis.chi_square_extent, 0.0);
+
+        let mut full = clean.clone();
+        embed(&mut full, 1.0, 2);
+        let analysis = analyze_channel("gray", &full, width);
+        assert!(analysis.chi_square > 0.5, "{analysis:?}");
+        assert_eq!(analysis.chi_square_extent, 1.0);
+
+        // A payload over the first third only.
+        let mut start = clean;
+        embed(&mut start[..width * height / 3], 1.0, 3);
+        let analysis = analyze_channel("gray", &start, width);
+        assert!(analysis.chi_square < 0.01, "{analysis:?}");
+        assert!(
+            (0.2..=0.35).contains(&analysis.chi_square_extent),
+            "{analysis:?}"
+        );
+    }
+
+    #[test]
+    fn test_flat_and_unsupported() {
+        let analysis = analyze_channel("alpha", &[255; 400], 20);
+        assert_eq!(analysis.chi_square, 0.0);
+        assert!(analysis.rs.unwrap().abs() < 0.01);
+        assert!(analysis.suspicion() < 0.01);
+        let analysis = Analysis {
+            channels: vec![analysis],
+        };
+        assert_eq!(analysis.verdict(), "unlikely");
+        assert_eq!(analysis.payload_estimate(400), 0);
+        assert_eq!(sample_pair_estimate(&[1, 2, 3], 1), None);
+
+        let png = crate::builder::PngBuilder::new(4, 4)
+            .with_color_type(ColorType::Indexed)
+            .build()
+            .unwrap();
+        assert_eq!(
+            analyze(&Raster::from_png(&png).unwrap()),
+            Err(SteganalysisError::Unsupported("indexed".to_string()))
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! A payload cut into pieces stored in several images, none of which holds
+//! all of it. Unlike secret sharing each piece is a plain slice of the
+//! payload; it takes every piece to put it back together.
+
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum StripeError {
+    #[error("StripeError a payload can be cut into 2 to {max} pieces, not {0}", max = u16::MAX)]
+    InvalidCount(usize),
+    #[error("StripeError payload {0} is not a piece of a spread payload")]
+    NotAPiece(usize),
+    #[error("StripeError pieces of different payloads")]
+    Mixed,
+    /// The index, from 0, and the count of the first piece missing.
+    #[error("StripeError piece {} of {1} is missing", .0 + 1)]
+    Missing(u16, u16),
+}
+
+/// Marks a payload as one piece of several.
+pub const STRIPE_MAGIC: [u8; 4] = [0x89, b'p', b's', b's'];
+
+/// Magic, a big-endian u32 id shared by the pieces of one payload, then u16
+/// index and count.
+pub const STRIPE_HEADER_LEN: usize = 12;
+
+/// One piece of a payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Piece<'a> {
+    pub id: u32,
+    pub index: u16,
+    pub count: u16,
+    pub data: &'a [u8],
+}
+
+impl<'a> Piece<'a> {
+    /// Parses a piece `split` made, or `None` for anything else.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < STRIPE_HEADER_LEN || bytes[..4] != STRIPE_MAGIC {
+            return None;
+        }
+        let id = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
+        let index = u16::from_be_bytes([bytes[8], bytes[9]]);
+        let count = u16::from_be_bytes([bytes[10], bytes[11]]);
+        (index < count).then_some(Self {
+            id,
+            index,
+            count,
+            data: &bytes[STRIPE_HEADER_LEN..],
+        })
+    }
+}
+
+/// Cuts `payload` into `count` pieces of as near the same size as can be,
+/// each headed with `id`, its index and the count.
+pub fn split(payload: &[u8], count: usize, id: u32) -> Result<Vec<Vec<u8>>, StripeError> {
+    let pieces = u16::try_from(count)
+        .ok()
+        .filter(|&count| count >= 2)
+        .ok_or(StripeError::InvalidCount(count))?;
+    let piece_len = payload.len().div_ceil(count);
+    Ok((0..pieces)
+        .map(|index| {
+            let start = (usize::from(index) * piece_len).min(payload.len());
+            let end = (start + piece_len).min(payload.len());
+            let mut piece = Vec::with_capacity(STRIPE_HEADER_LEN + end - start);
+            piece.extend_from_slice(&STRIPE_MAGIC);
+            piece.extend_from_slice(&id.to_be_bytes());
+            piece.extend_from_slice(&index.to_be_bytes());
+            piece.extend_from_slice(&pieces.to_be_bytes());
+            piece.extend_from_slice(&payload[start..end]);
+            piece
+        })
+        .collect())
+}
+
+/// Puts a payload back together from all of its pieces, in any order. With
+/// no pieces at all, the first of an unknown number is missing.
+pub fn join(pieces: &[Vec<u8>]) -> Result<Vec<u8>, StripeError> {
+    let mut parsed = pieces
+        .iter()
+        .enumerate()
+        .map(|(i, bytes)| Piece::parse(bytes).ok_or(StripeError::NotAPiece(i)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(first) = parsed.first().copied() else {
+        return Err(StripeError::Missing(0, 0));
+    };
+    if parsed
+        .iter()
+        .any(|piece| piece.id != first.id || piece.count != first.count)
+    {
+        return Err(StripeError::Mixed);
+    }
+    parsed.sort_by_key(|piece| piece.index);
+    parsed.dedup_by_key(|piece| piece.index);
+    if let Some(missing) = (0..first.count).find(|&index| {
+        parsed
+            .get(usize::from(index))
+            .is_none_or(|piece| piece.index != index)
+    }) {
+        return Err(StripeError::Missing(missing, first.count));
+    }
+    Ok(parsed
+        .iter()
+        .flat_map(|piece| piece.data)
+        .copied()
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_join() {
+        let payload: Vec<u8> = (0..100).collect();
+        let mut pieces = split(&payload, 3, 7).unwrap();
+        let lens: Vec<usize> = pieces
+            .iter()
+            .map(|piece| Piece::parse(piece).unwrap().data.len())
+            .collect();
+        assert_eq!(lens, [34, 34, 32]);
+        pieces.swap(0, 2);
+        assert_eq!(join(&pieces).unwrap(), payload);
+
+        assert_eq!(join(&pieces[..2]), Err(StripeError::Missing(0, 3)));
+        let other = split(&payload, 3, 8).unwrap();
+        assert_eq!(
+            join(&[pieces[0].clone(), other[1].clone()]),
+            Err(StripeError::Mixed)
+        );
+        assert_eq!(
+            join(&[pieces[0].clone(), payload.clone()]),
+            Err(StripeError::NotAPiece(1))
+        );
+        assert_eq!(split(&payload, 1, 0), Err(StripeError::InvalidCount(1)));
+
+        // More pieces than bytes leaves some empty.
+        let pieces = split(b"ab", 3, 0).unwrap();
+        assert_eq!(join(&pieces).unwrap(), b"ab");
+    }
+}
@@ -0,0 +1,442 @@
+use std::convert::TryFrom;
+
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::zlib::{self, ZlibError};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TextError {
+    #[error("TextError expected a {expected} chunk, found {found}")]
+    WrongType {
+        expected: &'static str,
+        found: String,
+    },
+    #[error("TextError keyword must be 1 to 79 bytes, got {0}")]
+    KeywordLength(usize),
+    #[error("TextError keyword {0:?} has leading, trailing or repeated spaces")]
+    KeywordSpaces(String),
+    #[error("TextError {0:?} cannot be encoded as Latin-1 without NULs")]
+    NotLatin1(String),
+    #[error("TextError missing NUL separator after the {0}")]
+    MissingSeparator(&'static str),
+    #[error("TextError unknown compression method {0}")]
+    UnknownCompression(u8),
+    #[error("TextError invalid compression flag {0}")]
+    InvalidCompressionFlag(u8),
+    #[error("TextError iTXt {0} is not valid UTF-8")]
+    NotUtf8(&'static str),
+    #[error("TextError compressed text inflates to more than {MAX_TEXT_LEN} bytes")]
+    TooLong,
+    #[error("TextError corrupt compressed text")]
+    Zlib(#[from] ZlibError),
+}
+
+/// The most the compressed text of a zTXt or iTXt entry may inflate to, so
+/// a small entry in an untrusted image can't expand to fill memory.
+pub const MAX_TEXT_LEN: usize = 16 << 20;
+
+/// Inflates the compressed text of an entry, up to `MAX_TEXT_LEN` bytes.
+fn inflate_text(compressed: &[u8]) -> Result<Vec<u8>, TextError> {
+    zlib::decompress_with_limit(compressed, MAX_TEXT_LEN).map_err(|err| match err {
+        ZlibError::OutputTooLarge(_) => TextError::TooLong,
+        err => TextError::Zlib(err),
+    })
+}
+
+/// An uncompressed Latin-1 text entry.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.tEXt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    keyword: String,
+    text: String,
+}
+
+/// A Latin-1 text entry whose text is zlib compressed.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.zTXt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedTextChunk {
+    keyword: String,
+    text: String,
+}
+
+/// A UTF-8 text entry with an optional language tag and a translation of the
+/// keyword, optionally compressed.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.iTXt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalTextChunk {
+    keyword: String,
+    compressed: bool,
+    language_tag: String,
+    translated_keyword: String,
+    text: String,
+}
+
+impl TextChunk {
+    pub const TYPE: &'static str = "tEXt";
+
+    pub fn new(keyword: &str, text: &str) -> Result<Self, TextError> {
+        validate_keyword(keyword)?;
+        encode_latin1(text)?;
+        Ok(TextChunk {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = latin1_bytes(&self.keyword);
+        data.push(0);
+        data.extend(latin1_bytes(&self.text));
+        Chunk::new(chunk_type(Self::TYPE), data)
+    }
+}
+
+impl CompressedTextChunk {
+    pub const TYPE: &'static str = "zTXt";
+
+    pub fn new(keyword: &str, text: &str) -> Result<Self, TextError> {
+        validate_keyword(keyword)?;
+        encode_latin1(text)?;
+        Ok(CompressedTextChunk {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = latin1_bytes(&self.keyword);
+        // NUL separator, then compression method 0 (zlib).
+        data.extend([0, 0]);
+        data.extend(zlib::compress(
+            &latin1_bytes(&self.text),
+            zlib::DEFAULT_LEVEL,
+        ));
+        Chunk::new(chunk_type(Self::TYPE), data)
+    }
+}
+
+impl InternationalTextChunk {
+    pub const TYPE: &'static str = "iTXt";
+
+    /// An uncompressed entry with no language tag or translated keyword.
+    pub fn new(keyword: &str, text: &str) -> Result<Self, TextError> {
+        validate_keyword(keyword)?;
+        Ok(InternationalTextChunk {
+            keyword: keyword.to_string(),
+            compressed: false,
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+            text: text.to_string(),
+        })
+    }
+
+    /// Sets the language tag, e.g. `en-GB`, and the keyword in that language.
+    /// The language tag is ASCII; the translated keyword can be any UTF-8 without NULs.
+    pub fn with_language(
+        mut self,
+        language_tag: &str,
+        translated_keyword: &str,
+    ) -> Result<Self, TextError> {
+        if !language_tag.is_ascii() || language_tag.contains('\0') {
+            return Err(TextError::NotLatin1(language_tag.to_string()));
+        }
+        if translated_keyword.contains('\0') {
+            return Err(TextError::NotLatin1(translated_keyword.to_string()));
+        }
+        self.language_tag = language_tag.to_string();
+        self.translated_keyword = translated_keyword.to_string();
+        Ok(self)
+    }
+
+    pub fn with_compression(mut self, compressed: bool) -> Self {
+        self.compressed = compressed;
+        self
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    pub fn language_tag(&self) -> &str {
+        &self.language_tag
+    }
+
+    pub fn translated_keyword(&self) -> &str {
+        &self.translated_keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = latin1_bytes(&self.keyword);
+        data.extend([0, u8::from(self.compressed), 0]);
+        data.extend(self.language_tag.as_bytes());
+        data.push(0);
+        data.extend(self.translated_keyword.as_bytes());
+        data.push(0);
+        if self.compressed {
+            data.extend(zlib::compress(self.text.as_bytes(), zlib::DEFAULT_LEVEL));
+        } else {
+            data.extend(self.text.as_bytes());
+        }
+        Chunk::new(chunk_type(Self::TYPE), data)
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = TextError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let (keyword, text) = split_keyword(chunk, Self::TYPE)?;
+        Ok(TextChunk {
+            keyword,
+            text: latin1(text),
+        })
+    }
+}
+
+impl TryFrom<&Chunk> for CompressedTextChunk {
+    type Error = TextError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let (keyword, rest) = split_keyword(chunk, Self::TYPE)?;
+        let text = match rest.split_first() {
+            Some((0, compressed)) => latin1(&inflate_text(compressed)?),
+            Some((&method, _)) => return Err(TextError::UnknownCompression(method)),
+            None => return Err(TextError::MissingSeparator("compression method")),
+        };
+        Ok(CompressedTextChunk { keyword, text })
+    }
+}
+
+impl TryFrom<&Chunk> for InternationalTextChunk {
+    type Error = TextError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let (keyword, rest) = split_keyword(chunk, Self::TYPE)?;
+        let (&flag, rest) = rest
+            .split_first()
+            .ok_or(TextError::MissingSeparator("compression flag"))?;
+        let (&method, rest) = rest
+            .split_first()
+            .ok_or(TextError::MissingSeparator("compression method"))?;
+
+        let mut fields = rest.splitn(3, |&b| b == 0);
+        let language_tag = fields.next().unwrap_or_default();
+        let translated_keyword = fields
+            .next()
+            .ok_or(TextError::MissingSeparator("language tag"))?;
+        let text = fields
+            .next()
+            .ok_or(TextError::MissingSeparator("translated keyword"))?;
+
+        let compressed = match (flag, method) {
+            (0, _) => false,
+            (1, 0) => true,
+            (1, method) => return Err(TextError::UnknownCompression(method)),
+            (flag, _) => return Err(TextError::InvalidCompressionFlag(flag)),
+        };
+        let text = if compressed {
+            inflate_text(text)?
+        } else {
+            text.to_vec()
+        };
+
+        Ok(InternationalTextChunk {
+            keyword,
+            compressed,
+            language_tag: utf8(language_tag, "language tag")?,
+            translated_keyword: utf8(translated_keyword, "translated keyword")?,
+            text: String::from_utf8(text).map_err(|_| TextError::NotUtf8("text"))?,
+        })
+    }
+}
+
+impl From<TextChunk> for Chunk {
+    fn from(text: TextChunk) -> Self {
+        text.to_chunk()
+    }
+}
+
+impl From<CompressedTextChunk> for Chunk {
+    fn from(text: CompressedTextChunk) -> Self {
+        text.to_chunk()
+    }
+}
+
+impl From<InternationalTextChunk> for Chunk {
+    fn from(text: InternationalTextChunk) -> Self {
+        text.to_chunk()
+    }
+}
+
+/// Checks the spec's keyword rules: 1 to 79 printable Latin-1 characters,
+/// without leading, trailing or consecutive spaces.
+pub fn validate_keyword(keyword: &str) -> Result<(), TextError> {
+    let bytes = encode_latin1(keyword)?;
+    if bytes.is_empty() || bytes.len() > 79 {
+        return Err(TextError::KeywordLength(bytes.len()));
+    }
+    if bytes.iter().any(|&b| !(32..=126).contains(&b) && b < 161) {
+        return Err(TextError::NotLatin1(keyword.to_string()));
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(TextError::KeywordSpaces(keyword.to_string()));
+    }
+    Ok(())
+}
+
+fn chunk_type(name: &str) -> ChunkType {
+    match name {
+        TextChunk::TYPE => ChunkType::TEXT,
+        CompressedTextChunk::TYPE => ChunkType::ZTXT,
+        _ => ChunkType::ITXT,
+    }
+}
+
+fn split_keyword<'a>(
+    chunk: &'a Chunk,
+    expected: &'static str,
+) -> Result<(String, &'a [u8]), TextError> {
+    if chunk.chunk_type().bytes() != expected.as_bytes() {
+        return Err(TextError::WrongType {
+            expected,
+            found: chunk.chunk_type().to_string(),
+        });
+    }
+    let data = chunk.data();
+    let nul = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(TextError::MissingSeparator("keyword"))?;
+    Ok((latin1(&data[..nul]), &data[nul + 1..]))
+}
+
+fn encode_latin1(text: &str) -> Result<Vec<u8>, TextError> {
+    text.chars()
+        .map(|c| match u8::try_from(u32::from(c)) {
+            Ok(b) if b != 0 => Ok(b),
+            _ => Err(TextError::NotLatin1(text.to_string())),
+        })
+        .collect()
+}
+
+/// Encodes text already checked by `encode_latin1`.
+fn latin1_bytes(text: &str) -> Vec<u8> {
+    text.chars().map(|c| u32::from(c) as u8).collect()
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+fn utf8(bytes: &[u8], field: &'static str) -> Result<String, TextError> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| TextError::NotUtf8(field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_round_trip() {
+        let text = TextChunk::new("Comment", "café").unwrap();
+        let chunk = text.to_chunk();
+        assert_eq!(chunk.data(), b"Comment\0caf\xe9");
+        assert_eq!(TextChunk::try_from(&chunk), Ok(text));
+
+        let ztxt = CompressedTextChunk::new("Author", "me").unwrap();
+        let chunk = Chunk::from(ztxt.clone());
+        assert_eq!(&chunk.data()[..8], b"Author\0\0");
+        assert_eq!(CompressedTextChunk::try_from(&chunk), Ok(ztxt));
+        assert!(matches!(
+            TextChunk::try_from(&chunk),
+            Err(TextError::WrongType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_international_round_trip() {
+        for compressed in [false, true] {
+            let itxt = InternationalTextChunk::new("Title", "Grüße, 世界")
+                .unwrap()
+                .with_language("de-DE", "Titel")
+                .unwrap()
+                .with_compression(compressed);
+            let parsed = InternationalTextChunk::try_from(&itxt.to_chunk()).unwrap();
+            assert_eq!(parsed, itxt);
+            assert_eq!(parsed.language_tag(), "de-DE");
+            assert_eq!(parsed.translated_keyword(), "Titel");
+        }
+
+        let chunk = Chunk::new("iTXt".parse().unwrap(), b"Title\0\0\0en".to_vec());
+        assert_eq!(
+            InternationalTextChunk::try_from(&chunk),
+            Err(TextError::MissingSeparator("language tag"))
+        );
+    }
+
+    #[test]
+    fn test_text_bomb() {
+        let bomb = zlib::compress(&vec![b'a'; MAX_TEXT_LEN + 1], zlib::DEFAULT_LEVEL);
+        assert!(bomb.len() < MAX_TEXT_LEN / 100);
+
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend_from_slice(&bomb);
+        let chunk = Chunk::new("zTXt".parse().unwrap(), data);
+        assert_eq!(
+            CompressedTextChunk::try_from(&chunk),
+            Err(TextError::TooLong)
+        );
+
+        let mut data = b"Comment\0\x01\0\0\0".to_vec();
+        data.extend_from_slice(&bomb);
+        let chunk = Chunk::new("iTXt".parse().unwrap(), data);
+        assert_eq!(
+            InternationalTextChunk::try_from(&chunk),
+            Err(TextError::TooLong)
+        );
+    }
+
+    #[test]
+    fn test_invalid_keywords() {
+        assert_eq!(TextChunk::new("", "x"), Err(TextError::KeywordLength(0)));
+        assert_eq!(
+            TextChunk::new(&"k".repeat(80), "x"),
+            Err(TextError::KeywordLength(80))
+        );
+        assert!(matches!(
+            TextChunk::new(" Title", "x"),
+            Err(TextError::KeywordSpaces(_))
+        ));
+        assert!(matches!(
+            TextChunk::new("Title", "世界"),
+            Err(TextError::NotLatin1(_))
+        ));
+        assert!(InternationalTextChunk::new("Title", "世界").is_ok());
+    }
+}
@@ -0,0 +1,280 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TimeError {
+    #[error("TimeError not a tIME chunk: {0}")]
+    WrongType(String),
+    #[error("TimeError data is {0} bytes, expected 7")]
+    InvalidLength(usize),
+    #[error("TimeError {0} is out of range")]
+    OutOfRange(&'static str),
+    #[error("TimeError invalid time {0:?}, expected YYYY-MM-DDTHH:MM:SS")]
+    InvalidFormat(String),
+    #[error("TimeError the system clock is before 1970")]
+    ClockBeforeEpoch,
+}
+
+/// The time of the last image modification, in UTC.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.tIME
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeChunk {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    /// Up to 60, to allow for leap seconds.
+    pub second: u8,
+}
+
+impl TimeChunk {
+    pub const LENGTH: usize = 7;
+
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Result<Self, TimeError> {
+        let time = TimeChunk {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        };
+        time.validate()?;
+        Ok(time)
+    }
+
+    /// The current time, as the spec recommends writing after a modification.
+    pub fn now() -> Result<Self, TimeError> {
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| TimeError::ClockBeforeEpoch)?;
+        Self::from_unix(elapsed.as_secs())
+    }
+
+    /// The UTC time `seconds` after the Unix epoch.
+    pub fn from_unix(seconds: u64) -> Result<Self, TimeError> {
+        let days = seconds / 86400;
+        let of_day = seconds % 86400;
+        let (year, month, day) = civil_from_days(days);
+        let year = u16::try_from(year).map_err(|_| TimeError::OutOfRange("year"))?;
+        Self::new(
+            year,
+            month,
+            day,
+            (of_day / 3600) as u8,
+            (of_day / 60 % 60) as u8,
+            (of_day % 60) as u8,
+        )
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, TimeError> {
+        let data: &[u8; Self::LENGTH] = data
+            .try_into()
+            .map_err(|_| TimeError::InvalidLength(data.len()))?;
+        Self::new(
+            u16::from_be_bytes([data[0], data[1]]),
+            data[2],
+            data[3],
+            data[4],
+            data[5],
+            data[6],
+        )
+    }
+
+    pub fn validate(&self) -> Result<(), TimeError> {
+        if !(1..=12).contains(&self.month) {
+            return Err(TimeError::OutOfRange("month"));
+        }
+        if self.day == 0 || self.day > days_in_month(self.year, self.month) {
+            return Err(TimeError::OutOfRange("day"));
+        }
+        if self.hour > 23 {
+            return Err(TimeError::OutOfRange("hour"));
+        }
+        if self.minute > 59 {
+            return Err(TimeError::OutOfRange("minute"));
+        }
+        if self.second > 60 {
+            return Err(TimeError::OutOfRange("second"));
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let [year_hi, year_lo] = self.year.to_be_bytes();
+        [
+            year_hi,
+            year_lo,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+        ]
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(Self::chunk_type(), self.to_bytes().to_vec())
+    }
+
+    pub fn chunk_type() -> ChunkType {
+        ChunkType::TIME
+    }
+}
+
+impl TryFrom<&Chunk> for TimeChunk {
+    type Error = TimeError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if chunk.chunk_type() != &Self::chunk_type() {
+            return Err(TimeError::WrongType(chunk.chunk_type().to_string()));
+        }
+        Self::from_bytes(chunk.data())
+    }
+}
+
+/// ISO 8601 in UTC, e.g. `2024-03-01T12:34:56Z`.
+impl fmt::Display for TimeChunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Parses `YYYY-MM-DDTHH:MM:SS`, with an optional trailing `Z`; a space may
+/// stand in for the `T`.
+impl FromStr for TimeChunk {
+    type Err = TimeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || TimeError::InvalidFormat(s.to_string());
+        let trimmed = s.strip_suffix('Z').unwrap_or(s);
+        let (date, time) = trimmed.split_once(['T', ' ']).ok_or_else(invalid)?;
+
+        let fields = |text: &str, widths: &[usize], separator: char| {
+            let parts: Vec<&str> = text.split(separator).collect();
+            if parts.len() != widths.len()
+                || parts.iter().zip(widths).any(|(part, &width)| {
+                    part.len() != width || !part.bytes().all(|b| b.is_ascii_digit())
+                })
+            {
+                return None;
+            }
+            parts
+                .iter()
+                .map(|part| part.parse::<u16>().ok())
+                .collect::<Option<Vec<_>>>()
+        };
+        let date = fields(date, &[4, 2, 2], '-').ok_or_else(invalid)?;
+        let time = fields(time, &[2, 2, 2], ':').ok_or_else(invalid)?;
+
+        Self::new(
+            date[0],
+            date[1] as u8,
+            date[2] as u8,
+            time[0] as u8,
+            time[1] as u8,
+            time[2] as u8,
+        )
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian date, after
+/// Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: u64) -> (u64, u8, u8) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let time = TimeChunk::new(2024, 2, 29, 23, 59, 60).unwrap();
+        assert_eq!(time.to_bytes(), [0x07, 0xe8, 2, 29, 23, 59, 60]);
+        assert_eq!(TimeChunk::try_from(&time.to_chunk()), Ok(time));
+        assert_eq!(time.to_string(), "2024-02-29T23:59:60Z");
+        assert_eq!("2024-02-29T23:59:60Z".parse(), Ok(time));
+        assert_eq!("2024-02-29 23:59:60".parse(), Ok(time));
+    }
+
+    #[test]
+    fn test_from_unix() {
+        assert_eq!(
+            TimeChunk::from_unix(0).unwrap().to_string(),
+            "1970-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            TimeChunk::from_unix(951_782_400 + 3661)
+                .unwrap()
+                .to_string(),
+            "2000-02-29T01:01:01Z"
+        );
+        assert_eq!(
+            TimeChunk::from_unix(1_735_689_599).unwrap().to_string(),
+            "2024-12-31T23:59:59Z"
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            TimeChunk::new(2023, 2, 29, 0, 0, 0),
+            Err(TimeError::OutOfRange("day"))
+        );
+        assert_eq!(
+            TimeChunk::from_bytes(&[0x07, 0xe8, 13, 1, 0, 0, 0]),
+            Err(TimeError::OutOfRange("month"))
+        );
+        assert_eq!(
+            TimeChunk::from_bytes(&[0; 6]),
+            Err(TimeError::InvalidLength(6))
+        );
+        assert!(matches!(
+            "2024-1-01T00:00:00".parse::<TimeChunk>(),
+            Err(TimeError::InvalidFormat(_))
+        ));
+    }
+}
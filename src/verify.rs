@@ -0,0 +1,257 @@
+use std::fmt;
+
+use crate::chunk::{Chunk, ParseOptions};
+use crate::chunk_type::ChunkType;
+use crate::ordering::{self, OrderingViolation};
+use crate::png::Png;
+
+/// Something wrong with the structure of a PNG file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    BadSignature,
+    /// The bytes at this offset are not a chunk; verification stops there.
+    Unreadable(String),
+    BadCrc {
+        chunk_type: ChunkType,
+        stored: u32,
+        computed: u32,
+    },
+    /// Bytes after IEND that do not form a chunk.
+    TrailingData(usize),
+    Missing(&'static str),
+    Ordering(OrderingViolation),
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::BadSignature => write!(f, "not a PNG signature"),
+            Issue::Unreadable(reason) => write!(f, "unreadable chunk: {reason}"),
+            Issue::BadCrc {
+                chunk_type,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "{chunk_type} crc is {stored:08x}, its contents give {computed:08x}"
+            ),
+            Issue::TrailingData(len) => write!(f, "{len} bytes of trailing data after IEND"),
+            Issue::Missing(chunk_type) => write!(f, "no {chunk_type} chunk"),
+            Issue::Ordering(violation) => write!(f, "{violation}"),
+        }
+    }
+}
+
+/// An issue and the byte offset it was found at, if it has one; a missing
+/// chunk has none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub offset: Option<usize>,
+    pub issue: Issue,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "offset {offset}: {}", self.issue),
+            None => write!(f, "{}", self.issue),
+        }
+    }
+}
+
+/// Checks the signature, every chunk's crc and the placement rules of
+/// `ordering::validate_ordering`, returning all the problems found in file order.
+///
+/// Unlike parsing a `Png`, this carries on past a bad signature or crc, and
+/// only stops at bytes it cannot read as a chunk at all.
+pub fn verify(bytes: &[u8]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let header_len = Png::STANDARD_HEADER.len();
+    if bytes.len() < header_len || bytes[..header_len] != Png::STANDARD_HEADER {
+        problems.push(Problem {
+            offset: Some(0),
+            issue: Issue::BadSignature,
+        });
+    }
+
+    let mut chunks: Vec<(usize, Chunk)> = Vec::new();
+    let mut offset = header_len.min(bytes.len());
+    while offset < bytes.len() {
+        let seen_iend = chunks.iter().any(|(_, chunk)| is(chunk, b"IEND"));
+        let chunk = match Chunk::parse_with(&bytes[offset..], &ParseOptions::lenient()) {
+            Ok(chunk) => chunk,
+            Err(_) if seen_iend => {
+                problems.push(Problem {
+                    offset: Some(offset),
+                    issue: Issue::TrailingData(bytes.len() - offset),
+                });
+                break;
+            }
+            Err(err) => {
+                problems.push(Problem {
+                    offset: Some(offset),
+                    issue: Issue::Unreadable(err.to_string()),
+                });
+                break;
+            }
+        };
+        if !chunk.has_valid_crc() {
+            problems.push(Problem {
+                offset: Some(offset),
+                issue: Issue::BadCrc {
+                    chunk_type: *chunk.chunk_type(),
+                    stored: chunk.crc(),
+                    computed: chunk.computed_crc(),
+                },
+            });
+        }
+        let len = 12 + chunk.data().len();
+        chunks.push((offset, chunk));
+        offset += len;
+    }
+
+    problems.extend(check_order(&chunks));
+    problems.sort_by_key(|problem| problem.offset.unwrap_or(usize::MAX));
+    problems
+}
+
+fn is(chunk: &Chunk, chunk_type: &[u8; 4]) -> bool {
+    &chunk.chunk_type().bytes() == chunk_type
+}
+
+fn check_order(chunks: &[(usize, Chunk)]) -> Vec<Problem> {
+    let offsets: Vec<usize> = chunks.iter().map(|(offset, _)| *offset).collect();
+    let chunks: Vec<Chunk> = chunks.iter().map(|(_, chunk)| chunk.clone()).collect();
+    let mut problems: Vec<Problem> = ordering::validate_ordering(&chunks)
+        .into_iter()
+        .map(|violation| Problem {
+            offset: Some(offsets[violation.index]),
+            issue: Issue::Ordering(violation),
+        })
+        .collect();
+
+    for chunk_type in ["IHDR", "IDAT", "IEND"] {
+        if !chunks
+            .iter()
+            .any(|chunk| chunk.chunk_type().bytes() == chunk_type.as_bytes())
+        {
+            problems.push(Problem {
+                offset: None,
+                issue: Issue::Missing(chunk_type),
+            });
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ordering::Rule;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(chunk_type.parse().unwrap(), data.to_vec())
+    }
+
+    fn issues(chunks: Vec<Chunk>) -> Vec<Issue> {
+        verify(&Png::from_chunks(chunks).as_bytes())
+            .into_iter()
+            .map(|problem| problem.issue)
+            .collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("PLTE", &[0; 3]),
+            chunk("IDAT", &[1]),
+            chunk("IDAT", &[2]),
+            chunk("tEXt", b"a\0b"),
+            chunk("IEND", &[]),
+        ]);
+        assert_eq!(verify(&png.as_bytes()), Vec::new());
+    }
+
+    #[test]
+    fn test_bad_signature_and_crc() {
+        let mut bytes = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("IDAT", &[1]),
+            chunk("IEND", &[]),
+        ])
+        .as_bytes();
+        bytes[0] = 0;
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        let problems = verify(&bytes);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].issue, Issue::BadSignature);
+        assert_eq!(problems[1].offset, Some(8 + 25 + 13));
+        assert!(matches!(problems[1].issue, Issue::BadCrc { .. }));
+    }
+
+    #[test]
+    fn test_ordering() {
+        let rules: Vec<Rule> = issues(vec![
+            chunk("tEXt", b"a\0b"),
+            chunk("IHDR", &[0; 13]),
+            chunk("IDAT", &[1]),
+            chunk("PLTE", &[0; 3]),
+            chunk("IDAT", &[2]),
+            chunk("IEND", &[]),
+            chunk("ruSt", &[]),
+        ])
+        .into_iter()
+        .map(|issue| match issue {
+            Issue::Ordering(violation) => violation.rule,
+            issue => panic!("unexpected {issue}"),
+        })
+        .collect();
+        assert_eq!(
+            rules,
+            [
+                Rule::IhdrFirst,
+                Rule::BeforeIdat,
+                Rule::ConsecutiveIdat,
+                Rule::AfterIend
+            ]
+        );
+
+        let issues = issues(vec![chunk("IHDR", &[0; 13]), chunk("IHDR", &[0; 13])]);
+        assert!(matches!(
+            &issues[0],
+            Issue::Ordering(violation) if violation.rule == Rule::Unique
+        ));
+        assert_eq!(
+            issues[1..],
+            [Issue::Missing("IDAT"), Issue::Missing("IEND")]
+        );
+    }
+
+    #[test]
+    fn test_trailing_and_truncated() {
+        let mut bytes = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("IDAT", &[1]),
+            chunk("IEND", &[]),
+        ])
+        .as_bytes();
+        let end = bytes.len();
+        bytes.extend(b"junk");
+        assert_eq!(
+            verify(&bytes),
+            vec![Problem {
+                offset: Some(end),
+                issue: Issue::TrailingData(4),
+            }]
+        );
+
+        bytes.truncate(8 + 25 + 5);
+        let problems = verify(&bytes);
+        assert_eq!(problems[0].offset, Some(8 + 25));
+        assert!(matches!(problems[0].issue, Issue::Unreadable(_)));
+        assert_eq!(problems[1].issue, Issue::Missing("IDAT"));
+    }
+}
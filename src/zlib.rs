@@ -0,0 +1,1013 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ZlibError {
+    #[error("ZlibError invalid zlib header")]
+    InvalidHeader,
+    #[error("ZlibError preset dictionaries are not supported")]
+    PresetDictionary,
+    #[error("ZlibError unexpected end of compressed data")]
+    UnexpectedEof,
+    #[error("ZlibError invalid block type {0}")]
+    InvalidBlockType(u32),
+    #[error("ZlibError stored block length does not match its complement")]
+    InvalidStoredLength,
+    #[error("ZlibError invalid huffman code")]
+    InvalidCode,
+    #[error("ZlibError distance {0} reaches before the start of the data")]
+    InvalidDistance(usize),
+    #[error("ZlibError adler32 checksum mismatch")]
+    ChecksumMismatch,
+    #[error("ZlibError decompressed data exceeds {0} bytes")]
+    OutputTooLarge(usize),
+}
+
+/// The strongest compression level, as in zlib.
+pub const MAX_LEVEL: u8 = 9;
+
+/// The level zlib uses when none is given.
+pub const DEFAULT_LEVEL: u8 = 6;
+
+const WINDOW_SIZE: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CODE_LEN: u8 = 15;
+const MAX_CODE_LEN_CODE_LEN: u8 = 7;
+const END_OF_BLOCK: usize = 256;
+const TOKENS_PER_BLOCK: usize = 1 << 14;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order in which code length code lengths are stored in a dynamic block header.
+const CODE_LEN_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Compresses `data` into a zlib stream (RFC 1950) at `level` 0 (stored) to 9.
+pub fn compress(data: &[u8], level: u8) -> Vec<u8> {
+    let level = level.min(MAX_LEVEL);
+    let mut writer = BitWriter::default();
+
+    let cmf: u8 = 0x78;
+    let flevel: u8 = match level {
+        0 | 1 => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+    let mut flg = flevel << 6;
+    flg += 31 - ((u16::from(cmf) << 8 | u16::from(flg)) % 31) as u8;
+    writer.bytes.extend_from_slice(&[cmf, flg]);
+
+    deflate(&mut writer, data, level);
+    writer.flush();
+    writer.bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    writer.bytes
+}
+
+/// Decompresses a zlib stream, verifying its adler32 checksum.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, ZlibError> {
+    decompress_with_limit(data, usize::MAX)
+}
+
+/// Like `decompress`, but gives up once the output would exceed `max_len` bytes,
+/// protecting against small inputs that expand enormously.
+pub fn decompress_with_limit(data: &[u8], max_len: usize) -> Result<Vec<u8>, ZlibError> {
+    if data.len() < 2 {
+        return Err(ZlibError::UnexpectedEof);
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || cmf >> 4 > 7 || (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+        return Err(ZlibError::InvalidHeader);
+    }
+    if flg & 0x20 != 0 {
+        return Err(ZlibError::PresetDictionary);
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+    let output = inflate(&mut reader, max_len)?;
+
+    let trailer = reader.remaining_bytes();
+    if trailer.len() < 4 {
+        return Err(ZlibError::UnexpectedEof);
+    }
+    let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    if adler32(&output) != expected {
+        return Err(ZlibError::ChecksumMismatch);
+    }
+    Ok(output)
+}
+
+/// The compression level advertised by the zlib header at the start of `data`, or
+/// `None` if it doesn't start with a header this module can decompress.
+///
+/// The header only records one of four level classes, so e.g. levels 2 to 5 all read
+/// back as 5.
+pub fn header_level(data: &[u8]) -> Option<u8> {
+    let (&cmf, &flg) = (data.first()?, data.get(1)?);
+    if cmf & 0x0f != 8 || cmf >> 4 > 7 || (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+        return None;
+    }
+    if flg & 0x20 != 0 {
+        return None;
+    }
+    Some([1, 5, DEFAULT_LEVEL, MAX_LEVEL][usize::from(flg >> 6)])
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    // 5552 is the largest run that cannot overflow a u32 before reducing.
+    let (mut a, mut b) = (1u32, 0u32);
+    for block in data.chunks(5552) {
+        for &byte in block {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    b << 16 | a
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u64,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            bit_buf: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn refill(&mut self) {
+        while self.bit_count <= 56 && self.pos < self.data.len() {
+            self.bit_buf |= u64::from(self.data[self.pos]) << self.bit_count;
+            self.pos += 1;
+            self.bit_count += 8;
+        }
+    }
+
+    /// The next `n` bits without consuming them; missing bits past the end read as zero.
+    fn peek(&mut self, n: u32) -> u32 {
+        if self.bit_count < n {
+            self.refill();
+        }
+        (self.bit_buf & ((1u64 << n) - 1)) as u32
+    }
+
+    fn consume(&mut self, n: u32) -> Result<(), ZlibError> {
+        if self.bit_count < n {
+            self.refill();
+            if self.bit_count < n {
+                return Err(ZlibError::UnexpectedEof);
+            }
+        }
+        self.bit_buf >>= n;
+        self.bit_count -= n;
+        Ok(())
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32, ZlibError> {
+        let value = self.peek(n);
+        self.consume(n)?;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        let extra = self.bit_count % 8;
+        self.bit_buf >>= extra;
+        self.bit_count -= extra;
+    }
+
+    /// The unread input after aligning to a byte boundary.
+    fn remaining_bytes(&mut self) -> &'a [u8] {
+        self.align_to_byte();
+        let buffered = (self.bit_count / 8) as usize;
+        &self.data[self.pos - buffered..]
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], ZlibError> {
+        let rest = self.remaining_bytes();
+        if rest.len() < len {
+            return Err(ZlibError::UnexpectedEof);
+        }
+        self.pos = self.data.len() - rest.len() + len;
+        self.bit_buf = 0;
+        self.bit_count = 0;
+        Ok(&rest[..len])
+    }
+}
+
+/// A canonical huffman code decoded through a single lookup table of `max_len` bits.
+struct Decoder {
+    /// `symbol << 4 | length`, indexed by the next `max_len` input bits; zero means invalid.
+    table: Vec<u16>,
+    max_len: u32,
+}
+
+impl Decoder {
+    fn new(lengths: &[u8]) -> Result<Self, ZlibError> {
+        let max_len = u32::from(lengths.iter().copied().max().unwrap_or(0));
+        let mut table = vec![0u16; 1 << max_len];
+
+        let mut count = [0u16; 16];
+        for &len in lengths {
+            count[len as usize] += 1;
+        }
+        count[0] = 0;
+
+        let mut next_code = [0u32; 16];
+        let mut code = 0u32;
+        let mut left = 1i32;
+        for len in 1..16 {
+            left = (left << 1) - i32::from(count[len]);
+            if left < 0 {
+                return Err(ZlibError::InvalidCode);
+            }
+            code = (code + u32::from(count[len - 1])) << 1;
+            next_code[len] = code;
+        }
+
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let len = u32::from(len);
+            let code = next_code[len as usize];
+            next_code[len as usize] += 1;
+
+            let reversed = reverse_bits(code, len) as usize;
+            let entry = (symbol as u16) << 4 | len as u16;
+            for index in (reversed..table.len()).step_by(1 << len) {
+                table[index] = entry;
+            }
+        }
+
+        Ok(Self { table, max_len })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<usize, ZlibError> {
+        let entry = self.table[reader.peek(self.max_len) as usize];
+        if entry == 0 {
+            return Err(ZlibError::InvalidCode);
+        }
+        reader.consume(u32::from(entry & 0x0f))?;
+        Ok((entry >> 4) as usize)
+    }
+}
+
+fn fixed_lengths() -> ([u8; 288], [u8; 30]) {
+    let mut lit = [0u8; 288];
+    lit[..144].fill(8);
+    lit[144..256].fill(9);
+    lit[256..280].fill(7);
+    lit[280..].fill(8);
+    (lit, [5u8; 30])
+}
+
+fn inflate(reader: &mut BitReader, max_len: usize) -> Result<Vec<u8>, ZlibError> {
+    let mut output = Vec::new();
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                let header = reader.read_bytes(4)?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                let nlen = u16::from_le_bytes([header[2], header[3]]);
+                if len != !nlen {
+                    return Err(ZlibError::InvalidStoredLength);
+                }
+                if output.len() + len as usize > max_len {
+                    return Err(ZlibError::OutputTooLarge(max_len));
+                }
+                output.extend_from_slice(reader.read_bytes(len as usize)?);
+            }
+            1 => {
+                let (lit, dist) = fixed_lengths();
+                let lit = Decoder::new(&lit)?;
+                let dist = Decoder::new(&dist)?;
+                inflate_block(reader, &mut output, &lit, &dist, max_len)?;
+            }
+            2 => {
+                let (lit, dist) = read_dynamic_header(reader)?;
+                inflate_block(reader, &mut output, &lit, &dist, max_len)?;
+            }
+            block_type => return Err(ZlibError::InvalidBlockType(block_type)),
+        }
+
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+fn read_dynamic_header(reader: &mut BitReader) -> Result<(Decoder, Decoder), ZlibError> {
+    let hlit = reader.bits(5)? as usize + 257;
+    let hdist = reader.bits(5)? as usize + 1;
+    let hclen = reader.bits(4)? as usize + 4;
+
+    let mut code_len_lengths = [0u8; 19];
+    for &index in &CODE_LEN_ORDER[..hclen] {
+        code_len_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_len_decoder = Decoder::new(&code_len_lengths)?;
+
+    let mut lengths = vec![0u8; hlit + hdist];
+    let mut i = 0;
+    while i < lengths.len() {
+        let (value, repeat) = match code_len_decoder.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths[..i].last().ok_or(ZlibError::InvalidCode)?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err(ZlibError::InvalidCode);
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+
+    if lengths[END_OF_BLOCK] == 0 {
+        return Err(ZlibError::InvalidCode);
+    }
+    Ok((
+        Decoder::new(&lengths[..hlit])?,
+        Decoder::new(&lengths[hlit..])?,
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    lit: &Decoder,
+    dist: &Decoder,
+    max_len: usize,
+) -> Result<(), ZlibError> {
+    loop {
+        let symbol = lit.decode(reader)?;
+        if symbol < END_OF_BLOCK {
+            if output.len() >= max_len {
+                return Err(ZlibError::OutputTooLarge(max_len));
+            }
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == END_OF_BLOCK {
+            return Ok(());
+        }
+
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len() {
+            return Err(ZlibError::InvalidCode);
+        }
+        let len =
+            LENGTH_BASE[index] as usize + reader.bits(u32::from(LENGTH_EXTRA[index]))? as usize;
+
+        let index = dist.decode(reader)?;
+        if index >= DIST_BASE.len() {
+            return Err(ZlibError::InvalidCode);
+        }
+        let distance =
+            DIST_BASE[index] as usize + reader.bits(u32::from(DIST_EXTRA[index]))? as usize;
+        if distance > output.len() {
+            return Err(ZlibError::InvalidDistance(distance));
+        }
+        if output.len() + len > max_len {
+            return Err(ZlibError::OutputTooLarge(max_len));
+        }
+
+        let start = output.len() - distance;
+        for i in 0..len {
+            output.push(output[start + i]);
+        }
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_buf: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, n: u32) {
+        self.bit_buf |= u64::from(value) << self.bit_count;
+        self.bit_count += n;
+        while self.bit_count >= 8 {
+            self.bytes.push(self.bit_buf as u8);
+            self.bit_buf >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.bit_count > 0 {
+            self.bytes.push(self.bit_buf as u8);
+            self.bit_buf = 0;
+            self.bit_count = 0;
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { len: u16, distance: u16 },
+}
+
+fn deflate(writer: &mut BitWriter, data: &[u8], level: u8) {
+    if level == 0 || data.is_empty() {
+        write_stored(writer, data, true);
+        return;
+    }
+
+    let tokens = find_matches(data, level);
+    let mut start = 0;
+    let blocks: Vec<&[Token]> = tokens.chunks(TOKENS_PER_BLOCK).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        let len: usize = block.iter().map(token_len).sum();
+        let last = i + 1 == blocks.len();
+        write_block(writer, block, &data[start..start + len], last);
+        start += len;
+    }
+}
+
+fn token_len(token: &Token) -> usize {
+    match token {
+        Token::Literal(_) => 1,
+        Token::Match { len, .. } => *len as usize,
+    }
+}
+
+fn write_stored(writer: &mut BitWriter, data: &[u8], last: bool) {
+    let pieces: Vec<&[u8]> = if data.is_empty() {
+        vec![data]
+    } else {
+        data.chunks(u16::MAX as usize).collect()
+    };
+    for (i, piece) in pieces.iter().enumerate() {
+        let final_piece = last && i + 1 == pieces.len();
+        writer.write(u32::from(final_piece), 1);
+        writer.write(0, 2);
+        writer.flush();
+        let len = piece.len() as u16;
+        writer.bytes.extend_from_slice(&len.to_le_bytes());
+        writer.bytes.extend_from_slice(&(!len).to_le_bytes());
+        writer.bytes.extend_from_slice(piece);
+    }
+}
+
+/// Greedy LZ77 over hash chains of 3-byte prefixes, with one step of lazy matching
+/// from level 4 up. Higher levels follow longer chains.
+fn find_matches(data: &[u8], level: u8) -> Vec<Token> {
+    const HASH_BITS: u32 = 15;
+    const NONE: u32 = u32::MAX;
+    let max_chain = [0, 4, 8, 16, 32, 64, 128, 256, 1024, 4096][level as usize];
+    let lazy = level >= 4;
+
+    let hash = |pos: usize| -> usize {
+        let value =
+            u32::from(data[pos]) << 16 | u32::from(data[pos + 1]) << 8 | u32::from(data[pos + 2]);
+        (value.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    };
+    let mut head = vec![NONE; 1 << HASH_BITS];
+    let mut prev = vec![NONE; WINDOW_SIZE];
+    let insert = |pos: usize, head: &mut Vec<u32>, prev: &mut Vec<u32>| {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(pos);
+            prev[pos % WINDOW_SIZE] = head[h];
+            head[h] = pos as u32;
+        }
+    };
+
+    let longest_match = |pos: usize, head: &Vec<u32>, prev: &Vec<u32>| -> (usize, usize) {
+        if pos + MIN_MATCH > data.len() {
+            return (0, 0);
+        }
+        let max_len = MAX_MATCH.min(data.len() - pos);
+        let (mut best_len, mut best_distance) = (0, 0);
+        let mut candidate = head[hash(pos)];
+        let mut chain = max_chain;
+        while candidate != NONE && chain > 0 {
+            let candidate_pos = candidate as usize;
+            let distance = pos - candidate_pos;
+            if distance == 0 || distance > WINDOW_SIZE {
+                break;
+            }
+            if data[candidate_pos + best_len.min(max_len - 1)]
+                == data[pos + best_len.min(max_len - 1)]
+            {
+                let len = data[candidate_pos..candidate_pos + max_len]
+                    .iter()
+                    .zip(&data[pos..pos + max_len])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    best_len = len;
+                    best_distance = distance;
+                    if len == max_len {
+                        break;
+                    }
+                }
+            }
+            let next = prev[candidate_pos % WINDOW_SIZE];
+            if next == NONE || next as usize >= candidate_pos {
+                break;
+            }
+            candidate = next;
+            chain -= 1;
+        }
+        if best_len >= MIN_MATCH {
+            (best_len, best_distance)
+        } else {
+            (0, 0)
+        }
+    };
+
+    let mut tokens = Vec::with_capacity(data.len() / 2);
+    let mut pos = 0;
+    while pos < data.len() {
+        let (mut len, mut distance) = longest_match(pos, &head, &prev);
+        if len == 0 {
+            insert(pos, &mut head, &mut prev);
+            tokens.push(Token::Literal(data[pos]));
+            pos += 1;
+            continue;
+        }
+
+        if lazy && len < MAX_MATCH && pos + 1 < data.len() {
+            insert(pos, &mut head, &mut prev);
+            let (next_len, next_distance) = longest_match(pos + 1, &head, &prev);
+            if next_len > len {
+                tokens.push(Token::Literal(data[pos]));
+                pos += 1;
+                len = next_len;
+                distance = next_distance;
+                insert(pos, &mut head, &mut prev);
+            }
+        } else {
+            insert(pos, &mut head, &mut prev);
+        }
+        for p in pos + 1..pos + len {
+            insert(p, &mut head, &mut prev);
+        }
+
+        tokens.push(Token::Match {
+            len: len as u16,
+            distance: distance as u16,
+        });
+        pos += len;
+    }
+    tokens
+}
+
+fn length_symbol(len: usize) -> (usize, u32, u32) {
+    let index = LENGTH_BASE.partition_point(|&base| base as usize <= len) - 1;
+    let index = if len == MAX_MATCH { 28 } else { index.min(27) };
+    (
+        257 + index,
+        (len - LENGTH_BASE[index] as usize) as u32,
+        u32::from(LENGTH_EXTRA[index]),
+    )
+}
+
+fn distance_symbol(distance: usize) -> (usize, u32, u32) {
+    let index = DIST_BASE.partition_point(|&base| base as usize <= distance) - 1;
+    (
+        index,
+        (distance - DIST_BASE[index] as usize) as u32,
+        u32::from(DIST_EXTRA[index]),
+    )
+}
+
+/// Writes one block using whichever of stored, fixed or dynamic huffman coding is smallest.
+fn write_block(writer: &mut BitWriter, tokens: &[Token], data: &[u8], last: bool) {
+    let mut lit_freq = [0u32; 286];
+    let mut dist_freq = [0u32; 30];
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => lit_freq[byte as usize] += 1,
+            Token::Match { len, distance } => {
+                lit_freq[length_symbol(len as usize).0] += 1;
+                dist_freq[distance_symbol(distance as usize).0] += 1;
+            }
+        }
+    }
+    lit_freq[END_OF_BLOCK] += 1;
+
+    let mut lit_lengths = huffman_lengths(&lit_freq, MAX_CODE_LEN);
+    let mut dist_lengths = huffman_lengths(&dist_freq, MAX_CODE_LEN);
+    // Inflaters disagree about incomplete codes, so always give them at least two symbols.
+    ensure_two_codes(&mut lit_lengths);
+    ensure_two_codes(&mut dist_lengths);
+
+    let hlit = 257.max(last_nonzero(&lit_lengths));
+    let hdist = 1.max(last_nonzero(&dist_lengths));
+    let mut all_lengths = lit_lengths[..hlit].to_vec();
+    all_lengths.extend_from_slice(&dist_lengths[..hdist]);
+    let rle = run_length_encode(&all_lengths);
+
+    let mut code_len_freq = [0u32; 19];
+    for &(symbol, _) in &rle {
+        code_len_freq[symbol as usize] += 1;
+    }
+    let mut code_len_lengths = huffman_lengths(&code_len_freq, MAX_CODE_LEN_CODE_LEN);
+    ensure_two_codes(&mut code_len_lengths);
+    let hclen = 4.max(
+        CODE_LEN_ORDER
+            .iter()
+            .rposition(|&i| code_len_lengths[i] != 0)
+            .map_or(0, |i| i + 1),
+    );
+
+    let (fixed_lit, fixed_dist) = fixed_lengths();
+    let data_cost = |lit: &[u8], dist: &[u8]| -> u64 {
+        let mut bits = 0u64;
+        for (symbol, &freq) in lit_freq.iter().enumerate() {
+            let extra = if symbol > 256 {
+                LENGTH_EXTRA[symbol - 257]
+            } else {
+                0
+            };
+            bits += u64::from(freq) * u64::from(lit[symbol] + extra);
+        }
+        for (symbol, &freq) in dist_freq.iter().enumerate() {
+            bits += u64::from(freq) * u64::from(dist[symbol] + DIST_EXTRA[symbol]);
+        }
+        bits
+    };
+
+    let fixed_cost = 3 + data_cost(&fixed_lit, &fixed_dist);
+    let header_cost = 3
+        + 14
+        + 3 * hclen as u64
+        + rle
+            .iter()
+            .map(|&(symbol, _)| {
+                let extra = match symbol {
+                    16 => 2,
+                    17 => 3,
+                    18 => 7,
+                    _ => 0,
+                };
+                u64::from(code_len_lengths[symbol as usize]) + extra
+            })
+            .sum::<u64>();
+    let dynamic_cost = header_cost + data_cost(&lit_lengths, &dist_lengths);
+    let stored_cost =
+        8 * (data.len() as u64 + 5 * data.len().div_ceil(u16::MAX as usize).max(1) as u64) + 7;
+
+    if stored_cost <= fixed_cost.min(dynamic_cost) {
+        write_stored(writer, data, last);
+        return;
+    }
+
+    writer.write(u32::from(last), 1);
+    if fixed_cost <= dynamic_cost {
+        writer.write(1, 2);
+        write_tokens(writer, tokens, &fixed_lit, &fixed_dist);
+    } else {
+        writer.write(2, 2);
+        writer.write((hlit - 257) as u32, 5);
+        writer.write((hdist - 1) as u32, 5);
+        writer.write((hclen - 4) as u32, 4);
+        for &index in &CODE_LEN_ORDER[..hclen] {
+            writer.write(u32::from(code_len_lengths[index]), 3);
+        }
+        let code_len_codes = canonical_codes(&code_len_lengths);
+        for &(symbol, extra) in &rle {
+            let symbol = symbol as usize;
+            writer.write(code_len_codes[symbol], u32::from(code_len_lengths[symbol]));
+            match symbol {
+                16 => writer.write(u32::from(extra), 2),
+                17 => writer.write(u32::from(extra), 3),
+                18 => writer.write(u32::from(extra), 7),
+                _ => {}
+            }
+        }
+        write_tokens(writer, tokens, &lit_lengths, &dist_lengths);
+    }
+}
+
+fn write_tokens(writer: &mut BitWriter, tokens: &[Token], lit_lengths: &[u8], dist_lengths: &[u8]) {
+    let lit_codes = canonical_codes(lit_lengths);
+    let dist_codes = canonical_codes(dist_lengths);
+
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => {
+                let symbol = byte as usize;
+                writer.write(lit_codes[symbol], u32::from(lit_lengths[symbol]));
+            }
+            Token::Match { len, distance } => {
+                let (symbol, extra, extra_bits) = length_symbol(len as usize);
+                writer.write(lit_codes[symbol], u32::from(lit_lengths[symbol]));
+                writer.write(extra, extra_bits);
+
+                let (symbol, extra, extra_bits) = distance_symbol(distance as usize);
+                writer.write(dist_codes[symbol], u32::from(dist_lengths[symbol]));
+                writer.write(extra, extra_bits);
+            }
+        }
+    }
+    writer.write(
+        lit_codes[END_OF_BLOCK],
+        u32::from(lit_lengths[END_OF_BLOCK]),
+    );
+}
+
+fn last_nonzero(lengths: &[u8]) -> usize {
+    lengths
+        .iter()
+        .rposition(|&len| len != 0)
+        .map_or(0, |i| i + 1)
+}
+
+fn ensure_two_codes(lengths: &mut [u8]) {
+    match lengths.iter().filter(|&&len| len != 0).count() {
+        0 => lengths[..2].fill(1),
+        1 => {
+            let unused = if lengths[0] == 0 { 0 } else { 1 };
+            lengths[unused] = 1;
+        }
+        _ => {}
+    }
+}
+
+/// Code lengths (at most `max_len` bits) for a huffman code over `freq`.
+///
+/// Lengths that come out too long are fixed by halving the frequencies and trying again,
+/// which flattens the tree a little at a time.
+fn huffman_lengths(freq: &[u32], max_len: u8) -> Vec<u8> {
+    let mut freq = freq.to_vec();
+    loop {
+        let lengths = unlimited_huffman_lengths(&freq);
+        if lengths.iter().all(|&len| len <= max_len) {
+            return lengths;
+        }
+        for f in freq.iter_mut().filter(|f| **f > 0) {
+            *f = (*f).div_ceil(2);
+        }
+    }
+}
+
+fn unlimited_huffman_lengths(freq: &[u32]) -> Vec<u8> {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    let mut lengths = vec![0u8; freq.len()];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = BinaryHeap::new();
+    // Nodes below freq.len() are leaves; the rest are internal, with their children's ids.
+    let mut parent: Vec<usize> = vec![usize::MAX; freq.len()];
+    for (symbol, &f) in freq.iter().enumerate() {
+        if f > 0 {
+            heap.push(Reverse((u64::from(f), symbol)));
+        }
+    }
+    if heap.len() == 1 {
+        let Reverse((_, symbol)) = heap.pop().unwrap();
+        lengths[symbol] = 1;
+        return lengths;
+    }
+
+    while heap.len() > 1 {
+        let Reverse((f1, a)) = heap.pop().unwrap();
+        let Reverse((f2, b)) = heap.pop().unwrap();
+        let node = parent.len();
+        parent.push(usize::MAX);
+        parent[a] = node;
+        parent[b] = node;
+        heap.push(Reverse((f1 + f2, node)));
+    }
+
+    let mut depth = vec![0u8; parent.len()];
+    for node in (0..parent.len()).rev() {
+        if parent[node] != usize::MAX {
+            depth[node] = depth[parent[node]].saturating_add(1);
+        }
+    }
+    for (symbol, &f) in freq.iter().enumerate() {
+        if f > 0 {
+            lengths[symbol] = depth[symbol];
+        }
+    }
+    lengths
+}
+
+/// Canonical codes for `lengths`, bit-reversed so they can be written least significant bit first.
+fn canonical_codes(lengths: &[u8]) -> Vec<u32> {
+    let mut count = [0u32; 16];
+    for &len in lengths {
+        count[len as usize] += 1;
+    }
+    count[0] = 0;
+
+    let mut next_code = [0u32; 16];
+    let mut code = 0;
+    for len in 1..16 {
+        code = (code + count[len - 1]) << 1;
+        next_code[len] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|&len| {
+            if len == 0 {
+                return 0;
+            }
+            let code = next_code[len as usize];
+            next_code[len as usize] += 1;
+            reverse_bits(code, u32::from(len))
+        })
+        .collect()
+}
+
+/// Run-length encodes code lengths with symbols 16-18, returning `(symbol, extra bits)`.
+fn run_length_encode(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let len = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == len).count();
+
+        if len == 0 && run >= 3 {
+            let take = run.min(138);
+            if take >= 11 {
+                out.push((18, (take - 11) as u8));
+            } else {
+                out.push((17, (take - 3) as u8));
+            }
+            i += take;
+        } else if len != 0 && run >= 4 {
+            out.push((len, 0));
+            let take = (run - 1).min(6);
+            out.push((16, (take - 3) as u8));
+            i += 1 + take;
+        } else {
+            out.push((len, 0));
+            i += 1;
+        }
+    }
+    out
+}
+
+fn reverse_bits(code: u32, len: u32) -> u32 {
+    code.reverse_bits() >> (32 - len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_text() -> Vec<u8> {
+        "The quick brown fox jumps over the lazy dog. "
+            .repeat(200)
+            .into_bytes()
+    }
+
+    fn testing_noise(len: usize) -> Vec<u8> {
+        let mut state = 0x1234_5678u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_round_trip_all_levels() {
+        let inputs = [
+            Vec::new(),
+            b"a".to_vec(),
+            testing_text(),
+            testing_noise(70_000),
+            vec![0u8; 100_000],
+        ];
+        for input in &inputs {
+            for level in 0..=MAX_LEVEL {
+                let compressed = compress(input, level);
+                assert_eq!(&decompress(&compressed).unwrap(), input, "level {level}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_compression_shrinks_redundant_data() {
+        let text = testing_text();
+        let compressed = compress(&text, DEFAULT_LEVEL);
+        assert!(compressed.len() < text.len() / 10);
+
+        let stored = compress(&text, 0);
+        assert!(stored.len() > text.len());
+    }
+
+    #[test]
+    fn test_decompress_zlib_output() {
+        // zlib.compress(b"hello hello hello hello\n", 9), a fixed huffman block
+        let compressed = [
+            0x78, 0xda, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0xb9, 0x00, 0x70,
+            0xbe, 0x08, 0xbb,
+        ];
+        assert_eq!(
+            decompress(&compressed).unwrap(),
+            b"hello hello hello hello\n"
+        );
+    }
+
+    #[test]
+    fn test_decompress_zlib_dynamic_block() {
+        // zlib.compress(b"".join(b"%d squared is %d; " % (i, i * i) for i in range(40)), 9)
+        let compressed = [
+            0x78, 0xda, 0x5d, 0x92, 0x31, 0x0e, 0x43, 0x31, 0x08, 0x43, 0xaf, 0xc2, 0x11, 0x02,
+            0x24, 0x24, 0x51, 0x4e, 0x53, 0xa9, 0x1d, 0x3a, 0xb6, 0x55, 0xef, 0x5f, 0xfc, 0x27,
+            0xdc, 0x31, 0x08, 0xd9, 0xcf, 0x26, 0x4d, 0x3e, 0xaf, 0xef, 0xed, 0xfd, 0xb8, 0xcb,
+            0xf3, 0x23, 0xed, 0x88, 0xd6, 0xb7, 0x1e, 0xb1, 0xfa, 0xee, 0x47, 0xbc, 0xbe, 0xf7,
+            0x91, 0x4e, 0xfb, 0x71, 0x64, 0xd4, 0x81, 0x8d, 0x23, 0x51, 0x07, 0x9e, 0x1b, 0x93,
+            0x24, 0x53, 0x63, 0xd5, 0x41, 0xa4, 0xc9, 0xae, 0x83, 0x95, 0x14, 0xda, 0xc8, 0xa6,
+            0x01, 0x94, 0x49, 0x0d, 0x5b, 0x04, 0xab, 0x3d, 0x95, 0xd4, 0x99, 0x2f, 0xdd, 0x94,
+            0x91, 0x77, 0x12, 0x29, 0x43, 0x83, 0x5a, 0x83, 0x73, 0x60, 0x8b, 0xc0, 0x6d, 0x41,
+            0x8b, 0xd0, 0xdd, 0xe0, 0xb8, 0x39, 0x2f, 0x3a, 0x24, 0xfa, 0x0e, 0x7a, 0x23, 0xfa,
+            0xde, 0xb1, 0xc5, 0x55, 0xaf, 0xd4, 0x32, 0xa2, 0x1f, 0x96, 0x8e, 0x46, 0xf4, 0x63,
+            0x26, 0x97, 0x11, 0x7d, 0x80, 0xde, 0x88, 0x3e, 0xae, 0x2d, 0xa2, 0x9f, 0x97, 0x16,
+            0xd1, 0xcf, 0xcb, 0x91, 0xab, 0x07, 0x97, 0x13, 0xfd, 0x06, 0xbd, 0x13, 0xfd, 0x46,
+            0x46, 0xe7, 0xee, 0x1b, 0xaa, 0x70, 0x2e, 0xbf, 0xa1, 0x31, 0xe7, 0xf6, 0x15, 0xc5,
+            0xfa, 0xe0, 0x53, 0x22, 0x81, 0x07, 0xcf, 0x70, 0x26, 0xa7, 0x08, 0xea, 0xb8, 0xa6,
+            0xaf, 0xbf, 0x9b, 0xc3, 0x97, 0x42, 0xe8, 0xc0, 0xdf, 0xf8, 0x01, 0xc0, 0x38, 0xdb,
+            0x15,
+        ];
+        let expected: String = (0..40)
+            .map(|i| format!("{i} squared is {}; ", i * i))
+            .collect();
+        assert_eq!(decompress(&compressed).unwrap(), expected.into_bytes());
+    }
+
+    #[test]
+    fn test_corrupt_streams() {
+        let mut compressed = compress(&testing_text(), DEFAULT_LEVEL);
+        assert_eq!(decompress(&compressed[..1]), Err(ZlibError::UnexpectedEof));
+        assert_eq!(decompress(&[0x78, 0x00]), Err(ZlibError::InvalidHeader));
+
+        let last = compressed.len() - 1;
+        compressed[last] ^= 1;
+        assert_eq!(decompress(&compressed), Err(ZlibError::ChecksumMismatch));
+
+        let truncated = &compressed[..compressed.len() / 2];
+        assert!(decompress(truncated).is_err());
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let compressed = compress(&vec![0u8; 100_000], DEFAULT_LEVEL);
+        assert_eq!(
+            decompress_with_limit(&compressed, 1000),
+            Err(ZlibError::OutputTooLarge(1000))
+        );
+        assert!(decompress_with_limit(&compressed, 100_000).is_ok());
+    }
+
+    #[test]
+    fn test_header_level() {
+        for (level, expected) in [(0, 1), (1, 1), (3, 5), (6, 6), (9, 9)] {
+            assert_eq!(header_level(&compress(b"x", level)), Some(expected));
+        }
+        assert_eq!(header_level(b"x"), None);
+        assert_eq!(header_level(b"plain text"), None);
+        assert_eq!(header_level(&[0x78, 0x00]), None);
+        assert_eq!(header_level(&[0x78, 0xbb]), None);
+    }
+}